@@ -0,0 +1,59 @@
+//! Run with `cargo bench --features foldhash` to compare the hashers.
+
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use user_trait::{PlainText, UserAuthenticator, UserBox, UsersMap};
+
+const USERS: usize = 100_000;
+
+fn fill<S: BuildHasher + Clone>(um: &mut UsersMap<PlainText, S>) {
+    for i in 0..USERS {
+        um.add_user(PlainText::new(format!("user{i}"), format!("password{i}")));
+    }
+}
+
+fn bench_map<S: BuildHasher + Clone>(c: &mut Criterion, name: &str, um: &UsersMap<PlainText, S>) {
+    let hit = format!("plaintext:user{}\npassword{}", USERS / 2, USERS / 2);
+    let miss = "plaintext:nobody\npassword";
+    c.bench_function(&format!("{name}/auth_hit"), |b| {
+        b.iter(|| um.auth_user_by_authstr(black_box(&hit)))
+    });
+    c.bench_function(&format!("{name}/auth_hit_shared"), |b| {
+        b.iter(|| um.try_auth_shared(black_box(&hit)))
+    });
+    c.bench_function(&format!("{name}/auth_miss"), |b| {
+        b.iter(|| um.auth_user_by_authstr(black_box(miss)))
+    });
+}
+
+fn auth_lookup(c: &mut Criterion) {
+    let mut um = UsersMap::new();
+    fill(&mut um);
+    bench_map(c, "siphash", &um);
+
+    #[cfg(feature = "foldhash")]
+    {
+        let mut um = user_trait::FastUsersMap::with_hasher(Default::default());
+        fill(&mut um);
+        bench_map(c, "foldhash", &um);
+    }
+}
+
+fn user_box_hash(c: &mut Criterion) {
+    let boxes: HashSet<UserBox> = (0..1000)
+        .map(|i| UserBox::new(PlainText::new(format!("user{i}"), format!("password{i}"))))
+        .collect();
+    let probe = UserBox::new(PlainText::new("user500".into(), "password500".into()));
+    c.bench_function("user_box/contains", |b| {
+        b.iter(|| boxes.contains(black_box(&probe)))
+    });
+    c.bench_function("user_box/new", |b| {
+        b.iter(|| UserBox::new(PlainText::new(black_box("user").into(), "password".into())))
+    });
+}
+
+criterion_group!(benches, auth_lookup, user_box_hash);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+/*
+ * C interface of the user_trait crate, built with its `ffi` feature.
+ * See src/ffi.rs for the documentation of each function.
+ */
+
+#ifndef USER_TRAIT_H
+#define USER_TRAIT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct UserTraitMap UserTraitMap;
+
+UserTraitMap *user_trait_map_new(void);
+void user_trait_map_free(UserTraitMap *map);
+
+int user_trait_map_add_plaintext(const UserTraitMap *map, const char *user, const char *pass);
+int user_trait_map_remove(const UserTraitMap *map, const char *id);
+size_t user_trait_map_len(const UserTraitMap *map);
+
+/* Returns the identity, to be freed with user_trait_string_free, or NULL. */
+char *user_trait_map_auth(const UserTraitMap *map, const uint8_t *authstr, size_t len);
+void user_trait_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,83 @@
+/*!
+Asynchronous authentication, for backends that have to wait on I/O such as Redis, SQL or HTTP.
+*/
+
+use async_trait::async_trait;
+
+use crate::{AuthError, User, UserAuthenticator};
+
+/// The asynchronous counterpart of [`UserAuthenticator`].
+///
+/// Every `Send + Sync` synchronous authenticator implements it, so code written against
+/// this trait accepts in-memory maps and remote backends alike.
+/// Bring only one of the two traits into scope, or call through the trait path,
+/// as both name their method `auth_user_by_authstr`.
+#[async_trait]
+pub trait AsyncUserAuthenticator<T: User> {
+    /// Authenticates a user using the provided authentication string.
+    async fn auth_user_by_authstr(&self, authstr: &str) -> Option<T>;
+
+    /// Like [`AsyncUserAuthenticator::auth_user_by_authstr`], telling why authentication
+    /// failed, e.g. [`AuthError::Backend`] when a remote store is unreachable.
+    async fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.auth_user_by_authstr(authstr)
+            .await
+            .ok_or(AuthError::UnknownUser)
+    }
+}
+
+#[async_trait]
+impl<T, A> AsyncUserAuthenticator<T> for A
+where
+    T: User + 'static,
+    A: UserAuthenticator<T> + Sync + ?Sized,
+{
+    async fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        UserAuthenticator::auth_user_by_authstr(self, authstr)
+    }
+
+    async fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        UserAuthenticator::try_auth(self, authstr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use super::AsyncUserAuthenticator;
+    use crate::{PlainText, UsersMap};
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    async fn login<A: AsyncUserAuthenticator<PlainText> + ?Sized>(
+        auth: &A,
+        authstr: &str,
+    ) -> Option<String> {
+        auth.auth_user_by_authstr(authstr)
+            .await
+            .map(|u| u.user.clone())
+    }
+
+    #[test]
+    fn test_sync_as_async() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+
+        assert_eq!(block_on(login(&um, "plaintext:u\np")), Some("u".into()));
+        assert_eq!(block_on(login(&um, "plaintext:u\nx")), None);
+
+        let dyn_auth: &(dyn AsyncUserAuthenticator<PlainText> + Sync) = &um;
+        assert!(block_on(dyn_auth.auth_user_by_authstr("plaintext:u\np")).is_some());
+    }
+}
@@ -0,0 +1,136 @@
+/*!
+An asynchronous counterpart of [`UserStore`](crate::UserStore), for backends reached over
+the network.
+*/
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use futures_core::Stream;
+
+use crate::{SharedUsersMap, StoreError, UserTrait};
+
+/// The users of an [`AsyncUserStore`], as returned by [`AsyncUserStore::list_stream`]
+pub type UserStream<'a, T> = Pin<Box<dyn Stream<Item = Result<T, StoreError>> + Send + 'a>>;
+
+/// Create, read, update and delete operations on a set of users, keyed by identity,
+/// for admin APIs over asynchronous backends.
+///
+/// Methods take `&self`, as such backends are shared between tasks; the in-memory
+/// implementation is that of [`SharedUsersMap`].
+#[async_trait]
+pub trait AsyncUserStore<T>: Send + Sync {
+    /// Adds a user, failing with [`StoreError::AlreadyExists`] if its identity is taken.
+    async fn add(&self, user: T) -> Result<(), StoreError>;
+
+    /// Removes a user, returning it.
+    async fn remove(&self, id: &str) -> Result<T, StoreError>;
+
+    /// Replaces the user having the same identity, returning the previous one.
+    async fn update(&self, user: T) -> Result<T, StoreError>;
+
+    async fn get(&self, id: &str) -> Result<Option<T>, StoreError>;
+
+    /// Streams every user, in no particular order, so that backends holding more users
+    /// than fit in memory can be listed page by page.
+    fn list_stream(&self) -> UserStream<'_, T>;
+
+    async fn count(&self) -> Result<usize, StoreError>;
+}
+
+/// A stream yielding the items of an iterator, which never has to wait
+struct IterStream<I>(I);
+
+impl<I: Iterator + Unpin> Stream for IterStream<I> {
+    type Item = I::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+        Poll::Ready(self.0.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// Clones the stored users; [`AsyncUserStore::list_stream`] lists the users present
+/// when it is called.
+#[async_trait]
+impl<T: UserTrait + Clone> AsyncUserStore<T> for SharedUsersMap<T> {
+    async fn add(&self, user: T) -> Result<(), StoreError> {
+        let id = user.identity_str().to_string();
+        if self.add_new_user(user) {
+            Ok(())
+        } else {
+            Err(StoreError::AlreadyExists(id))
+        }
+    }
+
+    async fn remove(&self, id: &str) -> Result<T, StoreError> {
+        self.remove_user(id)
+            .map(|user| user.as_ref().clone())
+            .ok_or_else(|| StoreError::NotFound(id.to_string()))
+    }
+
+    async fn update(&self, user: T) -> Result<T, StoreError> {
+        let id = user.identity_str().to_string();
+        self.update_user(user)
+            .map(|old| old.as_ref().clone())
+            .ok_or(StoreError::NotFound(id))
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<T>, StoreError> {
+        Ok(self.get_user(id).map(|u| u.as_ref().clone()))
+    }
+
+    fn list_stream(&self) -> UserStream<'_, T> {
+        let users: Vec<Arc<T>> = self.read().iter().map(Arc::clone).collect();
+        Box::pin(IterStream(
+            users.into_iter().map(|user| Ok(user.as_ref().clone())),
+        ))
+    }
+
+    async fn count(&self) -> Result<usize, StoreError> {
+        Ok(self.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::poll_fn;
+
+    use super::AsyncUserStore;
+    use crate::{PlainText, SharedUsersMap, StoreError};
+
+    #[test]
+    fn test_shared_map_async_store() -> Result<(), StoreError> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let store: &dyn AsyncUserStore<PlainText> = &SharedUsersMap::default();
+            store.add(PlainText::from("u p")).await?;
+            store.add(PlainText::from("v p")).await?;
+            assert!(matches!(
+                store.add(PlainText::from("u q")).await,
+                Err(StoreError::AlreadyExists(_))
+            ));
+            assert_eq!(store.update(PlainText::from("u q")).await?.pass, "p");
+            assert_eq!(store.count().await?, 2);
+
+            let mut stream = store.list_stream();
+            let mut names = Vec::new();
+            while let Some(user) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+                names.push(user?.user.clone());
+            }
+            names.sort();
+            assert_eq!(names, ["u", "v"]);
+
+            assert_eq!(store.remove("v").await?.pass, "p");
+            assert!(store.get("v").await?.is_none());
+            Ok(())
+        })
+    }
+}
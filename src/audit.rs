@@ -0,0 +1,371 @@
+/*!
+Audit trails of authentication attempts.
+*/
+
+use std::collections::BTreeMap;
+#[cfg(feature = "serde")]
+use std::fs::OpenOptions;
+#[cfg(feature = "serde")]
+use std::io::{self, Write};
+#[cfg(feature = "serde")]
+use std::path::Path;
+#[cfg(feature = "serde")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+#[cfg(feature = "serde")]
+use std::time::UNIX_EPOCH;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+use crate::interner::intern_with;
+use crate::{
+    AuthContext, AuthError, Clock, ContextAuthenticator, IdInterner, SystemClock, User,
+    UserAuthenticator,
+};
+
+/// The result of an audited authentication attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AuditOutcome {
+    Success,
+    UnknownUser,
+    BadCredential,
+    Expired,
+    Disabled,
+    Forbidden,
+    RateLimited,
+    BackendError,
+}
+
+impl<T> From<&Result<T, AuthError>> for AuditOutcome {
+    fn from(result: &Result<T, AuthError>) -> Self {
+        match result {
+            Ok(_) => AuditOutcome::Success,
+            Err(AuthError::UnknownUser) => AuditOutcome::UnknownUser,
+            Err(AuthError::BadCredential) => AuditOutcome::BadCredential,
+            Err(AuthError::Expired) => AuditOutcome::Expired,
+            Err(AuthError::Disabled) => AuditOutcome::Disabled,
+            Err(AuthError::Forbidden) => AuditOutcome::Forbidden,
+            Err(AuthError::RateLimited { .. }) => AuditOutcome::RateLimited,
+            Err(AuthError::Backend(_)) => AuditOutcome::BackendError,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn unix_millis<S: Serializer>(t: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+    let ms = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+    s.serialize_u64(ms.try_into().unwrap_or(u64::MAX))
+}
+
+/// One authentication attempt, e.g. serialized by [`JsonLinesAuditSink`] as
+///
+/// ```json
+/// {"timestamp":1700000000000,"identity":"u","outcome":"success","context":{"remote":"10.0.0.1:5000"}}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct AuditEvent {
+    /// Milliseconds since the Unix epoch when serialized
+    #[cfg_attr(feature = "serde", serde(serialize_with = "unix_millis"))]
+    pub timestamp: SystemTime,
+
+    /// The authenticated identity; `None` for failures, as the credential
+    /// doesn't tell whom it was meant for.
+    pub identity: Option<Arc<str>>,
+
+    pub outcome: AuditOutcome,
+
+    /// Supplied by the caller, such as the remote address or the protocol
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "BTreeMap::is_empty"))]
+    pub context: BTreeMap<String, String>,
+}
+
+/// Receives the events of an [`AuditingAuthenticator`].
+///
+/// Sinks are called on the authentication path, so they should be quick;
+/// a slow destination should be fed through a channel.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: &AuditEvent);
+}
+
+/// Keeps events in memory, e.g. for tests or an admin view of recent logins.
+#[derive(Debug, Default)]
+pub struct MemoryAuditSink {
+    events: Mutex<Vec<AuditEvent>>,
+}
+
+impl MemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the recorded events
+    pub fn events(&self) -> Vec<AuditEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Removes and returns the recorded events
+    pub fn take(&self) -> Vec<AuditEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(event.clone());
+    }
+}
+
+/// Appends one JSON object per line to a file, or to any writer.
+///
+/// Write failures don't fail authentication; they are counted by
+/// [`JsonLinesAuditSink::write_errors`], which monitoring should watch.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub struct JsonLinesAuditSink {
+    file: Mutex<Box<dyn Write + Send>>,
+    write_errors: AtomicU64,
+}
+
+#[cfg(feature = "serde")]
+impl JsonLinesAuditSink {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Writes the lines to `writer`, e.g. a socket or, on platforms without a filesystem,
+    /// a buffer shipped elsewhere.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        JsonLinesAuditSink {
+            file: Mutex::new(Box::new(writer)),
+            write_errors: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of events that could not be written
+    pub fn write_errors(&self) -> u64 {
+        self.write_errors.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Debug for JsonLinesAuditSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonLinesAuditSink")
+            .field("write_errors", &self.write_errors)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl AuditSink for JsonLinesAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        let mut line = match serde_json::to_vec(event) {
+            Ok(line) => line,
+            Err(_) => {
+                self.write_errors.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        line.push(b'\n');
+        // One write per line, so that lines of concurrent writers don't interleave
+        let written = self
+            .file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write_all(&line);
+        if written.is_err() {
+            self.write_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Wraps an authenticator, reporting every attempt to an [`AuditSink`].
+pub struct AuditingAuthenticator<A> {
+    backend: A,
+    sink: Arc<dyn AuditSink>,
+    clock: Arc<dyn Clock>,
+    interner: Option<Arc<IdInterner>>,
+}
+
+impl<A: std::fmt::Debug> std::fmt::Debug for AuditingAuthenticator<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditingAuthenticator")
+            .field("backend", &self.backend)
+            .field("clock", &self.clock)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> AuditingAuthenticator<A> {
+    pub fn new(backend: A, sink: Arc<dyn AuditSink>) -> Self {
+        AuditingAuthenticator {
+            backend,
+            sink,
+            clock: Arc::new(SystemClock),
+            interner: None,
+        }
+    }
+
+    /// Replaces the clock timestamping events.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Takes the identities of events from `interner`, so that sinks keeping many
+    /// events don't store a copy per event.
+    pub fn with_interner(mut self, interner: Arc<IdInterner>) -> Self {
+        self.interner = Some(interner);
+        self
+    }
+
+    pub fn backend(&self) -> &A {
+        &self.backend
+    }
+
+    /// Authenticates `authstr`, attaching `context` (e.g. `[("remote", addr)]`) to the event.
+    pub fn try_auth_with_context<T: User>(
+        &self,
+        authstr: &str,
+        context: &[(&str, &str)],
+    ) -> Result<T, AuthError>
+    where
+        A: UserAuthenticator<T>,
+    {
+        let result = self.backend.try_auth(authstr);
+        let context = context
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        self.record(result, context)
+    }
+
+    fn record<T: User>(
+        &self,
+        result: Result<T, AuthError>,
+        context: BTreeMap<String, String>,
+    ) -> Result<T, AuthError> {
+        self.sink.record(&AuditEvent {
+            timestamp: self.clock.now(),
+            identity: result
+                .as_ref()
+                .ok()
+                .map(|u| intern_with(self.interner.as_deref(), u.identity_str())),
+            outcome: AuditOutcome::from(&result),
+            context,
+        });
+        result
+    }
+}
+
+/// Records the extensions of the context, and its `source_addr`, `host` and `protocol`
+/// under these keys.
+impl<T: User, A: ContextAuthenticator<T>> ContextAuthenticator<T> for AuditingAuthenticator<A> {
+    fn auth_with_context(&self, authstr: &str, cx: &AuthContext) -> Result<T, AuthError> {
+        let result = self.backend.auth_with_context(authstr, cx);
+        let mut context: BTreeMap<String, String> = cx
+            .extensions
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if let Some(addr) = cx.source_addr {
+            context.insert("source_addr".into(), addr.to_string());
+        }
+        if let Some(host) = &cx.host {
+            context.insert("host".into(), host.clone());
+        }
+        if let Some(protocol) = &cx.protocol {
+            context.insert("protocol".into(), protocol.clone());
+        }
+        self.record(result, context)
+    }
+}
+
+impl<T: User, A: UserAuthenticator<T>> UserAuthenticator<T> for AuditingAuthenticator<A> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.try_auth(authstr).ok()
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.try_auth_with_context(authstr, &[])
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        let result = self.backend.try_auth_as(identity, authstr);
+        self.record(result, BTreeMap::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::{AuditOutcome, AuditingAuthenticator, MemoryAuditSink};
+    use crate::{ManualClock, PlainText, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_audit_sinks() -> Result<(), Box<dyn std::error::Error>> {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+
+        let memory = Arc::new(MemoryAuditSink::new());
+        let auth = AuditingAuthenticator::new(um.clone(), memory.clone())
+            .with_clock(Arc::new(clock.clone()));
+        auth.try_auth_with_context::<PlainText>("plaintext:u\np", &[("remote", "10.0.0.1")])?;
+        assert!(auth.auth_user_by_authstr("plaintext:u\nx").is_none());
+
+        let events = memory.take();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].identity.as_deref(), Some("u"));
+        assert_eq!(events[1].outcome, AuditOutcome::UnknownUser);
+        assert!(memory.events().is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_lines_sink() -> Result<(), Box<dyn std::error::Error>> {
+        use super::JsonLinesAuditSink;
+
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+
+        let path =
+            std::env::temp_dir().join(format!("user_trait_audit_{}.jsonl", std::process::id()));
+        let file = Arc::new(JsonLinesAuditSink::open(&path)?);
+        let auth = AuditingAuthenticator::new(um, file.clone()).with_clock(Arc::new(clock));
+        auth.try_auth_with_context::<PlainText>("plaintext:u\np", &[("remote", "10.0.0.1")])?;
+        assert!(auth.auth_user_by_authstr("plaintext:v\np").is_none());
+
+        let written = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            written,
+            concat!(
+                r#"{"timestamp":1000,"identity":"u","outcome":"success","context":{"remote":"10.0.0.1"}}"#,
+                "\n",
+                r#"{"timestamp":1000,"identity":null,"outcome":"unknown_user"}"#,
+                "\n"
+            )
+        );
+        assert_eq!(file.write_errors(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+/*!
+The `scheme:payload` form of auth strings, and the [`AuthFormat`] of `user\npass` payloads.
+*/
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use core::error::Error;
+use core::fmt;
+
+use crate::secret::Redacted;
+
+/// An auth string split at its first `:`, e.g. `plaintext:alice\nsecret` into the
+/// scheme `plaintext` and the payload `alice\nsecret`.
+///
+/// The scheme tells which kind of user owns the credential; it is made of ASCII
+/// letters, digits, `-`, `_`, `.` and `+`, so a `:` or a newline in it is refused.
+/// The payload may contain anything, including more colons. `Debug` doesn't show it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuthStr<'a> {
+    s: &'a str,
+    colon: usize,
+}
+
+/// Why a string is not a valid [`AuthStr`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AuthStrError {
+    /// There is no `:` after the scheme.
+    MissingSeparator,
+
+    EmptyScheme,
+
+    /// The scheme contains a character other than ASCII letters, digits, `-`, `_`, `.` and `+`.
+    IllegalSchemeCharacter(char),
+
+    /// The payload is not a `user\npass` pair of its [`AuthFormat`].
+    MalformedPayload,
+}
+
+impl fmt::Display for AuthStrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthStrError::MissingSeparator => f.write_str("missing `:` after the auth scheme"),
+            AuthStrError::EmptyScheme => f.write_str("empty auth scheme"),
+            AuthStrError::IllegalSchemeCharacter(ch) => {
+                write!(f, "illegal character {ch:?} in the auth scheme")
+            }
+            AuthStrError::MalformedPayload => f.write_str("malformed user and password payload"),
+        }
+    }
+}
+
+impl Error for AuthStrError {}
+
+pub(crate) fn check_scheme(scheme: &str) -> Result<(), AuthStrError> {
+    if scheme.is_empty() {
+        return Err(AuthStrError::EmptyScheme);
+    }
+    match scheme
+        .chars()
+        .find(|&c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+')))
+    {
+        Some(ch) => Err(AuthStrError::IllegalSchemeCharacter(ch)),
+        None => Ok(()),
+    }
+}
+
+impl<'a> AuthStr<'a> {
+    pub fn parse(s: &'a str) -> Result<Self, AuthStrError> {
+        let colon = s.find(':').ok_or(AuthStrError::MissingSeparator)?;
+        check_scheme(&s[..colon])?;
+        Ok(AuthStr { s, colon })
+    }
+
+    /// Joins `scheme` and `payload`, refusing schemes that wouldn't parse back.
+    pub fn format(scheme: &str, payload: &str) -> Result<String, AuthStrError> {
+        check_scheme(scheme)?;
+        Ok(format!("{scheme}:{payload}"))
+    }
+
+    pub fn scheme(&self) -> &'a str {
+        &self.s[..self.colon]
+    }
+
+    pub fn payload(&self) -> &'a str {
+        &self.s[self.colon + 1..]
+    }
+
+    /// The scheme and the payload
+    pub fn parts(&self) -> (&'a str, &'a str) {
+        (self.scheme(), self.payload())
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.s
+    }
+}
+
+impl<'a> TryFrom<&'a str> for AuthStr<'a> {
+    type Error = AuthStrError;
+
+    fn try_from(s: &'a str) -> Result<Self, Self::Error> {
+        AuthStr::parse(s)
+    }
+}
+
+impl AsRef<str> for AuthStr<'_> {
+    fn as_ref(&self) -> &str {
+        self.s
+    }
+}
+
+/// Shows the scheme only, e.g. `AuthStr(plaintext:✱✱✱)`.
+impl fmt::Debug for AuthStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AuthStr")
+            .field(&Redacted(self.scheme()))
+            .finish()
+    }
+}
+
+/// The encoding of a user name and a password in the payload of an auth string, e.g. for
+/// [`PlainText`](crate::PlainText).
+///
+/// Versions after [`AuthFormat::Legacy`] tag the scheme with a `.v{N}` suffix, so that
+/// [`AuthFormat::decode`] tells them apart and stored credentials can be migrated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AuthFormat {
+    /// `plaintext:{user}\n{pass}`, unescaped, so a user name containing `\n` is read back
+    /// with part of it in the password.
+    #[default]
+    Legacy,
+
+    /// `plaintext.v2:{user}\n{pass}`, where `\` and newlines of the user name are escaped
+    /// as `\\` and `\n`. The password is the rest of the payload, unescaped.
+    V2,
+}
+
+/// A `user\npass` payload decoded by [`AuthFormat::decode`]
+#[derive(Clone, PartialEq, Eq)]
+pub struct UserPass<'a> {
+    pub format: AuthFormat,
+
+    /// The scheme without its version tag, e.g. `plaintext`
+    pub scheme: &'a str,
+
+    pub user: Cow<'a, str>,
+    pub pass: &'a str,
+}
+
+/// Doesn't show the password.
+impl fmt::Debug for UserPass<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserPass")
+            .field("format", &self.format)
+            .field("scheme", &self.scheme)
+            .field("user", &self.user)
+            .field("pass", &crate::REDACTED)
+            .finish()
+    }
+}
+
+impl AuthFormat {
+    /// The format new credentials should use
+    pub const LATEST: AuthFormat = AuthFormat::V2;
+
+    /// The suffix of the scheme, e.g. `v2`; the legacy format has none.
+    pub fn tag(self) -> Option<&'static str> {
+        match self {
+            AuthFormat::Legacy => None,
+            AuthFormat::V2 => Some("v2"),
+        }
+    }
+
+    /// Encodes `user` and `pass` as an auth string of `scheme`, e.g. `plaintext`.
+    pub fn encode(self, scheme: &str, user: &str, pass: &str) -> String {
+        match self {
+            AuthFormat::Legacy => format!("{scheme}:{user}\n{pass}"),
+            AuthFormat::V2 => {
+                let user = user.replace('\\', "\\\\").replace('\n', "\\n");
+                format!("{scheme}.v2:{user}\n{pass}")
+            }
+        }
+    }
+
+    /// Decodes an auth string of any format, telling the format by the scheme's tag.
+    pub fn decode(authstr: &str) -> Result<UserPass<'_>, AuthStrError> {
+        let (scheme, payload) = AuthStr::parse(authstr)?.parts();
+        let Some(scheme) = scheme.strip_suffix(".v2") else {
+            let (user, pass) = payload
+                .split_once('\n')
+                .ok_or(AuthStrError::MalformedPayload)?;
+            return Ok(UserPass {
+                format: AuthFormat::Legacy,
+                scheme,
+                user: Cow::Borrowed(user),
+                pass,
+            });
+        };
+
+        let mut user = String::new();
+        let mut chars = payload.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\n' => {
+                    return Ok(UserPass {
+                        format: AuthFormat::V2,
+                        scheme,
+                        user: Cow::Owned(user),
+                        pass: &payload[i + 1..],
+                    })
+                }
+                '\\' => match chars.next() {
+                    Some((_, '\\')) => user.push('\\'),
+                    Some((_, 'n')) => user.push('\n'),
+                    _ => break,
+                },
+                c => user.push(c),
+            }
+        }
+        Err(AuthStrError::MalformedPayload)
+    }
+
+    /// Re-encodes an auth string of any format in this one, e.g. to move stored
+    /// credentials to [`AuthFormat::LATEST`].
+    ///
+    /// Legacy auth strings are split at their first newline, as they always were read.
+    pub fn migrate(self, authstr: &str) -> Result<String, AuthStrError> {
+        let decoded = AuthFormat::decode(authstr)?;
+        Ok(self.encode(decoded.scheme, &decoded.user, decoded.pass))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AuthFormat, AuthStr, AuthStrError};
+    use crate::{PlainText, UserTrait};
+
+    #[test]
+    fn test_auth_str() {
+        let a = AuthStr::parse("ss:aes-256-gcm@8388\np:w").unwrap();
+        assert_eq!(a.parts(), ("ss", "aes-256-gcm@8388\np:w"));
+        assert_eq!(format!("{a:?}"), "AuthStr(ss:✱✱✱)");
+        assert_eq!(PlainText::from("u p").auth_scheme(), Some("plaintext"));
+
+        assert_eq!(AuthStr::parse("token"), Err(AuthStrError::MissingSeparator));
+        assert_eq!(AuthStr::parse(":x"), Err(AuthStrError::EmptyScheme));
+        assert_eq!(
+            AuthStr::parse("u\np:w"),
+            Err(AuthStrError::IllegalSchemeCharacter('\n'))
+        );
+        assert_eq!(AuthStr::format("token", "abc").as_deref(), Ok("token:abc"));
+        assert!(AuthStr::format("to ken", "abc").is_err());
+    }
+
+    #[test]
+    fn test_auth_format() {
+        let v2 = AuthFormat::V2.encode("plaintext", "a\\b\nc", "p\nq");
+        assert_eq!(v2, "plaintext.v2:a\\\\b\\nc\np\nq");
+        let decoded = AuthFormat::decode(&v2).unwrap();
+        assert_eq!(decoded.format, AuthFormat::V2);
+        assert_eq!(decoded.scheme, "plaintext");
+        assert_eq!((&*decoded.user, decoded.pass), ("a\\b\nc", "p\nq"));
+
+        let legacy = AuthFormat::decode("plaintext:u\np").unwrap();
+        assert_eq!(legacy.format, AuthFormat::Legacy);
+        assert_eq!((&*legacy.user, legacy.pass), ("u", "p"));
+        assert_eq!(
+            AuthFormat::LATEST.migrate("plaintext:u\np").as_deref(),
+            Ok("plaintext.v2:u\np")
+        );
+        assert_eq!(
+            AuthFormat::Legacy.migrate("plaintext.v2:u\np").as_deref(),
+            Ok("plaintext:u\np")
+        );
+
+        for bad in ["plaintext:u", "plaintext.v2:u\\x\np", "plaintext.v2:u"] {
+            assert_eq!(
+                AuthFormat::decode(bad).err(),
+                Some(AuthStrError::MalformedPayload)
+            );
+        }
+    }
+}
@@ -0,0 +1,311 @@
+/*!
+`userctl`, managing the users of a JSON, TOML or SQLite store from the command line.
+
+Requires the `cli` feature: `cargo install user_trait --features cli`.
+*/
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use user_trait::{
+    load_htpasswd_path, load_users_from_toml_path, load_users_from_yaml_path,
+    load_v2ray_clients_path, Error, HtpasswdUser, LoadError, PlainText, Result, SqliteUserStore,
+    UserStore, UserTrait, UsersMap, UuidUser,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "userctl",
+    version,
+    about = "Manages the users of user_trait stores"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Adds a user with a plaintext password, creating the store if needed
+    Add {
+        store: PathBuf,
+        user: String,
+        password: String,
+        #[arg(long)]
+        format: Option<Format>,
+    },
+
+    /// Removes a user
+    Remove {
+        store: PathBuf,
+        user: String,
+        #[arg(long)]
+        format: Option<Format>,
+    },
+
+    /// Lists the user names, sorted
+    List {
+        store: PathBuf,
+        #[arg(long)]
+        format: Option<Format>,
+    },
+
+    /// Prints an htpasswd line with the hashed password
+    Hash {
+        user: String,
+        password: String,
+        #[arg(long, value_enum, default_value_t = Scheme::Bcrypt)]
+        scheme: Scheme,
+    },
+
+    /// Prints a random token in hex
+    Token {
+        /// Random bytes in the token
+        #[arg(long, default_value_t = 32)]
+        bytes: usize,
+    },
+
+    /// Prints a random UUID, e.g. for a V2Ray client
+    Uuid,
+
+    /// Loads a user file, reporting the first error
+    Validate {
+        file: PathBuf,
+        #[arg(long)]
+        format: Option<Format>,
+    },
+}
+
+/// The kind of a store or user file; inferred from the file extension by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// A map saved by `UsersMap::save_to_path`
+    Json,
+    /// `users = ["user pass"]` or `[[users]]` tables
+    Toml,
+    Yaml,
+    Sqlite,
+    Htpasswd,
+    /// A V2Ray config with `clients`
+    V2ray,
+}
+
+impl Format {
+    fn of(path: &Path, format: Option<Format>) -> Result<Format> {
+        if let Some(format) = format {
+            return Ok(format);
+        }
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        Ok(match ext {
+            "json" => Format::Json,
+            "toml" => Format::Toml,
+            "yaml" | "yml" => Format::Yaml,
+            "db" | "sqlite" | "sqlite3" => Format::Sqlite,
+            "htpasswd" => Format::Htpasswd,
+            _ => {
+                let msg = format!("cannot tell the format of {path:?}, pass --format");
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg).into());
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Scheme {
+    Bcrypt,
+    Apr1,
+    Sha1,
+}
+
+/// The `[[users]]` form of TOML user lists, which keeps whitespace in passwords
+#[derive(Serialize)]
+struct TomlUsers<'a> {
+    users: Vec<TomlUser<'a>>,
+}
+
+#[derive(Serialize)]
+struct TomlUser<'a> {
+    user: &'a str,
+    pass: &'a str,
+}
+
+fn random_bytes(n: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; n];
+    getrandom::fill(&mut bytes).map_err(|e| Error::backend(e.to_string()))?;
+    Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Reads a JSON or TOML store; a missing file is an empty store if `create`.
+fn load_file(path: &Path, format: Format, create: bool) -> Result<UsersMap<PlainText>> {
+    let map = match format {
+        Format::Json => UsersMap::load_from_path(path).map_err(LoadError::from),
+        Format::Toml => load_users_from_toml_path(path),
+        _ => unreachable!(),
+    };
+    match map {
+        Err(LoadError::Io(e)) if create && e.kind() == io::ErrorKind::NotFound => {
+            Ok(UsersMap::new())
+        }
+        map => Ok(map?),
+    }
+}
+
+/// Rewrites a TOML store with the users only; other keys of the file are dropped.
+fn save_toml(path: &Path, map: &UsersMap<PlainText>) -> Result<()> {
+    let mut users: Vec<_> = map.iter().collect();
+    users.sort_by(|a, b| a.user.cmp(&b.user));
+    let doc = TomlUsers {
+        users: users
+            .iter()
+            .map(|u| TomlUser {
+                user: &u.user,
+                pass: &u.pass,
+            })
+            .collect(),
+    };
+    let text = toml::to_string(&doc).map_err(Error::backend)?;
+    Ok(std::fs::write(path, text)?)
+}
+
+/// Runs `f` on the store at `path`, saving file stores afterwards if `write`.
+fn with_store<R>(
+    path: &Path,
+    format: Option<Format>,
+    write: bool,
+    f: impl FnOnce(&mut dyn UserStore<PlainText>) -> Result<R>,
+) -> Result<R> {
+    let format = Format::of(path, format)?;
+    if format == Format::Sqlite {
+        let mut store = SqliteUserStore::open(path).map_err(Error::backend)?;
+        return f(&mut store);
+    }
+    if !matches!(format, Format::Json | Format::Toml) {
+        let msg = format!("{format:?} files can be validated, but not edited");
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg).into());
+    }
+    let mut map = load_file(path, format, write)?;
+    let r = f(&mut map)?;
+    if write {
+        match format {
+            Format::Json => map.save_to_path(path)?,
+            _ => save_toml(path, &map)?,
+        }
+    }
+    Ok(r)
+}
+
+/// Loads `path`, returning how many users it holds.
+fn validate(path: &Path, format: Option<Format>) -> Result<usize> {
+    Ok(match Format::of(path, format)? {
+        Format::Json => UsersMap::<PlainText>::load_from_path(path)?.len(),
+        Format::Toml => load_users_from_toml_path(path)?.len(),
+        Format::Yaml => load_users_from_yaml_path(path)?.len(),
+        Format::Htpasswd => load_htpasswd_path(path)?.len(),
+        Format::V2ray => load_v2ray_clients_path(path)?.len(),
+        Format::Sqlite => with_store(path, Some(Format::Sqlite), false, |s| Ok(s.count()?))?,
+    })
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::Add {
+            store,
+            user,
+            password,
+            format,
+        } => {
+            let user = PlainText::new(user, password);
+            if !user.password_non_empty() {
+                let msg = "the user name and the password must not be empty";
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg).into());
+            }
+            with_store(&store, format, true, |s| Ok(s.add(user)?))?;
+        }
+        Command::Remove {
+            store,
+            user,
+            format,
+        } => {
+            with_store(&store, format, true, |s| Ok(s.remove(&user).map(drop)?))?;
+        }
+        Command::List { store, format } => {
+            let mut users = with_store(&store, format, false, |s| Ok(s.list()?))?;
+            users.sort_by(|a, b| a.user.cmp(&b.user));
+            for user in users {
+                println!("{}", user.identity_str());
+            }
+        }
+        Command::Hash {
+            user,
+            password,
+            scheme,
+        } => {
+            let hashed = match scheme {
+                Scheme::Bcrypt => HtpasswdUser::bcrypt(&user, &password),
+                Scheme::Apr1 => HtpasswdUser::apr1(&user, &password),
+                Scheme::Sha1 => HtpasswdUser::sha1(&user, &password),
+            };
+            println!("{}:{}", hashed.user(), hashed.hash());
+        }
+        Command::Token { bytes } => println!("{}", hex(&random_bytes(bytes)?)),
+        Command::Uuid => {
+            let mut uuid: [u8; 16] = random_bytes(16)?.try_into().expect("16 bytes");
+            // Version 4, variant 1
+            uuid[6] = (uuid[6] & 0x0f) | 0x40;
+            uuid[8] = (uuid[8] & 0x3f) | 0x80;
+            println!("{}", UuidUser::new(uuid, None).uuid_str());
+        }
+        Command::Validate { file, format } => {
+            let n = validate(&file, format)?;
+            println!("{}: {n} users", file.display());
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("userctl: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{validate, with_store, Format};
+    use crate::PlainText;
+
+    #[test]
+    fn test_stores() -> user_trait::Result<()> {
+        assert_eq!(Format::of(Path::new("u.yml"), None)?, Format::Yaml);
+        assert!(Format::of(Path::new("users"), None).is_err());
+
+        let dir = std::env::temp_dir().join(format!("userctl_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        for name in ["users.json", "users.toml", "users.db"] {
+            let path = dir.join(name);
+            with_store(&path, None, true, |s| {
+                s.add(PlainText::from("b p q"))?;
+                s.add(PlainText::from("a p"))?;
+                Ok(s.remove("a").map(drop)?)
+            })?;
+            let users = with_store(&path, None, false, |s| Ok(s.list()?))?;
+            assert_eq!(users, [PlainText::from("b p q")], "{name}");
+            assert_eq!(validate(&path, None)?, 1);
+        }
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,464 @@
+/*!
+Boxed users of any type, for collections mixing user types.
+
+Requires the `dyn-clone` feature.
+*/
+
+use alloc::boxed::Box;
+#[cfg(feature = "serde")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::hash::Hash;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{DebugWith, HashSet, PlainText, User, UserRef, UserTrait, REDACTED};
+
+/// A wrapper for a boxed user implementing the `User` trait.
+///
+/// This struct provides implementations for `Debug`, `Hash`, `PartialOrd`, `Ord`, `PartialEq`, and `Eq`.
+///
+/// `UserBox` implements [`UserTrait`] itself, so users of different types can be stored
+/// in one `UsersMap<UserBox>`. It serializes as its inner user tagged with the user's type,
+/// e.g. `{"PlainText":{...}}`.
+///
+/// It hashes like the [`UserRef`] of the same user, so either can look up the other.
+/// The hash isn't cached, which would need a field besides the public box; key hot maps
+/// by [`UserBox::as_user_ref`] or by identity instead.
+#[derive(Clone)]
+pub struct UserBox(pub Box<dyn User>);
+
+impl UserBox {
+    pub fn new(user: impl User + 'static) -> Self {
+        Self::from_box(Box::new(user))
+    }
+
+    pub fn from_box(user: Box<dyn User>) -> Self {
+        UserBox(user)
+    }
+
+    pub fn user(&self) -> &dyn User {
+        self.0.as_ref()
+    }
+
+    pub fn into_inner(self) -> Box<dyn User> {
+        self.0
+    }
+
+    /// The identity of the user, see [`UserTrait::identity_str`]
+    pub fn id(&self) -> &str {
+        self.0.identity_str()
+    }
+
+    /// The auth string of the user, see [`UserTrait::auth_str`]
+    pub fn auth(&self) -> &str {
+        self.0.auth_str()
+    }
+
+    /// Borrows the identity and auth string, hashing and comparing like the box.
+    pub fn as_user_ref(&self) -> UserRef<'_> {
+        UserRef::new(self.0.identity_str(), self.0.auth_str())
+    }
+
+    /// Formats the user with its auth string, unlike `Debug`. Keep it out of logs.
+    pub fn debug_unredacted(&self) -> impl Debug + '_ {
+        DebugWith(|f: &mut core::fmt::Formatter<'_>| {
+            f.debug_tuple("UserBox").field(&self.0.auth_str()).finish()
+        })
+    }
+}
+
+/// Shows the identity.
+impl core::fmt::Display for UserBox {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.0.identity_str())
+    }
+}
+
+/// Shows the identity only, so that logging a user doesn't leak its credential.
+/// See [`UserBox::debug_unredacted`].
+impl Debug for UserBox {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("UserBox")
+            .field("identity", &self.0.identity_str())
+            .field("auth_str", &REDACTED)
+            .finish()
+    }
+}
+
+impl Hash for UserBox {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.auth_str().hash(state);
+    }
+}
+
+impl PartialOrd for UserBox {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UserBox {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.0.auth_str().cmp(other.0.auth_str())
+    }
+}
+
+impl PartialEq for UserBox {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.auth_str() == other.0.auth_str()
+    }
+}
+
+impl Eq for UserBox {}
+
+/// Compares the identity, e.g. `user == "alice"`, unlike `UserBox == UserBox`
+/// which compares auth strings.
+impl PartialEq<str> for UserBox {
+    fn eq(&self, other: &str) -> bool {
+        self.0.identity_str() == other
+    }
+}
+
+/// Compares the identity, see `PartialEq<str>`.
+impl PartialEq<&str> for UserBox {
+    fn eq(&self, other: &&str) -> bool {
+        self.0.identity_str() == *other
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for UserBox {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let user: &dyn UserTrait = self.0.as_ref();
+        user.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for UserBox {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let user: Box<dyn UserTrait> = Deserialize::deserialize(deserializer)?;
+        Ok(UserBox::from_box(Box::new(SharedUser(Arc::from(user)))))
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl UserTrait for UserBox {
+    fn identity_str(&self) -> &str {
+        self.0.identity_str()
+    }
+
+    fn identity_bytes(&self) -> &[u8] {
+        self.0.identity_bytes()
+    }
+
+    fn auth_str(&self) -> &str {
+        self.0.auth_str()
+    }
+
+    fn auth_bytes(&self) -> &[u8] {
+        self.0.auth_bytes()
+    }
+
+    fn previous_auth_str(&self) -> Option<&str> {
+        self.0.previous_auth_str()
+    }
+
+    fn fingerprint(&self) -> [u8; 32] {
+        self.0.fingerprint()
+    }
+
+    fn auth_eq(&self, other: &[u8]) -> bool {
+        self.0.auth_eq(other)
+    }
+}
+
+/// Makes a deserialized `Box<dyn UserTrait>` cloneable, so that it fits in a [`UserBox`].
+///
+/// It is transparent to serialization: it reports the type name of the inner user and
+/// serializes as the inner user, so a deserialized `UserBox` serializes exactly like the
+/// one it was read from. That is why `UserTrait` is implemented by hand here, instead of
+/// through `#[typetag::serde]` which would tag it with its own name.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone)]
+struct SharedUser(Arc<dyn UserTrait>);
+
+#[cfg(feature = "serde")]
+impl Serialize for SharedUser {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        erased_serde::serialize(self.0.as_ref(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl UserTrait for SharedUser {
+    fn identity_str(&self) -> &str {
+        self.0.identity_str()
+    }
+
+    fn identity_bytes(&self) -> &[u8] {
+        self.0.identity_bytes()
+    }
+
+    fn auth_str(&self) -> &str {
+        self.0.auth_str()
+    }
+
+    fn auth_bytes(&self) -> &[u8] {
+        self.0.auth_bytes()
+    }
+
+    fn previous_auth_str(&self) -> Option<&str> {
+        self.0.previous_auth_str()
+    }
+
+    fn fingerprint(&self) -> [u8; 32] {
+        self.0.fingerprint()
+    }
+
+    fn auth_eq(&self, other: &[u8]) -> bool {
+        self.0.auth_eq(other)
+    }
+
+    fn typetag_name(&self) -> &'static str {
+        self.0.typetag_name()
+    }
+
+    fn typetag_deserialize(&self) {}
+}
+
+/// A Vec of `UserBox` with additional functionality.
+///
+/// This struct provides a method to  hash the set, ensuring that
+/// the hash value is different for different orders within the vec.
+///
+/// This is intended. As different orders are considered to be important in the UserVec's case.
+///
+/// It serializes as a sequence of tagged users, like [`UserBox`], so that a config file
+/// can list users of several types.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct UserVec(pub Vec<UserBox>);
+
+impl Hash for UserVec {
+    /// hashes its contents with the order of the vec.
+    ///
+    /// This ensures that the hash value is not the same for different orders of different users.
+    ///
+    /// The hash is different if the content is the same but with an different order.
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.iter().for_each(|b| {
+            b.hash(state);
+        })
+    }
+}
+
+/// The set operations compare users by auth string, like `UserBox`'s `Eq`, and keep
+/// the order of `self`, then of `other`.
+impl UserVec {
+    pub fn contains_id(&self, id: &str) -> bool {
+        self.0.iter().any(|u| u.identity_str() == id)
+    }
+
+    /// Removes the users whose auth string appeared earlier, keeping the first one.
+    pub fn dedup_by_auth(&mut self) {
+        let mut seen = HashSet::new();
+        let keep: Vec<bool> = self.0.iter().map(|u| seen.insert(u)).collect();
+        drop(seen);
+        let mut keep = keep.into_iter();
+        self.0.retain(|_| keep.next().unwrap_or(true));
+    }
+
+    /// Sorts by identity, keeping the order of users with the same identity.
+    pub fn sort_by_identity(&mut self) {
+        self.0
+            .sort_by(|a, b| a.identity_str().cmp(b.identity_str()));
+    }
+
+    /// The users of `self`, then those of `other` that `self` doesn't have.
+    pub fn union(&self, other: &UserVec) -> UserVec {
+        let mut seen: HashSet<&UserBox> = self.0.iter().collect();
+        let extra = other.0.iter().filter(|u| seen.insert(u));
+        UserVec(self.0.iter().chain(extra).cloned().collect())
+    }
+
+    /// The users of `self` that `other` also has.
+    pub fn intersection(&self, other: &UserVec) -> UserVec {
+        let other: HashSet<&UserBox> = other.0.iter().collect();
+        UserVec(
+            self.0
+                .iter()
+                .filter(|u| other.contains(u))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// The users of `self` that `other` doesn't have.
+    pub fn difference(&self, other: &UserVec) -> UserVec {
+        let other: HashSet<&UserBox> = other.0.iter().collect();
+        UserVec(
+            self.0
+                .iter()
+                .filter(|u| !other.contains(u))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+impl From<Vec<PlainText>> for UserVec {
+    fn from(users: Vec<PlainText>) -> Self {
+        users.into_iter().map(UserBox::new).collect()
+    }
+}
+
+impl FromIterator<UserBox> for UserVec {
+    fn from_iter<I: IntoIterator<Item = UserBox>>(iter: I) -> Self {
+        UserVec(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    use super::{UserBox, UserVec};
+    use crate::{PlainText, UserAuthenticator, UserTrait, UsersMap};
+
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct TokenUser {
+        name: String,
+        token: String,
+    }
+
+    #[cfg_attr(feature = "serde", typetag::serde)]
+    impl UserTrait for TokenUser {
+        fn identity_str(&self) -> &str {
+            &self.name
+        }
+
+        fn identity_bytes(&self) -> &[u8] {
+            self.name.as_bytes()
+        }
+
+        fn auth_str(&self) -> &str {
+            &self.token
+        }
+
+        fn auth_bytes(&self) -> &[u8] {
+            self.token.as_bytes()
+        }
+    }
+
+    #[test]
+    fn test_dyn_users_map() -> Result<(), Box<dyn std::error::Error>> {
+        let mut um: UsersMap<UserBox> = UsersMap::new();
+        um.add_user(UserBox::new(PlainText::from("u p")));
+        um.add_user(UserBox::new(TokenUser {
+            name: "t".into(),
+            token: "token:abc".into(),
+        }));
+
+        let o = um.auth_user_by_authstr("token:abc");
+        assert_eq!(o.map(|u| u.identity_str().to_string()), Some("t".into()));
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_some());
+
+        let b = um.get_user("u").unwrap();
+        #[cfg(not(feature = "serde"))]
+        let b2 = b.as_ref().clone();
+        #[cfg(feature = "serde")]
+        let b2 = {
+            let s = serde_json::to_string(b.as_ref())?;
+            assert!(s.starts_with(r#"{"PlainText":"#));
+
+            let b2: UserBox = serde_json::from_str(&s)?;
+            assert_eq!(&b2, b.as_ref());
+            assert_eq!(serde_json::to_string(&b2)?, s);
+            b2
+        };
+        assert_eq!(b2.fingerprint(), PlainText::from("u p").fingerprint());
+        assert_ne!(b2.fingerprint(), PlainText::from("u p2").fingerprint());
+        assert!(b2.auth_eq(b"plaintext:u\np"));
+        assert!(!b2.auth_eq(b"plaintext:u\nq"));
+        assert!(!b2.auth_eq(b"plaintext:u\np2"));
+
+        assert_eq!(
+            format!("{b2:?}"),
+            r#"UserBox { identity: "u", auth_str: <redacted> }"#
+        );
+        assert_eq!(
+            format!("{:?}", b2.debug_unredacted()),
+            r#"UserBox("plaintext:u\np")"#
+        );
+        let p = PlainText::from("u p");
+        assert_eq!(
+            format!("{p:?}"),
+            r#"PlainText { user: "u", pass: <redacted> }"#
+        );
+        assert!(format!("{:?}", p.debug_unredacted()).contains(r#"pass: "p""#));
+        assert_eq!(p.to_string(), "u");
+        assert_eq!(b2.to_string(), "u");
+        assert_eq!(b2.redacted().to_string(), "u:✱✱✱");
+
+        let set: std::collections::HashSet<UserBox> = [
+            b2.clone(),
+            b.as_ref().clone(),
+            UserBox(Box::new(PlainText::from("u p2"))),
+        ]
+        .into();
+        assert_eq!(set.len(), 2);
+
+        #[cfg(feature = "serde")]
+        {
+            let users = UserVec(vec![b2, UserBox::new(PlainText::from("v q"))]);
+            let s = serde_json::to_string(&users)?;
+            assert!(s.starts_with(r#"[{"PlainText":"#));
+            let users2: UserVec = serde_json::from_str(&s)?;
+            assert_eq!(users2, users);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_box_comparisons() {
+        let user = UserBox::new(PlainText::from("alice p"));
+        assert!(user == "alice");
+        assert_eq!(user, *"alice");
+        assert!(user != "bob");
+        assert_eq!((user.id(), user.auth()), ("alice", "plaintext:alice\np"));
+
+        let mut users = [
+            PlainText::from("b 1"),
+            PlainText::from("a 2"),
+            PlainText::from("a 1"),
+        ];
+        users.sort();
+        let names: Vec<_> = users.iter().map(|u| u.to_string() + &u.pass).collect();
+        assert_eq!(names, ["a1", "a2", "b1"]);
+    }
+
+    #[test]
+    fn test_user_vec_set_ops() {
+        let users =
+            |s: &[&str]| UserVec::from(s.iter().map(|&u| PlainText::from(u)).collect::<Vec<_>>());
+        let mut a = users(&["b 1", "a 1", "b 1", "a 2"]);
+        a.dedup_by_auth();
+        assert_eq!(a, users(&["b 1", "a 1", "a 2"]));
+        a.sort_by_identity();
+        assert_eq!(a, users(&["a 1", "a 2", "b 1"]));
+        assert!(a.contains_id("b"));
+        assert!(!a.contains_id("c"));
+
+        let b = users(&["c 1", "a 2"]);
+        assert_eq!(a.union(&b), users(&["a 1", "a 2", "b 1", "c 1"]));
+        assert_eq!(a.intersection(&b), users(&["a 2"]));
+        assert_eq!(a.difference(&b), users(&["a 1", "b 1"]));
+    }
+}
@@ -0,0 +1,368 @@
+/*!
+Fluent construction of users with optional details, validated when they are built.
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::Deref;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "dyn-clone")]
+use crate::UserBox;
+use crate::{
+    Clock, PlainText, SystemClock, UserTrait, UserWithExpiry, UserWithMeta, UserWithRoles,
+};
+
+/// A user with the details set through a [`UserBuilder`]: a description, an expiry time,
+/// metadata and roles.
+///
+/// `ProfiledUser<PlainText>` and `ProfiledUser<UserBox>` implement [`UserTrait`], so they
+/// can be stored in a [`UsersMap`](crate::UsersMap) and serialized as trait objects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProfiledUser<T> {
+    pub user: T,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub description: Option<String>,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub expires_at: Option<SystemTime>,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    pub meta: HashMap<String, String>,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub roles: Vec<String>,
+}
+
+impl<T> ProfiledUser<T> {
+    pub fn into_inner(self) -> T {
+        self.user
+    }
+}
+
+impl<T> Deref for ProfiledUser<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.user
+    }
+}
+
+impl_wrapper_user_trait!(ProfiledUser<PlainText>, "ProfiledPlainText");
+#[cfg(feature = "dyn-clone")]
+impl_wrapper_user_trait!(ProfiledUser<UserBox>, "ProfiledUserBox");
+
+impl<T> UserWithExpiry for ProfiledUser<T>
+where
+    ProfiledUser<T>: UserTrait,
+{
+    fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+}
+
+impl<T> UserWithMeta for ProfiledUser<T>
+where
+    ProfiledUser<T>: UserTrait,
+{
+    fn meta(&self) -> &HashMap<String, String> {
+        &self.meta
+    }
+}
+
+impl<T> UserWithRoles for ProfiledUser<T>
+where
+    ProfiledUser<T>: UserTrait,
+{
+    fn roles(&self) -> &[String] {
+        &self.roles
+    }
+}
+
+/// Why [`UserBuilder::build`] refused a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildUserError {
+    /// No user name was set, or it is empty.
+    MissingIdentity,
+
+    /// No password was set for a [`PlainText`] user.
+    MissingPassword,
+
+    /// The auth string of the user is empty.
+    EmptyCredential,
+
+    /// `field` contains a character it may not, e.g. a line break in a user name.
+    IllegalCharacter {
+        field: &'static str,
+        ch: char,
+    },
+
+    EmptyRole,
+
+    DuplicateRole(String),
+
+    EmptyMetaKey,
+}
+
+impl fmt::Display for BuildUserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildUserError::MissingIdentity => f.write_str("missing user name"),
+            BuildUserError::MissingPassword => f.write_str("missing password"),
+            BuildUserError::EmptyCredential => f.write_str("empty auth string"),
+            BuildUserError::IllegalCharacter { field, ch } => {
+                write!(f, "illegal character {ch:?} in {field}")
+            }
+            BuildUserError::EmptyRole => f.write_str("empty role name"),
+            BuildUserError::DuplicateRole(role) => write!(f, "duplicate role {role:?}"),
+            BuildUserError::EmptyMetaKey => f.write_str("empty metadata key"),
+        }
+    }
+}
+
+impl Error for BuildUserError {}
+
+/// The name and password of a [`PlainText`] being built, see [`PlainText::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct PlainTextParts {
+    user: Option<String>,
+    pass: Option<String>,
+}
+
+/// Builds a [`ProfiledUser`], checking its details in [`UserBuilder::build`] instead of
+/// at every setter.
+///
+/// ```
+/// # use user_trait::{PlainText, UserBuilder, UserTrait, UserWithRoles};
+/// let user = PlainText::builder()
+///     .user("alice")
+///     .pass("secret")
+///     .description("on-call admin")
+///     .role("admin")
+///     .build()?;
+/// assert_eq!(user.auth_str(), "plaintext:alice\nsecret");
+/// assert!(user.has_role("admin"));
+///
+/// let token = UserBuilder::new(PlainText::from("bob hunter2")).meta("team", "ops").build()?;
+/// # Ok::<(), user_trait::BuildUserError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UserBuilder<T> {
+    user: T,
+    description: Option<String>,
+    expires_at: Option<SystemTime>,
+    meta: HashMap<String, String>,
+    roles: Vec<String>,
+}
+
+impl<T> UserBuilder<T> {
+    /// Starts from an existing user.
+    pub fn new(user: T) -> Self {
+        UserBuilder {
+            user,
+            description: None,
+            expires_at: None,
+            meta: HashMap::new(),
+            roles: Vec::new(),
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn expires_at(mut self, at: SystemTime) -> Self {
+        self.expires_at = Some(at);
+        self
+    }
+
+    /// Makes the user expire `ttl` from now.
+    pub fn expires_after(self, ttl: Duration) -> Self {
+        self.expires_at(SystemClock.now() + ttl)
+    }
+
+    /// Sets a metadata label.
+    pub fn meta(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.meta.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn role(mut self, role: impl Into<String>) -> Self {
+        self.roles.push(role.into());
+        self
+    }
+
+    pub fn roles<I: IntoIterator<Item = R>, R: Into<String>>(mut self, roles: I) -> Self {
+        self.roles.extend(roles.into_iter().map(Into::into));
+        self
+    }
+
+    /// Checks the details and wraps the user with them.
+    fn finish(self) -> Result<ProfiledUser<T>, BuildUserError> {
+        for (i, role) in self.roles.iter().enumerate() {
+            if role.is_empty() {
+                return Err(BuildUserError::EmptyRole);
+            }
+            if self.roles[..i].contains(role) {
+                return Err(BuildUserError::DuplicateRole(role.clone()));
+            }
+        }
+        if self.meta.keys().any(String::is_empty) {
+            return Err(BuildUserError::EmptyMetaKey);
+        }
+        Ok(ProfiledUser {
+            user: self.user,
+            description: self.description,
+            expires_at: self.expires_at,
+            meta: self.meta,
+            roles: self.roles,
+        })
+    }
+}
+
+impl<T: UserTrait> UserBuilder<T> {
+    /// Returns the user with its details, or the first problem found.
+    pub fn build(self) -> Result<ProfiledUser<T>, BuildUserError> {
+        check_identity(self.user.identity_str())?;
+        if self.user.auth_str().is_empty() {
+            return Err(BuildUserError::EmptyCredential);
+        }
+        self.finish()
+    }
+}
+
+impl UserBuilder<PlainTextParts> {
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user.user = Some(user.into());
+        self
+    }
+
+    pub fn pass(mut self, pass: impl Into<String>) -> Self {
+        self.user.pass = Some(pass.into());
+        self
+    }
+
+    /// Returns the user with its details, or the first problem found.
+    ///
+    /// The password may be empty, but must be set.
+    pub fn build(self) -> Result<ProfiledUser<PlainText>, BuildUserError> {
+        let UserBuilder {
+            user: parts,
+            description,
+            expires_at,
+            meta,
+            roles,
+        } = self;
+        let user = parts.user.ok_or(BuildUserError::MissingIdentity)?;
+        check_identity(&user)?;
+        let pass = parts.pass.ok_or(BuildUserError::MissingPassword)?;
+        UserBuilder {
+            user: PlainText::new(user, pass),
+            description,
+            expires_at,
+            meta,
+            roles,
+        }
+        .build()
+    }
+}
+
+impl PlainText {
+    /// Starts building a `PlainText` user with optional details.
+    pub fn builder() -> UserBuilder<PlainTextParts> {
+        UserBuilder::new(PlainTextParts::default())
+    }
+}
+
+fn check_identity(id: &str) -> Result<(), BuildUserError> {
+    if id.is_empty() {
+        return Err(BuildUserError::MissingIdentity);
+    }
+    match id.chars().find(|c| c.is_control()) {
+        Some(ch) => Err(BuildUserError::IllegalCharacter {
+            field: "user name",
+            ch,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use super::{BuildUserError, PlainTextParts};
+    #[cfg(all(feature = "serde", feature = "dyn-clone"))]
+    use crate::UserBox;
+    use crate::{PlainText, UserBuilder, UserTrait, UserWithExpiry, UserWithMeta, UserWithRoles};
+
+    #[test]
+    fn test_builder() -> Result<(), Box<dyn std::error::Error>> {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1 << 32);
+        let user = PlainText::builder()
+            .user("alice")
+            .pass("secret")
+            .description("admin")
+            .expires_at(at)
+            .meta("team", "ops")
+            .roles(["admin", "dev"])
+            .build()?;
+        assert_eq!(user.auth_str(), "plaintext:alice\nsecret");
+        assert_eq!(user.expires_at(), Some(at));
+        assert_eq!(user.meta_value("team"), Some("ops"));
+        assert!(user.has_role("dev"));
+
+        #[cfg(all(feature = "serde", feature = "dyn-clone"))]
+        {
+            let boxed = UserBox::new(user.clone());
+            let back: UserBox = serde_json::from_str(&serde_json::to_string(&boxed)?)?;
+            assert_eq!(back, boxed);
+        }
+
+        let err = |b: UserBuilder<PlainTextParts>| b.build().unwrap_err();
+        assert_eq!(
+            err(PlainText::builder().pass("p")),
+            BuildUserError::MissingIdentity
+        );
+        assert_eq!(
+            err(PlainText::builder().user("u")),
+            BuildUserError::MissingPassword
+        );
+        assert_eq!(
+            err(PlainText::builder().user("u\nv").pass("p")),
+            BuildUserError::IllegalCharacter {
+                field: "user name",
+                ch: '\n'
+            }
+        );
+        assert_eq!(
+            err(PlainText::builder().user("u").pass("p").role("a").role("a")),
+            BuildUserError::DuplicateRole("a".into())
+        );
+        assert_eq!(
+            UserBuilder::new(PlainText::from(" p")).build().unwrap_err(),
+            BuildUserError::MissingIdentity
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+/*!
+A TTL-bounded cache of authentication results in front of a slow authenticator.
+*/
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    AsyncUserAuthenticator, AuthError, CacheStats, Clock, SystemClock, User, UserAuthenticator,
+};
+
+#[derive(Debug)]
+struct CacheEntry<T> {
+    /// The authenticated user, or why authentication was refused
+    result: Result<T, AuthError>,
+
+    expires_at: SystemTime,
+}
+
+/// Returns a copy of a refusal worth caching.
+///
+/// Backend failures and rate limiting are transient, so they are not cached.
+fn cacheable_refusal(e: &AuthError) -> Option<AuthError> {
+    match e {
+        AuthError::UnknownUser => Some(AuthError::UnknownUser),
+        AuthError::BadCredential => Some(AuthError::BadCredential),
+        AuthError::Expired => Some(AuthError::Expired),
+        AuthError::Disabled => Some(AuthError::Disabled),
+        _ => None,
+    }
+}
+
+/// Caches the answers of a remote backend (Redis, SQL, HTTP, ...) by auth string,
+/// so that a connection storm doesn't turn into a storm of backend requests.
+///
+/// Successes are kept for `positive_ttl` and refusals for `negative_ttl`, which is usually
+/// shorter so that newly added users can log in soon. Backend errors are never cached.
+///
+/// Unlike [`LruUsersCache`](crate::LruUsersCache), the number of entries is not bounded;
+/// call [`CachedAuthenticator::purge_expired`] periodically.
+#[derive(Debug)]
+pub struct CachedAuthenticator<T: User + Clone, A> {
+    backend: A,
+    positive_ttl: Duration,
+    negative_ttl: Duration,
+    clock: Arc<dyn Clock>,
+
+    /// Maps auth strings to cached results
+    entries: Mutex<HashMap<String, CacheEntry<T>>>,
+
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<T: User + Clone, A> CachedAuthenticator<T, A> {
+    pub fn new(backend: A, positive_ttl: Duration, negative_ttl: Duration) -> Self {
+        CachedAuthenticator {
+            backend,
+            positive_ttl,
+            negative_ttl,
+            clock: Arc::new(SystemClock),
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Replaces the clock deciding when entries expire, e.g. with a
+    /// [`ManualClock`](crate::ManualClock) in tests.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, CacheEntry<T>>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn backend(&self) -> &A {
+        &self.backend
+    }
+
+    /// Number of cached results, including expired ones that were not purged yet.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    /// Removes the cached result of an auth string.
+    pub fn invalidate(&self, authstr: &str) {
+        self.entries().remove(authstr);
+    }
+
+    /// Removes every cached success of an identity, e.g. after it was disabled or
+    /// changed its password in the backend.
+    ///
+    /// Cached refusals are not tied to an identity; they only expire.
+    pub fn invalidate_identity(&self, id: &str) {
+        self.entries()
+            .retain(|_, e| !e.result.as_ref().is_ok_and(|u| u.identity_str() == id));
+    }
+
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    /// Removes expired entries, returning how many were removed.
+    pub fn purge_expired(&self) -> usize {
+        let now = self.clock.now();
+        let mut entries = self.entries();
+        let before = entries.len();
+        entries.retain(|_, e| e.expires_at > now);
+        let removed = before - entries.len();
+        self.evictions.fetch_add(removed as u64, Ordering::Relaxed);
+        removed
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn get_cached(&self, authstr: &str) -> Option<Result<T, AuthError>> {
+        let now = self.clock.now();
+        let mut entries = self.entries();
+        let cached = match entries.get(authstr) {
+            Some(e) if e.expires_at > now => match &e.result {
+                Ok(user) => Some(Ok(user.clone())),
+                Err(e) => cacheable_refusal(e).map(Err),
+            },
+            Some(_) => {
+                entries.remove(authstr);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            None => None,
+        };
+        match cached {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        cached
+    }
+
+    /// Caches the backend's answer if it is worth caching, and passes it on.
+    fn remember(&self, authstr: &str, result: Result<T, AuthError>) -> Result<T, AuthError> {
+        let entry = match &result {
+            Ok(user) => Ok(user.clone()),
+            Err(e) => match cacheable_refusal(e) {
+                Some(e) => Err(e),
+                None => return result,
+            },
+        };
+        let ttl = if entry.is_ok() {
+            self.positive_ttl
+        } else {
+            self.negative_ttl
+        };
+        self.entries().insert(
+            authstr.to_string(),
+            CacheEntry {
+                result: entry,
+                expires_at: self.clock.now() + ttl,
+            },
+        );
+        result
+    }
+
+    /// Authenticates through an asynchronous backend, with the same caching as
+    /// [`UserAuthenticator::try_auth`].
+    pub async fn try_auth_async(&self, authstr: &str) -> Result<T, AuthError>
+    where
+        T: 'static,
+        A: AsyncUserAuthenticator<T> + Sync,
+    {
+        if let Some(cached) = self.get_cached(authstr) {
+            return cached;
+        }
+        let result = AsyncUserAuthenticator::try_auth(&self.backend, authstr).await;
+        self.remember(authstr, result)
+    }
+}
+
+impl<T: User + Clone, A: UserAuthenticator<T>> UserAuthenticator<T> for CachedAuthenticator<T, A> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.try_auth(authstr).ok()
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        if let Some(cached) = self.get_cached(authstr) {
+            return cached;
+        }
+        self.remember(authstr, self.backend.try_auth(authstr))
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        if let Some(cached) = self.get_cached(authstr) {
+            return cached;
+        }
+        self.remember(authstr, self.backend.try_auth_as(identity, authstr))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::CachedAuthenticator;
+    use crate::{AuthError, ManualClock, PlainText, SharedUsersMap, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_cached_authenticator() {
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let backend = Arc::new(SharedUsersMap::new(UsersMap::new()));
+        backend.add_user(PlainText::from("u p"));
+        let cache = CachedAuthenticator::new(
+            Arc::clone(&backend),
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+        )
+        .with_clock(Arc::new(clock.clone()));
+
+        assert!(cache.auth_user_by_authstr("plaintext:u\np").is_some());
+        assert!(matches!(
+            cache.try_auth("plaintext:v\np"),
+            Err(AuthError::UnknownUser)
+        ));
+
+        // Served from the cache while the backend changes
+        backend.remove_user("u");
+        backend.add_user(PlainText::from("v p"));
+        assert!(cache.auth_user_by_authstr("plaintext:u\np").is_some());
+        assert!(cache.auth_user_by_authstr("plaintext:v\np").is_none());
+
+        clock.advance(Duration::from_secs(5));
+        assert!(cache.auth_user_by_authstr("plaintext:v\np").is_some());
+
+        cache.invalidate_identity("u");
+        assert!(cache.auth_user_by_authstr("plaintext:u\np").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 4, 1));
+    }
+}
@@ -0,0 +1,198 @@
+/*!
+Composition of several authenticators, e.g. a local map, then LDAP, then a webhook.
+*/
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{AuthError, User, UserAuthenticator};
+
+type Backend<T> = Box<dyn UserAuthenticator<T> + Send + Sync>;
+
+/// Tries its backends in order and returns the first success.
+pub struct ChainAuthenticator<T: User> {
+    backends: Vec<(String, Backend<T>)>,
+}
+
+impl<T: User> fmt::Debug for ChainAuthenticator<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChainAuthenticator")
+            .field("backends", &self.names().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<T: User> Default for ChainAuthenticator<T> {
+    fn default() -> Self {
+        ChainAuthenticator::new()
+    }
+}
+
+impl<T: User> ChainAuthenticator<T> {
+    pub fn new() -> Self {
+        ChainAuthenticator {
+            backends: Vec::new(),
+        }
+    }
+
+    /// Appends a backend, named for error reports.
+    pub fn push(
+        &mut self,
+        name: impl Into<String>,
+        backend: impl UserAuthenticator<T> + Send + Sync + 'static,
+    ) {
+        self.backends.push((name.into(), Box::new(backend)));
+    }
+
+    /// Appends a backend, returning self for chaining.
+    pub fn with(
+        mut self,
+        name: impl Into<String>,
+        backend: impl UserAuthenticator<T> + Send + Sync + 'static,
+    ) -> Self {
+        self.push(name, backend);
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.backends.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.backends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.backends.is_empty()
+    }
+
+    /// Tries every backend in order, returning the first success
+    /// or the error of every backend.
+    pub fn auth_detailed(&self, authstr: &str) -> Result<T, ChainError> {
+        self.try_each(|backend| backend.try_auth(authstr))
+    }
+
+    fn try_each(
+        &self,
+        attempt: impl Fn(&Backend<T>) -> Result<T, AuthError>,
+    ) -> Result<T, ChainError> {
+        let mut errors = Vec::with_capacity(self.backends.len());
+        for (name, backend) in &self.backends {
+            match attempt(backend) {
+                Ok(user) => return Ok(user),
+                Err(e) => errors.push((name.clone(), e)),
+            }
+        }
+        Err(ChainError { errors })
+    }
+}
+
+/// Picks the error [`ChainAuthenticator`] reports as a [`UserAuthenticator`].
+fn first_refusal<T>(result: Result<T, ChainError>) -> Result<T, AuthError> {
+    let mut err = match result {
+        Ok(user) => return Ok(user),
+        Err(err) => err,
+    };
+    let first = err
+        .errors
+        .iter()
+        .position(|(_, e)| !matches!(e, AuthError::UnknownUser));
+    match first {
+        None => Err(AuthError::UnknownUser),
+        Some(i) if matches!(err.errors[i].1, AuthError::Backend(_)) => Err(AuthError::backend(err)),
+        Some(i) => Err(err.errors.swap_remove(i).1),
+    }
+}
+
+impl<T: User> UserAuthenticator<T> for ChainAuthenticator<T> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.backends
+            .iter()
+            .find_map(|(_, backend)| backend.auth_user_by_authstr(authstr))
+    }
+
+    /// Reports [`AuthError::UnknownUser`] if no backend knows the user.
+    /// Otherwise reports the first backend that refused it, e.g. with
+    /// [`AuthError::BadCredential`]; a backend failure is reported as
+    /// [`AuthError::Backend`] holding the whole [`ChainError`].
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        first_refusal(self.auth_detailed(authstr))
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        first_refusal(self.try_each(|backend| backend.try_auth_as(identity, authstr)))
+    }
+}
+
+/// The failures of every backend of a [`ChainAuthenticator`], in order.
+#[derive(Debug)]
+pub struct ChainError {
+    pub errors: Vec<(String, AuthError)>,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.errors.is_empty() {
+            return f.write_str("no authentication backend");
+        }
+        for (i, (name, e)) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{name}: {e}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ChainError {}
+
+#[cfg(test)]
+mod test {
+    use super::ChainAuthenticator;
+    use crate::{AuthError, PlainText, UserAuthenticator, UsersMap};
+
+    struct Unreachable;
+
+    impl UserAuthenticator<PlainText> for Unreachable {
+        fn auth_user_by_authstr(&self, _: &str) -> Option<PlainText> {
+            None
+        }
+
+        fn try_auth(&self, _: &str) -> Result<PlainText, AuthError> {
+            Err(AuthError::backend("connection refused"))
+        }
+    }
+
+    #[test]
+    fn test_chain() {
+        let mut local = UsersMap::new();
+        local.add_user(PlainText::from("a p"));
+        local.add_user(PlainText::from("off p"));
+        local.set_enabled("off", false);
+        let mut remote = UsersMap::new();
+        remote.add_user(PlainText::from("b p"));
+
+        let chain = ChainAuthenticator::new()
+            .with("local", local)
+            .with("ldap", Unreachable)
+            .with("remote", remote);
+
+        assert!(chain.auth_user_by_authstr("plaintext:a\np").is_some());
+        assert!(chain.auth_user_by_authstr("plaintext:b\np").is_some());
+        assert!(matches!(
+            chain.try_auth("plaintext:off\np"),
+            Err(AuthError::Disabled)
+        ));
+
+        let err = chain.auth_detailed("plaintext:c\np").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "local: unknown user; ldap: backend error: connection refused; remote: unknown user"
+        );
+        assert!(matches!(
+            chain.try_auth("plaintext:c\np"),
+            Err(AuthError::Backend(_))
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+/*!
+Two-phase challenge–response authentication, for protocols that never send the secret on the wire.
+*/
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::clock::Instant;
+use crate::{PlainText, User, UserTrait, UsersMap};
+
+/// A random nonce the client has to prove knowledge of its secret against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Challenge([u8; 32]);
+
+impl Challenge {
+    /// Generates a challenge from the operating system's random number generator.
+    pub fn random() -> Self {
+        let mut nonce = [0; 32];
+        getrandom::fill(&mut nonce).expect("the system random number generator failed");
+        Challenge(nonce)
+    }
+
+    pub fn from_nonce(nonce: [u8; 32]) -> Self {
+        Challenge(nonce)
+    }
+
+    pub fn nonce(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+/// Authenticates in two steps: the server sends a [`Challenge`] returned by `begin`,
+/// the client answers with a proof computed from the challenge and its secret.
+pub trait ChallengeAuthenticator<T: User> {
+    /// Issues a challenge for `identity`, replacing any pending one.
+    ///
+    /// Unknown identities get a challenge too, so that `begin` doesn't reveal which exist.
+    fn begin(&self, identity: &str) -> Challenge;
+
+    /// Checks the proof of a challenge issued by `begin`. Each challenge can be completed once.
+    fn complete(&self, identity: &str, challenge: &Challenge, proof: &[u8]) -> Option<T>;
+}
+
+/// A user able to check the proof of a [`Challenge`].
+pub trait ChallengeUser: UserTrait {
+    fn verify_proof(&self, challenge: &Challenge, proof: &[u8]) -> bool;
+}
+
+impl ChallengeUser for PlainText {
+    /// The expected proof is `SHA-256(nonce || password)`.
+    fn verify_proof(&self, challenge: &Challenge, proof: &[u8]) -> bool {
+        let mut hasher = Sha256::new();
+        hasher.update(challenge.nonce());
+        hasher.update(self.pass.as_bytes());
+        hasher.finalize().as_slice().ct_eq(proof).into()
+    }
+}
+
+/// The in-memory store of issued challenges, one per identity.
+#[derive(Debug)]
+pub struct PendingChallenges {
+    ttl: Duration,
+    pending: Mutex<HashMap<String, (Challenge, Instant)>>,
+}
+
+impl PendingChallenges {
+    /// Creates a store whose challenges are valid for `ttl` after being issued.
+    pub fn new(ttl: Duration) -> Self {
+        PendingChallenges {
+            ttl,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Issues a new challenge for `identity`, replacing any pending one.
+    pub fn issue(&self, identity: &str) -> Challenge {
+        let challenge = Challenge::random();
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(identity.to_string(), (challenge, Instant::now()));
+        challenge
+    }
+
+    /// Removes the pending challenge of `identity`, returning true if it was `challenge`
+    /// and had not expired.
+    pub fn take(&self, identity: &str, challenge: &Challenge) -> bool {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        match pending.get(identity) {
+            Some((c, _)) if c != challenge => false,
+            Some(_) => pending
+                .remove(identity)
+                .is_some_and(|(_, issued)| issued.elapsed() < self.ttl),
+            None => false,
+        }
+    }
+
+    /// Forgets expired challenges, returning how many were removed.
+    pub fn purge_expired(&self) -> usize {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let before = pending.len();
+        pending.retain(|_, (_, issued)| issued.elapsed() < self.ttl);
+        before - pending.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for PendingChallenges {
+    /// Challenges are valid for one minute.
+    fn default() -> Self {
+        PendingChallenges::new(Duration::from_secs(60))
+    }
+}
+
+/// A [`ChallengeAuthenticator`] over the users of a [`UsersMap`].
+///
+/// Disabled and expired users are refused, and statistics are recorded,
+/// like with [`UserAuthenticator`](crate::UserAuthenticator).
+#[derive(Debug)]
+pub struct MapChallengeAuthenticator<T: ChallengeUser + Clone, S = RandomState> {
+    map: UsersMap<T, S>,
+    pending: PendingChallenges,
+}
+
+impl<T: ChallengeUser + Clone, S: BuildHasher> MapChallengeAuthenticator<T, S> {
+    pub fn new(map: UsersMap<T, S>, pending: PendingChallenges) -> Self {
+        MapChallengeAuthenticator { map, pending }
+    }
+
+    pub fn map(&self) -> &UsersMap<T, S> {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut UsersMap<T, S> {
+        &mut self.map
+    }
+
+    pub fn pending(&self) -> &PendingChallenges {
+        &self.pending
+    }
+}
+
+impl<T: ChallengeUser + Clone, S: BuildHasher> ChallengeAuthenticator<T>
+    for MapChallengeAuthenticator<T, S>
+{
+    fn begin(&self, identity: &str) -> Challenge {
+        self.pending.issue(identity)
+    }
+
+    fn complete(&self, identity: &str, challenge: &Challenge, proof: &[u8]) -> Option<T> {
+        let issued = self.pending.take(identity, challenge);
+        let user = self
+            .map
+            .get_user(identity)
+            .filter(|u| issued && u.verify_proof(challenge, proof));
+        self.map.admit(user.as_ref()).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sha2::{Digest, Sha256};
+
+    use super::{Challenge, ChallengeAuthenticator, MapChallengeAuthenticator, PendingChallenges};
+    use crate::{PlainText, UsersMap};
+
+    fn prove(challenge: &Challenge, secret: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(challenge.nonce());
+        hasher.update(secret);
+        hasher.finalize().to_vec()
+    }
+
+    #[test]
+    fn test_challenge_response() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u secret"));
+        let auth = MapChallengeAuthenticator::new(um, PendingChallenges::default());
+
+        // A failed attempt consumes the challenge
+        let challenge = auth.begin("u");
+        assert!(auth.complete("u", &challenge, b"bad proof").is_none());
+        assert!(auth
+            .complete("u", &challenge, &prove(&challenge, b"secret"))
+            .is_none());
+
+        let challenge = auth.begin("u");
+        let proof = prove(&challenge, b"secret");
+        assert!(auth.complete("u", &challenge, &proof).is_some());
+        assert!(auth.complete("u", &challenge, &proof).is_none());
+        assert!(auth.pending().is_empty());
+
+        auth.begin("nobody");
+        assert_eq!(auth.pending().len(), 1);
+    }
+}
@@ -0,0 +1,60 @@
+/*!
+Injectable wall clocks, so that time-dependent behavior can be tested.
+
+`SystemTime::now` and `Instant::now` panic on `wasm32-unknown-unknown`, so the crate reads
+the time through [`SystemClock`] and this module's `Instant`, which ask the browser there.
+*/
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) use std::time::Instant;
+use std::time::{Duration, SystemTime};
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) use web_time::Instant;
+
+/// A source of the current time.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The real wall clock, [`SystemTime::now`], or `Date.now()` in browsers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    fn now(&self) -> SystemTime {
+        web_time::web::SystemTimeExt::to_std(web_time::SystemTime::now())
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<Mutex<SystemTime>>);
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        ManualClock(Arc::new(Mutex::new(now)))
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
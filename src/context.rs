@@ -0,0 +1,172 @@
+/*!
+Information about an authentication attempt besides the credential, and authenticators using it.
+*/
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::{
+    AuthError, Clock, SharedUsersMap, SystemClock, User, UserAuthenticator, UserTrait, UsersMap,
+};
+
+/// Where, when and how an authentication attempt was made.
+///
+/// Authenticators that enforce per-user policies, e.g.
+/// [`NetworkPolicyAuthenticator`](crate::NetworkPolicyAuthenticator), read it;
+/// others ignore it. Fields are filled by the protocol implementation when known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AuthContext {
+    /// The address of the client
+    pub source_addr: Option<IpAddr>,
+
+    /// The host name the client asked for, e.g. the TLS SNI or the HTTP `Host` header
+    pub host: Option<String>,
+
+    /// The protocol the client speaks, e.g. `socks5` or `http`
+    pub protocol: Option<String>,
+
+    /// The identity the client claimed apart from the credential, e.g. the SOCKS5 user
+    /// name, see [`UserAuthenticator::try_auth_as`]
+    pub identity: Option<String>,
+
+    /// When the attempt was made; [`AuthContext::new`] sets the current time.
+    pub timestamp: SystemTime,
+
+    /// Anything else a policy needs, e.g. a TLS client certificate fingerprint
+    pub extensions: HashMap<String, String>,
+}
+
+impl Default for AuthContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthContext {
+    pub fn new() -> Self {
+        AuthContext {
+            source_addr: None,
+            host: None,
+            protocol: None,
+            identity: None,
+            timestamp: SystemClock.now(),
+            extensions: HashMap::new(),
+        }
+    }
+
+    pub fn with_source_addr(mut self, addr: IpAddr) -> Self {
+        self.source_addr = Some(addr);
+        self
+    }
+
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn with_protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.protocol = Some(protocol.into());
+        self
+    }
+
+    pub fn with_identity(mut self, identity: impl Into<String>) -> Self {
+        self.identity = Some(identity.into());
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_extension(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extensions.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn extension(&self, key: &str) -> Option<&str> {
+        self.extensions.get(key).map(String::as_str)
+    }
+}
+
+/// An authenticator that can take the [`AuthContext`] of an attempt into account.
+///
+/// Wrappers such as [`ThrottledAuthenticator`](crate::ThrottledAuthenticator) and
+/// [`AuditingAuthenticator`](crate::AuditingAuthenticator) use the context themselves
+/// and pass it on to their backend, so policies can be stacked. Plain user stores
+/// ignore it.
+pub trait ContextAuthenticator<T: User> {
+    fn auth_with_context(&self, authstr: &str, cx: &AuthContext) -> Result<T, AuthError>;
+}
+
+impl<T: User, A: ContextAuthenticator<T> + ?Sized> ContextAuthenticator<T> for Arc<A> {
+    fn auth_with_context(&self, authstr: &str, cx: &AuthContext) -> Result<T, AuthError> {
+        self.as_ref().auth_with_context(authstr, cx)
+    }
+}
+
+impl<T: UserTrait + Clone, S: BuildHasher> ContextAuthenticator<T> for UsersMap<T, S> {
+    fn auth_with_context(&self, authstr: &str, _cx: &AuthContext) -> Result<T, AuthError> {
+        self.try_auth(authstr)
+    }
+}
+
+impl<T: UserTrait + Clone> ContextAuthenticator<T> for SharedUsersMap<T> {
+    fn auth_with_context(&self, authstr: &str, _cx: &AuthContext) -> Result<T, AuthError> {
+        self.try_auth(authstr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use std::sync::Arc;
+
+    use super::{AuthContext, ContextAuthenticator};
+    use crate::{
+        AuditingAuthenticator, AuthError, MemoryAuditSink, NetworkPolicyAuthenticator, NetworkUser,
+        PlainText, ThrottlePolicy, ThrottledAuthenticator, UsersMap,
+    };
+
+    #[test]
+    fn test_context_stack() {
+        let mut um = UsersMap::new();
+        um.add_user(NetworkUser::new(
+            PlainText::from("u p"),
+            vec!["10.0.0.0/8".parse().unwrap()],
+        ));
+        let sink = Arc::new(MemoryAuditSink::new());
+        let auth = AuditingAuthenticator::new(
+            ThrottledAuthenticator::new(
+                NetworkPolicyAuthenticator::new(um),
+                ThrottlePolicy::default(),
+            ),
+            sink.clone(),
+        );
+
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let cx = AuthContext::new()
+            .with_source_addr(ip)
+            .with_protocol("socks5")
+            .with_extension("tls", "1.3");
+        assert!(auth.auth_with_context("plaintext:u\np", &cx).is_ok());
+        assert!(auth.auth_with_context("plaintext:u\nx", &cx).is_err());
+        assert_eq!(auth.backend().source_failures("10.0.0.1"), 1);
+
+        let outside = AuthContext::new().with_source_addr("192.168.0.1".parse().unwrap());
+        assert!(matches!(
+            auth.auth_with_context("plaintext:u\np", &outside),
+            Err(AuthError::Forbidden)
+        ));
+
+        let events = sink.take();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].context["source_addr"], "10.0.0.1");
+        assert_eq!(events[0].context["protocol"], "socks5");
+        assert_eq!(events[0].context["tls"], "1.3");
+    }
+}
@@ -0,0 +1,91 @@
+/*!
+Differences between two user sets.
+*/
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+
+use crate::{HashMap, HashSet, UserTrait, UsersMap};
+
+/// The identities that differ between an old and a new user set.
+///
+/// Each list is sorted, so diffs can be compared and logged deterministically.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsersDiff {
+    /// Identities only present in the new set
+    pub added: Vec<String>,
+
+    /// Identities only present in the old set
+    pub removed: Vec<String>,
+
+    /// Identities present in both sets whose auth string changed
+    pub changed: Vec<String>,
+}
+
+impl UsersDiff {
+    /// Computes the diff from `old` to `new`, comparing users by identity and auth string.
+    pub fn between<T, S1, S2>(old: &UsersMap<T, S1>, new: &UsersMap<T, S2>) -> Self
+    where
+        T: UserTrait + Clone,
+        S1: BuildHasher,
+        S2: BuildHasher,
+    {
+        let old_users: HashMap<&str, &str> = old
+            .iter()
+            .map(|u| (u.identity_str(), u.auth_str()))
+            .collect();
+
+        let mut diff = UsersDiff::default();
+        for u in new.iter() {
+            match old_users.get(u.identity_str()) {
+                None => diff.added.push(u.identity_str().to_string()),
+                Some(authstr) if *authstr != u.auth_str() => {
+                    diff.changed.push(u.identity_str().to_string())
+                }
+                Some(_) => {}
+            }
+        }
+        let new_ids: HashSet<&str> = new.iter().map(|u| u.identity_str()).collect();
+        diff.removed = old_users
+            .into_keys()
+            .filter(|id| !new_ids.contains(id))
+            .map(str::to_string)
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        diff
+    }
+
+    /// Returns true if nothing was added, removed or changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UsersDiff;
+    use crate::{PlainText, UsersMap};
+
+    #[test]
+    fn test_diff() {
+        let mut old = UsersMap::new();
+        old.add_user(PlainText::from("a 1"));
+        old.add_user(PlainText::from("b 1"));
+        old.add_user(PlainText::from("c 1"));
+
+        let mut new = UsersMap::new();
+        new.add_user(PlainText::from("a 1"));
+        new.add_user(PlainText::from("b 2"));
+        new.add_user(PlainText::from("d 1"));
+
+        let diff = UsersDiff::between(&old, &new);
+        assert_eq!(diff.added, ["d"]);
+        assert_eq!(diff.removed, ["c"]);
+        assert_eq!(diff.changed, ["b"]);
+        assert!(UsersDiff::between(&new, &new).is_empty());
+    }
+}
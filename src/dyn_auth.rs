@@ -0,0 +1,120 @@
+/*!
+An object-safe authenticator, for pipelines assembled at runtime from configuration.
+*/
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::{AuthError, User, UserAuthenticator, UserBox};
+
+/// An authenticator that can be held as `Arc<dyn DynAuthenticator>`.
+///
+/// [`UserAuthenticator<T>`] is generic over the user type, so authenticators of different
+/// user types can't share one trait object type. This trait returns every user as a
+/// [`UserBox`] instead. Wrap a generic authenticator in a [`DynAdapter`] to get one;
+/// `dyn DynAuthenticator` implements `UserAuthenticator<UserBox>` in turn.
+pub trait DynAuthenticator: Send + Sync {
+    fn auth_boxed(&self, authstr: &str) -> Option<UserBox>;
+
+    fn try_auth_boxed(&self, authstr: &str) -> Result<UserBox, AuthError> {
+        self.auth_boxed(authstr).ok_or(AuthError::UnknownUser)
+    }
+
+    /// See [`UserAuthenticator::try_auth_as`]; the default ignores `identity`.
+    fn try_auth_as_boxed(&self, identity: &str, authstr: &str) -> Result<UserBox, AuthError> {
+        let _ = identity;
+        self.try_auth_boxed(authstr)
+    }
+}
+
+/// Makes a [`UserAuthenticator<T>`] a [`DynAuthenticator`] by boxing its users.
+pub struct DynAdapter<T, A> {
+    inner: A,
+    _user: PhantomData<fn() -> T>,
+}
+
+impl<T, A: fmt::Debug> fmt::Debug for DynAdapter<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynAdapter").field(&self.inner).finish()
+    }
+}
+
+impl<T, A> DynAdapter<T, A> {
+    pub fn new(inner: A) -> Self {
+        DynAdapter {
+            inner,
+            _user: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+impl<T, A> DynAuthenticator for DynAdapter<T, A>
+where
+    T: User + 'static,
+    A: UserAuthenticator<T> + Send + Sync,
+{
+    fn auth_boxed(&self, authstr: &str) -> Option<UserBox> {
+        self.inner.auth_user_by_authstr(authstr).map(UserBox::new)
+    }
+
+    fn try_auth_boxed(&self, authstr: &str) -> Result<UserBox, AuthError> {
+        self.inner.try_auth(authstr).map(UserBox::new)
+    }
+
+    fn try_auth_as_boxed(&self, identity: &str, authstr: &str) -> Result<UserBox, AuthError> {
+        self.inner.try_auth_as(identity, authstr).map(UserBox::new)
+    }
+}
+
+impl UserAuthenticator<UserBox> for dyn DynAuthenticator {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<UserBox> {
+        self.auth_boxed(authstr)
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<UserBox, AuthError> {
+        self.try_auth_boxed(authstr)
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<UserBox, AuthError> {
+        self.try_auth_as_boxed(identity, authstr)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::sync::Arc;
+
+    use super::{DynAdapter, DynAuthenticator};
+    use crate::{ChainAuthenticator, PlainText, RoledUser, UserAuthenticator, UserTrait, UsersMap};
+
+    #[test]
+    fn test_dyn_authenticators() {
+        let mut plain = UsersMap::new();
+        plain.add_user(PlainText::from("a p"));
+        let mut roled = UsersMap::new();
+        roled.add_user(RoledUser::new(PlainText::from("b p"), vec!["admin".into()]));
+
+        let backends: Vec<Arc<dyn DynAuthenticator>> = vec![
+            Arc::new(DynAdapter::new(plain)),
+            Arc::new(DynAdapter::new(roled)),
+        ];
+        let mut chain = ChainAuthenticator::new();
+        for (i, backend) in backends.into_iter().enumerate() {
+            chain.push(i.to_string(), backend);
+        }
+
+        let b = chain.auth_user_by_authstr("plaintext:b\np").unwrap();
+        assert_eq!(b.identity_str(), "b");
+        #[cfg(feature = "serde")]
+        assert_eq!(b.user().typetag_name(), "RoledPlainText");
+        assert!(chain.auth_user_by_authstr("plaintext:c\np").is_none());
+    }
+}
@@ -0,0 +1,202 @@
+/*!
+Encrypted storage of a [`UsersMap`], so that persisted credentials aren't plaintext on disk.
+
+Requires the `encryption` feature.
+*/
+
+use std::fmt;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::persist::write_atomic;
+use crate::{UserTrait, UsersMap};
+
+/// Identifies the format, and its version
+const MAGIC: &[u8; 8] = b"UTENC\0\0\x01";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+/// The `kdf` byte of the header
+const KDF_NONE: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+
+/// The key of an encrypted user file.
+///
+/// Its `Debug` output doesn't show the key. With the `zeroize` feature, the key is
+/// cleared when it is dropped.
+#[derive(Clone)]
+pub enum FileKey {
+    /// A random 256-bit key, e.g. from a secrets manager
+    Raw([u8; 32]),
+
+    /// A passphrase, stretched with Argon2id and a random salt stored in the file
+    Passphrase(String),
+}
+
+impl fmt::Debug for FileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileKey::Raw(_) => f.write_str("FileKey::Raw(..)"),
+            FileKey::Passphrase(_) => f.write_str("FileKey::Passphrase(..)"),
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for FileKey {
+    fn drop(&mut self) {
+        match self {
+            FileKey::Raw(key) => zeroize::Zeroize::zeroize(key),
+            FileKey::Passphrase(pass) => zeroize::Zeroize::zeroize(pass),
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for FileKey {}
+
+impl FileKey {
+    pub fn passphrase(pass: impl Into<String>) -> Self {
+        FileKey::Passphrase(pass.into())
+    }
+
+    fn kdf(&self) -> u8 {
+        match self {
+            FileKey::Raw(_) => KDF_NONE,
+            FileKey::Passphrase(_) => KDF_ARGON2ID,
+        }
+    }
+
+    fn derive(&self, salt: &[u8]) -> io::Result<[u8; 32]> {
+        match self {
+            FileKey::Raw(key) => Ok(*key),
+            FileKey::Passphrase(pass) => {
+                let mut key = [0u8; 32];
+                Argon2::default()
+                    .hash_password_into(pass.as_bytes(), salt, &mut key)
+                    .map_err(|e| io::Error::other(e.to_string()))?;
+                Ok(key)
+            }
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Encrypts `plaintext` with AES-256-GCM. The header is authenticated too.
+fn seal(plaintext: &[u8], key: &FileKey) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(key.kdf());
+    let mut random = [0u8; SALT_LEN + NONCE_LEN];
+    getrandom::fill(&mut random).map_err(|e| io::Error::other(e.to_string()))?;
+    header.extend_from_slice(&random);
+
+    let (salt, nonce) = random.split_at(SALT_LEN);
+    let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::from(key.derive(salt)?));
+    let nonce: [u8; NONCE_LEN] = nonce.try_into().expect("nonce length");
+    let ciphertext = cipher
+        .encrypt(
+            &Nonce::from(nonce),
+            aes_gcm::aead::Payload {
+                msg: plaintext,
+                aad: &header,
+            },
+        )
+        .map_err(|_| io::Error::other("encryption failed"))?;
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+/// Decrypts the output of [`seal`].
+fn open(data: &[u8], key: &FileKey) -> io::Result<Vec<u8>> {
+    if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+        return Err(invalid("not an encrypted user file"));
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    if header[MAGIC.len()] != key.kdf() {
+        return Err(invalid(match key {
+            FileKey::Raw(_) => "the file is encrypted with a passphrase",
+            FileKey::Passphrase(_) => "the file is encrypted with a raw key",
+        }));
+    }
+    let salt = &header[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+    let nonce: [u8; NONCE_LEN] = header[HEADER_LEN - NONCE_LEN..]
+        .try_into()
+        .expect("nonce length");
+    let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::from(key.derive(salt)?));
+    cipher
+        .decrypt(
+            &Nonce::from(nonce),
+            aes_gcm::aead::Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| invalid("wrong key, or the file was tampered with"))
+}
+
+impl<T: UserTrait + Clone + Serialize, S: BuildHasher> UsersMap<T, S> {
+    /// Like [`UsersMap::save_to_path`], encrypting the file with AES-256-GCM.
+    pub fn save_encrypted(&self, path: &Path, key: &FileKey) -> io::Result<()> {
+        write_atomic(path, &seal(&self.to_json()?, key)?)
+    }
+}
+
+impl<T: UserTrait + Clone + DeserializeOwned> UsersMap<T> {
+    /// Reads a map written by [`UsersMap::save_encrypted`].
+    ///
+    /// A wrong key and a modified file are both reported as [`io::ErrorKind::InvalidData`].
+    pub fn load_encrypted(path: &Path, key: &FileKey) -> io::Result<Self> {
+        Self::load_from_slice(&open(&fs::read(path)?, key)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FileKey;
+    use crate::{PlainText, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_encrypted_file() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("user_trait_encrypted_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("users.enc");
+
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u secret"));
+        let key = FileKey::passphrase("correct horse");
+        um.save_encrypted(&path, &key)?;
+        let raw = std::fs::read(&path)?;
+        assert!(!raw.windows(6).any(|w| w == b"secret"));
+
+        let loaded: UsersMap<PlainText> = UsersMap::load_encrypted(&path, &key)?;
+        assert!(loaded.auth_user_by_authstr("plaintext:u\nsecret").is_some());
+
+        let err = UsersMap::<PlainText>::load_encrypted(&path, &FileKey::passphrase("wrong"))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let err = UsersMap::<PlainText>::load_encrypted(&path, &FileKey::Raw([7; 32])).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut tampered = raw.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, tampered)?;
+        assert!(UsersMap::<PlainText>::load_encrypted(&path, &key).is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
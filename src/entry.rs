@@ -0,0 +1,145 @@
+/*!
+Entry API of [`UsersMap`], modeled after [`std::collections::hash_map::Entry`].
+*/
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use core::hash::BuildHasher;
+
+use crate::{RandomState, UserTrait, UsersMap};
+
+/// A view into a single identity of a [`UsersMap`], created by [`UsersMap::entry`].
+#[derive(Debug)]
+pub enum Entry<'a, T: UserTrait + Clone, S = RandomState> {
+    Occupied(OccupiedEntry<'a, T, S>),
+    Vacant(VacantEntry<'a, T, S>),
+}
+
+/// An identity that is present in the map.
+#[derive(Debug)]
+pub struct OccupiedEntry<'a, T: UserTrait + Clone, S> {
+    map: &'a mut UsersMap<T, S>,
+    user: Arc<T>,
+}
+
+/// An identity that is absent from the map.
+#[derive(Debug)]
+pub struct VacantEntry<'a, T: UserTrait + Clone, S> {
+    map: &'a mut UsersMap<T, S>,
+    id: String,
+}
+
+impl<T: UserTrait + Clone, S: BuildHasher> UsersMap<T, S> {
+    /// Gets the entry of an identity, e.g. to create a user on first sight:
+    ///
+    /// ```
+    /// # use user_trait::{PlainText, UsersMap};
+    /// let mut um = UsersMap::new();
+    /// let user = um.entry("alice").or_insert_with(|| PlainText::from("alice pass"));
+    /// assert_eq!(user.pass, "pass");
+    /// ```
+    pub fn entry(&mut self, id: &str) -> Entry<'_, T, S> {
+        match self.get_user(id) {
+            Some(user) => Entry::Occupied(OccupiedEntry { map: self, user }),
+            None => Entry::Vacant(VacantEntry {
+                map: self,
+                id: id.to_string(),
+            }),
+        }
+    }
+}
+
+impl<'a, T: UserTrait + Clone, S: BuildHasher> Entry<'a, T, S> {
+    /// The identity this entry was created for
+    pub fn key(&self) -> &str {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// Returns the stored user, inserting `default` if the identity is absent.
+    pub fn or_insert(self, default: T) -> Arc<T> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the stored user, inserting the result of `f` if the identity is absent.
+    pub fn or_insert_with(self, f: impl FnOnce() -> T) -> Arc<T> {
+        match self {
+            Entry::Occupied(e) => e.user,
+            Entry::Vacant(e) => e.insert(f()),
+        }
+    }
+}
+
+impl<'a, T: UserTrait + Clone, S: BuildHasher> OccupiedEntry<'a, T, S> {
+    pub fn key(&self) -> &str {
+        self.user.identity_str()
+    }
+
+    pub fn get(&self) -> &Arc<T> {
+        &self.user
+    }
+
+    /// Replaces the user, as [`UsersMap::add_user`] would.
+    pub fn insert(self, user: T) -> Arc<T> {
+        let user = Arc::new(user);
+        self.map.insert_arc(Arc::clone(&user));
+        user
+    }
+
+    /// Removes the user and all of its credentials, returning it.
+    pub fn remove(self) -> Arc<T> {
+        self.map.remove_user(self.user.identity_str());
+        self.user
+    }
+}
+
+impl<'a, T: UserTrait + Clone, S: BuildHasher> VacantEntry<'a, T, S> {
+    pub fn key(&self) -> &str {
+        &self.id
+    }
+
+    /// Inserts the user, as [`UsersMap::add_user`] would.
+    ///
+    /// The user is stored under its own identity, which is expected to match [`VacantEntry::key`].
+    pub fn insert(self, user: T) -> Arc<T> {
+        debug_assert_eq!(
+            self.map.options().normalize_id(user.identity_str()),
+            self.map.options().normalize_id(&self.id)
+        );
+        let user = Arc::new(user);
+        self.map.insert_arc(Arc::clone(&user));
+        user
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Entry;
+    use crate::{PlainText, UsersMap};
+
+    #[test]
+    fn test_entry() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("a 1"));
+
+        assert!(matches!(um.entry("a"), Entry::Occupied(_)));
+        assert_eq!(um.entry("a").or_insert(PlainText::from("a 2")).pass, "1");
+
+        let mut created = 0;
+        for _ in 0..2 {
+            um.entry("b").or_insert_with(|| {
+                created += 1;
+                PlainText::from("b 1")
+            });
+        }
+        assert_eq!(created, 1);
+        assert_eq!(um.len(), 2);
+
+        if let Entry::Occupied(e) = um.entry("a") {
+            e.remove();
+        }
+        assert!(um.get_user("a").is_none());
+    }
+}
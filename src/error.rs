@@ -0,0 +1,293 @@
+/*!
+Errors reported by authentication and user stores.
+*/
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::error::Error as StdError;
+use core::fmt;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::AuthStrError;
+#[cfg(feature = "std")]
+use crate::{BuildUserError, LimitExceeded};
+
+/// Why an authentication attempt failed, see [`UserAuthenticator::try_auth`](crate::UserAuthenticator::try_auth).
+///
+/// Callers should log the precise cause but usually answer clients with one generic
+/// failure, so as not to reveal which identities exist.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AuthError {
+    /// No user matches the presented identity or credential.
+    UnknownUser,
+
+    /// The user exists, but the presented secret is wrong.
+    BadCredential,
+
+    Expired,
+
+    Disabled,
+
+    /// The credential is valid, but the user may not log in in this context,
+    /// e.g. from this source address.
+    Forbidden,
+
+    /// Too many attempts or connections; retrying after `retry_after`, if known, may succeed.
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+
+    /// The backend storing the users failed, e.g. a database was unreachable.
+    Backend(Box<dyn StdError + Send + Sync>),
+}
+
+impl AuthError {
+    /// Wraps a backend failure.
+    pub fn backend(e: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        AuthError::Backend(e.into())
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::UnknownUser => f.write_str("unknown user"),
+            AuthError::BadCredential => f.write_str("bad credential"),
+            AuthError::Expired => f.write_str("user expired"),
+            AuthError::Disabled => f.write_str("user disabled"),
+            AuthError::Forbidden => f.write_str("not allowed in this context"),
+            AuthError::RateLimited { retry_after: None } => f.write_str("rate limited"),
+            AuthError::RateLimited {
+                retry_after: Some(d),
+            } => write!(f, "rate limited, retry after {d:?}"),
+            AuthError::Backend(e) => write!(f, "backend error: {e}"),
+        }
+    }
+}
+
+impl StdError for AuthError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            AuthError::Backend(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<LimitExceeded> for AuthError {
+    fn from(_: LimitExceeded) -> Self {
+        AuthError::RateLimited { retry_after: None }
+    }
+}
+
+/// Errors of [`UserStore`](crate::UserStore) operations.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StoreError {
+    /// `add` found a user with the same identity.
+    AlreadyExists(String),
+
+    /// `update` or `remove` found no user with the identity.
+    NotFound(String),
+
+    /// The storage failed, e.g. a database was unreachable.
+    Backend(Box<dyn StdError + Send + Sync>),
+}
+
+impl StoreError {
+    /// Wraps a storage failure.
+    pub fn backend(e: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        StoreError::Backend(e.into())
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::AlreadyExists(id) => write!(f, "user {id:?} already exists"),
+            StoreError::NotFound(id) => write!(f, "user {id:?} not found"),
+            StoreError::Backend(e) => write!(f, "backend error: {e}"),
+        }
+    }
+}
+
+impl StdError for StoreError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            StoreError::Backend(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// A user could not be added because its identity is taken, see
+/// [`UsersMap::add_user_checked`](crate::UsersMap::add_user_checked).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateIdentity(pub String);
+
+impl fmt::Display for DuplicateIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "user {:?} already exists", self.0)
+    }
+}
+
+impl StdError for DuplicateIdentity {}
+
+impl From<DuplicateIdentity> for StoreError {
+    fn from(e: DuplicateIdentity) -> Self {
+        StoreError::AlreadyExists(e.0)
+    }
+}
+
+impl From<DuplicateIdentity> for Error {
+    fn from(e: DuplicateIdentity) -> Self {
+        Error::Store(e.into())
+    }
+}
+
+/// Errors of parsing a user from its compact string form, e.g. `"user pass"`
+/// for [`PlainText`](crate::PlainText).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseUserError {
+    EmptyIdentity,
+
+    /// Nothing follows the identity.
+    MissingPassword,
+
+    /// A control character, e.g. a newline, which would make the auth string ambiguous.
+    IllegalCharacter(char),
+}
+
+impl fmt::Display for ParseUserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseUserError::EmptyIdentity => f.write_str("empty user name"),
+            ParseUserError::MissingPassword => f.write_str("missing password"),
+            ParseUserError::IllegalCharacter(ch) => write!(f, "illegal character {ch:?}"),
+        }
+    }
+}
+
+impl StdError for ParseUserError {}
+
+/// Errors of loading a user list from a config file, e.g. by
+/// [`load_users_from_toml`](crate::load_users_from_toml).
+#[cfg(feature = "std")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LoadError {
+    Io(io::Error),
+
+    /// The file is malformed or an entry is invalid. `line` is 1-based, if known.
+    Syntax {
+        line: Option<usize>,
+        message: String,
+    },
+
+    /// A second entry uses an identity that is already taken.
+    Duplicate {
+        line: Option<usize>,
+        id: String,
+    },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = |f: &mut fmt::Formatter<'_>, line: &Option<usize>| match line {
+            Some(line) => write!(f, "line {line}: "),
+            None => Ok(()),
+        };
+        match self {
+            LoadError::Io(e) => write!(f, "cannot read users: {e}"),
+            LoadError::Syntax { line, message } => {
+                at(f, line)?;
+                f.write_str(message)
+            }
+            LoadError::Duplicate { line, id } => {
+                at(f, line)?;
+                write!(f, "duplicate user {id:?}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl StdError for LoadError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+/// Lets loaders be used where an [`io::Result`] is expected, e.g. by
+/// the loader of a `FileUserSource`. Invalid files become [`io::ErrorKind::InvalidData`].
+#[cfg(feature = "std")]
+impl From<LoadError> for io::Error {
+    fn from(e: LoadError) -> Self {
+        match e {
+            LoadError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Any error of this crate, for applications that handle them alike.
+///
+/// Each error type of the crate converts into it, so `?` works across APIs.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Parse(#[from] ParseUserError),
+
+    #[error(transparent)]
+    AuthStr(#[from] AuthStrError),
+
+    #[cfg(feature = "std")]
+    #[error(transparent)]
+    Build(#[from] BuildUserError),
+
+    /// Authentication failed or a policy refused the user.
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+
+    #[cfg(feature = "std")]
+    #[error(transparent)]
+    Load(#[from] LoadError),
+
+    #[error(transparent)]
+    Store(#[from] StoreError),
+
+    #[cfg(feature = "std")]
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// A failure of a backend outside the other categories.
+    #[error("backend error: {0}")]
+    Backend(Box<dyn StdError + Send + Sync>),
+}
+
+impl Error {
+    /// Wraps a backend failure.
+    pub fn backend(e: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        Error::Backend(e.into())
+    }
+}
+
+/// A `Result` with this crate's [`Error`].
+pub type Result<T, E = Error> = core::result::Result<T, E>;
@@ -0,0 +1,167 @@
+/*!
+A [`UsersMap`] whose entries can carry an expiry deadline.
+*/
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::Instant;
+use crate::{AuthError, UserAuthenticator, UserTrait, UsersMap};
+
+/// A [`UsersMap`] where each user may carry an expiry [`Instant`].
+///
+/// Lookups past a user's deadline return `None`, but the entry stays in memory
+/// until [`ExpiringUsersMap::purge_expired`] is called.
+#[derive(Debug, Clone, Default)]
+pub struct ExpiringUsersMap<T: UserTrait + Clone> {
+    map: UsersMap<T>,
+
+    /// Maps user identity strings to their deadlines. Users without an entry never expire.
+    deadlines: HashMap<String, Instant>,
+}
+
+impl<T: UserTrait + Clone> ExpiringUsersMap<T> {
+    pub fn new() -> Self {
+        ExpiringUsersMap {
+            map: UsersMap::new(),
+            deadlines: HashMap::new(),
+        }
+    }
+
+    /// Adds a user that expires at `expires_at`, or never if it is `None`.
+    pub fn add_user(&mut self, user: T, expires_at: Option<Instant>) {
+        let id = user.identity_str().to_string();
+        self.map.add_user(user);
+        match expires_at {
+            Some(deadline) => self.deadlines.insert(id, deadline),
+            None => self.deadlines.remove(&id),
+        };
+    }
+
+    /// Adds a user that expires `ttl` from now.
+    pub fn add_user_with_ttl(&mut self, user: T, ttl: Duration) {
+        self.add_user(user, Some(Instant::now() + ttl))
+    }
+
+    /// Changes the deadline of an existing user. Returns false if the user is unknown.
+    pub fn set_expiry(&mut self, id: &str, expires_at: Option<Instant>) -> bool {
+        if self.map.get_user(id).is_none() {
+            return false;
+        }
+        match expires_at {
+            Some(deadline) => self.deadlines.insert(id.to_string(), deadline),
+            None => self.deadlines.remove(id),
+        };
+        true
+    }
+
+    /// Returns the deadline of the user, if any
+    pub fn expires_at(&self, id: &str) -> Option<Instant> {
+        self.deadlines.get(id).copied()
+    }
+
+    pub fn is_expired(&self, id: &str) -> bool {
+        self.deadlines
+            .get(id)
+            .is_some_and(|deadline| *deadline <= Instant::now())
+    }
+
+    pub fn remove_user(&mut self, id: &str) -> Option<Arc<T>> {
+        self.deadlines.remove(id);
+        self.map.remove_user(id)
+    }
+
+    /// Removes every expired user, returning how many were removed.
+    pub fn purge_expired(&mut self) -> usize {
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.remove_user(id);
+        }
+        expired.len()
+    }
+
+    /// Number of stored users, including expired ones that were not purged yet.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Retrieves a non-expired user by their identity string
+    pub fn get_user(&self, id: &str) -> Option<Arc<T>> {
+        if self.is_expired(id) {
+            return None;
+        }
+        self.map.get_user(id)
+    }
+
+    /// Retrieves a non-expired user by their authentication string
+    pub fn get_user_by_authstr(&self, authstr: &str) -> Option<Arc<T>> {
+        self.map
+            .get_user_by_authstr(authstr)
+            .filter(|u| !self.is_expired(u.identity_str()))
+    }
+
+    /// The underlying map, including expired users.
+    pub fn inner(&self) -> &UsersMap<T> {
+        &self.map
+    }
+}
+
+impl<T: UserTrait + Clone> UserAuthenticator<T> for ExpiringUsersMap<T> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.get_user_by_authstr(authstr)
+            .map(|arc_user| arc_user.as_ref().clone())
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        let user = self
+            .map
+            .get_user_by_authstr(authstr)
+            .ok_or(AuthError::UnknownUser)?;
+        if self.is_expired(user.identity_str()) {
+            return Err(AuthError::Expired);
+        }
+        Ok(user.as_ref().clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::ExpiringUsersMap;
+    use crate::{PlainText, UserAuthenticator};
+
+    #[test]
+    fn test_expiry() {
+        let mut um = ExpiringUsersMap::new();
+        um.add_user(
+            PlainText::new("old".into(), "p".into()),
+            Some(Instant::now() - Duration::from_secs(1)),
+        );
+        um.add_user_with_ttl(
+            PlainText::new("new".into(), "p".into()),
+            Duration::from_secs(60),
+        );
+        um.add_user(PlainText::new("forever".into(), "p".into()), None);
+
+        assert!(um.auth_user_by_authstr("plaintext:old\np").is_none());
+        assert!(um.get_user("old").is_none());
+        assert!(um.auth_user_by_authstr("plaintext:new\np").is_some());
+        assert!(um.auth_user_by_authstr("plaintext:forever\np").is_some());
+
+        assert_eq!(um.len(), 3);
+        assert_eq!(um.purge_expired(), 1);
+        assert_eq!(um.len(), 2);
+    }
+}
@@ -0,0 +1,95 @@
+/*!
+Users that carry their own expiry time.
+*/
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::{Clock, UserTrait};
+
+/// A user that stops being valid at some point, e.g. when a subscription ends.
+///
+/// See [`UsersMap::enforce_expiry`](crate::UsersMap::enforce_expiry).
+pub trait UserWithExpiry: UserTrait {
+    /// When the user expires, or `None` if it never does.
+    fn expires_at(&self) -> Option<SystemTime>;
+
+    fn is_expired_at(&self, now: SystemTime) -> bool {
+        self.expires_at().is_some_and(|t| t <= now)
+    }
+}
+
+/// How a map decides that a stored user has expired.
+///
+/// A plain function pointer keeps the map usable with user types that don't implement
+/// [`UserWithExpiry`] while enforcement is off.
+#[derive(Debug, Clone)]
+pub(crate) struct ExpiryPolicy<T> {
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) is_expired_at: fn(&T, SystemTime) -> bool,
+}
+
+impl<T: UserWithExpiry> ExpiryPolicy<T> {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        ExpiryPolicy {
+            clock,
+            is_expired_at: |user, now| user.is_expired_at(now),
+        }
+    }
+}
+
+impl<T> ExpiryPolicy<T> {
+    pub(crate) fn is_expired(&self, user: &T) -> bool {
+        (self.is_expired_at)(user, self.clock.now())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    use super::UserWithExpiry;
+    use crate::{ManualClock, PlainText, UserAuthenticator, UsersMap};
+
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct SubscribedUser {
+        user: PlainText,
+        until: SystemTime,
+    }
+
+    impl_wrapper_user_trait!(SubscribedUser, "SubscribedUser");
+
+    impl UserWithExpiry for SubscribedUser {
+        fn expires_at(&self) -> Option<SystemTime> {
+            Some(self.until)
+        }
+    }
+
+    #[test]
+    fn test_enforce_expiry() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let clock = ManualClock::new(start);
+
+        let mut um = UsersMap::new();
+        um.add_user(SubscribedUser {
+            user: PlainText::from("u p"),
+            until: start + Duration::from_secs(60),
+        });
+        um.enforce_expiry(Arc::new(clock.clone()));
+
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_some());
+        clock.advance(Duration::from_secs(60));
+        assert!(um.is_expired("u"));
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_none());
+        assert!(um.auth_user_constant_time("plaintext:u\np").is_none());
+        assert!(um.get_user("u").is_some());
+
+        um.disable_expiry();
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_some());
+    }
+}
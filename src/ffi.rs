@@ -0,0 +1,181 @@
+/*!
+A C interface to a [`SharedUsersMap`] of [`PlainText`] users, so that proxy cores written
+in other languages can embed the user management of this crate.
+
+The map is an opaque pointer created by [`user_trait_map_new`] and destroyed by
+[`user_trait_map_free`]. It locks internally, so it may be used from several threads.
+Strings are NUL-terminated UTF-8, except auth strings, which are passed with their length
+since they contain newlines and may contain NULs. `include/user_trait.h` declares the functions.
+
+Requires the `ffi` feature.
+*/
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use crate::{PlainText, SharedUsersMap, UsersMap};
+
+/// The map behind the pointers of this module
+pub type UserTraitMap = SharedUsersMap<PlainText>;
+
+/// Reads a NUL-terminated UTF-8 string, or `None` if it is null or not UTF-8.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string valid for the returned lifetime.
+unsafe fn c_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Creates an empty map, to be destroyed with [`user_trait_map_free`].
+#[no_mangle]
+pub extern "C" fn user_trait_map_new() -> *mut UserTraitMap {
+    Box::into_raw(Box::new(SharedUsersMap::new(UsersMap::new())))
+}
+
+/// Destroys a map created by [`user_trait_map_new`]. Null is ignored.
+///
+/// # Safety
+///
+/// `map` must be null or a pointer returned by [`user_trait_map_new`] that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn user_trait_map_free(map: *mut UserTraitMap) {
+    if !map.is_null() {
+        drop(Box::from_raw(map));
+    }
+}
+
+/// Adds a user with a plaintext password, replacing the user with the same name.
+///
+/// Returns 0, or -1 if an argument is null or not UTF-8, or the user name is empty.
+///
+/// # Safety
+///
+/// `map` must be a live map, and `user` and `pass` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn user_trait_map_add_plaintext(
+    map: *const UserTraitMap,
+    user: *const c_char,
+    pass: *const c_char,
+) -> c_int {
+    let (Some(map), Some(user), Some(pass)) = (map.as_ref(), c_str(user), c_str(pass)) else {
+        return -1;
+    };
+    let user = PlainText::new(user.to_string(), pass.to_string());
+    if !user.valid() {
+        return -1;
+    }
+    map.add_user(user);
+    0
+}
+
+/// Removes the user with the identity `id` and all of its credentials.
+///
+/// Returns 1 if it was removed, 0 if it is unknown, or -1 if an argument is null or not UTF-8.
+///
+/// # Safety
+///
+/// `map` must be a live map, and `id` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn user_trait_map_remove(
+    map: *const UserTraitMap,
+    id: *const c_char,
+) -> c_int {
+    let (Some(map), Some(id)) = (map.as_ref(), c_str(id)) else {
+        return -1;
+    };
+    map.remove_user(id).is_some().into()
+}
+
+/// Number of users in the map, or 0 if it is null
+///
+/// # Safety
+///
+/// `map` must be null or a live map.
+#[no_mangle]
+pub unsafe extern "C" fn user_trait_map_len(map: *const UserTraitMap) -> usize {
+    map.as_ref().map_or(0, SharedUsersMap::len)
+}
+
+/// Authenticates the `len` bytes at `authstr`, e.g. `plaintext:user\npass`.
+///
+/// Returns the identity of the user, to be freed with [`user_trait_string_free`], or null
+/// if authentication failed or an argument is invalid.
+///
+/// # Safety
+///
+/// `map` must be a live map, and `authstr` point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn user_trait_map_auth(
+    map: *const UserTraitMap,
+    authstr: *const u8,
+    len: usize,
+) -> *mut c_char {
+    let Some(map) = map.as_ref() else {
+        return ptr::null_mut();
+    };
+    if authstr.is_null() {
+        return ptr::null_mut();
+    }
+    let Ok(authstr) = std::str::from_utf8(std::slice::from_raw_parts(authstr, len)) else {
+        return ptr::null_mut();
+    };
+    match map.read().try_auth_shared(authstr) {
+        Ok(user) => CString::new(user.user.as_str()).map_or(ptr::null_mut(), CString::into_raw),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees a string returned by this module. Null is ignored.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by this module that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn user_trait_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        let map = user_trait_map_new();
+        unsafe {
+            assert_eq!(
+                user_trait_map_add_plaintext(map, c"u".as_ptr(), c"p".as_ptr()),
+                0
+            );
+            assert_eq!(
+                user_trait_map_add_plaintext(map, c"".as_ptr(), c"p".as_ptr()),
+                -1
+            );
+            assert_eq!(
+                user_trait_map_add_plaintext(map, ptr::null(), c"p".as_ptr()),
+                -1
+            );
+            assert_eq!(user_trait_map_len(map), 1);
+
+            let auth = b"plaintext:u\np";
+            let id = user_trait_map_auth(map, auth.as_ptr(), auth.len());
+            assert_eq!(CStr::from_ptr(id).to_str(), Ok("u"));
+            user_trait_string_free(id);
+            let wrong = b"plaintext:u\nq";
+            assert!(user_trait_map_auth(map, wrong.as_ptr(), wrong.len()).is_null());
+
+            assert_eq!(user_trait_map_remove(map, c"u".as_ptr()), 1);
+            assert_eq!(user_trait_map_remove(map, c"u".as_ptr()), 0);
+            assert!(user_trait_map_auth(map, auth.as_ptr(), auth.len()).is_null());
+            user_trait_map_free(map);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+/*!
+Generation counters for detecting changes of a user set.
+*/
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A monotonically increasing counter bumped on every mutation of its owner.
+///
+/// Cloning copies the current value into an independent counter,
+/// so receivers of the original never see changes made to a clone.
+///
+/// Each counter also has an instance number unique in the process, as two counters
+/// may reach the same value after different changes.
+#[derive(Debug)]
+pub(crate) struct Generation {
+    counter: Arc<AtomicU64>,
+    instance: u64,
+}
+
+/// The instance number of the next [`Generation`]
+static NEXT_INSTANCE: AtomicU64 = AtomicU64::new(0);
+
+impl Generation {
+    fn starting_at(value: u64) -> Self {
+        Generation {
+            counter: Arc::new(AtomicU64::new(value)),
+            instance: NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Generation {
+    fn default() -> Self {
+        Generation::starting_at(0)
+    }
+}
+
+impl Clone for Generation {
+    fn clone(&self) -> Self {
+        Generation::starting_at(self.get())
+    }
+}
+
+impl Generation {
+    pub(crate) fn get(&self) -> u64 {
+        self.counter.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn instance(&self) -> u64 {
+        self.instance
+    }
+
+    pub(crate) fn bump(&self) {
+        self.counter.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn subscribe(&self) -> GenerationReceiver {
+        GenerationReceiver {
+            current: Arc::clone(&self.counter),
+            seen: self.get(),
+        }
+    }
+}
+
+/// A watch-style receiver of a generation counter, created by
+/// [`UsersMap::subscribe`](crate::UsersMap::subscribe).
+///
+/// It is cheap to poll from long-lived tasks: checking for a change is a single atomic load.
+#[derive(Debug, Clone)]
+pub struct GenerationReceiver {
+    current: Arc<AtomicU64>,
+    seen: u64,
+}
+
+impl GenerationReceiver {
+    /// The current generation of the watched map
+    pub fn generation(&self) -> u64 {
+        self.current.load(Ordering::Acquire)
+    }
+
+    /// The generation marked as seen, initially the one at subscription time
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Returns true if the map changed since the last [`GenerationReceiver::mark_seen`].
+    pub fn has_changed(&self) -> bool {
+        self.generation() != self.seen
+    }
+
+    /// Marks the current generation as seen and returns it.
+    pub fn mark_seen(&mut self) -> u64 {
+        self.seen = self.generation();
+        self.seen
+    }
+}
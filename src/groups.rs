@@ -0,0 +1,109 @@
+/*!
+Named groups of user identities.
+*/
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Maps group names to sets of user identities, so that permissions can be managed per group.
+///
+/// It serializes as a plain map, e.g. `{"admins": ["alice"], "guests": ["bob", "carol"]}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct GroupsMap(BTreeMap<String, BTreeSet<String>>);
+
+impl GroupsMap {
+    pub fn new() -> Self {
+        GroupsMap(BTreeMap::new())
+    }
+
+    /// Adds an identity to a group, creating the group if needed.
+    ///
+    /// Returns false if the identity was already a member.
+    pub fn add_to_group(&mut self, group: &str, id: &str) -> bool {
+        self.0
+            .entry(group.to_string())
+            .or_default()
+            .insert(id.to_string())
+    }
+
+    /// Removes an identity from a group. Empty groups are kept.
+    ///
+    /// Returns false if the identity was not a member.
+    pub fn remove_from_group(&mut self, group: &str, id: &str) -> bool {
+        self.0.get_mut(group).is_some_and(|m| m.remove(id))
+    }
+
+    /// Removes an identity from every group
+    pub fn remove_member(&mut self, id: &str) {
+        self.0.values_mut().for_each(|m| {
+            m.remove(id);
+        })
+    }
+
+    /// Removes a whole group, returning its members
+    pub fn remove_group(&mut self, group: &str) -> Option<BTreeSet<String>> {
+        self.0.remove(group)
+    }
+
+    /// Returns the identities in a group
+    pub fn members(&self, group: &str) -> Option<&BTreeSet<String>> {
+        self.0.get(group)
+    }
+
+    pub fn is_member(&self, group: &str, id: &str) -> bool {
+        self.0.get(group).is_some_and(|m| m.contains(id))
+    }
+
+    /// Returns the names of every group containing the identity
+    pub fn groups_of<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(_, m)| m.contains(id))
+            .map(|(g, _)| g.as_str())
+    }
+
+    /// Iterates over group names and their members, ordered by group name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BTreeSet<String>)> {
+        self.0.iter().map(|(g, m)| (g.as_str(), m))
+    }
+
+    /// Number of groups
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GroupsMap;
+
+    #[test]
+    fn test_groups_serde() -> Result<(), Box<dyn std::error::Error>> {
+        let mut groups = GroupsMap::new();
+        assert!(groups.add_to_group("admins", "alice"));
+        assert!(!groups.add_to_group("admins", "alice"));
+        groups.add_to_group("guests", "alice");
+
+        assert_eq!(
+            groups.groups_of("alice").collect::<Vec<_>>(),
+            ["admins", "guests"]
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let s = serde_json::to_string(&groups)?;
+            assert_eq!(s, r#"{"admins":["alice"],"guests":["alice"]}"#);
+            assert_eq!(serde_json::from_str::<GroupsMap>(&s)?, groups);
+        }
+        Ok(())
+    }
+}
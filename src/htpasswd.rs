@@ -0,0 +1,349 @@
+/*!
+Apache `htpasswd` files.
+
+Requires the `htpasswd` feature.
+*/
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use base64::Engine;
+use md5::Md5;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use subtle::ConstantTimeEq;
+
+use crate::{LoadError, UserTrait, UsersMap, VerifyUser};
+
+/// How the password of an [`HtpasswdUser`] is hashed, told by the prefix of the hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HashScheme {
+    /// `$2y$`, `$2a$` or `$2b$`, written by `htpasswd -B`
+    Bcrypt,
+
+    /// `$apr1$`, the Apache MD5 variant written by `htpasswd -m`
+    Apr1,
+
+    /// `{SHA}`, an unsalted SHA-1 written by `htpasswd -s`
+    Sha1,
+
+    /// DES crypt or anything else; such users never verify.
+    Unsupported,
+}
+
+impl HashScheme {
+    pub fn of(hash: &str) -> Self {
+        if ["$2y$", "$2a$", "$2b$"].iter().any(|p| hash.starts_with(p)) {
+            HashScheme::Bcrypt
+        } else if hash.starts_with("$apr1$") {
+            HashScheme::Apr1
+        } else if hash.starts_with("{SHA}") {
+            HashScheme::Sha1
+        } else {
+            HashScheme::Unsupported
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct HtpasswdEntry {
+    user: String,
+    hash: String,
+}
+
+/// A user of an `htpasswd` file.
+///
+/// Its auth string is its `user:hash` line, which can't be derived from a presented
+/// password; authenticate it with [`VerifyingAuthenticator`](crate::VerifyingAuthenticator),
+/// which calls [`VerifyUser::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "HtpasswdEntry", into = "HtpasswdEntry")
+)]
+pub struct HtpasswdUser {
+    /// `user:hash`
+    line: String,
+
+    /// Length of the user name in `line`
+    split: usize,
+}
+
+impl From<HtpasswdEntry> for HtpasswdUser {
+    fn from(e: HtpasswdEntry) -> Self {
+        HtpasswdUser::new(&e.user, &e.hash)
+    }
+}
+
+impl From<HtpasswdUser> for HtpasswdEntry {
+    fn from(u: HtpasswdUser) -> Self {
+        HtpasswdEntry {
+            user: u.user().to_string(),
+            hash: u.hash().to_string(),
+        }
+    }
+}
+
+impl HtpasswdUser {
+    /// Creates a user from an existing hash.
+    pub fn new(user: &str, hash: &str) -> Self {
+        HtpasswdUser {
+            line: format!("{user}:{hash}"),
+            split: user.len(),
+        }
+    }
+
+    /// Hashes `pass` with bcrypt at the default cost.
+    pub fn bcrypt(user: &str, pass: &str) -> Self {
+        let hash = bcrypt::hash(pass, bcrypt::DEFAULT_COST)
+            .expect("bcrypt hashing with the default cost failed");
+        Self::new(user, &hash)
+    }
+
+    /// Hashes `pass` with a random 8 character salt in the `$apr1$` scheme.
+    pub fn apr1(user: &str, pass: &str) -> Self {
+        let mut bytes = [0u8; 6];
+        getrandom::fill(&mut bytes).expect("the system random number generator failed");
+        let mut salt = String::new();
+        for chunk in bytes.chunks(3) {
+            to64(
+                &mut salt,
+                u32::from(chunk[0]) << 16 | u32::from(chunk[1]) << 8 | u32::from(chunk[2]),
+                4,
+            );
+        }
+        Self::new(user, &apr1_hash(pass.as_bytes(), salt.as_bytes()))
+    }
+
+    /// Hashes `pass` in the `{SHA}` scheme. It is unsalted, so prefer [`HtpasswdUser::bcrypt`].
+    pub fn sha1(user: &str, pass: &str) -> Self {
+        Self::new(user, &sha1_hash(pass.as_bytes()))
+    }
+
+    pub fn user(&self) -> &str {
+        &self.line[..self.split]
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.line[self.split + 1..]
+    }
+
+    pub fn scheme(&self) -> HashScheme {
+        HashScheme::of(self.hash())
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl UserTrait for HtpasswdUser {
+    fn identity_str(&self) -> &str {
+        self.user()
+    }
+
+    fn identity_bytes(&self) -> &[u8] {
+        self.user().as_bytes()
+    }
+
+    fn auth_str(&self) -> &str {
+        &self.line
+    }
+
+    fn auth_bytes(&self) -> &[u8] {
+        self.line.as_bytes()
+    }
+}
+
+impl VerifyUser for HtpasswdUser {
+    fn verify(&self, presented: &[u8]) -> bool {
+        let hash = self.hash();
+        match self.scheme() {
+            HashScheme::Bcrypt => bcrypt::verify(presented, hash).unwrap_or(false),
+            HashScheme::Apr1 => {
+                let salt = hash["$apr1$".len()..].split('$').next().unwrap_or_default();
+                apr1_hash(presented, salt.as_bytes())
+                    .as_bytes()
+                    .ct_eq(hash.as_bytes())
+                    .into()
+            }
+            HashScheme::Sha1 => sha1_hash(presented)
+                .as_bytes()
+                .ct_eq(hash.as_bytes())
+                .into(),
+            HashScheme::Unsupported => false,
+        }
+    }
+}
+
+fn sha1_hash(pass: &[u8]) -> String {
+    let digest = Sha1::digest(pass);
+    format!(
+        "{{SHA}}{}",
+        base64::engine::general_purpose::STANDARD.encode(digest)
+    )
+}
+
+const ITOA64: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Appends the `n` low 6-bit groups of `v` in crypt's base64 alphabet.
+fn to64(out: &mut String, mut v: u32, n: usize) {
+    for _ in 0..n {
+        out.push(ITOA64[(v & 0x3f) as usize] as char);
+        v >>= 6;
+    }
+}
+
+/// The `$apr1$` variant of md5crypt, as implemented by APR.
+fn apr1_hash(pass: &[u8], salt: &[u8]) -> String {
+    const MAGIC: &[u8] = b"$apr1$";
+    let salt = &salt[..salt.len().min(8)];
+
+    let alt = Md5::new()
+        .chain_update(pass)
+        .chain_update(salt)
+        .chain_update(pass)
+        .finalize();
+    let mut ctx = Md5::new()
+        .chain_update(pass)
+        .chain_update(MAGIC)
+        .chain_update(salt);
+    for chunk in pass.chunks(16) {
+        ctx.update(&alt[..chunk.len()]);
+    }
+    let mut i = pass.len();
+    while i > 0 {
+        if i & 1 == 1 {
+            ctx.update([0u8]);
+        } else {
+            ctx.update(&pass[..1]);
+        }
+        i >>= 1;
+    }
+    let mut digest = ctx.finalize();
+
+    for i in 0..1000 {
+        let mut ctx = Md5::new();
+        if i & 1 == 1 {
+            ctx.update(pass);
+        } else {
+            ctx.update(digest);
+        }
+        if i % 3 != 0 {
+            ctx.update(salt);
+        }
+        if i % 7 != 0 {
+            ctx.update(pass);
+        }
+        if i & 1 == 1 {
+            ctx.update(digest);
+        } else {
+            ctx.update(pass);
+        }
+        digest = ctx.finalize();
+    }
+
+    let mut out = String::with_capacity(37);
+    out.push_str("$apr1$");
+    out.push_str(&String::from_utf8_lossy(salt));
+    out.push('$');
+    let d = |i: usize| u32::from(digest[i]);
+    for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)] {
+        to64(&mut out, d(a) << 16 | d(b) << 8 | d(c), 4);
+    }
+    to64(&mut out, d(11), 2);
+    out
+}
+
+/// Parses an `htpasswd` file: one `user:hash` per line, blank lines and `#` comments ignored.
+///
+/// Users with unsupported hashes are loaded, but never verify.
+pub fn parse_htpasswd(text: &str) -> Result<UsersMap<HtpasswdUser>, LoadError> {
+    let mut map = UsersMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (user, hash) = match line.split_once(':') {
+            Some((user, hash)) if !user.is_empty() && !hash.is_empty() => (user, hash),
+            _ => {
+                return Err(LoadError::Syntax {
+                    line: Some(i + 1),
+                    message: "expected \"user:hash\"".into(),
+                })
+            }
+        };
+        if map.get_user(user).is_some() {
+            return Err(LoadError::Duplicate {
+                line: Some(i + 1),
+                id: user.to_string(),
+            });
+        }
+        map.add_user(HtpasswdUser::new(user, hash));
+    }
+    Ok(map)
+}
+
+/// Reads a file with [`parse_htpasswd`].
+pub fn load_htpasswd_path(path: &Path) -> Result<UsersMap<HtpasswdUser>, LoadError> {
+    parse_htpasswd(&fs::read_to_string(path)?)
+}
+
+/// Writes the users in `htpasswd` format, ordered by identity.
+pub fn write_htpasswd<S: std::hash::BuildHasher>(map: &UsersMap<HtpasswdUser, S>) -> String {
+    let mut users: Vec<_> = map.iter().collect();
+    users.sort_by(|a, b| a.user().cmp(b.user()));
+    let mut out = String::new();
+    for u in users {
+        let _ = writeln!(out, "{}", u.line);
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_htpasswd, write_htpasswd, HashScheme, HtpasswdUser};
+    use crate::{LoadError, VerifyUser, VerifyingAuthenticator};
+
+    #[test]
+    fn test_htpasswd_schemes() {
+        let apr1 = HtpasswdUser::new("myName", "$apr1$r31.....$HqJZimcKQFAMYayBlzkrA/");
+        assert_eq!(apr1.scheme(), HashScheme::Apr1);
+        assert!(apr1.verify(b"myPassword"));
+        assert!(!apr1.verify(b"myPassword2"));
+
+        let sha = HtpasswdUser::new("u", "{SHA}VBPuJHI7uixaa6LQGWx4s+5GKNE=");
+        assert!(sha.verify(b"myPassword"));
+        assert!(HtpasswdUser::sha1("u", "myPassword") == sha);
+
+        let fresh = HtpasswdUser::apr1("u", "pw");
+        assert!(fresh.verify(b"pw") && !fresh.verify(b"px"));
+        let b = HtpasswdUser::bcrypt("u", "pw");
+        assert_eq!(b.scheme(), HashScheme::Bcrypt);
+        assert!(b.verify(b"pw") && !b.verify(b"px"));
+
+        assert!(!HtpasswdUser::new("u", "rqXexS6ZhobKA").verify(b"pw"));
+    }
+
+    #[test]
+    fn test_htpasswd_file() -> Result<(), LoadError> {
+        let text = "# users\nmyName:$apr1$r31.....$HqJZimcKQFAMYayBlzkrA/\n\nbob:{SHA}VBPuJHI7uixaa6LQGWx4s+5GKNE=\n";
+        let um = parse_htpasswd(text)?;
+        assert_eq!(
+            write_htpasswd(&um),
+            "bob:{SHA}VBPuJHI7uixaa6LQGWx4s+5GKNE=\nmyName:$apr1$r31.....$HqJZimcKQFAMYayBlzkrA/\n"
+        );
+        let auth = VerifyingAuthenticator::new(um);
+        assert!(auth.authenticate("myName", b"myPassword").is_some());
+        assert!(auth.authenticate("bob", b"myPassword").is_some());
+        assert!(auth.authenticate("bob", b"nope").is_none());
+
+        let err = parse_htpasswd("a:x\nbroken\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: expected \"user:hash\"");
+        let err = parse_htpasswd("a:x\na:y\n").unwrap_err();
+        assert!(matches!(err, LoadError::Duplicate { line: Some(2), .. }));
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+/*!
+Periodic fetching of a central users file over HTTP(S) into a [`SharedUsersMap`].
+
+Requires the `http` feature.
+*/
+
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    load_users_from_toml, PlainText, SharedUsersMap, UserSource, UserTrait, UsersDiff, UsersMap,
+};
+
+/// Builds a [`UsersMap`] from a fetched users file.
+pub type UsersBytesLoader<T> = Arc<dyn Fn(&[u8]) -> io::Result<UsersMap<T>> + Send + Sync>;
+
+/// Checks a loaded [`UsersMap`] before it replaces the current one.
+pub type UsersValidator<T> = Arc<dyn Fn(&UsersMap<T>) -> io::Result<()> + Send + Sync>;
+
+/// What the server said about the last version it sent, for conditional requests.
+#[derive(Debug, Default)]
+struct CacheValidators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// A users file on an HTTP(S) server that fleets of proxy nodes pull from.
+///
+/// Requests carry `If-None-Match` and `If-Modified-Since` from the last accepted
+/// answer, so an unchanged file costs a `304 Not Modified`. A changed file is loaded,
+/// validated, and swapped into the shared map atomically; a file that fails to load or
+/// validate leaves the current map untouched. By default, empty user lists are refused,
+/// so that a misconfigured server can't lock every user out.
+pub struct HttpUserSource<T: UserTrait + Clone> {
+    client: reqwest::Client,
+    url: String,
+    shared: Arc<SharedUsersMap<T>>,
+    loader: UsersBytesLoader<T>,
+    validator: UsersValidator<T>,
+    validators: Mutex<CacheValidators>,
+}
+
+impl<T: UserTrait + Clone> std::fmt::Debug for HttpUserSource<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpUserSource")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: UserTrait + Clone + DeserializeOwned> HttpUserSource<T> {
+    /// Creates a source fetching JSON files written by [`UsersMap::save_to_path`].
+    pub fn new(url: impl Into<String>, shared: Arc<SharedUsersMap<T>>) -> Self {
+        Self::with_loader(
+            url,
+            shared,
+            Arc::new(|b: &[u8]| UsersMap::load_from_slice(b)),
+        )
+    }
+}
+
+impl HttpUserSource<PlainText> {
+    /// Creates a source fetching TOML user lists, parsed with [`load_users_from_toml`].
+    pub fn toml(url: impl Into<String>, shared: Arc<SharedUsersMap<PlainText>>) -> Self {
+        Self::with_loader(
+            url,
+            shared,
+            Arc::new(|b: &[u8]| {
+                let text = std::str::from_utf8(b)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Ok(load_users_from_toml(text)?)
+            }),
+        )
+    }
+}
+
+impl<T: UserTrait + Clone> HttpUserSource<T> {
+    /// Creates a source parsing the file with a custom loader, e.g. for another format.
+    pub fn with_loader(
+        url: impl Into<String>,
+        shared: Arc<SharedUsersMap<T>>,
+        loader: UsersBytesLoader<T>,
+    ) -> Self {
+        HttpUserSource {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("the HTTP client could not be initialized"),
+            url: url.into(),
+            shared,
+            loader,
+            validator: Arc::new(|map: &UsersMap<T>| {
+                if map.is_empty() {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "the fetched user list is empty",
+                    ))
+                } else {
+                    Ok(())
+                }
+            }),
+            validators: Mutex::new(CacheValidators::default()),
+        }
+    }
+
+    /// Replaces the check run on every loaded map, e.g. to accept empty lists or to
+    /// require some admin user.
+    pub fn with_validator(mut self, validator: UsersValidator<T>) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Uses a preconfigured client, e.g. with client certificates or a proxy.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn shared(&self) -> &Arc<SharedUsersMap<T>> {
+        &self.shared
+    }
+
+    /// Fetches the file and swaps it into the shared map, returning what changed,
+    /// or `None` if the server answered that it didn't change.
+    ///
+    /// The statistics collector of the current map, if any, is carried over to the new one.
+    pub async fn fetch(&self) -> io::Result<Option<UsersDiff>> {
+        Ok(self.fetch_map().await?.map(|new| self.shared.reload(new)))
+    }
+
+    /// Fetches, loads and validates the file, without touching the shared map.
+    async fn fetch_map(&self) -> io::Result<Option<UsersMap<T>>> {
+        let mut req = self.client.get(&self.url);
+        {
+            let v = self
+                .validators
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if let Some(etag) = &v.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(date) = &v.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, date);
+            }
+        }
+        let resp = req.send().await.map_err(io::Error::other)?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let resp = resp.error_for_status().map_err(io::Error::other)?;
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let received = CacheValidators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        let body = resp.bytes().await.map_err(io::Error::other)?;
+
+        let new = (self.loader)(&body)?;
+        (self.validator)(&new)?;
+        *self
+            .validators
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = received;
+        Ok(Some(new))
+    }
+
+    /// Fetches the file every `interval`, forever. Run it in a background task.
+    ///
+    /// `on_change` receives the outcome of every fetch that got a new file, including
+    /// failures; unchanged files are not reported.
+    pub async fn run(&self, interval: Duration, mut on_change: impl FnMut(io::Result<UsersDiff>)) {
+        loop {
+            match self.fetch().await {
+                Ok(None) => {}
+                Ok(Some(diff)) => on_change(Ok(diff)),
+                Err(e) => on_change(Err(e)),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// Pulls the file without swapping it into [`HttpUserSource::shared`], for
+/// [`spawn_refresh_task`](crate::spawn_refresh_task) or another refresh loop.
+#[async_trait]
+impl<T: UserTrait + Clone> UserSource<T> for HttpUserSource<T> {
+    async fn fetch_users(&self) -> io::Result<Option<UsersMap<T>>> {
+        self.fetch_map().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::HttpUserSource;
+    use crate::{SharedUsersMap, UserAuthenticator};
+
+    #[test]
+    fn test_http_source() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/users.toml", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let bodies = ["users = [\"u p\"]\n", "", "users = []\n"];
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut conditional = false;
+                for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+                    let line = line.unwrap();
+                    conditional |= line.eq_ignore_ascii_case("if-none-match: \"v1\"");
+                    if line.is_empty() {
+                        break;
+                    }
+                }
+                let (status, body) = match i {
+                    1 if conditional => ("304 Not Modified", ""),
+                    _ => ("200 OK", bodies[i.min(2)]),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\netag: \"v1\"\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+
+        let shared = Arc::new(SharedUsersMap::default());
+        let source = HttpUserSource::toml(url, shared.clone());
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let diff = source.fetch().await.unwrap().unwrap();
+            assert_eq!(diff.added, vec!["u".to_string()]);
+            assert!(source.fetch().await.unwrap().is_none());
+            // An empty list is refused and the users are kept
+            assert!(source.fetch().await.is_err());
+        });
+        assert!(shared.auth_user_by_authstr("plaintext:u\np").is_some());
+    }
+}
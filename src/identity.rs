@@ -0,0 +1,116 @@
+/*!
+Unicode normalization and confusable detection for identities.
+
+Requires the `unicode` feature, which also makes [`PlainText::new`](crate::PlainText::new)
+and [`MapOptions::normalize_id`](crate::MapOptions::normalize_id) normalize identities to NFC.
+*/
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+/// Returns `id` in Unicode Normalization Form C, borrowing when it already is.
+///
+/// `"e\u{301}"` (e and a combining acute accent) and `"é"` become the same identity.
+pub fn nfc_identity(id: &str) -> Cow<'_, str> {
+    if is_nfc_quick(id.chars()) == IsNormalized::Yes {
+        Cow::Borrowed(id)
+    } else {
+        Cow::Owned(id.nfc().collect())
+    }
+}
+
+/// Returns `id` lowercased and in NFC, for identities compared without case.
+pub fn fold_identity(id: &str) -> String {
+    nfc_identity(id).to_lowercase().nfc().collect()
+}
+
+/// Maps a character to the Latin letter or digit it looks like, if any.
+fn latin_lookalike(c: char) -> Option<char> {
+    Some(match c {
+        // Cyrillic
+        'а' | 'А' => 'a',
+        'В' | 'в' => 'b',
+        'с' | 'С' => 'c',
+        'ԁ' => 'd',
+        'е' | 'Е' | 'ё' => 'e',
+        'һ' | 'Н' | 'н' => 'h',
+        'і' | 'І' => 'i',
+        'ј' | 'Ј' => 'j',
+        'К' | 'к' => 'k',
+        'ӏ' | 'Ӏ' => 'l',
+        'М' | 'м' => 'm',
+        'о' | 'О' => 'o',
+        'р' | 'Р' => 'p',
+        'ԛ' => 'q',
+        'ѕ' | 'Ѕ' => 's',
+        'Т' | 'т' => 't',
+        'у' | 'У' => 'y',
+        'ԝ' => 'w',
+        'х' | 'Х' => 'x',
+        // Greek
+        'α' | 'Α' => 'a',
+        'Β' | 'β' => 'b',
+        'Ε' | 'ε' => 'e',
+        'Η' => 'h',
+        'ι' | 'Ι' => 'i',
+        'Κ' | 'κ' => 'k',
+        'Μ' => 'm',
+        'Ν' | 'ν' => 'v',
+        'ο' | 'Ο' => 'o',
+        'ρ' | 'Ρ' => 'p',
+        'Τ' | 'τ' => 't',
+        'υ' | 'Υ' => 'y',
+        'χ' | 'Χ' => 'x',
+        'Ζ' => 'z',
+        // Latin and digits
+        'ı' => 'i',
+        '1' | '|' => 'l',
+        '0' => 'o',
+        _ => return None,
+    })
+}
+
+/// Returns the "skeleton" of an identity: what it looks like, ignoring case, accents,
+/// compatibility forms such as fullwidth letters, and the common Latin lookalikes
+/// of Cyrillic and Greek letters and digits.
+///
+/// Two identities with the same skeleton are likely to be mistaken for each other.
+/// This covers the usual homoglyph attacks, not the whole Unicode confusables table.
+pub fn identity_skeleton(id: &str) -> String {
+    id.nfkd()
+        .filter(|&c| !is_combining_mark(c))
+        .map(|c| latin_lookalike(c).unwrap_or(c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Checks if two different identities look alike, e.g. `admin` and `аdmin`
+/// with a Cyrillic `а`. Equal identities are not confusable.
+pub fn is_confusable(a: &str, b: &str) -> bool {
+    a != b && identity_skeleton(a) == identity_skeleton(b)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fold_identity, identity_skeleton, is_confusable, nfc_identity};
+    use alloc::borrow::Cow;
+
+    #[test]
+    fn test_identity_normalization() {
+        assert!(matches!(nfc_identity("alice"), Cow::Borrowed("alice")));
+        assert_eq!(nfc_identity("jose\u{301}"), "jos\u{e9}");
+        assert_eq!(fold_identity("JOSE\u{301}"), "jos\u{e9}");
+
+        assert_eq!(identity_skeleton("Ａdmin"), "admin");
+        assert!(is_confusable("admin", "\u{430}dmin"));
+        assert!(is_confusable("paypal", "p\u{430}yp\u{430}l"));
+        assert!(is_confusable("admin", "ADM\u{399}N"));
+        assert!(is_confusable("root", "r00t"));
+        assert!(is_confusable("bill", "bi1l"));
+        assert!(!is_confusable("admin", "admin"));
+        assert!(!is_confusable("admin", "admins"));
+    }
+}
@@ -0,0 +1,183 @@
+/*!
+A [`UserVec`] indexed by identity and auth string.
+*/
+
+use alloc::string::{String, ToString};
+use core::hash::{Hash, Hasher};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{HashMap, UserAuthenticator, UserBox, UserTrait, UserVec};
+
+/// A [`UserVec`] that keeps its order, and so its hash, with an index from identities
+/// and auth strings to positions, for lookups without a linear scan.
+///
+/// Like a `UserVec`, it may hold several users with the same identity or auth string;
+/// lookups return the first one. It serializes as its `UserVec`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "UserVec", into = "UserVec"))]
+pub struct IndexedUserVec {
+    users: UserVec,
+    by_id: HashMap<String, usize>,
+    by_auth: HashMap<String, usize>,
+}
+
+impl IndexedUserVec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a user.
+    pub fn push(&mut self, user: UserBox) {
+        let i = self.users.0.len();
+        self.by_id
+            .entry(user.identity_str().to_string())
+            .or_insert(i);
+        self.by_auth.entry(user.auth_str().to_string()).or_insert(i);
+        self.users.0.push(user);
+    }
+
+    /// Removes every user with the identity, returning the first one.
+    ///
+    /// Following users move down, so the index is rebuilt, in linear time.
+    pub fn remove_by_id(&mut self, id: &str) -> Option<UserBox> {
+        self.by_id.get(id)?;
+        let mut removed = None;
+        self.users.0.retain(|u| {
+            if u.identity_str() != id {
+                return true;
+            }
+            removed.get_or_insert_with(|| u.clone());
+            false
+        });
+        self.reindex();
+        removed
+    }
+
+    fn reindex(&mut self) {
+        self.by_id.clear();
+        self.by_auth.clear();
+        for (i, u) in self.users.0.iter().enumerate() {
+            self.by_id.entry(u.identity_str().to_string()).or_insert(i);
+            self.by_auth.entry(u.auth_str().to_string()).or_insert(i);
+        }
+    }
+
+    pub fn get_by_id(&self, id: &str) -> Option<&UserBox> {
+        self.by_id.get(id).map(|&i| &self.users.0[i])
+    }
+
+    pub fn get_by_authstr(&self, authstr: &str) -> Option<&UserBox> {
+        self.by_auth.get(authstr).map(|&i| &self.users.0[i])
+    }
+
+    /// The position of the first user with the identity
+    pub fn position_of_id(&self, id: &str) -> Option<usize> {
+        self.by_id.get(id).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.0.is_empty()
+    }
+
+    /// Iterates over the users in insertion order.
+    pub fn iter(&self) -> core::slice::Iter<'_, UserBox> {
+        self.users.0.iter()
+    }
+
+    pub fn as_user_vec(&self) -> &UserVec {
+        &self.users
+    }
+
+    pub fn into_user_vec(self) -> UserVec {
+        self.users
+    }
+}
+
+impl From<UserVec> for IndexedUserVec {
+    fn from(users: UserVec) -> Self {
+        let mut indexed = IndexedUserVec {
+            users,
+            by_id: HashMap::new(),
+            by_auth: HashMap::new(),
+        };
+        indexed.reindex();
+        indexed
+    }
+}
+
+impl From<IndexedUserVec> for UserVec {
+    fn from(indexed: IndexedUserVec) -> Self {
+        indexed.users
+    }
+}
+
+impl FromIterator<UserBox> for IndexedUserVec {
+    fn from_iter<I: IntoIterator<Item = UserBox>>(iter: I) -> Self {
+        UserVec::from_iter(iter).into()
+    }
+}
+
+/// Compares the users, in order, like [`UserVec`].
+impl PartialEq for IndexedUserVec {
+    fn eq(&self, other: &Self) -> bool {
+        self.users == other.users
+    }
+}
+
+impl Eq for IndexedUserVec {}
+
+/// Hashes like the [`UserVec`], so that it depends on the order.
+impl Hash for IndexedUserVec {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.users.hash(state);
+    }
+}
+
+impl UserAuthenticator<UserBox> for IndexedUserVec {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<UserBox> {
+        self.get_by_authstr(authstr).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IndexedUserVec;
+    use crate::{PlainText, UserAuthenticator, UserBox, UserTrait, UserVec};
+
+    #[test]
+    fn test_indexed_user_vec() {
+        let mut users: IndexedUserVec = UserVec::from(vec![
+            PlainText::from("a 1"),
+            PlainText::from("b 2"),
+            PlainText::from("a 3"),
+        ])
+        .into();
+        users.push(UserBox::new(PlainText::from("c 4")));
+
+        assert_eq!(users.get_by_id("a").unwrap().auth_str(), "plaintext:a\n1");
+        assert_eq!(users.position_of_id("c"), Some(3));
+        assert!(users.auth_user_by_authstr("plaintext:a\n3").is_some());
+
+        let removed = users.remove_by_id("a").unwrap();
+        assert_eq!(removed.auth_str(), "plaintext:a\n1");
+        assert!(users.get_by_authstr("plaintext:a\n3").is_none());
+        assert_eq!(users.position_of_id("c"), Some(1));
+        assert!(users.remove_by_id("a").is_none());
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&users).unwrap();
+            assert_eq!(json, serde_json::to_string(users.as_user_vec()).unwrap());
+            let users2: IndexedUserVec = serde_json::from_str(&json).unwrap();
+            assert_eq!(users2, users);
+            assert_eq!(users2.get_by_id("b").unwrap().identity_str(), "b");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+/*!
+Sharing one allocation per identity string across the structures keyed by identity.
+*/
+
+use std::collections::HashSet;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// A set of identity strings handed out as `Arc<str>`, so that the maps, sessions,
+/// traffic counters and audit events of an identity can share a single string.
+///
+/// Give the same interner to [`UsersMap::set_interner`](crate::UsersMap::set_interner),
+/// [`SessionManager::with_interner`](crate::SessionManager::with_interner),
+/// [`TrafficAccountant::with_interner`](crate::TrafficAccountant::with_interner) and
+/// [`AuditingAuthenticator::with_interner`](crate::AuditingAuthenticator::with_interner).
+///
+/// Strings stay in the interner after every other user dropped them; call
+/// [`IdInterner::purge`] now and then, e.g. after reloading the users.
+#[derive(Debug, Default)]
+pub struct IdInterner {
+    ids: RwLock<HashSet<Arc<str>>>,
+}
+
+impl IdInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared string equal to `id`, adding it if it is new.
+    pub fn intern(&self, id: &str) -> Arc<str> {
+        if let Some(id) = self.get(id) {
+            return id;
+        }
+        let mut ids = self.ids.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(id) = ids.get(id) {
+            return Arc::clone(id);
+        }
+        let id: Arc<str> = id.into();
+        ids.insert(Arc::clone(&id));
+        id
+    }
+
+    /// Returns the shared string equal to `id`, if it was interned.
+    pub fn get(&self, id: &str) -> Option<Arc<str>> {
+        self.ids
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .map(Arc::clone)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the strings that only the interner still holds, returning how many were dropped.
+    pub fn purge(&self) -> usize {
+        let mut ids = self.ids.write().unwrap_or_else(PoisonError::into_inner);
+        let before = ids.len();
+        ids.retain(|id| Arc::strong_count(id) > 1);
+        before - ids.len()
+    }
+}
+
+/// Interns `id` if there is an interner, allocates it otherwise.
+pub(crate) fn intern_with(interner: Option<&IdInterner>, id: &str) -> Arc<str> {
+    match interner {
+        Some(interner) => interner.intern(id),
+        None => id.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::IdInterner;
+    use crate::{PlainText, SessionManager, SessionPolicy, TrafficAccountant, UsersMap};
+
+    #[test]
+    fn test_interner_shares_ids() {
+        let interner = Arc::new(IdInterner::new());
+        let mut um = UsersMap::new();
+        um.set_interner(Some(Arc::clone(&interner)));
+        um.add_user(PlainText::from("alice p"));
+        let traffic = TrafficAccountant::new().with_interner(Arc::clone(&interner));
+        traffic.add_upload("alice", 10);
+        let sessions =
+            SessionManager::new(SessionPolicy::default()).with_interner(Arc::clone(&interner));
+        sessions.create(PlainText::from("alice p"));
+
+        assert_eq!(interner.len(), 1);
+        // The map, the accountant, the session manager and the handle below
+        let id = interner.get("alice").unwrap();
+        assert_eq!(Arc::strong_count(&id), 5);
+
+        drop(id);
+        um.remove_user("alice");
+        assert_eq!(interner.purge(), 0);
+        traffic.clear();
+        sessions.revoke_identity("alice");
+        assert_eq!(interner.purge(), 1);
+        assert!(interner.is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+/*!
+Authentication against an LDAP directory, e.g. Active Directory.
+
+Requires the `ldap` feature.
+*/
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ldap3::{dn_escape, LdapConnAsync, LdapConnSettings};
+
+use crate::{AsyncUserAuthenticator, AuthError, PlainText, SharedUsersMap, UserAuthenticator};
+
+/// LDAP result code of a bind with a wrong DN or password
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Authenticates a username and password by binding with them to an LDAP server,
+/// so that proxy accounts are the directory's accounts.
+///
+/// The bind DN is made from a template where `{user}` is replaced by the escaped
+/// username, e.g. `uid={user},ou=people,dc=example,dc=com`, or `{user}@corp.example.com`
+/// for Active Directory. On success a [`PlainText`] user is returned.
+///
+/// With [`LdapAuthenticator::with_cache`], users who bound successfully are added to
+/// a local map that is tried first, so that they don't cost a bind per connection.
+/// Cached users stay valid until removed from the map, even if their directory
+/// password changes; wrap the authenticator in a
+/// [`CachedAuthenticator`](crate::CachedAuthenticator) instead for answers that expire.
+#[derive(Debug)]
+pub struct LdapAuthenticator {
+    url: String,
+    dn_template: String,
+    timeout: Duration,
+    cache: Option<Arc<SharedUsersMap<PlainText>>>,
+}
+
+impl LdapAuthenticator {
+    /// `url` is e.g. `ldaps://ldap.example.com`.
+    pub fn new(url: impl Into<String>, dn_template: impl Into<String>) -> Self {
+        LdapAuthenticator {
+            url: url.into(),
+            dn_template: dn_template.into(),
+            timeout: Duration::from_secs(5),
+            cache: None,
+        }
+    }
+
+    /// Sets how long to wait for the server to accept the connection, 5s by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Arc<SharedUsersMap<PlainText>>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn cache(&self) -> Option<&Arc<SharedUsersMap<PlainText>>> {
+        self.cache.as_ref()
+    }
+
+    /// Returns the DN to bind as for `user`.
+    pub fn bind_dn(&self, user: &str) -> String {
+        self.dn_template.replace("{user}", &dn_escape(user))
+    }
+
+    /// Binds as `user` with `pass`.
+    ///
+    /// An empty password is refused without asking the server, as LDAP treats a bind
+    /// with one as an anonymous bind, which succeeds. Unreachable servers and other
+    /// failures are reported as [`AuthError::Backend`].
+    pub async fn authenticate(&self, user: &str, pass: &str) -> Result<PlainText, AuthError> {
+        if user.is_empty() || pass.is_empty() {
+            return Err(AuthError::BadCredential);
+        }
+        let candidate = PlainText::new(user.to_string(), pass.to_string());
+        if let Some(cache) = &self.cache {
+            if let Ok(user) = UserAuthenticator::try_auth(cache, candidate.auth_str()) {
+                return Ok(user);
+            }
+        }
+
+        let settings = LdapConnSettings::new().set_conn_timeout(self.timeout);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.url)
+            .await
+            .map_err(AuthError::backend)?;
+        ldap3::drive!(conn);
+        let result = ldap
+            .simple_bind(&self.bind_dn(user), pass)
+            .await
+            .map_err(AuthError::backend)?;
+        let _ = ldap.unbind().await;
+        match result.rc {
+            0 => {}
+            INVALID_CREDENTIALS => return Err(AuthError::BadCredential),
+            _ => return Err(AuthError::backend(result)),
+        }
+
+        if let Some(cache) = &self.cache {
+            cache.add_user(candidate.clone());
+        }
+        Ok(candidate)
+    }
+}
+
+#[async_trait]
+impl AsyncUserAuthenticator<PlainText> for LdapAuthenticator {
+    async fn auth_user_by_authstr(&self, authstr: &str) -> Option<PlainText> {
+        AsyncUserAuthenticator::try_auth(self, authstr).await.ok()
+    }
+
+    /// Takes the auth string of a [`PlainText`] user, `plaintext:{user}\n{pass}`.
+    async fn try_auth(&self, authstr: &str) -> Result<PlainText, AuthError> {
+        let (user, pass) = authstr
+            .strip_prefix("plaintext:")
+            .and_then(|s| s.split_once('\n'))
+            .ok_or(AuthError::UnknownUser)?;
+        self.authenticate(user, pass).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::LdapAuthenticator;
+    use crate::{AsyncUserAuthenticator, AuthError, PlainText, SharedUsersMap};
+
+    #[test]
+    fn test_ldap_without_server() {
+        let cache = Arc::new(SharedUsersMap::default());
+        cache.add_user(PlainText::from("alice secret"));
+        let auth = LdapAuthenticator::new("ldap://127.0.0.1:1", "uid={user},ou=people,dc=example")
+            .with_cache(cache);
+        assert_eq!(
+            auth.bind_dn("a,b=c"),
+            "uid=a\\2cb\\3dc,ou=people,dc=example"
+        );
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            assert!(matches!(
+                auth.authenticate("alice", "").await,
+                Err(AuthError::BadCredential)
+            ));
+            let cached = AsyncUserAuthenticator::try_auth(&auth, "plaintext:alice\nsecret").await;
+            assert_eq!(cached.unwrap().user, "alice");
+            assert!(matches!(
+                auth.authenticate("alice", "other").await,
+                Err(AuthError::Backend(_))
+            ));
+        });
+    }
+}
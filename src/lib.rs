@@ -1,20 +1,343 @@
 /*!
 Provides basic traits and helper structures for user authentication.
+
+Without the default `std` feature, the crate is `no_std` and only needs `alloc`:
+the core traits, [`PlainText`], [`UserBox`], [`UserVec`] and a [`UsersMap`] backed by
+`hashbrown` remain; the other modules require `std`.
 */
 
-use std::collections::HashMap;
-use std::fmt::Debug;
-use std::sync::Arc;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::collections::{hash_map::RandomState, HashMap, HashSet};
+
+#[cfg(feature = "dyn-clone")]
 use dyn_clone::DynClone;
+#[cfg(not(feature = "std"))]
+use hashbrown::{DefaultHashBuilder as RandomState, HashMap, HashSet};
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use std::hash::Hash;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+#[cfg(feature = "derive")]
+pub use user_trait_derive::UserTrait;
+
+// Lets `#[derive(UserTrait)]` name this crate in its own tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as user_trait;
+
+/// Implements [`UserTrait`] for a concrete instantiation of a generic wrapper holding the
+/// wrapped user in its `user` field. typetag cannot register generic impls, so every
+/// supported instantiation needs its own serialization name.
+#[cfg(feature = "std")]
+macro_rules! impl_wrapper_user_trait {
+    ($ty:ty, $name:literal) => {
+        #[cfg_attr(feature = "serde", typetag::serde(name = $name))]
+        impl $crate::UserTrait for $ty {
+            fn identity_str(&self) -> &str {
+                self.user.identity_str()
+            }
+
+            fn identity_bytes(&self) -> &[u8] {
+                self.user.identity_bytes()
+            }
+
+            fn auth_str(&self) -> &str {
+                self.user.auth_str()
+            }
+
+            fn auth_bytes(&self) -> &[u8] {
+                self.user.auth_bytes()
+            }
+
+            fn previous_auth_str(&self) -> Option<&str> {
+                self.user.previous_auth_str()
+            }
+
+            fn fingerprint(&self) -> [u8; 32] {
+                self.user.fingerprint()
+            }
+
+            fn auth_eq(&self, other: &[u8]) -> bool {
+                self.user.auth_eq(other)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "std")]
+mod async_auth;
+#[cfg(feature = "std")]
+mod async_store;
+#[cfg(feature = "std")]
+mod audit;
+mod auth_str;
+#[cfg(feature = "dyn-clone")]
+mod boxed;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(feature = "std")]
+mod chain;
+#[cfg(feature = "std")]
+mod challenge;
+#[cfg(feature = "std")]
+mod clock;
+#[cfg(feature = "std")]
+mod context;
+mod diff;
+#[cfg(feature = "dyn-clone")]
+mod dyn_auth;
+#[cfg(feature = "encryption")]
+mod encrypted;
+pub mod entry;
+mod error;
+#[cfg(feature = "std")]
+mod expiring;
+#[cfg(feature = "std")]
+mod expiry;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod generation;
+mod groups;
+#[cfg(feature = "htpasswd")]
+mod htpasswd;
+#[cfg(feature = "http")]
+mod http_source;
+#[cfg(feature = "unicode")]
+mod identity;
+#[cfg(feature = "dyn-clone")]
+mod indexed;
+#[cfg(feature = "std")]
+mod interner;
+#[cfg(feature = "ldap")]
+mod ldap;
+#[cfg(feature = "std")]
+mod load;
+#[cfg(feature = "std")]
+mod lockout;
+#[cfg(feature = "std")]
+mod lru;
+#[cfg(feature = "tokio")]
+mod manager;
+mod map;
+#[cfg(feature = "std")]
+mod meta;
+#[cfg(feature = "std")]
+mod negative;
+#[cfg(feature = "std")]
+mod network;
+#[cfg(feature = "keyring")]
+mod os_keyring;
+#[cfg(feature = "serde")]
+mod persist;
+pub mod prelude;
+#[cfg(feature = "proxy-auth")]
+mod proxy_auth;
+#[cfg(feature = "std")]
+mod quota;
+#[cfg(feature = "std")]
+mod ratelimit;
+#[cfg(feature = "redis")]
+mod redis_store;
+#[cfg(feature = "std")]
+mod refresh;
+#[cfg(feature = "std")]
+mod roles;
+#[cfg(feature = "std")]
+mod rotation;
+#[cfg(feature = "dyn-clone")]
+mod scheme;
+mod secret;
+#[cfg(feature = "std")]
+mod session;
+#[cfg(feature = "std")]
+mod shadowsocks;
+#[cfg(feature = "std")]
+mod sharded;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "signing")]
+mod signed;
+#[cfg(feature = "arc-swap")]
+mod snapshot;
+#[cfg(feature = "std")]
+mod socks5;
+#[cfg(feature = "sqlx")]
+mod sql_store;
+#[cfg(feature = "sqlite")]
+mod sqlite_store;
+#[cfg(feature = "std")]
+mod stats;
+mod store;
+#[cfg(feature = "std")]
+mod throttle;
+mod user_ref;
+#[cfg(feature = "std")]
+mod v2ray;
+#[cfg(feature = "std")]
+mod verify;
+#[cfg(feature = "vmess")]
+mod vmess;
+#[cfg(feature = "notify")]
+mod watch;
+#[cfg(feature = "webhook")]
+mod webhook;
+
+#[cfg(feature = "std")]
+pub use async_auth::AsyncUserAuthenticator;
+#[cfg(feature = "std")]
+pub use async_store::{AsyncUserStore, UserStream};
+#[cfg(feature = "serde")]
+pub use audit::JsonLinesAuditSink;
+#[cfg(feature = "std")]
+pub use audit::{AuditEvent, AuditOutcome, AuditSink, AuditingAuthenticator, MemoryAuditSink};
+pub use auth_str::{AuthFormat, AuthStr, AuthStrError, UserPass};
+#[cfg(feature = "dyn-clone")]
+pub use boxed::{UserBox, UserVec};
+#[cfg(feature = "std")]
+pub use builder::{BuildUserError, PlainTextParts, ProfiledUser, UserBuilder};
+#[cfg(feature = "std")]
+pub use cache::CachedAuthenticator;
+#[cfg(feature = "std")]
+pub use chain::{ChainAuthenticator, ChainError};
+#[cfg(feature = "std")]
+pub use challenge::{
+    Challenge, ChallengeAuthenticator, ChallengeUser, MapChallengeAuthenticator, PendingChallenges,
+};
+#[cfg(feature = "std")]
+pub use clock::{Clock, ManualClock, SystemClock};
+#[cfg(feature = "std")]
+pub use context::{AuthContext, ContextAuthenticator};
+pub use diff::UsersDiff;
+#[cfg(feature = "dyn-clone")]
+pub use dyn_auth::{DynAdapter, DynAuthenticator};
+#[cfg(feature = "encryption")]
+pub use encrypted::FileKey;
+#[cfg(feature = "std")]
+pub use error::LoadError;
+pub use error::{AuthError, DuplicateIdentity, Error, ParseUserError, Result, StoreError};
+#[cfg(feature = "std")]
+pub use expiring::ExpiringUsersMap;
+#[cfg(feature = "std")]
+pub use expiry::UserWithExpiry;
+pub use generation::GenerationReceiver;
+pub use groups::GroupsMap;
+#[cfg(feature = "htpasswd")]
+pub use htpasswd::{load_htpasswd_path, parse_htpasswd, write_htpasswd, HashScheme, HtpasswdUser};
+#[cfg(feature = "http")]
+pub use http_source::{HttpUserSource, UsersBytesLoader, UsersValidator};
+#[cfg(feature = "unicode")]
+pub use identity::{fold_identity, identity_skeleton, is_confusable, nfc_identity};
+#[cfg(feature = "dyn-clone")]
+pub use indexed::IndexedUserVec;
+#[cfg(feature = "std")]
+pub use interner::IdInterner;
+#[cfg(feature = "ldap")]
+pub use ldap::LdapAuthenticator;
+#[cfg(feature = "mmap")]
+pub use load::load_users_mmap;
+#[cfg(feature = "serde")]
+pub use load::{load_users_from_toml, load_users_from_toml_path};
+#[cfg(feature = "yaml")]
+pub use load::{load_users_from_yaml, load_users_from_yaml_path};
+#[cfg(feature = "std")]
+pub use lockout::{LockoutPolicy, LockoutTracker};
+#[cfg(feature = "std")]
+pub use lru::{CacheStats, LruUsersCache};
+#[cfg(feature = "tokio")]
+pub use manager::UserManager;
+#[cfg(feature = "foldhash")]
+pub use map::FastUsersMap;
+pub use map::{MapOptions, UsersMap};
+#[cfg(feature = "std")]
+pub use meta::{MetaUser, UserWithMeta};
+#[cfg(feature = "std")]
+pub use negative::NegativeFilter;
+#[cfg(feature = "std")]
+pub use network::{
+    authorize_source, Cidr, CidrParseError, NetworkPolicyAuthenticator, NetworkUser,
+    UserWithNetworkPolicy,
+};
+#[cfg(feature = "keyring")]
+pub use os_keyring::{keyring_secret, set_keyring_secret};
+#[cfg(feature = "serde")]
+pub use persist::{FileStorage, MemoryStorage, UsersStorage};
+#[cfg(feature = "proxy-auth")]
+pub use proxy_auth::{
+    authenticate_proxy_authorization, parse_proxy_authorization, proxy_auth_required,
+    proxy_authenticate, Credential, ProxyAuthError,
+};
+#[cfg(feature = "std")]
+pub use quota::{TrafficAccountant, TrafficCounter, TrafficUsage, UserWithQuota};
+#[cfg(feature = "std")]
+pub use ratelimit::{ConnGuard, ConnLimiter, LimitExceeded, UserWithRateLimit};
+#[cfg(feature = "redis")]
+pub use redis_store::RedisUserStore;
+#[cfg(feature = "tokio")]
+pub use refresh::spawn_refresh_task;
+#[cfg(feature = "std")]
+pub use refresh::UserSource;
+#[cfg(feature = "std")]
+pub use roles::{RolePolicy, RoledUser, UserWithRoles};
+#[cfg(feature = "std")]
+pub use rotation::{RetiredCredential, RotatingUser};
+#[cfg(feature = "dyn-clone")]
+pub use scheme::AuthSchemeRegistry;
+#[cfg(feature = "std")]
+pub use secret::AuthCache;
+pub use secret::{Redacted, SecretString};
+#[cfg(feature = "std")]
+pub use session::{Session, SessionId, SessionManager, SessionPolicy};
+#[cfg(feature = "std")]
+pub use shadowsocks::SsUser;
+#[cfg(feature = "serde")]
+pub use shadowsocks::{load_shadowsocks_config, load_shadowsocks_config_path};
+#[cfg(feature = "std")]
+pub use sharded::ShardedUsersMap;
+#[cfg(feature = "std")]
+pub use shared::SharedUsersMap;
+#[cfg(feature = "tokio")]
+pub use shared::UsersUpdate;
+#[cfg(feature = "signing")]
+pub use signed::{signature_path, verify_signed};
+#[cfg(feature = "arc-swap")]
+pub use snapshot::SnapshotAuthenticator;
+#[cfg(feature = "std")]
+pub use socks5::{Socks5AuthNegotiator, Socks5Error, Socks5Step};
+#[cfg(feature = "sqlx")]
+pub use sql_store::SqlUserStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite_store::SqliteUserStore;
+#[cfg(feature = "std")]
+pub use stats::MapStats;
+pub use store::UserStore;
+#[cfg(feature = "std")]
+pub use throttle::{ThrottlePolicy, ThrottledAuthenticator};
+pub use user_ref::UserRef;
+#[cfg(feature = "serde")]
+pub use v2ray::{load_v2ray_clients, load_v2ray_clients_path};
+#[cfg(feature = "std")]
+pub use v2ray::{UuidIndex, UuidParseError, UuidUser};
+#[cfg(feature = "std")]
+pub use verify::{EqualizedAuthenticator, SecretAuthenticator, VerifyUser, VerifyingAuthenticator};
+#[cfg(feature = "notify")]
+pub use watch::{FileUserSource, FileWatch, UsersFileLoader};
+#[cfg(feature = "webhook")]
+pub use webhook::{WebhookAuthenticator, WebhookUser};
 
 /// Trait for user authentication.
 ///
 /// This trait defines the necessary methods for user identification and authentication.
 /// Implementations should ensure that each user can be uniquely identified and authenticated.
-#[typetag::serde]
+#[cfg_attr(feature = "serde", typetag::serde)]
 pub trait UserTrait: Debug + Send + Sync {
     /// Returns a unique string identifier for the user.
     /// This is equivalent to a username and is used in non-sensitive environments.
@@ -32,100 +355,137 @@ pub trait UserTrait: Debug + Send + Sync {
     /// Returns a byte slice used for authenticating the user.
     /// This can be the same as `auth_str` or different, depending on implementation.
     fn auth_bytes(&self) -> &[u8];
+
+    /// Returns a replaced auth string that is still accepted, e.g. during the grace window
+    /// of a [`RotatingUser`]. [`UsersMap`] indexes it next to `auth_str` and refuses it
+    /// once this returns `None`.
+    fn previous_auth_str(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns a SHA-256 digest of `auth_bytes`, which refers to the user in logs, metrics
+    /// or replication without exposing the credential. Unlike [`Hash`], it is stable
+    /// across Rust versions and processes.
+    fn fingerprint(&self) -> [u8; 32] {
+        Sha256::digest(self.auth_bytes()).into()
+    }
+
+    /// Returns true if `other` equals `auth_bytes`. Verification paths use it instead of
+    /// `==`, which returns at the first differing byte and so leaks through timing how
+    /// much of a guess is right.
+    ///
+    /// The default implementation compares in constant time with [`subtle`]; only the
+    /// lengths may leak.
+    fn auth_eq(&self, other: &[u8]) -> bool {
+        self.auth_bytes().ct_eq(other).into()
+    }
+
+    /// Returns a form of the user that is safe to display, e.g. `alice:✱✱✱`.
+    fn redacted(&self) -> Redacted<'_> {
+        Redacted(self.identity_str())
+    }
+
+    /// Returns the scheme of `auth_str`, e.g. `plaintext`, if it has the
+    /// `scheme:payload` form, see [`AuthStr`].
+    fn auth_scheme(&self) -> Option<&str> {
+        AuthStr::parse(self.auth_str()).ok().map(|a| a.scheme())
+    }
 }
 
 /// A cloneable [`UserTrait`].
 ///
 /// Note: Using `DynClone` allows for cloning of trait objects, which is not possible with `Clone` alone.
+#[cfg(feature = "dyn-clone")]
 pub trait User: UserTrait + DynClone {}
 
 /// Implements User trait for any type that implements UserTrait and DynClone
+#[cfg(feature = "dyn-clone")]
 impl<T: UserTrait + DynClone> User for T {}
 
 // Enables cloning of User trait objects
+#[cfg(feature = "dyn-clone")]
 dyn_clone::clone_trait_object!(User);
 
-/// A wrapper for a boxed user implementing the `User` trait.
-///
-/// This struct provides implementations for `Debug`, `Hash`, `PartialOrd`, `Ord`, `PartialEq`, and `Eq`.
-#[derive(Clone)]
-pub struct UserBox(pub Box<dyn User>);
+/// A cloneable [`UserTrait`]. Without the `dyn-clone` feature, users can't be cloned
+/// behind a `dyn User`, so there is no [`UserBox`].
+#[cfg(not(feature = "dyn-clone"))]
+pub trait User: UserTrait {}
 
-impl Debug for UserBox {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("UserBox").field(&self.0.auth_str()).finish()
-    }
-}
-
-impl Hash for UserBox {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.0.auth_str().hash(state);
-    }
-}
+#[cfg(not(feature = "dyn-clone"))]
+impl<T: UserTrait + Clone> User for T {}
 
-impl PartialOrd for UserBox {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
+/// Shown instead of credentials in `Debug` output
+pub(crate) const REDACTED: core::fmt::Arguments<'static> = format_args!("<redacted>");
 
-impl Ord for UserBox {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.auth_str().cmp(&other.0.auth_str())
-    }
-}
+/// Implements `Debug` with a closure, for the `debug_unredacted` methods.
+pub(crate) struct DebugWith<F>(pub(crate) F);
 
-impl PartialEq for UserBox {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.auth_str() == other.0.auth_str()
+impl<F: Fn(&mut core::fmt::Formatter<'_>) -> core::fmt::Result> Debug for DebugWith<F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        (self.0)(f)
     }
 }
 
-impl Eq for UserBox {}
-
-/// A Vec of `UserBox` with additional functionality.
-///
-/// This struct provides a method to  hash the set, ensuring that
-/// the hash value is different for different orders within the vec.
+/// Trait for asynchronous user authentication.
 ///
-/// This is intended. As different orders are considered to be important in the UserVec's case.
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
-pub struct UserVec(pub Vec<UserBox>);
+/// This trait defines a method for authenticating users based on an authentication string.
+pub trait UserAuthenticator<T: User> {
+    /// Authenticates a user using the provided authentication string.
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T>;
 
-impl Hash for UserVec {
-    /// hashes its contents with the order of the vec.
+    /// Like [`UserAuthenticator::auth_user_by_authstr`], telling why authentication failed.
     ///
-    /// This ensures that the hash value is not the same for different orders of different users.
+    /// The default implementation reports every failure as [`AuthError::UnknownUser`];
+    /// implementations that know more override it.
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.auth_user_by_authstr(authstr)
+            .ok_or(AuthError::UnknownUser)
+    }
+
+    /// Like [`UserAuthenticator::try_auth`], for protocols sending the claimed identity
+    /// apart from the secret, e.g. SOCKS5 or HTTP Basic, so that failures can be counted
+    /// against it even if the user is unknown.
     ///
-    /// The hash is different if the content is the same but with an different order.
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.0.iter().for_each(|b| {
-            b.hash(state);
-        })
+    /// The default implementation ignores `identity`.
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        let _ = identity;
+        self.try_auth(authstr)
     }
 }
 
-/// Trait for asynchronous user authentication.
-///
-/// This trait defines a method for authenticating users based on an authentication string.
-pub trait UserAuthenticator<T: User> {
-    /// Authenticates a user using the provided authentication string.
-    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T>;
+/// Lets an authenticator shared with other tasks, e.g. an `Arc<SharedUsersMap<T>>`
+/// that a reload task updates, be wrapped by caches and chains.
+impl<T: User, A: UserAuthenticator<T> + ?Sized> UserAuthenticator<T> for Arc<A> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.as_ref().auth_user_by_authstr(authstr)
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.as_ref().try_auth(authstr)
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        self.as_ref().try_auth_as(identity, authstr)
+    }
 }
 
 /// A simple implementation of a user with plaintext username and password.
 ///
 /// This struct provides methods for creating and validating plaintext users.
-#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+/// Its `Debug` output doesn't show the password, see [`PlainText::debug_unredacted`].
+#[derive(Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PlainText {
     pub user: String,
 
     /// The field is intended to be stored in plaintext.
     /// If the user wants more security, they should have a custom struct
     /// that implements the [`UserTrait`] trait.
+    ///
+    /// With the `zeroize` feature, it is cleared when the user is dropped.
     pub pass: String,
 
-    auth_str: String,
+    auth_str: Box<str>,
 }
 
 impl From<&str> for PlainText {
@@ -148,17 +508,61 @@ impl From<&str> for PlainText {
     }
 }
 
+/// Parses `"user pass"` like `From<&str>`, but refuses an empty user name or password
+/// and control characters. Whitespace around the password is kept.
+impl core::str::FromStr for PlainText {
+    type Err = ParseUserError;
+
+    fn from_str(userpass: &str) -> Result<Self, Self::Err> {
+        let (user, pass) = userpass
+            .split_once(char::is_whitespace)
+            .unwrap_or((userpass, ""));
+        if user.is_empty() {
+            return Err(ParseUserError::EmptyIdentity);
+        }
+        if pass.is_empty() {
+            return Err(ParseUserError::MissingPassword);
+        }
+        if let Some(ch) = user.chars().chain(pass.chars()).find(|c| c.is_control()) {
+            return Err(ParseUserError::IllegalCharacter(ch));
+        }
+        Ok(PlainText::new(user.to_string(), pass.to_string()))
+    }
+}
+
 impl PlainText {
     /// Creates a new `PlainText` user with the specified username and password.
     ///
     /// The authentication string is formatted as "plaintext:{user}\n{pass}".
+    ///
+    /// With the `unicode` feature, the username is normalized to NFC first.
     pub fn new(user: String, pass: String) -> Self {
-        let astr = format!("plaintext:{}\n{}", user, pass);
+        #[cfg(feature = "unicode")]
+        let user = match identity::nfc_identity(&user) {
+            alloc::borrow::Cow::Borrowed(_) => user,
+            alloc::borrow::Cow::Owned(nfc) => nfc,
+        };
+        let astr = alloc::format!("plaintext:{}\n{}", user, pass);
         PlainText {
             user,
             pass,
-            auth_str: astr,
+            auth_str: astr.into(),
+        }
+    }
+
+    /// Creates a user whose authentication string is encoded in `format`, e.g.
+    /// [`AuthFormat::V2`] for user names that may contain newlines.
+    pub fn with_format(user: String, pass: String, format: AuthFormat) -> Self {
+        let mut plain = PlainText::new(user, pass);
+        if format != AuthFormat::Legacy {
+            plain.auth_str = format.encode("plaintext", &plain.user, &plain.pass).into();
         }
+        plain
+    }
+
+    /// The format of the authentication string
+    pub fn auth_format(&self) -> AuthFormat {
+        AuthFormat::decode(&self.auth_str).map_or(AuthFormat::Legacy, |d| d.format)
     }
 
     /// Checks if the user is valid by ensuring the username is not empty.
@@ -173,95 +577,123 @@ impl PlainText {
 
     /// Returns the authentication string for the user.
     pub fn auth_str(&self) -> &str {
-        self.auth_str.as_str()
-    }
-}
-
-#[typetag::serde]
-impl UserTrait for PlainText {
-    fn identity_str(&self) -> &str {
-        self.user.as_str()
+        &self.auth_str
     }
 
-    fn identity_bytes(&self) -> &[u8] {
-        self.user.as_bytes()
+    /// Formats the user with its password, unlike `Debug`. Keep it out of logs.
+    pub fn debug_unredacted(&self) -> impl Debug + '_ {
+        DebugWith(|f: &mut core::fmt::Formatter<'_>| {
+            f.debug_struct("PlainText")
+                .field("user", &self.user)
+                .field("pass", &self.pass)
+                .finish()
+        })
     }
+}
 
-    fn auth_str(&self) -> &str {
-        self.auth_str.as_str()
+/// Orders by user name, then password.
+impl Ord for PlainText {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (&self.user, &self.pass, &self.auth_str).cmp(&(&other.user, &other.pass, &other.auth_str))
     }
+}
 
-    fn auth_bytes(&self) -> &[u8] {
-        self.auth_str.as_bytes()
+impl PartialOrd for PlainText {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-/// A map structure that stores users with both identity and authentication mappings.
-#[derive(Debug, Clone, Default)]
-pub struct UsersMap<T: UserTrait + Clone> {
-    /// Maps user identity strings to user instances
-    id_map: HashMap<String, Arc<T>>,
-
-    /// Maps user authentication strings to user instances
-    /// Note that the keys for auth_map are different from keys for id_map.
-    auth_map: HashMap<String, Arc<T>>,
+/// Shows the user name.
+impl core::fmt::Display for PlainText {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.user)
+    }
 }
 
-impl<T: UserTrait + Clone> UsersMap<T> {
-    /// Adds a new user to both id_map and auth_map
-    pub fn add_user(&mut self, user: T) {
-        let user = Arc::new(user);
-
-        self.id_map
-            .insert(user.identity_str().to_string(), Arc::clone(&user));
-        self.auth_map
-            .insert(user.auth_str().to_string(), Arc::clone(&user));
+impl Debug for PlainText {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PlainText")
+            .field("user", &self.user)
+            .field("pass", &REDACTED)
+            .finish()
     }
+}
 
-    /// Removes a user from both maps using their identity string
-    pub fn remove_user(&mut self, id: &str) {
-        if let Some(user) = self.id_map.remove(id) {
-            self.auth_map.remove(&user.auth_str().to_string());
-        }
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl UserTrait for PlainText {
+    fn identity_str(&self) -> &str {
+        self.user.as_str()
     }
 
-    pub fn len(&self) -> usize {
-        debug_assert_eq!(self.id_map.len(), self.auth_map.len());
-        self.id_map.len()
+    fn identity_bytes(&self) -> &[u8] {
+        self.user.as_bytes()
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.id_map.is_empty()
+    fn auth_str(&self) -> &str {
+        &self.auth_str
     }
 
-    /// Retrieves a user by their identity string
-    pub fn get_user(&self, id: &str) -> Option<Arc<T>> {
-        self.id_map.get(id).map(Arc::clone)
+    fn auth_bytes(&self) -> &[u8] {
+        self.auth_str.as_bytes()
     }
+}
 
-    /// Retrieves a user by their authentication string
-    pub fn get_user_by_authstr(&self, authstr: &str) -> Option<Arc<T>> {
-        self.auth_map.get(authstr).map(Arc::clone)
+/// Clears the password and the auth string, which contains it.
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for PlainText {
+    fn zeroize(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.pass);
+        zeroize::Zeroize::zeroize(&mut self.auth_str);
     }
 }
 
-/// Implementation of UserAuthenticator trait for UsersMap
-impl<T: UserTrait + Clone> UserAuthenticator<T> for UsersMap<T> {
-    /// Authenticates a user by their authentication string and returns a clone if found
-    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
-        self.auth_map
-            .get(authstr)
-            .map(|arc_user| Arc::clone(arc_user).as_ref().clone())
+#[cfg(feature = "zeroize")]
+impl Drop for PlainText {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(self);
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for PlainText {}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
 
+    #[cfg(feature = "derive")]
+    use serde::{Deserialize, Serialize};
+
     use super::PlainText;
+    #[cfg(all(feature = "derive", feature = "dyn-clone"))]
+    use crate::UserBox;
+    #[cfg(feature = "derive")]
+    use crate::UserTrait;
     use crate::{UserAuthenticator, UsersMap};
 
+    #[test]
+    fn test_from_str() {
+        use crate::ParseUserError;
+
+        let u: PlainText = "u\tp q".parse().unwrap();
+        assert_eq!((u.user.as_str(), u.pass.as_str()), ("u", "p q"));
+        assert_eq!(
+            "u".parse::<PlainText>(),
+            Err(ParseUserError::MissingPassword)
+        );
+        assert_eq!(
+            " p".parse::<PlainText>(),
+            Err(ParseUserError::EmptyIdentity)
+        );
+        assert_eq!(
+            "u p\nq".parse::<PlainText>(),
+            Err(ParseUserError::IllegalCharacter('\n'))
+        );
+        // Still accepted by `From`
+        assert_eq!(PlainText::from("u").pass, "");
+    }
+
     #[test]
     fn test_hashmap() {
         let mut map = HashMap::new();
@@ -294,4 +726,59 @@ mod test {
 
         Ok(())
     }
+
+    #[cfg(feature = "derive")]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, UserTrait)]
+    #[user_trait(name = "TestDerivedUser")]
+    struct DerivedUser {
+        #[identity]
+        name: String,
+
+        #[auth(prefix = "derived", identity)]
+        pass: crate::SecretString,
+
+        #[auth_cache]
+        #[serde(skip)]
+        auth: crate::AuthCache,
+    }
+
+    #[cfg(feature = "derive")]
+    #[derive(Debug, Clone, Serialize, Deserialize, UserTrait)]
+    struct DerivedToken {
+        #[identity]
+        name: String,
+
+        #[auth]
+        token: String,
+    }
+
+    #[cfg(all(feature = "derive", feature = "dyn-clone"))]
+    #[test]
+    fn test_derive() -> Result<(), Box<dyn std::error::Error>> {
+        let user = DerivedUser {
+            name: "u".into(),
+            pass: "p".into(),
+            auth: Default::default(),
+        };
+        assert_eq!(user.auth_str(), "derived:u\np");
+        assert_eq!(user.auth_bytes(), b"derived:u\np");
+
+        let boxed = UserBox::new(user.clone());
+        let json = serde_json::to_string(&boxed)?;
+        assert_eq!(json, r#"{"TestDerivedUser":{"name":"u","pass":"p"}}"#);
+        let back: UserBox = serde_json::from_str(&json)?;
+        assert_eq!(back.auth_str(), "derived:u\np");
+
+        let mut um = UsersMap::new();
+        um.add_user(UserBox::new(DerivedToken {
+            name: "t".into(),
+            token: "token:abc".into(),
+        }));
+        assert_eq!(
+            um.auth_user_by_authstr("token:abc")
+                .map(|u| u.identity_str().to_string()),
+            Some("t".into())
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,497 @@
+/*!
+Loading plaintext user lists from hand-written config files and the environment.
+
+The TOML loaders require the `serde` feature, the YAML loader the `yaml` feature, and the
+memory-mapped loader the `mmap` feature.
+*/
+
+use std::ffi::OsString;
+#[cfg(any(feature = "serde", feature = "mmap"))]
+use std::fs;
+#[cfg(any(feature = "serde", feature = "mmap"))]
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use toml::{Spanned, Value};
+
+use crate::{LoadError, PlainText, UserTrait, UsersMap};
+
+/// 1-based line number of a byte offset in `text`
+#[cfg(feature = "serde")]
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+/// Parses the compact `"user pass"` form.
+fn parse_compact(userpass: &str, line: Option<usize>) -> Result<PlainText, LoadError> {
+    match userpass.trim().split_once(char::is_whitespace) {
+        Some((user, pass)) => new_user(user, pass.trim_start(), line),
+        None => Err(LoadError::Syntax {
+            line,
+            message: format!("expected \"user pass\", found {userpass:?}"),
+        }),
+    }
+}
+
+fn new_user(user: &str, pass: &str, line: Option<usize>) -> Result<PlainText, LoadError> {
+    if user.is_empty() {
+        return Err(LoadError::Syntax {
+            line,
+            message: "empty user name".into(),
+        });
+    }
+    Ok(PlainText::new(user.to_string(), pass.to_string()))
+}
+
+/// Collects users parsed from a list, refusing duplicate identities.
+struct ListBuilder {
+    map: UsersMap<PlainText>,
+}
+
+impl ListBuilder {
+    fn new() -> Self {
+        ListBuilder {
+            map: UsersMap::new(),
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    fn with_capacity(capacity: usize) -> Self {
+        ListBuilder {
+            map: UsersMap::with_capacity_and_hasher(capacity, Default::default()),
+        }
+    }
+
+    fn compact(&mut self, userpass: &str, line: Option<usize>) -> Result<(), LoadError> {
+        self.insert(parse_compact(userpass, line)?, line)
+    }
+
+    #[cfg(feature = "serde")]
+    fn add(&mut self, user: &str, pass: &str, line: Option<usize>) -> Result<(), LoadError> {
+        self.insert(new_user(user, pass, line)?, line)
+    }
+
+    fn insert(&mut self, user: PlainText, line: Option<usize>) -> Result<(), LoadError> {
+        if self.map.get_user(user.identity_str()).is_some() {
+            return Err(LoadError::Duplicate {
+                line,
+                id: user.identity_str().to_string(),
+            });
+        }
+        self.map.add_user(user);
+        Ok(())
+    }
+}
+
+/// The part of a TOML file read by [`load_users_from_toml`]; entries keep their position
+/// for error messages.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct TomlUsers {
+    #[serde(default)]
+    users: Vec<Spanned<Value>>,
+}
+
+/// Parses a TOML user list, written either as tables or as compact strings:
+///
+/// ```toml
+/// users = ["alice secret", "bob hunter2"]
+/// ```
+///
+/// ```toml
+/// [[users]]
+/// user = "alice"
+/// pass = "secret"
+/// ```
+///
+/// Other top-level keys are ignored, so the list can live in a larger config file.
+/// Errors report the line of the offending entry.
+#[cfg(feature = "serde")]
+pub fn load_users_from_toml(text: &str) -> Result<UsersMap<PlainText>, LoadError> {
+    let doc: TomlUsers = toml::from_str(text).map_err(|e| LoadError::Syntax {
+        line: e.span().map(|s| line_of(text, s.start)),
+        message: e.message().to_string(),
+    })?;
+
+    let mut builder = ListBuilder::new();
+    for entry in doc.users {
+        let line = Some(line_of(text, entry.span().start));
+        match entry.get_ref() {
+            Value::String(userpass) => builder.compact(userpass, line)?,
+            Value::Table(t) => {
+                let field = |key: &str| match t.get(key) {
+                    Some(Value::String(s)) => Ok(s.as_str()),
+                    Some(_) => Err(LoadError::Syntax {
+                        line,
+                        message: format!("`{key}` must be a string"),
+                    }),
+                    None => Err(LoadError::Syntax {
+                        line,
+                        message: format!("missing `{key}`"),
+                    }),
+                };
+                builder.add(field("user")?, field("pass")?, line)?;
+            }
+            other => {
+                return Err(LoadError::Syntax {
+                    line,
+                    message: format!(
+                        "expected a table or a \"user pass\" string, found a {}",
+                        other.type_str()
+                    ),
+                })
+            }
+        }
+    }
+    Ok(builder.map)
+}
+
+/// Reads a file with [`load_users_from_toml`].
+///
+/// With the `notify` feature, `FileUserSource::toml` reloads such a file whenever it changes.
+#[cfg(feature = "serde")]
+pub fn load_users_from_toml_path(path: &Path) -> Result<UsersMap<PlainText>, LoadError> {
+    load_users_from_toml(&fs::read_to_string(path)?)
+}
+
+impl UsersMap<PlainText> {
+    /// Reads `"user pass"` entries from the environment, see
+    /// [`UsersMap::from_env_with_separator`]; entries of `prefix` are separated by `;`.
+    pub fn from_env(prefix: &str) -> Result<Self, LoadError> {
+        Self::from_env_with_separator(prefix, ';')
+    }
+
+    /// Reads `"user pass"` entries from the variable `prefix`, split at `separator`
+    /// (e.g. `USERS="a p;b q"`), and from the variables `prefix_0`, `prefix_1`, ...
+    /// (e.g. `USERS_0="alice pass1"`), for deployments that can't ship a users file.
+    ///
+    /// Numbered variables are read in numeric order and don't need to be contiguous.
+    /// Errors name the offending variable instead of a line.
+    pub fn from_env_with_separator(prefix: &str, separator: char) -> Result<Self, LoadError> {
+        let vars = std::env::vars_os().filter_map(|(k, v)| Some((k.into_string().ok()?, v)));
+        users_from_vars(prefix, separator, vars)
+    }
+}
+
+fn users_from_vars(
+    prefix: &str,
+    separator: char,
+    vars: impl Iterator<Item = (String, OsString)>,
+) -> Result<UsersMap<PlainText>, LoadError> {
+    let mut single = None;
+    let mut numbered = Vec::new();
+    for (key, value) in vars {
+        let Some(rest) = key.strip_prefix(prefix) else {
+            continue;
+        };
+        if rest.is_empty() {
+            single = Some((key, value));
+        } else if let Some(n) = rest.strip_prefix('_').and_then(|n| n.parse::<u64>().ok()) {
+            numbered.push((n, key, value));
+        }
+    }
+    numbered.sort_by_key(|(n, _, _)| *n);
+
+    let mut builder = ListBuilder::new();
+    let mut add = |key: &str, value: &str| {
+        builder.compact(value, None).map_err(|e| match e {
+            LoadError::Syntax { message, .. } => LoadError::Syntax {
+                line: None,
+                message: format!("{key}: {message}"),
+            },
+            e => e,
+        })
+    };
+    let text = |key: &str, value: OsString| {
+        value.into_string().map_err(|_| LoadError::Syntax {
+            line: None,
+            message: format!("{key} is not valid UTF-8"),
+        })
+    };
+    if let Some((key, value)) = single {
+        for entry in text(&key, value)?.split(separator) {
+            if !entry.trim().is_empty() {
+                add(&key, entry)?;
+            }
+        }
+    }
+    for (_, key, value) in numbered {
+        let value = text(&key, value)?;
+        add(&key, &value)?;
+    }
+    Ok(builder.map)
+}
+
+/// Parses a YAML user list, written as a list of `{user, pass}` mappings, `user: pass`
+/// mappings and `"user pass"` strings, or as a single mapping of users to passwords:
+///
+/// ```yaml
+/// users:
+///   - user: alice
+///     pass: secret
+///   - bob: hunter2
+/// ```
+///
+/// ```yaml
+/// users:
+///   alice: secret
+///   bob: hunter2
+/// ```
+///
+/// Other top-level keys are ignored. Syntax errors report their line; invalid entries
+/// their position in the list, as YAML values don't keep their location.
+#[cfg(feature = "yaml")]
+pub fn load_users_from_yaml(text: &str) -> Result<UsersMap<PlainText>, LoadError> {
+    use serde_yaml::{Mapping, Value};
+
+    let doc: Value = serde_yaml::from_str(text).map_err(|e| {
+        let location = e.location();
+        let message = e.to_string();
+        LoadError::Syntax {
+            line: location.as_ref().map(|l| line_of(text, l.index())),
+            message: match location {
+                Some(_) => message
+                    .rsplit_once(" at line ")
+                    .map_or(message.as_str(), |(m, _)| m)
+                    .to_string(),
+                None => message,
+            },
+        }
+    })?;
+    let syntax = |message: String| LoadError::Syntax {
+        line: None,
+        message,
+    };
+    let string = |v: &Value, what: &str| match v {
+        Value::String(s) => Ok(s.clone()),
+        // Unquoted numbers and booleans are common as passwords
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(syntax(format!("{what} must be a string"))),
+    };
+
+    let mut builder = ListBuilder::new();
+    let add_mapping = |builder: &mut ListBuilder, m: &Mapping, what: &str| {
+        for (user, pass) in m {
+            let user = string(user, &format!("{what}: user name"))?;
+            let pass = string(pass, &format!("{what}: password of {user:?}"))?;
+            builder.add(&user, &pass, None)?;
+        }
+        Ok::<_, LoadError>(())
+    };
+    match doc.get("users") {
+        None | Some(Value::Null) => {}
+        Some(Value::Mapping(m)) => add_mapping(&mut builder, m, "users")?,
+        Some(Value::Sequence(entries)) => {
+            for (i, entry) in entries.iter().enumerate() {
+                let what = format!("users[{i}]");
+                match entry {
+                    Value::Mapping(m) if m.contains_key("user") => {
+                        let field = |key: &str| match m.get(key) {
+                            Some(v) => string(v, &format!("{what}: `{key}`")),
+                            None => Err(syntax(format!("{what}: missing `{key}`"))),
+                        };
+                        builder.add(&field("user")?, &field("pass")?, None)?;
+                    }
+                    Value::Mapping(m) => add_mapping(&mut builder, m, &what)?,
+                    Value::String(userpass) => builder.compact(userpass, None)?,
+                    _ => return Err(syntax(format!("{what}: expected a mapping"))),
+                }
+            }
+        }
+        Some(_) => return Err(syntax("`users` must be a list or a mapping".into())),
+    }
+    Ok(builder.map)
+}
+
+/// Reads a file with [`load_users_from_yaml`].
+#[cfg(feature = "yaml")]
+pub fn load_users_from_yaml_path(path: &Path) -> Result<UsersMap<PlainText>, LoadError> {
+    load_users_from_yaml(&fs::read_to_string(path)?)
+}
+
+/// Parses a line of a file read by [`load_users_mmap`].
+#[cfg(feature = "mmap")]
+fn parse_list_line(bytes: &[u8], line: usize) -> Result<Option<PlainText>, LoadError> {
+    let text = std::str::from_utf8(bytes).map_err(|_| LoadError::Syntax {
+        line: Some(line),
+        message: "invalid UTF-8".into(),
+    })?;
+    let text = text.trim();
+    if text.is_empty() || text.starts_with('#') {
+        return Ok(None);
+    }
+    parse_compact(text, Some(line)).map(Some)
+}
+
+/// Loads a newline-delimited file of `"user pass"` lines by memory-mapping it, for
+/// importing hundreds of thousands of users at startup. Empty lines and lines starting
+/// with `#` are skipped.
+///
+/// The map is sized for the number of lines before the first user is added, so it is
+/// never rehashed while loading. With the `rayon` feature, the lines are parsed on all
+/// cores; the reported error is then that of any invalid line, not necessarily the first.
+///
+/// Requires the `mmap` feature. The file must not be modified while it is loaded:
+/// replace it with a rename instead of writing to it in place.
+#[cfg(feature = "mmap")]
+pub fn load_users_mmap(path: &Path) -> Result<UsersMap<PlainText>, LoadError> {
+    let file = fs::File::open(path)?;
+    // SAFETY: the mapping is only read during this call, and the file must not be
+    // modified meanwhile, as documented above.
+    let data = unsafe { memmap2::Mmap::map(&file)? };
+    let lines = data
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .map(|(i, bytes)| (i + 1, bytes));
+    let mut builder = ListBuilder::with_capacity(count_lines(&data));
+
+    #[cfg(not(feature = "rayon"))]
+    for (line, bytes) in lines {
+        if let Some(user) = parse_list_line(bytes, line)? {
+            builder.insert(user, Some(line))?;
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+
+        let users = lines
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(|(line, bytes)| {
+                parse_list_line(bytes, line)
+                    .map(|user| user.map(|user| (line, user)))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (line, user) in users {
+            builder.insert(user, Some(line))?;
+        }
+    }
+
+    Ok(builder.map)
+}
+
+/// Number of lines of `data`, counting an unterminated last line
+#[cfg(feature = "mmap")]
+fn count_lines(data: &[u8]) -> usize {
+    let newlines = data.iter().filter(|&&b| b == b'\n').count();
+    newlines + usize::from(data.last().is_some_and(|&b| b != b'\n'))
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsString;
+
+    use super::users_from_vars;
+    use crate::{LoadError, UserAuthenticator, UsersMap};
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_toml() -> Result<(), LoadError> {
+        use super::load_users_from_toml;
+
+        let um = load_users_from_toml(
+            r#"
+listen = "0.0.0.0:1080"
+users = ["alice secret", "bob  two words"]
+"#,
+        )?;
+        assert!(um.auth_user_by_authstr("plaintext:alice\nsecret").is_some());
+        assert_eq!(
+            um.get_user("bob").map(|u| u.pass.clone()),
+            Some("two words".into())
+        );
+
+        let um = load_users_from_toml("[[users]]\nuser = \"u\"\npass = \"p\"\n")?;
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_some());
+
+        let err = load_users_from_toml("users = [\n  \"a 1\",\n  \"a 2\",\n]").unwrap_err();
+        assert!(matches!(err, LoadError::Duplicate { line: Some(3), .. }));
+        let err = load_users_from_toml(
+            "[[users]]\nuser = \"u\"\npass = \"p\"\n\n[[users]]\nuser = \"v\"\npass = 1\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "line 5: `pass` must be a string");
+        let err = load_users_from_toml("users = [\"lonely\"]").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("line 1: expected \"user pass\""));
+        let err = load_users_from_toml("users = [\n").unwrap_err();
+        assert!(matches!(err, LoadError::Syntax { line: Some(_), .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_env() -> Result<(), LoadError> {
+        let vars = [
+            ("USERS", "a p; b q ;"),
+            ("USERS_10", "d s"),
+            ("USERS_2", "c r"),
+            ("USERS_FILE", "/etc/users"),
+            ("OTHER", "x y"),
+        ];
+        let vars = || vars.iter().map(|(k, v)| (k.to_string(), OsString::from(v)));
+        let um = users_from_vars("USERS", ';', vars())?;
+        assert_eq!(um.len(), 4);
+        assert!(um.auth_user_by_authstr("plaintext:b\nq").is_some());
+
+        let err = users_from_vars("OTHER", ';', [("OTHER_1".into(), "x".into())].into_iter())
+            .unwrap_err();
+        assert!(err.to_string().starts_with("OTHER_1: expected"));
+
+        std::env::set_var("USER_TRAIT_TEST_USERS", "e t,f u");
+        let um = UsersMap::from_env_with_separator("USER_TRAIT_TEST_USERS", ',')?;
+        assert!(um.auth_user_by_authstr("plaintext:f\nu").is_some());
+        Ok(())
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() -> Result<(), LoadError> {
+        let um = super::load_users_from_yaml(
+            "port: 7890\nusers:\n  - user: alice\n    pass: secret\n  - bob: 1234\n",
+        )?;
+        assert!(um.auth_user_by_authstr("plaintext:alice\nsecret").is_some());
+        assert!(um.auth_user_by_authstr("plaintext:bob\n1234").is_some());
+
+        let um = super::load_users_from_yaml("users:\n  u: p\n  v: q\n")?;
+        assert_eq!(um.len(), 2);
+
+        let err = super::load_users_from_yaml("users:\n  - user: u\n").unwrap_err();
+        assert_eq!(err.to_string(), "users[0]: missing `pass`");
+        let err = super::load_users_from_yaml("users:\n  - a: 1\n  - a: 2\n").unwrap_err();
+        assert!(matches!(err, LoadError::Duplicate { .. }));
+        let err = super::load_users_from_yaml("users:\n  - [\n").unwrap_err();
+        assert!(matches!(err, LoadError::Syntax { line: Some(_), .. }));
+        Ok(())
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap() -> Result<(), LoadError> {
+        let path = std::env::temp_dir().join(format!("user_trait_mmap_{}.txt", std::process::id()));
+        let mut text = String::from("# exported users\r\n\n");
+        for i in 0..1000 {
+            text.push_str(&format!("user{i} pass{i}\r\n"));
+        }
+        text.push_str("last p");
+        std::fs::write(&path, &text)?;
+        let um = super::load_users_mmap(&path)?;
+        assert_eq!(um.len(), 1001);
+        assert!(um
+            .auth_user_by_authstr("plaintext:user999\npass999")
+            .is_some());
+        assert!(um.auth_user_by_authstr("plaintext:last\np").is_some());
+
+        std::fs::write(&path, "a 1\nb 2\na 3\n")?;
+        let err = super::load_users_mmap(&path).unwrap_err();
+        assert!(matches!(err, LoadError::Duplicate { line: Some(3), .. }));
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
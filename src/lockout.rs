@@ -0,0 +1,193 @@
+/*!
+Per-account lockout after repeated failed logins.
+*/
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use crate::{Clock, SystemClock};
+
+/// Locks an account for `lock_duration` once it has `max_failures` failed logins
+/// within `window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    pub max_failures: usize,
+    pub window: Duration,
+    pub lock_duration: Duration,
+}
+
+impl Default for LockoutPolicy {
+    /// 10 failures within 15 minutes lock the account for 30 minutes.
+    fn default() -> Self {
+        LockoutPolicy {
+            max_failures: 10,
+            window: Duration::from_secs(15 * 60),
+            lock_duration: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct AccountState {
+    /// Times of the failures within the window, oldest first
+    failures: VecDeque<SystemTime>,
+
+    locked_until: Option<SystemTime>,
+}
+
+/// Tracks failed logins per identity according to a [`LockoutPolicy`].
+///
+/// Unlike [`ThrottledAuthenticator`](crate::ThrottledAuthenticator), which slows down
+/// attempts, a locked account is refused even with the right credential until the lock
+/// expires or an admin calls [`LockoutTracker::unlock`].
+///
+/// A [`UsersMap`](crate::UsersMap) consults it when set with
+/// [`UsersMap::set_lockout`](crate::UsersMap::set_lockout).
+#[derive(Debug)]
+pub struct LockoutTracker {
+    policy: LockoutPolicy,
+    clock: Arc<dyn Clock>,
+    accounts: Mutex<HashMap<String, AccountState>>,
+}
+
+impl LockoutTracker {
+    pub fn new(policy: LockoutPolicy) -> Self {
+        LockoutTracker {
+            policy,
+            clock: Arc::new(SystemClock),
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the clock measuring windows and locks.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn accounts(&self) -> MutexGuard<'_, HashMap<String, AccountState>> {
+        self.accounts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn policy(&self) -> LockoutPolicy {
+        self.policy
+    }
+
+    /// Returns when the lock of an identity ends, if it is locked.
+    pub fn locked_until(&self, id: &str) -> Option<SystemTime> {
+        let now = self.clock.now();
+        self.accounts()
+            .get(id)?
+            .locked_until
+            .filter(|until| *until > now)
+    }
+
+    /// Returns how long the identity stays locked, if it is locked.
+    pub fn remaining_lock(&self, id: &str) -> Option<Duration> {
+        let until = self.locked_until(id)?;
+        until.duration_since(self.clock.now()).ok()
+    }
+
+    pub fn is_locked(&self, id: &str) -> bool {
+        self.locked_until(id).is_some()
+    }
+
+    /// Records a failed login, returning true if the identity is locked afterwards.
+    pub fn record_failure(&self, id: &str) -> bool {
+        let now = self.clock.now();
+        let mut accounts = self.accounts();
+        let account = accounts.entry(id.to_string()).or_default();
+        if account.locked_until.is_some_and(|until| until > now) {
+            return true;
+        }
+        while account.failures.front().is_some_and(|t| {
+            now.duration_since(*t)
+                .is_ok_and(|d| d >= self.policy.window)
+        }) {
+            account.failures.pop_front();
+        }
+        account.failures.push_back(now);
+        if account.failures.len() >= self.policy.max_failures {
+            account.failures.clear();
+            account.locked_until = Some(now + self.policy.lock_duration);
+            return true;
+        }
+        false
+    }
+
+    /// Forgets the failures of an identity after a successful login; an active lock stays.
+    pub fn record_success(&self, id: &str) {
+        let now = self.clock.now();
+        let mut accounts = self.accounts();
+        if accounts
+            .get(id)
+            .is_some_and(|a| a.locked_until.is_none_or(|until| until <= now))
+        {
+            accounts.remove(id);
+        }
+    }
+
+    /// Lifts the lock of an identity and forgets its failures.
+    pub fn unlock(&self, id: &str) {
+        self.accounts().remove(id);
+    }
+
+    /// Forgets identities that are neither locked nor have failures within the window.
+    pub fn purge_idle(&self) {
+        let now = self.clock.now();
+        let window = self.policy.window;
+        self.accounts().retain(|_, a| {
+            a.locked_until.is_some_and(|until| until > now)
+                || a.failures
+                    .back()
+                    .is_some_and(|t| now.duration_since(*t).is_ok_and(|d| d < window))
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::{LockoutPolicy, LockoutTracker};
+    use crate::{
+        AuthError, ManualClock, PlainText, UserAuthenticator, UsersMap, VerifyingAuthenticator,
+    };
+
+    #[test]
+    fn test_lockout() {
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let lockout = Arc::new(
+            LockoutTracker::new(LockoutPolicy {
+                max_failures: 3,
+                window: Duration::from_secs(60),
+                lock_duration: Duration::from_secs(300),
+            })
+            .with_clock(Arc::new(clock.clone())),
+        );
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+        um.set_lockout(Some(Arc::clone(&lockout)));
+        let auth = VerifyingAuthenticator::new(um);
+
+        assert!(auth.authenticate("u", b"x").is_none());
+        assert!(auth.authenticate("u", b"x").is_none());
+        clock.advance(Duration::from_secs(60));
+        assert!(auth.authenticate("u", b"x").is_none());
+        assert!(!lockout.is_locked("u"));
+        assert!(auth.authenticate("u", b"x").is_none());
+        assert!(auth.authenticate("u", b"x").is_none());
+        assert!(lockout.is_locked("u"));
+
+        assert!(matches!(
+            auth.try_authenticate("u", b"p"),
+            Err(AuthError::RateLimited { retry_after: Some(d) }) if d == Duration::from_secs(300)
+        ));
+        assert!(auth.map().try_auth("plaintext:u\np").is_err());
+
+        lockout.unlock("u");
+        assert!(auth.authenticate("u", b"p").is_some());
+    }
+}
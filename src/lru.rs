@@ -0,0 +1,215 @@
+/*!
+A capacity-bounded LRU cache in front of another [`UserAuthenticator`].
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use crate::{AuthError, User, UserAuthenticator};
+
+/// Hit/miss counters of a [`LruUsersCache`] or [`CachedAuthenticator`](crate::CachedAuthenticator).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Debug)]
+struct LruState<T> {
+    /// Maps auth strings to the cached user and its last-use tick
+    entries: HashMap<String, (T, u64)>,
+
+    /// Maps last-use ticks to auth strings; the first entry is the least recently used.
+    order: BTreeMap<u64, String>,
+
+    tick: u64,
+}
+
+/// A [`UserAuthenticator`] that caches up to `capacity` successful authentications
+/// of a slow backend (DB, HTTP, ...), evicting the least recently used entry when full.
+///
+/// Failed authentications are not cached.
+#[derive(Debug)]
+pub struct LruUsersCache<T: User + Clone, A: UserAuthenticator<T>> {
+    backend: A,
+    capacity: usize,
+    state: Mutex<LruState<T>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<T: User + Clone, A: UserAuthenticator<T>> LruUsersCache<T, A> {
+    /// Creates a cache holding at most `capacity` users. A zero capacity disables caching.
+    pub fn new(backend: A, capacity: usize) -> Self {
+        LruUsersCache {
+            backend,
+            capacity,
+            state: Mutex::new(LruState {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, LruState<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn backend(&self) -> &A {
+        &self.backend
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.state().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state().entries.is_empty()
+    }
+
+    /// Puts a user into the cache under `authstr`, evicting the least recently used entry if full.
+    pub fn insert(&self, authstr: &str, user: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state();
+        state.tick += 1;
+        let tick = state.tick;
+        if let Some((_, old_tick)) = state.entries.insert(authstr.to_string(), (user, tick)) {
+            state.order.remove(&old_tick);
+        }
+        state.order.insert(tick, authstr.to_string());
+
+        while state.entries.len() > self.capacity {
+            let Some((_, lru)) = state.order.pop_first() else {
+                break;
+            };
+            state.entries.remove(&lru);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes a cached entry, e.g. after the user changed their password in the backend.
+    pub fn invalidate(&self, authstr: &str) {
+        let mut state = self.state();
+        if let Some((_, tick)) = state.entries.remove(authstr) {
+            state.order.remove(&tick);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state();
+        state.entries.clear();
+        state.order.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the cached user, or runs `miss` and caches its user on success.
+    fn get_or_try(
+        &self,
+        authstr: &str,
+        miss: impl FnOnce() -> Result<T, AuthError>,
+    ) -> Result<T, AuthError>
+    where
+        T: Clone,
+    {
+        if let Some(user) = self.get_cached(authstr) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(user);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let user = miss()?;
+        self.insert(authstr, user.clone());
+        Ok(user)
+    }
+
+    fn get_cached(&self, authstr: &str) -> Option<T> {
+        let mut state = self.state();
+        state.tick += 1;
+        let tick = state.tick;
+        let (user, last) = state.entries.get_mut(authstr)?;
+        let old_tick = std::mem::replace(last, tick);
+        let user = user.clone();
+        state.order.remove(&old_tick);
+        state.order.insert(tick, authstr.to_string());
+        Some(user)
+    }
+}
+
+impl<T: User + Clone, A: UserAuthenticator<T>> UserAuthenticator<T> for LruUsersCache<T, A> {
+    /// Returns the cached user, or asks the backend and caches its answer on success.
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        if let Some(user) = self.get_cached(authstr) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(user);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let user = self.backend.auth_user_by_authstr(authstr)?;
+        self.insert(authstr, user.clone());
+        Some(user)
+    }
+
+    /// Like `auth_user_by_authstr`, passing on the backend's error on a miss.
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.get_or_try(authstr, || self.backend.try_auth(authstr))
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        self.get_or_try(authstr, || self.backend.try_auth_as(identity, authstr))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CacheStats, LruUsersCache};
+    use crate::{PlainText, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut um = UsersMap::new();
+        for i in 0..3 {
+            um.add_user(PlainText::new(format!("u{i}"), "p".into()));
+        }
+        let cache = LruUsersCache::new(um, 2);
+
+        assert!(cache.auth_user_by_authstr("plaintext:u0\np").is_some());
+        assert!(cache.auth_user_by_authstr("plaintext:u1\np").is_some());
+        assert!(cache.auth_user_by_authstr("plaintext:u0\np").is_some());
+        // u1 is the least recently used one now
+        assert!(cache.auth_user_by_authstr("plaintext:u2\np").is_some());
+        assert!(cache.auth_user_by_authstr("nope").is_none());
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 4,
+                evictions: 1
+            }
+        );
+
+        assert!(cache.auth_user_by_authstr("plaintext:u0\np").is_some());
+        assert_eq!(cache.stats().hits, 2);
+    }
+}
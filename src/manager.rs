@@ -0,0 +1,180 @@
+/*!
+A task owning a [`UsersMap`] and applying the changes sent to it, one at a time.
+
+Requires the `tokio` feature.
+*/
+
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{StoreError, UserStore, UserTrait, UsersDiff, UsersMap};
+
+/// The requests handled by the manager task, each with the sender of its answer.
+enum Command<T: UserTrait + Clone> {
+    Add(T, oneshot::Sender<Result<(), StoreError>>),
+    Remove(String, oneshot::Sender<Option<Arc<T>>>),
+    Replace(Box<UsersMap<T>>, oneshot::Sender<UsersDiff>),
+    Query(String, oneshot::Sender<Option<Arc<T>>>),
+    Snapshot(oneshot::Sender<Arc<UsersMap<T>>>),
+}
+
+/// A handle to a task that owns a [`UsersMap`], for applications where several tasks
+/// change the users, e.g. an admin API and a sync job.
+///
+/// Changes are sent to the task over a channel and applied in order, so no two writers
+/// race and none holds a lock across an `.await`. Handles are cheap to clone; the task
+/// stops when the last one is dropped.
+///
+/// Authenticate with a [`UserManager::snapshot`], which is shared with the task until its
+/// next change, rather than with a round trip per connection.
+#[derive(Debug)]
+pub struct UserManager<T: UserTrait + Clone> {
+    commands: mpsc::Sender<Command<T>>,
+}
+
+impl<T: UserTrait + Clone> Clone for UserManager<T> {
+    fn clone(&self) -> Self {
+        UserManager {
+            commands: self.commands.clone(),
+        }
+    }
+}
+
+impl<T: UserTrait + Clone> std::fmt::Debug for Command<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Command::Add(..) => "Add",
+            Command::Remove(..) => "Remove",
+            Command::Replace(..) => "Replace",
+            Command::Query(..) => "Query",
+            Command::Snapshot(..) => "Snapshot",
+        })
+    }
+}
+
+/// Number of commands that can wait for the task before senders wait too
+const QUEUE_LEN: usize = 64;
+
+impl<T: UserTrait + Clone + 'static> UserManager<T> {
+    /// Spawns the task owning `map` on the current Tokio runtime.
+    pub fn spawn(map: UsersMap<T>) -> Self {
+        let (commands, mut rx) = mpsc::channel(QUEUE_LEN);
+        tokio::spawn(async move {
+            let mut map = Arc::new(map);
+            while let Some(command) = rx.recv().await {
+                // The answers are dropped if the caller stopped waiting
+                match command {
+                    Command::Add(user, reply) => {
+                        let _ = reply.send(Arc::make_mut(&mut map).add(user));
+                    }
+                    Command::Remove(id, reply) => {
+                        let _ = reply.send(Arc::make_mut(&mut map).remove_user(&id));
+                    }
+                    Command::Replace(mut new, reply) => {
+                        new.set_stats(map.stats());
+                        let diff = UsersDiff::between(&map, &new);
+                        map = Arc::new(*new);
+                        let _ = reply.send(diff);
+                    }
+                    Command::Query(id, reply) => {
+                        let _ = reply.send(map.get_user(&id));
+                    }
+                    Command::Snapshot(reply) => {
+                        let _ = reply.send(Arc::clone(&map));
+                    }
+                }
+            }
+        });
+        UserManager { commands }
+    }
+}
+
+impl<T: UserTrait + Clone> UserManager<T> {
+    async fn request<R>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<R>) -> Command<T>,
+    ) -> Result<R, StoreError> {
+        let stopped = || StoreError::backend("the user manager task has stopped");
+        let (reply, answer) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| stopped())?;
+        answer.await.map_err(|_| stopped())
+    }
+
+    /// Adds a user, failing with [`StoreError::AlreadyExists`] if its identity is taken.
+    pub async fn add(&self, user: T) -> Result<(), StoreError> {
+        self.request(|reply| Command::Add(user, reply)).await?
+    }
+
+    /// Removes a user, returning it.
+    pub async fn remove(&self, id: &str) -> Result<Arc<T>, StoreError> {
+        self.request(|reply| Command::Remove(id.to_string(), reply))
+            .await?
+            .ok_or_else(|| StoreError::NotFound(id.to_string()))
+    }
+
+    /// Replaces the whole map, e.g. with a reloaded one, returning what changed.
+    ///
+    /// The statistics collector of the current map, if any, is carried over to the new one.
+    pub async fn replace(&self, map: UsersMap<T>) -> Result<UsersDiff, StoreError> {
+        self.request(|reply| Command::Replace(Box::new(map), reply))
+            .await
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<Arc<T>>, StoreError> {
+        self.request(|reply| Command::Query(id.to_string(), reply))
+            .await
+    }
+
+    /// Returns the current map. Later changes copy the map rather than change this one.
+    pub async fn snapshot(&self) -> Result<Arc<UsersMap<T>>, StoreError> {
+        self.request(Command::Snapshot).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UserManager;
+    use crate::{PlainText, StoreError, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_user_manager() -> Result<(), StoreError> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let manager = UserManager::spawn(UsersMap::new());
+            let writers: Vec<_> = (0..10)
+                .map(|i| {
+                    let manager = manager.clone();
+                    tokio::spawn(async move {
+                        manager
+                            .add(PlainText::new(format!("u{i}"), "p".into()))
+                            .await
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.await.unwrap()?;
+            }
+            assert!(matches!(
+                manager.add(PlainText::from("u0 q")).await,
+                Err(StoreError::AlreadyExists(_))
+            ));
+
+            let snapshot = manager.snapshot().await?;
+            assert_eq!(manager.remove("u0").await?.pass, "p");
+            assert!(manager.get("u0").await?.is_none());
+            // The snapshot is unchanged
+            assert!(snapshot.auth_user_by_authstr("plaintext:u0\np").is_some());
+
+            let diff = manager.replace(UsersMap::new()).await?;
+            assert_eq!(diff.removed.len(), 9);
+            assert!(manager.snapshot().await?.is_empty());
+            Ok(())
+        })
+    }
+}
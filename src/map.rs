@@ -0,0 +1,1388 @@
+/*!
+The in-memory [`UsersMap`].
+*/
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::hash::BuildHasher;
+
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+#[cfg(feature = "std")]
+use crate::expiry::ExpiryPolicy;
+use crate::generation::Generation;
+#[cfg(feature = "std")]
+use crate::interner::intern_with;
+use crate::{
+    AuthError, DuplicateIdentity, GenerationReceiver, GroupsMap, HashMap, RandomState,
+    UserAuthenticator, UserTrait,
+};
+#[cfg(feature = "std")]
+use crate::{Clock, IdInterner, LockoutTracker, MapStats, UserWithExpiry};
+#[cfg(feature = "dyn-clone")]
+use crate::{UserBox, UserVec};
+
+/// Options controlling how [`UsersMap`] treats identity strings.
+///
+/// Identities are normalized both when users are inserted and when they are looked up,
+/// so with `case_insensitive_ids` enabled `get_user("Alice")` finds the user `alice`.
+/// Auth strings are never normalized.
+///
+/// With the `unicode` feature, identities are also normalized to NFC, and case-insensitive
+/// ones are lowercased with [`fold_identity`](crate::fold_identity).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MapOptions {
+    /// Compares identities after lowercasing them.
+    pub case_insensitive_ids: bool,
+
+    /// Ignores leading and trailing whitespace of identities.
+    pub trim_ids: bool,
+}
+
+impl MapOptions {
+    /// Returns the key under which `id` is stored, borrowing when nothing needs to change.
+    pub fn normalize_id<'a>(&self, id: &'a str) -> Cow<'a, str> {
+        let id = if self.trim_ids { id.trim() } else { id };
+        #[cfg(feature = "unicode")]
+        let id = crate::nfc_identity(id);
+        #[cfg(not(feature = "unicode"))]
+        let id = Cow::Borrowed(id);
+        if !self.case_insensitive_ids || !id.chars().any(char::is_uppercase) {
+            return id;
+        }
+        #[cfg(feature = "unicode")]
+        return Cow::Owned(crate::fold_identity(&id));
+        #[cfg(not(feature = "unicode"))]
+        Cow::Owned(id.to_lowercase())
+    }
+}
+
+/// A user stored in [`UsersMap`] together with every auth string indexed for it.
+#[derive(Debug, Clone)]
+struct UserRecord<T> {
+    user: Arc<T>,
+
+    /// Starts with the user's own `auth_str`; extra ones are added by
+    /// [`UsersMap::add_credential`]. They share their allocation with the keys of `auth_map`.
+    credentials: Vec<Arc<str>>,
+
+    /// The [`UserTrait::previous_auth_str`] indexed with the user, also listed in `credentials`.
+    /// It is refused once the user stops reporting it.
+    previous: Option<Arc<str>>,
+
+    /// SHA-256 digests of `credentials`, in the same order,
+    /// for [`UsersMap::auth_user_constant_time`].
+    digests: Vec<[u8; 32]>,
+
+    /// Disabled users are kept, but refused by [`UsersMap::auth_user_by_authstr`].
+    enabled: bool,
+}
+
+type Hook<T> = Arc<dyn Fn(&T) + Send + Sync>;
+type ReplaceHook<T> = Arc<dyn Fn(&T, &T) + Send + Sync>;
+
+/// Callbacks invoked by [`UsersMap`] after the user set changed.
+#[derive(Clone)]
+struct MapHooks<T> {
+    on_add: Vec<Hook<T>>,
+    on_remove: Vec<Hook<T>>,
+    on_replace: Vec<ReplaceHook<T>>,
+}
+
+impl<T> Default for MapHooks<T> {
+    fn default() -> Self {
+        MapHooks {
+            on_add: Vec::new(),
+            on_remove: Vec::new(),
+            on_replace: Vec::new(),
+        }
+    }
+}
+
+impl<T> Debug for MapHooks<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MapHooks")
+            .field("on_add", &self.on_add.len())
+            .field("on_remove", &self.on_remove.len())
+            .field("on_replace", &self.on_replace.len())
+            .finish()
+    }
+}
+
+/// The bytes that identify a credential of `user` in [`UsersMap::get_user_by_authbytes`].
+///
+/// The user's own auth string uses [`UserTrait::auth_bytes`], extra credentials their UTF-8 bytes.
+fn credential_bytes<'a, T: UserTrait>(user: &'a T, authstr: &'a str) -> &'a [u8] {
+    if authstr == user.auth_str() {
+        user.auth_bytes()
+    } else {
+        authstr.as_bytes()
+    }
+}
+
+fn credential_digest(authstr: &str) -> [u8; 32] {
+    Sha256::digest(authstr.as_bytes()).into()
+}
+
+impl<T: UserTrait + Clone, S: BuildHasher> UsersMap<T, S> {
+    /// Indexes `authstr` as a credential of `user` in auth_map and, if needed, bytes_map,
+    /// taking it from the user owning it, if any.
+    fn index_credential(&mut self, user: &Arc<T>, authstr: &Arc<str>) {
+        if let Some(owner) = self.auth_map.get(&**authstr) {
+            if !Arc::ptr_eq(owner, user) {
+                self.disown(&Arc::clone(owner), authstr);
+            }
+        }
+        let bytes = credential_bytes(user.as_ref(), authstr);
+        if bytes != authstr.as_bytes() {
+            self.bytes_map.insert(bytes.into(), Arc::clone(user));
+        }
+        self.auth_map.insert(Arc::clone(authstr), Arc::clone(user));
+    }
+
+    /// Removes `authstr` from the indexes and the record of `owner`, as another user takes it.
+    fn disown(&mut self, owner: &Arc<T>, authstr: &str) {
+        self.unindex_credential(owner, authstr);
+        let id = self.options.normalize_id(owner.identity_str());
+        let Some(record) = self
+            .id_map
+            .get_mut(id.as_ref())
+            .filter(|r| Arc::ptr_eq(&r.user, owner))
+        else {
+            return;
+        };
+        if let Some(i) = record.credentials.iter().position(|c| **c == *authstr) {
+            record.credentials.remove(i);
+            record.digests.remove(i);
+        }
+        if record.previous.as_deref() == Some(authstr) {
+            record.previous = None;
+        }
+    }
+
+    /// Undoes [`UsersMap::index_credential`], unless another user took `authstr` over since.
+    fn unindex_credential(&mut self, user: &T, authstr: &str) {
+        let owned_by = |owner: &Arc<T>| core::ptr::eq(owner.as_ref(), user);
+        if self.auth_map.get(authstr).is_some_and(owned_by) {
+            self.auth_map.remove(authstr);
+        }
+        let bytes = credential_bytes(user, authstr);
+        if bytes != authstr.as_bytes() && self.bytes_map.get(bytes).is_some_and(owned_by) {
+            self.bytes_map.remove(bytes);
+        }
+    }
+}
+
+impl<T: UserTrait> UserRecord<T> {
+    /// Returns true if `authstr` is the user's previous auth string and its grace window has passed.
+    fn is_retired(&self, authstr: &str) -> bool {
+        let eq =
+            |a: Option<&str>| a.is_some_and(|a| bool::from(a.as_bytes().ct_eq(authstr.as_bytes())));
+        eq(self.previous.as_deref()) && !eq(self.user.previous_auth_str())
+    }
+}
+
+/// A map structure that stores users with both identity and authentication mappings.
+///
+/// One identity may own several auth strings (see [`UsersMap::add_credential`]),
+/// which allows token rotation and per-device credentials.
+///
+/// The hasher of the internal maps is pluggable like that of [`HashMap`],
+/// so consumers on a hot path can use a faster one (e.g. `ahash`) through
+/// [`UsersMap::with_hasher`] or [`UsersMap::with_capacity_and_hasher`].
+/// With the `foldhash` feature, [`FastUsersMap`] is such a map; `cargo bench` compares both.
+#[derive(Debug, Clone, Default)]
+pub struct UsersMap<T: UserTrait + Clone, S = RandomState> {
+    /// Maps user identity strings to user instances and their credentials
+    id_map: HashMap<Arc<str>, UserRecord<T>, S>,
+
+    /// Maps user authentication strings to user instances
+    /// Note that the keys for auth_map are different from keys for id_map.
+    auth_map: HashMap<Arc<str>, Arc<T>, S>,
+
+    /// Maps the raw bytes of the credentials whose [`UserTrait::auth_bytes`] differ from
+    /// their auth string, for the protocols delivering credentials that are not valid UTF-8.
+    /// The others are found through auth_map, so that they are not stored twice.
+    bytes_map: HashMap<Box<[u8]>, Arc<T>, S>,
+
+    options: MapOptions,
+
+    groups: GroupsMap,
+
+    hooks: MapHooks<T>,
+
+    /// Number of records with `enabled == false`, so that authentication can
+    /// skip the identity lookup while no user is disabled.
+    disabled_count: usize,
+
+    #[cfg(feature = "std")]
+    stats: Option<Arc<MapStats>>,
+
+    /// Bumped on every mutation of the user set, see [`UsersMap::subscribe`].
+    generation: Generation,
+
+    /// Set by [`UsersMap::enforce_expiry`]
+    #[cfg(feature = "std")]
+    expiry: Option<ExpiryPolicy<T>>,
+
+    #[cfg(feature = "std")]
+    lockout: Option<Arc<LockoutTracker>>,
+
+    /// Shares the identity keys with other structures, see [`UsersMap::set_interner`].
+    #[cfg(feature = "std")]
+    interner: Option<Arc<IdInterner>>,
+}
+
+/// A [`UsersMap`] hashing with foldhash, which takes a fraction of the time of the default
+/// SipHash on short auth strings. It is randomly seeded, so clients still can't
+/// craft colliding auth strings. Create it with
+/// `FastUsersMap::with_hasher(Default::default())`.
+///
+/// Requires the `foldhash` feature.
+#[cfg(feature = "foldhash")]
+pub type FastUsersMap<T> = UsersMap<T, foldhash::fast::RandomState>;
+
+impl<T: UserTrait + Clone> UsersMap<T> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::default())
+    }
+
+    /// Creates an empty map with room for at least `capacity` users, e.g. before importing
+    /// a large user list.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::default())
+    }
+
+    /// Creates an empty map that normalizes identities according to `options`.
+    pub fn with_options(options: MapOptions) -> Self {
+        UsersMap {
+            options,
+            ..Self::new()
+        }
+    }
+}
+
+impl<T: UserTrait + Clone, S: BuildHasher + Clone> UsersMap<T, S> {
+    /// Creates an empty map which will use the given hash builder for its internal maps.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_capacity_and_hasher(0, hasher)
+    }
+
+    /// Creates an empty map with room for at least `capacity` users,
+    /// using the given hash builder for its internal maps.
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        UsersMap {
+            id_map: HashMap::with_capacity_and_hasher(capacity, hasher.clone()),
+            auth_map: HashMap::with_capacity_and_hasher(capacity, hasher.clone()),
+            bytes_map: HashMap::with_capacity_and_hasher(capacity, hasher),
+            options: MapOptions::default(),
+            groups: GroupsMap::new(),
+            hooks: MapHooks::default(),
+            disabled_count: 0,
+            #[cfg(feature = "std")]
+            stats: None,
+            generation: Generation::default(),
+            #[cfg(feature = "std")]
+            expiry: None,
+            #[cfg(feature = "std")]
+            lockout: None,
+            #[cfg(feature = "std")]
+            interner: None,
+        }
+    }
+}
+
+impl<T: UserTrait + Clone, S: BuildHasher> UsersMap<T, S> {
+    pub fn options(&self) -> MapOptions {
+        self.options
+    }
+
+    /// A number bumped on every change of the users, their credentials,
+    /// their enabled flags or the groups.
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    /// A number telling this map apart from every other map of the process, including its
+    /// clones, so that `(instance, generation)` identifies a version of a user set.
+    pub fn instance(&self) -> u64 {
+        self.generation.instance()
+    }
+
+    /// Returns a receiver which detects changes of this map (not of its clones)
+    /// cheaply, without diffing the user set.
+    pub fn subscribe(&self) -> GenerationReceiver {
+        self.generation.subscribe()
+    }
+
+    /// Starts collecting authentication statistics, returning the collector.
+    ///
+    /// If statistics are already collected, the existing collector is returned.
+    #[cfg(feature = "std")]
+    pub fn enable_stats(&mut self) -> Arc<MapStats> {
+        Arc::clone(self.stats.get_or_insert_with(Arc::default))
+    }
+
+    /// Collects authentication statistics into `stats`, or stops collecting them if it is `None`.
+    #[cfg(feature = "std")]
+    pub fn set_stats(&mut self, stats: Option<Arc<MapStats>>) {
+        self.stats = stats;
+    }
+
+    /// Returns the statistics collector, if statistics are enabled.
+    #[cfg(feature = "std")]
+    pub fn stats(&self) -> Option<Arc<MapStats>> {
+        self.stats.clone()
+    }
+
+    /// Makes authentication refuse identities locked by `lockout`, and report successes
+    /// to it. Failures are reported where the identity is known, e.g. by
+    /// [`VerifyingAuthenticator`](crate::VerifyingAuthenticator).
+    #[cfg(feature = "std")]
+    pub fn set_lockout(&mut self, lockout: Option<Arc<LockoutTracker>>) {
+        self.lockout = lockout;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn lockout(&self) -> Option<&Arc<LockoutTracker>> {
+        self.lockout.as_ref()
+    }
+
+    /// Takes the identity keys of users added from now on from `interner`, so that
+    /// they share their allocation with the other structures using it.
+    #[cfg(feature = "std")]
+    pub fn set_interner(&mut self, interner: Option<Arc<IdInterner>>) {
+        self.interner = interner;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn interner(&self) -> Option<&Arc<IdInterner>> {
+        self.interner.as_ref()
+    }
+
+    /// Registers a callback invoked after a user with a new identity was added.
+    pub fn on_add(&mut self, f: impl Fn(&T) + Send + Sync + 'static) {
+        self.hooks.on_add.push(Arc::new(f));
+    }
+
+    /// Registers a callback invoked after a user was removed by [`UsersMap::remove_user`].
+    pub fn on_remove(&mut self, f: impl Fn(&T) + Send + Sync + 'static) {
+        self.hooks.on_remove.push(Arc::new(f));
+    }
+
+    /// Registers a callback invoked with the old and the new user after
+    /// [`UsersMap::add_user`] replaced a user with the same identity.
+    pub fn on_replace(&mut self, f: impl Fn(&T, &T) + Send + Sync + 'static) {
+        self.hooks.on_replace.push(Arc::new(f));
+    }
+
+    /// Unregisters every callback
+    pub fn clear_hooks(&mut self) {
+        self.hooks = MapHooks::default();
+    }
+
+    /// Adds a new user to id_map, auth_map and bytes_map, replacing the user with the same
+    /// identity, if any, and returning it.
+    ///
+    /// All the credentials of the replaced user are dropped, including the extra ones;
+    /// [`UsersMap::update_user`] keeps them.
+    pub fn add_user(&mut self, user: T) -> Option<Arc<T>> {
+        self.insert_arc(Arc::new(user))
+    }
+
+    /// Adds a new user, failing if its identity is taken.
+    pub fn add_user_checked(&mut self, user: T) -> Result<(), DuplicateIdentity> {
+        if self.get_user(user.identity_str()).is_some() {
+            return Err(DuplicateIdentity(user.identity_str().into()));
+        }
+        self.insert_arc(Arc::new(user));
+        Ok(())
+    }
+
+    pub(crate) fn insert_arc(&mut self, user: Arc<T>) -> Option<Arc<T>> {
+        if let Some(old) = self
+            .id_map
+            .get(self.options.normalize_id(user.identity_str()).as_ref())
+        {
+            let old_user = Arc::clone(&old.user);
+            for authstr in old.credentials.clone() {
+                self.unindex_credential(&old_user, &authstr);
+            }
+        }
+
+        let authstr: Arc<str> = user.auth_str().into();
+        let previous = self.unclaimed_previous(&user);
+
+        self.index_credential(&user, &authstr);
+        let mut credentials = vec![authstr];
+        if let Some(previous) = &previous {
+            self.index_credential(&user, previous);
+            credentials.push(Arc::clone(previous));
+        }
+        let id = self.options.normalize_id(user.identity_str());
+        #[cfg(feature = "std")]
+        let id = intern_with(self.interner.as_deref(), &id);
+        #[cfg(not(feature = "std"))]
+        let id: Arc<str> = id.into();
+        let old = self.id_map.insert(
+            id,
+            UserRecord {
+                user: Arc::clone(&user),
+                digests: credentials.iter().map(|c| credential_digest(c)).collect(),
+                credentials,
+                previous,
+                enabled: true,
+            },
+        );
+        if old.as_ref().is_some_and(|r| !r.enabled) {
+            self.disabled_count -= 1;
+        }
+        self.generation.bump();
+        match &old {
+            Some(old) => self
+                .hooks
+                .on_replace
+                .iter()
+                .for_each(|f| f(&old.user, &user)),
+            None => self.hooks.on_add.iter().for_each(|f| f(&user)),
+        }
+        old.map(|old| old.user)
+    }
+
+    /// Returns `user`'s previous auth string if it is not taken by another identity.
+    fn unclaimed_previous(&self, user: &T) -> Option<Arc<str>> {
+        let previous = user.previous_auth_str()?;
+        let taken = previous == user.auth_str()
+            || self
+                .auth_map
+                .get(previous)
+                .is_some_and(|owner| owner.identity_str() != user.identity_str());
+        (!taken).then(|| previous.into())
+    }
+
+    /// Replaces the stored user having the same identity as `user`, keeping its extra
+    /// credentials, enabled flag and group memberships. The old primary auth string
+    /// stops working.
+    ///
+    /// Returns the previous user, or `None` without adding `user` if the identity is unknown.
+    pub fn update_user(&mut self, user: T) -> Option<Arc<T>> {
+        let previous = self.unclaimed_previous(&user);
+        let record = self
+            .id_map
+            .get_mut(self.options.normalize_id(user.identity_str()).as_ref())?;
+        let user = Arc::new(user);
+        let old = core::mem::replace(&mut record.user, Arc::clone(&user));
+
+        let primary: Arc<str> = user.auth_str().into();
+        let stale = core::mem::take(&mut record.credentials);
+        // Extra credentials taken over by another user since stay theirs.
+        let extra = stale.iter().filter(|c| {
+            ***c != *old.auth_str()
+                && **c != primary
+                && Some(&***c) != record.previous.as_deref()
+                && Some(*c) != previous.as_ref()
+                && self
+                    .auth_map
+                    .get(&***c)
+                    .is_some_and(|owner| Arc::ptr_eq(owner, &old))
+        });
+        record.credentials = core::iter::once(Arc::clone(&primary))
+            .chain(previous.clone())
+            .chain(extra.cloned())
+            .collect();
+        record.previous = previous;
+        record.digests = record
+            .credentials
+            .iter()
+            .map(|c| credential_digest(c))
+            .collect();
+        let credentials = record.credentials.clone();
+        for authstr in &stale {
+            self.unindex_credential(old.as_ref(), authstr);
+        }
+        for authstr in &credentials {
+            self.index_credential(&user, authstr);
+        }
+
+        self.generation.bump();
+        self.hooks.on_replace.iter().for_each(|f| f(&old, &user));
+        Some(old)
+    }
+
+    /// Adds an extra auth string for an existing identity.
+    ///
+    /// Returns false if the identity is unknown or if `authstr` already belongs to another identity.
+    pub fn add_credential(&mut self, id: &str, authstr: &str) -> bool {
+        let Some(record) = self.id_map.get_mut(self.options.normalize_id(id).as_ref()) else {
+            return false;
+        };
+        if let Some(owner) = self.auth_map.get(authstr) {
+            return Arc::ptr_eq(owner, &record.user);
+        }
+        let authstr: Arc<str> = authstr.into();
+        let user = Arc::clone(&record.user);
+        record.credentials.push(Arc::clone(&authstr));
+        record.digests.push(credential_digest(&authstr));
+        self.index_credential(&user, &authstr);
+        self.generation.bump();
+        true
+    }
+
+    /// Removes a single auth string of an identity, leaving the user and its other credentials in place.
+    ///
+    /// Returns false if the identity does not own `authstr`.
+    pub fn remove_credential(&mut self, id: &str, authstr: &str) -> bool {
+        let Some(record) = self.id_map.get_mut(self.options.normalize_id(id).as_ref()) else {
+            return false;
+        };
+        let Some(pos) = record.credentials.iter().position(|c| **c == *authstr) else {
+            return false;
+        };
+        record.credentials.swap_remove(pos);
+        record.digests.swap_remove(pos);
+        if record.previous.as_deref() == Some(authstr) {
+            record.previous = None;
+        }
+        let user = Arc::clone(&record.user);
+        self.unindex_credential(&user, authstr);
+        self.generation.bump();
+        true
+    }
+
+    /// Drops the previous auth strings whose grace window has passed from the indexes,
+    /// returning how many were dropped. Authentication refuses them even before.
+    pub fn purge_retired_credentials(&mut self) -> usize {
+        let retired: Vec<(Arc<str>, Arc<str>)> = self
+            .id_map
+            .iter()
+            .filter_map(|(id, r)| {
+                let previous = r.previous.as_ref()?;
+                r.is_retired(previous)
+                    .then(|| (id.clone(), previous.clone()))
+            })
+            .collect();
+        for (id, authstr) in &retired {
+            self.remove_credential(id, authstr);
+        }
+        retired.len()
+    }
+
+    /// Returns every auth string indexed for the identity
+    pub fn credentials(&self, id: &str) -> Option<&[Arc<str>]> {
+        self.id_map
+            .get(self.options.normalize_id(id).as_ref())
+            .map(|r| r.credentials.as_slice())
+    }
+
+    /// The credentials of an identity added by [`UsersMap::add_credential`], without its
+    /// own auth string and its previous one.
+    #[cfg(feature = "serde")]
+    pub(crate) fn added_credentials(&self, id: &str) -> impl Iterator<Item = &Arc<str>> {
+        self.id_map
+            .get(self.options.normalize_id(id).as_ref())
+            .into_iter()
+            .flat_map(|r| {
+                r.credentials.iter().filter(move |c| {
+                    ***c != *r.user.auth_str() && Some(&***c) != r.previous.as_deref()
+                })
+            })
+    }
+
+    /// Removes a user and all of their credentials using their identity string, returning it.
+    pub fn remove_user(&mut self, id: &str) -> Option<Arc<T>> {
+        let record = self.id_map.remove(self.options.normalize_id(id).as_ref())?;
+        Some(self.unindex(record))
+    }
+
+    /// Cleans up the other indexes after `record` was taken out of id_map.
+    fn unindex(&mut self, record: UserRecord<T>) -> Arc<T> {
+        if !record.enabled {
+            self.disabled_count -= 1;
+        }
+        for authstr in &record.credentials {
+            self.unindex_credential(&record.user, authstr);
+        }
+        self.groups.remove_member(record.user.identity_str());
+        self.generation.bump();
+        self.hooks.on_remove.iter().for_each(|f| f(&record.user));
+        record.user
+    }
+
+    /// Keeps only the users for which `f(id, user)` returns true, removing the others
+    /// and all of their credentials, as [`UsersMap::remove_user`] would.
+    ///
+    /// `id` is the stored identity, i.e. normalized according to [`MapOptions`].
+    pub fn retain(&mut self, mut f: impl FnMut(&str, &T) -> bool) {
+        let mut removed = Vec::new();
+        self.id_map.retain(|id, record| {
+            let keep = f(id, &record.user);
+            if !keep {
+                removed.push(UserRecord {
+                    user: Arc::clone(&record.user),
+                    credentials: core::mem::take(&mut record.credentials),
+                    previous: None,
+                    digests: Vec::new(),
+                    enabled: record.enabled,
+                });
+            }
+            keep
+        });
+        for record in removed {
+            self.unindex(record);
+        }
+    }
+
+    /// Removes every user, returning them. The `on_remove` hooks are called for each one.
+    pub fn drain(&mut self) -> impl Iterator<Item = Arc<T>> {
+        let records: Vec<_> = self.id_map.drain().map(|(_, r)| r).collect();
+        let users: Vec<_> = records.into_iter().map(|r| self.unindex(r)).collect();
+        self.auth_map.clear();
+        self.bytes_map.clear();
+        users.into_iter()
+    }
+
+    /// Removes every user and their group memberships, keeping the allocated capacity.
+    /// Unlike [`UsersMap::drain`], the `on_remove` hooks are not called.
+    pub fn clear(&mut self) {
+        for record in self.id_map.values() {
+            self.groups.remove_member(record.user.identity_str());
+        }
+        self.id_map.clear();
+        self.auth_map.clear();
+        self.bytes_map.clear();
+        self.disabled_count = 0;
+        self.generation.bump();
+    }
+
+    /// Reserves room for at least `additional` more users, each with one credential.
+    pub fn reserve(&mut self, additional: usize) {
+        self.id_map.reserve(additional);
+        self.auth_map.reserve(additional);
+    }
+
+    /// Shrinks the internal maps as much as possible, e.g. after removing many users.
+    pub fn shrink_to_fit(&mut self) {
+        self.id_map.shrink_to_fit();
+        self.auth_map.shrink_to_fit();
+        self.bytes_map.shrink_to_fit();
+    }
+
+    /// Number of users the map can hold without reallocating its identity index
+    pub fn capacity(&self) -> usize {
+        self.id_map.capacity()
+    }
+
+    /// Estimates the bytes allocated by the map: its tables, identities, auth strings
+    /// and the users themselves, but not what the users allocate on their own,
+    /// e.g. the strings of a [`PlainText`](crate::PlainText).
+    ///
+    /// Hash table overhead is approximated, so take it as an order of magnitude.
+    pub fn memory_estimate(&self) -> usize {
+        use core::mem::size_of;
+
+        let tables = self.id_map.capacity() * size_of::<(Arc<str>, UserRecord<T>)>()
+            + self.auth_map.capacity() * size_of::<(Arc<str>, Arc<T>)>()
+            + self.bytes_map.capacity() * size_of::<(Box<[u8]>, Arc<T>)>();
+        // Arc allocations hold two counters before the value.
+        let arc = 2 * size_of::<usize>();
+        let records: usize = self
+            .id_map
+            .iter()
+            .map(|(id, r)| {
+                arc + id.len()
+                    + arc
+                    + size_of::<T>()
+                    + r.credentials.capacity() * size_of::<Arc<str>>()
+                    + r.credentials.iter().map(|c| arc + c.len()).sum::<usize>()
+                    + r.digests.capacity() * size_of::<[u8; 32]>()
+            })
+            .sum();
+        let bytes: usize = self.bytes_map.keys().map(|k| k.len()).sum();
+        tables + records + bytes
+    }
+
+    /// Suspends or re-enables a user without removing it.
+    ///
+    /// Disabled users are refused by [`UsersMap::auth_user_by_authstr`] while
+    /// the `get_user*` methods still return them, e.g. for admin views.
+    /// Returns false if the identity is unknown.
+    pub fn set_enabled(&mut self, id: &str, enabled: bool) -> bool {
+        let Some(record) = self.id_map.get_mut(self.options.normalize_id(id).as_ref()) else {
+            return false;
+        };
+        if record.enabled != enabled {
+            record.enabled = enabled;
+            if enabled {
+                self.disabled_count -= 1;
+            } else {
+                self.disabled_count += 1;
+            }
+            self.generation.bump();
+        }
+        true
+    }
+
+    /// Returns whether the user exists and is not disabled
+    pub fn is_enabled(&self, id: &str) -> bool {
+        self.id_map
+            .get(self.options.normalize_id(id).as_ref())
+            .is_some_and(|r| r.enabled)
+    }
+
+    /// Returns false if `user`'s identity is stored as disabled.
+    fn user_enabled(&self, user: &T) -> bool {
+        self.disabled_count == 0
+            || self
+                .id_map
+                .get(self.options.normalize_id(user.identity_str()).as_ref())
+                .is_none_or(|r| r.enabled)
+    }
+
+    /// Returns false if expiry is enforced and `user` has expired.
+    #[cfg(feature = "std")]
+    fn user_unexpired(&self, user: &T) -> bool {
+        self.expiry.as_ref().is_none_or(|e| !e.is_expired(user))
+    }
+
+    /// Expiry can't be enforced without a clock.
+    #[cfg(not(feature = "std"))]
+    fn user_unexpired(&self, _: &T) -> bool {
+        true
+    }
+
+    /// Stops refusing expired users, see [`UsersMap::enforce_expiry`].
+    #[cfg(feature = "std")]
+    pub fn disable_expiry(&mut self) {
+        self.expiry = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.id_map.len()
+    }
+
+    /// Iterates over the stored users in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<T>> {
+        self.id_map.values().map(|r| &r.user)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id_map.is_empty()
+    }
+
+    /// Retrieves a user by their identity string
+    pub fn get_user(&self, id: &str) -> Option<Arc<T>> {
+        self.id_map
+            .get(self.options.normalize_id(id).as_ref())
+            .map(|r| Arc::clone(&r.user))
+    }
+
+    /// Finds a user whose identity looks like `id` without being it, e.g. `admin` for
+    /// `аdmin` with a Cyrillic `а`, so that panels can refuse the impersonating name.
+    ///
+    /// See [`is_confusable`](crate::is_confusable). This scans every user.
+    #[cfg(feature = "unicode")]
+    pub fn find_confusable(&self, id: &str) -> Option<Arc<T>> {
+        let key = self.options.normalize_id(id);
+        let skeleton = crate::identity_skeleton(&key);
+        self.id_map
+            .iter()
+            .find(|(k, _)| ***k != *key && crate::identity_skeleton(k) == skeleton)
+            .map(|(_, r)| Arc::clone(&r.user))
+    }
+
+    /// Retrieves a user by their authentication string, even if it is disabled
+    pub fn get_user_by_authstr(&self, authstr: &str) -> Option<Arc<T>> {
+        self.auth_map.get(authstr).map(Arc::clone)
+    }
+
+    /// Retrieves a user by the raw bytes of their credential, see [`UserTrait::auth_bytes`]
+    pub fn get_user_by_authbytes(&self, authbytes: &[u8]) -> Option<Arc<T>> {
+        if let Some(user) = self.bytes_map.get(authbytes) {
+            return Some(Arc::clone(user));
+        }
+        let authstr = core::str::from_utf8(authbytes).ok()?;
+        self.auth_map
+            .get(authstr)
+            .filter(|user| credential_bytes(user.as_ref(), authstr) == authbytes)
+            .map(Arc::clone)
+    }
+
+    /// The group definitions of this map. Removing a user also removes it from every group.
+    pub fn groups(&self) -> &GroupsMap {
+        &self.groups
+    }
+
+    /// Gives mutable access to the group definitions, which counts as a change of the map.
+    pub fn groups_mut(&mut self) -> &mut GroupsMap {
+        self.generation.bump();
+        &mut self.groups
+    }
+
+    /// Replaces the group definitions, e.g. with ones loaded from a config file.
+    pub fn set_groups(&mut self, groups: GroupsMap) {
+        self.groups = groups;
+        self.generation.bump();
+    }
+
+    /// Retrieves the stored users that are members of `group`.
+    ///
+    /// Identities listed in the group but missing from the map are skipped.
+    pub fn users_in_group(&self, group: &str) -> Vec<Arc<T>> {
+        self.groups
+            .members(group)
+            .map(|ids| ids.iter().filter_map(|id| self.get_user(id)).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl<T: UserTrait + Clone, S: BuildHasher> UsersMap<T, S> {
+    /// Authenticates a user like [`UserAuthenticator::auth_user_by_authstr`], without
+    /// leaking through timing which credentials exist.
+    ///
+    /// A hash map lookup returns early when the key is absent, and comparing strings stops
+    /// at the first differing byte. Instead, this hashes `authstr` with SHA-256 and compares
+    /// the digest with the digest of every stored credential using [`ConstantTimeEq`],
+    /// so the work done only depends on the number of stored credentials.
+    /// This is O(n), so it suits small user sets or high-value endpoints.
+    pub fn auth_user_constant_time(&self, authstr: &str) -> Option<T> {
+        let digest = credential_digest(authstr);
+
+        let mut found = None;
+        for record in self.id_map.values() {
+            for (d, c) in record.digests.iter().zip(&record.credentials) {
+                let eq: bool = d.ct_eq(&digest).into();
+                if eq {
+                    found = Some((record, c));
+                }
+            }
+        }
+        let result = match found {
+            Some((record, c)) if record.is_retired(c) => self.refuse(AuthError::BadCredential),
+            found => self.admit_shared(found.map(|(record, _)| &record.user)),
+        };
+        result.ok().map(|arc_user| arc_user.as_ref().clone())
+    }
+
+    /// Like [`UserAuthenticator::try_auth`], but returns the stored user instead of a clone,
+    /// which saves most of the cost of a successful lookup for users owning heap data.
+    pub fn try_auth_shared(&self, authstr: &str) -> Result<Arc<T>, AuthError> {
+        let user = self.auth_map.get(authstr);
+        let retired = user.is_some_and(|user| {
+            !user.auth_eq(authstr.as_bytes())
+                && self
+                    .id_map
+                    .get(self.options.normalize_id(user.identity_str()).as_ref())
+                    .is_some_and(|r| r.is_retired(authstr))
+        });
+        if retired {
+            return self.refuse(AuthError::BadCredential);
+        }
+        self.admit_shared(user)
+    }
+
+    /// Finishes an authentication attempt that found `user`: refuses it if it is missing,
+    /// disabled or expired, records the outcome in the statistics and returns a clone on success.
+    #[cfg(feature = "std")]
+    pub(crate) fn admit(&self, user: Option<&Arc<T>>) -> Result<T, AuthError> {
+        self.admit_shared(user).map(|user| user.as_ref().clone())
+    }
+
+    fn admit_shared(&self, user: Option<&Arc<T>>) -> Result<Arc<T>, AuthError> {
+        let user = self.check_admissible(user)?;
+        #[cfg(feature = "std")]
+        if let Some(lockout) = &self.lockout {
+            lockout.record_success(user.identity_str());
+        }
+        #[cfg(feature = "std")]
+        if let Some(stats) = &self.stats {
+            stats.record_success(user.identity_str());
+        }
+        Ok(Arc::clone(user))
+    }
+
+    /// Refuses `user` if it is unknown, disabled, expired or locked out, without
+    /// recording a success, so that callers checking a secret themselves can refuse
+    /// before looking at it.
+    pub(crate) fn check_admissible<'a>(
+        &self,
+        user: Option<&'a Arc<T>>,
+    ) -> Result<&'a Arc<T>, AuthError> {
+        let user = match user {
+            None => return self.refuse(AuthError::UnknownUser),
+            Some(user) if !self.user_enabled(user) => return self.refuse(AuthError::Disabled),
+            Some(user) if !self.user_unexpired(user) => return self.refuse(AuthError::Expired),
+            Some(user) => user,
+        };
+        #[cfg(feature = "std")]
+        if let Some(lockout) = &self.lockout {
+            if let Some(remaining) = lockout.remaining_lock(user.identity_str()) {
+                return self.refuse(AuthError::RateLimited {
+                    retry_after: Some(remaining),
+                });
+            }
+        }
+        Ok(user)
+    }
+
+    /// Records a failed authentication attempt in the statistics and returns `err`.
+    pub(crate) fn refuse<U>(&self, err: AuthError) -> Result<U, AuthError> {
+        #[cfg(feature = "std")]
+        if let Some(stats) = &self.stats {
+            stats.record_failure();
+        }
+        Err(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: UserWithExpiry + Clone, S: BuildHasher> UsersMap<T, S> {
+    /// Makes authentication refuse users whose [`UserWithExpiry::expires_at`] has passed
+    /// according to `clock`. Expired users are still returned by the `get_user*` methods.
+    ///
+    /// Pass [`SystemClock`](crate::SystemClock) in production and a
+    /// [`ManualClock`](crate::ManualClock) in tests.
+    pub fn enforce_expiry(&mut self, clock: Arc<dyn Clock>) {
+        self.expiry = Some(ExpiryPolicy::new(clock));
+    }
+
+    /// Returns true if the user exists and expiry is enforced and it has expired.
+    pub fn is_expired(&self, id: &str) -> bool {
+        self.get_user(id)
+            .is_some_and(|user| !self.user_unexpired(&user))
+    }
+}
+
+#[cfg(feature = "dyn-clone")]
+impl<S: BuildHasher> UsersMap<UserBox, S> {
+    /// Returns the users sorted by identity, so that equal maps give equal vecs,
+    /// with equal hashes.
+    pub fn to_user_vec(&self) -> UserVec {
+        let mut users: UserVec = self.iter().map(|u| u.as_ref().clone()).collect();
+        users.sort_by_identity();
+        users
+    }
+}
+
+/// Adds the users in order, so that a user replaces the earlier ones with its identity.
+#[cfg(feature = "dyn-clone")]
+impl<S: BuildHasher + Clone + Default> From<UserVec> for UsersMap<UserBox, S> {
+    fn from(users: UserVec) -> Self {
+        let mut map = UsersMap::with_capacity_and_hasher(users.0.len(), S::default());
+        for user in users.0 {
+            map.add_user(user);
+        }
+        map
+    }
+}
+
+/// Implementation of UserAuthenticator trait for UsersMap
+impl<T: UserTrait + Clone, S: BuildHasher> UserAuthenticator<T> for UsersMap<T, S> {
+    /// Authenticates a user by their authentication string and returns a clone if found,
+    /// enabled and not expired
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.try_auth(authstr).ok()
+    }
+
+    /// Reports [`AuthError::UnknownUser`] if no user owns `authstr`,
+    /// [`AuthError::BadCredential`] if it is a previous auth string past its grace window,
+    /// or why the owner was refused.
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.try_auth_shared(authstr)
+            .map(|user| user.as_ref().clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::hash::BuildHasherDefault;
+    use std::sync::Arc;
+
+    use super::{MapOptions, UsersMap};
+    use crate::{PlainText, UserAuthenticator};
+    #[cfg(feature = "dyn-clone")]
+    use crate::{UserBox, UserVec};
+
+    #[test]
+    fn test_multiple_credentials() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::new("u".into(), "p".into()));
+        um.add_user(PlainText::new("u2".into(), "p2".into()));
+
+        assert!(um.add_credential("u", "token:abc"));
+        assert!(!um.add_credential("u2", "token:abc"));
+        assert!(!um.add_credential("nobody", "token:def"));
+
+        let o = um.auth_user_by_authstr("token:abc");
+        assert_eq!(o.map(|u| u.user.clone()), Some("u".to_string()));
+        assert!(um.get_user_by_authbytes(b"token:abc").is_some());
+        assert!(um.get_user_by_authbytes(b"plaintext:u2\np2").is_some());
+        assert!(Arc::ptr_eq(
+            &um.try_auth_shared("token:abc").unwrap(),
+            &um.get_user("u").unwrap()
+        ));
+        assert_eq!(um.credentials("u").map(|c| c.len()), Some(2));
+
+        assert!(um.remove_credential("u", "token:abc"));
+        assert!(um.auth_user_by_authstr("token:abc").is_none());
+        assert!(um.get_user_by_authbytes(b"token:abc").is_none());
+
+        um.add_credential("u", "token:xyz");
+        um.remove_user("u");
+        assert!(um.auth_user_by_authstr("token:xyz").is_none());
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_none());
+        assert!(um.get_user_by_authbytes(b"plaintext:u\np").is_none());
+        assert_eq!(um.len(), 1);
+
+        // b takes over an extra credential of a, which updating or removing a leaves in place.
+        um.add_user(PlainText::from("a pa"));
+        um.add_credential("a", "plaintext:b\npb");
+        um.add_user(PlainText::from("b pb"));
+        assert_eq!(um.credentials("a").map(|c| c.len()), Some(1));
+        let b = um.auth_user_constant_time("plaintext:b\npb");
+        assert_eq!(b.map(|u| u.user.clone()), Some("b".to_string()));
+        um.update_user(PlainText::from("a pa2"));
+        um.remove_user("a");
+        let b = um.auth_user_by_authstr("plaintext:b\npb");
+        assert_eq!(b.map(|u| u.user.clone()), Some("b".to_string()));
+    }
+
+    #[test]
+    fn test_add_user_replaces() {
+        let mut um = UsersMap::new();
+        assert!(um.add_user(PlainText::from("u p")).is_none());
+        um.add_credential("u", "token:abc");
+        let old = um.add_user(PlainText::from("u q")).unwrap();
+        assert_eq!(old.pass, "p");
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_none());
+        assert!(um.auth_user_by_authstr("token:abc").is_none());
+        assert!(um.auth_user_by_authstr("plaintext:u\nq").is_some());
+
+        assert_eq!(
+            um.add_user_checked(PlainText::from("u r")),
+            Err(crate::DuplicateIdentity("u".into()))
+        );
+        assert!(um.add_user_checked(PlainText::from("v r")).is_ok());
+        assert_eq!(um.len(), 2);
+    }
+
+    #[cfg(feature = "dyn-clone")]
+    #[test]
+    fn test_user_vec() {
+        let users = UserVec::from(vec![
+            PlainText::from("b 1"),
+            PlainText::from("a 1"),
+            PlainText::from("b 2"),
+        ]);
+        let um: UsersMap<UserBox> = users.into();
+        assert_eq!(um.len(), 2);
+        assert!(um.auth_user_by_authstr("plaintext:b\n2").is_some());
+        assert!(um.auth_user_by_authstr("plaintext:b\n1").is_none());
+        assert_eq!(
+            um.to_user_vec(),
+            UserVec::from(vec![PlainText::from("a 1"), PlainText::from("b 2")])
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_ids() {
+        let mut um = UsersMap::with_options(MapOptions {
+            case_insensitive_ids: true,
+            trim_ids: true,
+        });
+        um.add_user(PlainText::new("Alice".into(), "p".into()));
+
+        assert!(um.get_user(" alice ").is_some());
+        assert!(um.get_user("ALICE").is_some());
+        assert!(um.auth_user_by_authstr("plaintext:Alice\np").is_some());
+
+        um.remove_user("aLiCe");
+        assert!(um.is_empty());
+        assert!(um.auth_user_by_authstr("plaintext:Alice\np").is_none());
+        um.add_user(PlainText::new("Alice".into(), "p".into()));
+        um.groups_mut().add_to_group("g", "Alice");
+        um.clear();
+        assert!(!um.groups().is_member("g", "Alice"));
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_unicode_ids() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::new("jose\u{301}".into(), "p".into()));
+        um.add_user(PlainText::new("admin".into(), "p".into()));
+
+        assert_eq!(um.get_user("jos\u{e9}").unwrap().user, "jos\u{e9}");
+        assert!(um.get_user("jose\u{301}").is_some());
+        assert!(um.auth_user_by_authstr("plaintext:jos\u{e9}\np").is_some());
+
+        assert_eq!(um.find_confusable("\u{430}dmin").unwrap().user, "admin");
+        assert!(um.find_confusable("admin").is_none());
+        assert!(um.find_confusable("bob").is_none());
+    }
+
+    #[test]
+    fn test_users_in_group() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::new("a".into(), "p".into()));
+        um.add_user(PlainText::new("b".into(), "p".into()));
+        um.groups_mut().add_to_group("admins", "a");
+        um.groups_mut().add_to_group("admins", "ghost");
+        um.groups_mut().add_to_group("guests", "b");
+
+        let admins = um.users_in_group("admins");
+        assert_eq!(admins.len(), 1);
+        assert_eq!(admins[0].user, "a");
+        assert!(um.users_in_group("nobody").is_empty());
+
+        um.remove_user("b");
+        assert!(!um.groups().is_member("guests", "b"));
+    }
+
+    #[test]
+    fn test_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut um = UsersMap::new();
+        {
+            let events = Arc::clone(&events);
+            um.on_add(move |u: &PlainText| events.lock().unwrap().push(format!("add {}", u.user)));
+        }
+        {
+            let events = Arc::clone(&events);
+            um.on_remove(move |u| events.lock().unwrap().push(format!("remove {}", u.user)));
+        }
+        {
+            let events = Arc::clone(&events);
+            um.on_replace(move |old, new| {
+                events
+                    .lock()
+                    .unwrap()
+                    .push(format!("replace {} {}", old.pass, new.pass))
+            });
+        }
+
+        um.add_user(PlainText::new("u".into(), "p".into()));
+        um.add_user(PlainText::new("u".into(), "p2".into()));
+        assert_eq!(
+            um.remove_user("u").map(|u| u.pass.clone()),
+            Some("p2".into())
+        );
+        assert!(um.remove_user("u").is_none());
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["add u", "replace p p2", "remove u"]
+        );
+    }
+
+    #[test]
+    fn test_custom_hasher() {
+        type Hasher = BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+        let mut um: UsersMap<PlainText, Hasher> =
+            UsersMap::with_capacity_and_hasher(4, Hasher::default());
+        um.add_user(PlainText::new("u".into(), "p".into()));
+
+        assert!(um.get_user("u").is_some());
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_some());
+    }
+
+    #[test]
+    fn test_retain_drain() {
+        let mut um = UsersMap::new();
+        for i in 0..4 {
+            um.add_user(PlainText::new(format!("u{i}"), "p".into()));
+        }
+        um.add_credential("u1", "token:1");
+
+        um.retain(|id, _| id != "u1" && id != "u2");
+        assert_eq!(um.len(), 2);
+        assert!(um.auth_user_by_authstr("token:1").is_none());
+        assert!(um.get_user_by_authbytes(b"plaintext:u2\np").is_none());
+        assert!(um.auth_user_by_authstr("plaintext:u3\np").is_some());
+
+        let mut drained: Vec<_> = um.drain().map(|u| u.user.clone()).collect();
+        drained.sort();
+        assert_eq!(drained, ["u0", "u3"]);
+        assert!(um.is_empty());
+        assert!(um.auth_user_by_authstr("plaintext:u3\np").is_none());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut um = UsersMap::with_capacity(100);
+        assert!(um.capacity() >= 100);
+        let empty = um.memory_estimate();
+        for i in 0..10 {
+            um.add_user(PlainText::new(format!("u{i}"), "p".into()));
+        }
+        um.set_enabled("u0", false);
+        um.groups_mut().add_to_group("g", "u1");
+        assert!(um.memory_estimate() > empty);
+
+        um.clear();
+        assert!(um.is_empty());
+        assert!(!um.groups().is_member("g", "u1"));
+        assert!(um.auth_user_by_authstr("plaintext:u1\np").is_none());
+        um.shrink_to_fit();
+        assert!(um.capacity() < 100);
+        um.reserve(50);
+        assert!(um.capacity() >= 50);
+    }
+
+    #[test]
+    fn test_disabled_users() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::new("u".into(), "p".into()));
+
+        assert!(um.set_enabled("u", false));
+        assert!(!um.set_enabled("nobody", false));
+        assert!(!um.is_enabled("u"));
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_none());
+        assert!(um.get_user("u").is_some());
+
+        assert!(um.set_enabled("u", true));
+        assert!(um.auth_user_by_authstr("plaintext:u\np").is_some());
+
+        um.set_enabled("u", false);
+        um.remove_user("u");
+        assert_eq!(um.disabled_count, 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_stats() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::new("u".into(), "p".into()));
+        assert!(um.stats().is_none());
+
+        let stats = um.enable_stats();
+        um.auth_user_by_authstr("plaintext:u\np");
+        um.auth_user_by_authstr("plaintext:u\nwrong");
+        um.set_enabled("u", false);
+        um.auth_user_by_authstr("plaintext:u\np");
+
+        assert_eq!(stats.successes(), 1);
+        assert_eq!(stats.failures(), 2);
+        assert!(stats.last_auth("u").is_some());
+        assert!(stats.last_auth("nobody").is_none());
+
+        let at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1);
+        let stats =
+            Arc::new(crate::MapStats::new().with_clock(Arc::new(crate::ManualClock::new(at))));
+        um.set_stats(Some(Arc::clone(&stats)));
+        um.set_enabled("u", true);
+        um.auth_user_by_authstr("plaintext:u\np");
+        assert_eq!(stats.last_auth("u"), Some(at));
+    }
+
+    #[test]
+    fn test_generation() {
+        let mut um = UsersMap::new();
+        let mut rx = um.subscribe();
+        assert!(!rx.has_changed());
+
+        um.add_user(PlainText::new("u".into(), "p".into()));
+        assert!(rx.has_changed());
+        assert_eq!(rx.mark_seen(), um.generation());
+        assert!(!rx.has_changed());
+
+        um.remove_user("nobody");
+        assert!(!rx.has_changed());
+
+        let mut cloned = um.clone();
+        cloned.remove_user("u");
+        assert!(!rx.has_changed());
+
+        um.set_enabled("u", false);
+        assert!(rx.has_changed());
+    }
+
+    #[test]
+    fn test_auth_constant_time() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+        um.add_user(PlainText::from("u2 p2"));
+        um.add_credential("u", "token:abc");
+
+        assert!(um.auth_user_constant_time("plaintext:u\np").is_some());
+        assert!(um.auth_user_constant_time("token:abc").is_some());
+        assert!(um.auth_user_constant_time("plaintext:u\nwrong").is_none());
+
+        um.remove_credential("u", "token:abc");
+        assert!(um.auth_user_constant_time("token:abc").is_none());
+        um.set_enabled("u2", false);
+        assert!(um.auth_user_constant_time("plaintext:u2\np2").is_none());
+
+        #[cfg(feature = "std")]
+        {
+            use crate::{LockoutPolicy, LockoutTracker};
+
+            let lockout = Arc::new(LockoutTracker::new(LockoutPolicy {
+                max_failures: 2,
+                ..LockoutPolicy::default()
+            }));
+            um.set_lockout(Some(Arc::clone(&lockout)));
+            assert!(!lockout.record_failure("u"));
+            // The success resets the failures.
+            assert!(um.auth_user_constant_time("plaintext:u\np").is_some());
+            assert!(!lockout.record_failure("u"));
+
+            assert!(lockout.record_failure("u"));
+            assert!(um.auth_user_constant_time("plaintext:u\np").is_none());
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    struct RawUser {
+        name: String,
+        auth_str: String,
+        raw: Vec<u8>,
+    }
+
+    #[cfg_attr(feature = "serde", typetag::serde)]
+    impl crate::UserTrait for RawUser {
+        fn identity_str(&self) -> &str {
+            &self.name
+        }
+
+        fn identity_bytes(&self) -> &[u8] {
+            self.name.as_bytes()
+        }
+
+        fn auth_str(&self) -> &str {
+            &self.auth_str
+        }
+
+        fn auth_bytes(&self) -> &[u8] {
+            &self.raw
+        }
+    }
+
+    #[test]
+    fn test_raw_auth_bytes() {
+        let mut um = UsersMap::new();
+        um.add_user(RawUser {
+            name: "r".into(),
+            auth_str: "raw:ff00".into(),
+            raw: vec![0xff, 0],
+        });
+        um.add_credential("r", "token:r");
+
+        assert!(um.get_user_by_authbytes(&[0xff, 0]).is_some());
+        assert!(um.get_user_by_authbytes(b"token:r").is_some());
+        assert!(um.get_user_by_authbytes(b"raw:ff00").is_none());
+        assert!(um.auth_user_by_authstr("raw:ff00").is_some());
+
+        um.remove_user("r");
+        assert!(um.get_user_by_authbytes(&[0xff, 0]).is_none());
+        assert!(um.get_user_by_authbytes(b"token:r").is_none());
+    }
+}
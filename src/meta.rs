@@ -0,0 +1,102 @@
+/*!
+Arbitrary non-credential labels attached to users.
+*/
+
+use std::collections::HashMap;
+use std::ops::Deref;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "dyn-clone")]
+use crate::UserBox;
+use crate::{PlainText, UserTrait};
+
+/// A user that carries labels such as an email address, a note, a plan or tags,
+/// for admin tooling.
+pub trait UserWithMeta: UserTrait {
+    fn meta(&self) -> &HashMap<String, String>;
+
+    fn meta_value(&self, key: &str) -> Option<&str> {
+        self.meta().get(key).map(String::as_str)
+    }
+}
+
+/// Adds metadata to any existing user type.
+///
+/// `MetaUser<PlainText>` and `MetaUser<UserBox>` implement [`UserTrait`], so they can be
+/// stored in a [`UsersMap`](crate::UsersMap) and serialized as trait objects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MetaUser<T> {
+    pub user: T,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    pub meta: HashMap<String, String>,
+}
+
+impl<T> MetaUser<T> {
+    pub fn new(user: T) -> Self {
+        MetaUser {
+            user,
+            meta: HashMap::new(),
+        }
+    }
+
+    /// Sets a label, returning self for chaining.
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.meta.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn into_inner(self) -> T {
+        self.user
+    }
+}
+
+impl<T> Deref for MetaUser<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.user
+    }
+}
+
+impl_wrapper_user_trait!(MetaUser<PlainText>, "MetaPlainText");
+#[cfg(feature = "dyn-clone")]
+impl_wrapper_user_trait!(MetaUser<UserBox>, "MetaUserBox");
+
+impl<T> UserWithMeta for MetaUser<T>
+where
+    MetaUser<T>: UserTrait,
+{
+    fn meta(&self) -> &HashMap<String, String> {
+        &self.meta
+    }
+}
+
+#[cfg(all(test, feature = "serde", feature = "dyn-clone"))]
+mod test {
+    use super::{MetaUser, UserWithMeta};
+    use crate::{PlainText, UserBox};
+
+    #[test]
+    fn test_meta_serde() -> Result<(), Box<dyn std::error::Error>> {
+        let user = MetaUser::new(PlainText::from("u p"))
+            .with("email", "u@example.com")
+            .with("plan", "pro");
+        assert_eq!(user.meta_value("plan"), Some("pro"));
+        assert_eq!(user.meta_value("note"), None);
+
+        let boxed = UserBox::new(user.clone());
+        let s = serde_json::to_string(&boxed)?;
+        assert!(s.starts_with(r#"{"MetaPlainText":"#));
+
+        let back: MetaUser<PlainText> = serde_json::from_str(&serde_json::to_string(&user)?)?;
+        assert_eq!(back, user);
+        Ok(())
+    }
+}
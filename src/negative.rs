@@ -0,0 +1,243 @@
+/*!
+A bloom filter of the existing credentials in front of a slow authenticator.
+*/
+
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+
+use crate::{AsyncUserAuthenticator, AuthError, User, UserAuthenticator, UserTrait, UsersMap};
+
+/// A bloom filter over auth strings.
+///
+/// Its hasher has random keys, so that clients can't craft auth strings that
+/// collide with existing ones.
+#[derive(Debug)]
+struct Bloom {
+    bits: Vec<u64>,
+    num_bits: u64,
+    hashes: u32,
+    hasher: RandomState,
+}
+
+impl Bloom {
+    /// Sizes the filter for `items` keys and the given false positive rate.
+    fn new(items: usize, false_positive_rate: f64) -> Self {
+        let items = items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-items * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let hashes = ((num_bits as f64 / items) * ln2).round().clamp(1.0, 16.0) as u32;
+        Bloom {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            hashes,
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// The bits of `key`, by double hashing
+    fn indexes(&self, key: &str) -> impl Iterator<Item = u64> {
+        let h1 = self.hasher.hash_one(key);
+        let h2 = self.hasher.hash_one((key, 1u8)) | 1;
+        let num_bits = self.num_bits;
+        (0..u64::from(self.hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    fn insert(&mut self, key: &str) {
+        for i in self.indexes(key).collect::<Vec<_>>() {
+            self.bits[(i / 64) as usize] |= 1 << (i % 64);
+        }
+    }
+
+    fn may_contain(&self, key: &str) -> bool {
+        self.indexes(key)
+            .all(|i| self.bits[(i / 64) as usize] & (1 << (i % 64)) != 0)
+    }
+}
+
+/// Rejects auth strings that certainly don't exist before they reach a slow backend,
+/// e.g. a [`RedisUserStore`](crate::RedisUserStore) or an LDAP server, so that
+/// credential stuffing costs no backend request.
+///
+/// The filter is built from every existing credential; an auth string that passes it
+/// exists, or is a false positive, at about `false_positive_rate`, and is checked by the
+/// backend. Credentials added to the backend are refused until the filter is rebuilt with
+/// them, so rebuild it on every change of the user set: with [`NegativeFilter::rebuild`],
+/// or [`NegativeFilter::sync`] for a [`UsersMap`] mirroring the backend.
+#[derive(Debug)]
+pub struct NegativeFilter<A> {
+    backend: A,
+    false_positive_rate: f64,
+    filter: RwLock<Bloom>,
+
+    /// The instance and [`UsersMap::generation`] of the map the filter was built from,
+    /// if it was built from a map
+    synced: Mutex<Option<(u64, u64)>>,
+
+    rejected: AtomicU64,
+}
+
+impl<A> NegativeFilter<A> {
+    /// Creates a filter of `credentials` in front of `backend`.
+    ///
+    /// # Panics
+    ///
+    /// If `false_positive_rate` is not between 0 and 1, exclusive.
+    pub fn new<I>(backend: A, credentials: I, false_positive_rate: f64) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "the false positive rate must be between 0 and 1"
+        );
+        NegativeFilter {
+            backend,
+            false_positive_rate,
+            filter: RwLock::new(Self::build(credentials, false_positive_rate)),
+            synced: Mutex::new(None),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    fn build<I>(credentials: I, false_positive_rate: f64) -> Bloom
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let credentials: Vec<_> = credentials.into_iter().collect();
+        let mut bloom = Bloom::new(credentials.len(), false_positive_rate);
+        for c in &credentials {
+            bloom.insert(c.as_ref());
+        }
+        bloom
+    }
+
+    /// Replaces the filter with one of `credentials`, sized for their number.
+    pub fn rebuild<I>(&self, credentials: I)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let bloom = Self::build(credentials, self.false_positive_rate);
+        *self.filter.write().unwrap_or_else(PoisonError::into_inner) = bloom;
+        *self.synced() = None;
+    }
+
+    fn synced(&self) -> std::sync::MutexGuard<'_, Option<(u64, u64)>> {
+        self.synced.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Rebuilds the filter from every credential of `map`, unless it was already built
+    /// from this generation of this very map. Returns true if it was rebuilt.
+    ///
+    /// Call it after changing the map, or swapping another one in, or periodically.
+    pub fn sync<T: UserTrait + Clone, S: BuildHasher>(&self, map: &UsersMap<T, S>) -> bool {
+        let version = Some((map.instance(), map.generation()));
+        if *self.synced() == version {
+            return false;
+        }
+        let credentials = map
+            .iter()
+            .filter_map(|u| map.credentials(u.identity_str()))
+            .flatten();
+        self.rebuild(credentials);
+        *self.synced() = version;
+        true
+    }
+
+    /// Returns false if `authstr` is certainly not a known credential.
+    pub fn may_exist(&self, authstr: &str) -> bool {
+        self.filter
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .may_contain(authstr)
+    }
+
+    /// Number of auth strings rejected without asking the backend
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn backend(&self) -> &A {
+        &self.backend
+    }
+
+    fn check(&self, authstr: &str) -> Result<(), AuthError> {
+        if self.may_exist(authstr) {
+            Ok(())
+        } else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            Err(AuthError::UnknownUser)
+        }
+    }
+
+    /// Authenticates through an asynchronous backend, after the same check as
+    /// [`UserAuthenticator::try_auth`].
+    pub async fn try_auth_async<T>(&self, authstr: &str) -> Result<T, AuthError>
+    where
+        T: User + 'static,
+        A: AsyncUserAuthenticator<T> + Sync,
+    {
+        self.check(authstr)?;
+        AsyncUserAuthenticator::try_auth(&self.backend, authstr).await
+    }
+}
+
+impl<T: User, A: UserAuthenticator<T>> UserAuthenticator<T> for NegativeFilter<A> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.try_auth(authstr).ok()
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.check(authstr)?;
+        self.backend.try_auth(authstr)
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        self.check(authstr)?;
+        self.backend.try_auth_as(identity, authstr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::NegativeFilter;
+    use crate::{AuthError, PlainText, SharedUsersMap, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_negative_filter() {
+        let shared = Arc::new(SharedUsersMap::new(UsersMap::new()));
+        shared.add_user(PlainText::from("u p"));
+        let filter = NegativeFilter::new(Arc::clone(&shared), ["plaintext:u\np"], 0.01);
+
+        assert!(filter.auth_user_by_authstr("plaintext:u\np").is_some());
+        for i in 0..1000 {
+            let result = filter.try_auth(&format!("plaintext:u\n{i}"));
+            assert!(matches!(result, Err(AuthError::UnknownUser)));
+        }
+        // Most never reached the backend
+        assert!(filter.rejected() > 950);
+
+        // New users are refused until the filter is rebuilt
+        shared.add_user(PlainText::from("v p"));
+        assert!(filter.auth_user_by_authstr("plaintext:v\np").is_none());
+        assert!(filter.sync(&shared.read()));
+        assert!(!filter.sync(&shared.read()));
+        assert!(filter.auth_user_by_authstr("plaintext:v\np").is_some());
+
+        // A map swapped in after as many changes has the same generation.
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p2"));
+        um.add_user(PlainText::from("v p2"));
+        assert_eq!(um.generation(), shared.read().generation());
+        shared.swap(um);
+        assert!(filter.sync(&shared.read()));
+        assert!(filter.auth_user_by_authstr("plaintext:u\np2").is_some());
+    }
+}
@@ -0,0 +1,315 @@
+/*!
+Binding users to the networks they may connect from.
+*/
+
+use std::fmt;
+use std::net::IpAddr;
+use std::ops::Deref;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "dyn-clone")]
+use crate::UserBox;
+use crate::{
+    AuthContext, AuthError, ContextAuthenticator, PlainText, User, UserAuthenticator, UserTrait,
+};
+
+/// An IPv4 or IPv6 network in CIDR notation, e.g. `10.0.0.0/8` or `2001:db8::/32`.
+///
+/// A bare address is parsed as a network containing only that address.
+/// Host bits below the prefix are ignored when matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+/// Error returned when parsing a [`Cidr`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CidrParseError(String);
+
+impl fmt::Display for CidrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CIDR {:?}", self.0)
+    }
+}
+
+impl std::error::Error for CidrParseError {}
+
+impl Cidr {
+    /// Returns `None` if `prefix_len` is longer than the address.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        (prefix_len <= max).then_some(Cidr { addr, prefix_len })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns true if `ip` is in this network. IPv4-mapped IPv6 addresses match IPv4 networks
+    /// and the other way around.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match (self.addr, ip) {
+            (IpAddr::V4(_), ip) => ip.to_canonical(),
+            (IpAddr::V6(_), IpAddr::V4(ip)) => IpAddr::V6(ip.to_ipv6_mapped()),
+            (IpAddr::V6(_), ip) => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = CidrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || CidrParseError(s.to_string());
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len.parse::<u8>().map_err(|_| err())?)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| err())?;
+        let prefix_len = prefix_len.unwrap_or(if addr.is_ipv4() { 32 } else { 128 });
+        Cidr::new(addr, prefix_len).ok_or_else(err)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Cidr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A user restricted to some source networks.
+///
+/// An address in a denied network is refused even if it is also in an allowed one.
+/// An empty allow list allows every address that is not denied.
+pub trait UserWithNetworkPolicy: UserTrait {
+    fn allowed_networks(&self) -> &[Cidr];
+
+    fn denied_networks(&self) -> &[Cidr] {
+        &[]
+    }
+
+    /// Returns true if the user has any network restriction.
+    fn is_network_restricted(&self) -> bool {
+        !self.allowed_networks().is_empty() || !self.denied_networks().is_empty()
+    }
+}
+
+/// Returns true if `user` may connect from `ip`.
+pub fn authorize_source<U: UserWithNetworkPolicy + ?Sized>(user: &U, ip: IpAddr) -> bool {
+    let allowed = user.allowed_networks();
+    !user.denied_networks().iter().any(|n| n.contains(ip))
+        && (allowed.is_empty() || allowed.iter().any(|n| n.contains(ip)))
+}
+
+/// A user together with the networks it may connect from.
+///
+/// `NetworkUser<PlainText>` and `NetworkUser<UserBox>` implement [`UserTrait`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NetworkUser<T> {
+    pub user: T,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allow: Vec<Cidr>,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub deny: Vec<Cidr>,
+}
+
+impl<T> NetworkUser<T> {
+    pub fn new(user: T, allow: Vec<Cidr>) -> Self {
+        NetworkUser {
+            user,
+            allow,
+            deny: Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.user
+    }
+}
+
+impl<T> Deref for NetworkUser<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.user
+    }
+}
+
+impl_wrapper_user_trait!(NetworkUser<PlainText>, "NetworkPlainText");
+#[cfg(feature = "dyn-clone")]
+impl_wrapper_user_trait!(NetworkUser<UserBox>, "NetworkUserBox");
+
+impl<T> UserWithNetworkPolicy for NetworkUser<T>
+where
+    NetworkUser<T>: UserTrait,
+{
+    fn allowed_networks(&self) -> &[Cidr] {
+        &self.allow
+    }
+
+    fn denied_networks(&self) -> &[Cidr] {
+        &self.deny
+    }
+}
+
+/// Wraps an authenticator, refusing users whose network policy doesn't allow the
+/// source address of the attempt with [`AuthError::Forbidden`].
+///
+/// When the source address is unknown, only users without restrictions are admitted,
+/// which is also what [`UserAuthenticator::try_auth`] does. The context is passed on to
+/// the backend through [`ContextAuthenticator`].
+#[derive(Debug, Clone)]
+pub struct NetworkPolicyAuthenticator<A> {
+    backend: A,
+}
+
+impl<A> NetworkPolicyAuthenticator<A> {
+    pub fn new(backend: A) -> Self {
+        NetworkPolicyAuthenticator { backend }
+    }
+
+    pub fn backend(&self) -> &A {
+        &self.backend
+    }
+
+    pub fn into_inner(self) -> A {
+        self.backend
+    }
+}
+
+impl<T, A> ContextAuthenticator<T> for NetworkPolicyAuthenticator<A>
+where
+    T: User + UserWithNetworkPolicy,
+    A: ContextAuthenticator<T>,
+{
+    /// Authenticates `authstr`, then checks the user's policy against `cx.source_addr`.
+    fn auth_with_context(&self, authstr: &str, cx: &AuthContext) -> Result<T, AuthError> {
+        let user = self.backend.auth_with_context(authstr, cx)?;
+        let allowed = match cx.source_addr {
+            Some(ip) => authorize_source(&user, ip),
+            None => !user.is_network_restricted(),
+        };
+        if allowed {
+            Ok(user)
+        } else {
+            Err(AuthError::Forbidden)
+        }
+    }
+}
+
+impl<T, A> UserAuthenticator<T> for NetworkPolicyAuthenticator<A>
+where
+    T: User + UserWithNetworkPolicy,
+    A: ContextAuthenticator<T>,
+{
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.try_auth(authstr).ok()
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.auth_with_context(authstr, &AuthContext::new())
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        self.auth_with_context(authstr, &AuthContext::new().with_identity(identity))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use super::{authorize_source, Cidr, NetworkPolicyAuthenticator, NetworkUser};
+    use crate::{
+        AuthContext, AuthError, ContextAuthenticator, PlainText, UserAuthenticator, UsersMap,
+    };
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(net.contains(ip("10.1.200.3")));
+        assert!(!net.contains(ip("10.2.0.1")));
+        assert!(net.contains(ip("::ffff:10.1.0.1")));
+        let mapped: Cidr = "::ffff:10.1.0.0/112".parse().unwrap();
+        assert!(mapped.contains(ip("10.1.2.3")));
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("8.8.8.8")));
+        assert!("2001:db8::/32"
+            .parse::<Cidr>()
+            .unwrap()
+            .contains(ip("2001:db8:1::1")));
+        assert_eq!("1.2.3.4".parse::<Cidr>().unwrap().to_string(), "1.2.3.4/32");
+        assert!("1.2.3.4/33".parse::<Cidr>().is_err());
+        assert!("1.2.3/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_network_policy() {
+        let mut user =
+            NetworkUser::new(PlainText::from("u p"), vec!["10.0.0.0/8".parse().unwrap()]);
+        user.deny.push("10.9.0.0/16".parse().unwrap());
+        assert!(authorize_source(&user, ip("10.1.1.1")));
+        assert!(!authorize_source(&user, ip("10.9.1.1")));
+        assert!(!authorize_source(&user, ip("192.168.0.1")));
+
+        let mut um = UsersMap::new();
+        um.add_user(user);
+        um.add_user(NetworkUser::new(PlainText::from("free p"), Vec::new()));
+        let auth = NetworkPolicyAuthenticator::new(um);
+
+        let cx = AuthContext::new().with_source_addr(ip("10.1.1.1"));
+        assert!(auth.auth_with_context("plaintext:u\np", &cx).is_ok());
+        let cx = AuthContext::new().with_source_addr(ip("192.168.0.1"));
+        assert!(matches!(
+            auth.auth_with_context("plaintext:u\np", &cx),
+            Err(AuthError::Forbidden)
+        ));
+        assert!(auth.auth_with_context("plaintext:free\np", &cx).is_ok());
+        assert!(auth.auth_user_by_authstr("plaintext:u\np").is_none());
+        assert!(auth.auth_user_by_authstr("plaintext:free\np").is_some());
+    }
+}
@@ -0,0 +1,61 @@
+/*!
+Secrets read from the OS keyring (Secret Service, Keychain, Windows Credential Manager),
+so that desktop deployments don't need passwords or tokens in their config files.
+
+Requires the `keyring` feature.
+*/
+
+use std::io;
+
+use keyring::Entry;
+
+use crate::PlainText;
+
+fn keyring_error(e: keyring::Error) -> io::Error {
+    match e {
+        keyring::Error::NoEntry => io::Error::new(io::ErrorKind::NotFound, e),
+        keyring::Error::NoStorageAccess(_) | keyring::Error::PlatformFailure(_) => {
+            io::Error::other(e)
+        }
+        _ => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// Reads the secret stored in the OS keyring for `account` under `service`.
+///
+/// A missing entry is reported as [`io::ErrorKind::NotFound`].
+pub fn keyring_secret(service: &str, account: &str) -> io::Result<String> {
+    Entry::new(service, account)
+        .and_then(|entry| entry.get_password())
+        .map_err(keyring_error)
+}
+
+/// Stores `secret` in the OS keyring for `account` under `service`, replacing any
+/// previous one, e.g. when provisioning a machine.
+pub fn set_keyring_secret(service: &str, account: &str, secret: &str) -> io::Result<()> {
+    Entry::new(service, account)
+        .and_then(|entry| entry.set_password(secret))
+        .map_err(keyring_error)
+}
+
+impl PlainText {
+    /// Creates the user `user`, with the password stored in the OS keyring under
+    /// `service`, with the user name as account.
+    pub fn from_keyring(service: &str, user: impl Into<String>) -> io::Result<Self> {
+        let user = user.into();
+        let pass = keyring_secret(service, &user)?;
+        Ok(PlainText::new(user, pass))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::PlainText;
+
+    #[test]
+    fn test_keyring_missing_entry() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+        let err = PlainText::from_keyring("user_trait_test", "nobody").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,288 @@
+/*!
+Durable JSON storage of a [`UsersMap`].
+*/
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{GroupsMap, UserTrait, UsersMap};
+
+fn enabled_default() -> bool {
+    true
+}
+
+fn is_true(b: &bool) -> bool {
+    *b
+}
+
+/// The on-disk form of one user of a [`UsersMap`].
+#[derive(Serialize, Deserialize)]
+struct StoredUser<T> {
+    user: T,
+
+    /// Credentials added by [`UsersMap::add_credential`]. The previous auth string of a
+    /// rotated user is not one; the user keeps it with its grace window.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extra_credentials: Vec<String>,
+
+    #[serde(default = "enabled_default", skip_serializing_if = "is_true")]
+    enabled: bool,
+}
+
+/// The on-disk form of a [`UsersMap`], e.g.
+///
+/// ```json
+/// {"users":[{"user":{"user":"u","pass":"p","auth_str":"plaintext:u\np"}}]}
+/// ```
+#[derive(Serialize, Deserialize)]
+struct StoredMap<T> {
+    users: Vec<StoredUser<T>>,
+
+    #[serde(default, skip_serializing_if = "GroupsMap::is_empty")]
+    groups: GroupsMap,
+}
+
+/// Where [`UsersMap::save`] writes and [`UsersMap::load`] reads a serialized map, so that
+/// it can be kept elsewhere than in a file, e.g. in the local storage of a browser.
+pub trait UsersStorage {
+    /// Reads the stored contents, failing with [`io::ErrorKind::NotFound`] if nothing
+    /// was written yet.
+    fn read(&self) -> io::Result<Vec<u8>>;
+
+    /// Replaces the stored contents. A failed write must leave the previous contents readable.
+    fn write(&self, contents: &[u8]) -> io::Result<()>;
+}
+
+/// A [`UsersStorage`] in a file, replaced atomically, see [`UsersMap::save_to_path`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStorage(PathBuf);
+
+impl FileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStorage(path.into())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl UsersStorage for FileStorage {
+    fn read(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.0)
+    }
+
+    fn write(&self, contents: &[u8]) -> io::Result<()> {
+        write_atomic(&self.0, contents)
+    }
+}
+
+/// A [`UsersStorage`] in memory, for tests and platforms without a filesystem.
+/// Clones share the contents. Its `Debug` output doesn't show them.
+#[derive(Clone, Default)]
+pub struct MemoryStorage(Arc<Mutex<Option<Vec<u8>>>>);
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl UsersStorage for MemoryStorage {
+    fn read(&self) -> io::Result<Vec<u8>> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn write(&self, contents: &[u8]) -> io::Result<()> {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(contents.to_vec());
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MemoryStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MemoryStorage(..)")
+    }
+}
+
+impl<T: UserTrait + Clone + Serialize, S: std::hash::BuildHasher> UsersMap<T, S> {
+    /// Writes the users, their extra credentials, enabled flags and groups to `path` as JSON.
+    ///
+    /// The file is written to a temporary file next to `path` first and then renamed over it,
+    /// so a crash never leaves a truncated user file behind.
+    /// Users are written ordered by identity, so unchanged maps produce identical files.
+    pub fn save_to_path(&self, path: &Path) -> io::Result<()> {
+        self.save(&FileStorage::new(path))
+    }
+
+    /// Writes the map to `storage` in the format of [`UsersMap::save_to_path`].
+    pub fn save(&self, storage: &impl UsersStorage) -> io::Result<()> {
+        storage.write(&self.to_json()?)
+    }
+
+    /// Serializes the map in the format of [`UsersMap::save_to_path`].
+    pub(crate) fn to_json(&self) -> io::Result<Vec<u8>> {
+        let mut users: Vec<_> = self.iter().collect();
+        users.sort_by(|a, b| a.identity_str().cmp(b.identity_str()));
+
+        let stored = StoredMap {
+            users: users
+                .into_iter()
+                .map(|u| StoredUser {
+                    user: u.as_ref(),
+                    extra_credentials: self
+                        .added_credentials(u.identity_str())
+                        .map(|c| c.to_string())
+                        .collect(),
+                    enabled: self.is_enabled(u.identity_str()),
+                })
+                .collect(),
+            groups: self.groups().clone(),
+        };
+        Ok(serde_json::to_vec_pretty(&stored)?)
+    }
+}
+
+/// Writes to a temporary file next to `path`, then renames it over `path`.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+impl<T: UserTrait + Clone + DeserializeOwned> UsersMap<T> {
+    /// Reads a map written by [`UsersMap::save_to_path`].
+    ///
+    /// Malformed files are reported as [`io::ErrorKind::InvalidData`].
+    pub fn load_from_path(path: &Path) -> io::Result<Self> {
+        Self::load(&FileStorage::new(path))
+    }
+
+    /// Reads a map written by [`UsersMap::save`].
+    pub fn load(storage: &impl UsersStorage) -> io::Result<Self> {
+        Self::load_from_slice(&storage.read()?)
+    }
+
+    /// Parses the contents of a file written by [`UsersMap::save_to_path`].
+    pub fn load_from_slice(json: &[u8]) -> io::Result<Self> {
+        let stored: StoredMap<T> = serde_json::from_slice(json)?;
+
+        let mut map = UsersMap::new();
+        for stored_user in stored.users {
+            let id = stored_user.user.identity_str().to_string();
+            map.add_user(stored_user.user);
+            for c in &stored_user.extra_credentials {
+                map.add_credential(&id, c);
+            }
+            if !stored_user.enabled {
+                map.set_enabled(&id, false);
+            }
+        }
+        map.set_groups(stored.groups);
+        Ok(map)
+    }
+}
+
+/// `users.json` -> `.users.json.tmp` in the same directory, so that the rename stays atomic.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::MemoryStorage;
+    use crate::{ManualClock, PlainText, RotatingUser, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_save_load() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("user_trait_persist_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("users.json");
+
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::new("u".into(), "p".into()));
+        um.add_user(PlainText::new("u2".into(), "p2".into()));
+        um.add_credential("u", "token:abc");
+        um.set_enabled("u2", false);
+        um.groups_mut().add_to_group("admins", "u");
+        um.save_to_path(&path)?;
+
+        let loaded: UsersMap<PlainText> = UsersMap::load_from_path(&path)?;
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.auth_user_by_authstr("token:abc").is_some());
+        assert!(!loaded.is_enabled("u2"));
+        assert!(loaded.groups().is_member("admins", "u"));
+
+        std::fs::write(&path, "not json")?;
+        let err = UsersMap::<PlainText>::load_from_path(&path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_load_rotated() -> std::io::Result<()> {
+        let hour = Duration::from_secs(3600);
+        let clock = ManualClock::new(SystemTime::now());
+        let mut user =
+            RotatingUser::new(PlainText::from("u old")).with_clock(Arc::new(clock.clone()));
+        user.rotate(PlainText::from("u new"), hour);
+        let mut um = UsersMap::new();
+        um.add_user(user.clone());
+
+        let storage = MemoryStorage::new();
+        um.save(&storage)?;
+        let loaded: UsersMap<RotatingUser<PlainText>> = UsersMap::load(&storage)?;
+        assert!(loaded.try_auth("plaintext:u\nold").is_ok());
+
+        // Rotated in the past, so the window is still open in `um` but closed once
+        // loaded with the system clock; the previous credential isn't kept for good
+        clock.set(SystemTime::now() - 2 * hour);
+        user.rotate(PlainText::from("u newer"), hour);
+        um.add_user(user);
+        assert!(um.try_auth("plaintext:u\nnew").is_ok());
+        um.save(&storage)?;
+        let loaded: UsersMap<RotatingUser<PlainText>> = UsersMap::load(&storage)?;
+        assert!(loaded.try_auth("plaintext:u\nnew").is_err());
+        assert!(loaded.try_auth("plaintext:u\nnewer").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_storage() -> std::io::Result<()> {
+        let storage = MemoryStorage::new();
+        let err = UsersMap::<PlainText>::load(&storage).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+        um.save(&storage.clone())?;
+
+        let loaded: UsersMap<PlainText> = UsersMap::load(&storage)?;
+        assert!(loaded.auth_user_by_authstr("plaintext:u\np").is_some());
+        assert_eq!(format!("{storage:?}"), "MemoryStorage(..)");
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+/*!
+The traits and the common types of the crate, for a single glob import:
+
+```
+use user_trait::prelude::*;
+
+let mut users = UsersMap::new();
+users.add_user(PlainText::from("alice secret"));
+assert!(users.auth_user_by_authstr("plaintext:alice\nsecret").is_some());
+```
+
+Every [`UserAuthenticator`] is also an [`AsyncUserAuthenticator`](crate::AsyncUserAuthenticator)
+with methods of the same names, so async code imports [`prelude::asynchronous`](asynchronous)
+instead, which has the async traits in place of the blocking ones.
+*/
+
+pub use crate::{
+    AuthError, Error, PlainText, Result, User, UserAuthenticator, UserRef, UserStore, UserTrait,
+    UsersMap,
+};
+#[cfg(feature = "std")]
+pub use crate::{Clock, SharedUsersMap, UserSource};
+#[cfg(feature = "dyn-clone")]
+pub use crate::{UserBox, UserVec};
+
+/// The prelude for async code, with [`AsyncUserAuthenticator`] and [`AsyncUserStore`]
+/// in place of [`UserAuthenticator`](crate::UserAuthenticator) and
+/// [`UserStore`](crate::UserStore).
+#[cfg(feature = "std")]
+pub mod asynchronous {
+    pub use crate::{
+        AsyncUserAuthenticator, AsyncUserStore, AuthError, Clock, Error, PlainText, Result,
+        SharedUsersMap, User, UserRef, UserSource, UserTrait, UsersMap,
+    };
+    #[cfg(feature = "dyn-clone")]
+    pub use crate::{UserBox, UserVec};
+}
@@ -0,0 +1,227 @@
+/*!
+Per-user traffic quotas and byte accounting.
+*/
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::interner::intern_with;
+use crate::{IdInterner, UserTrait};
+
+/// A user with traffic limits, in bytes. `None` means unlimited.
+pub trait UserWithQuota: UserTrait {
+    fn upload_limit(&self) -> Option<u64>;
+
+    fn download_limit(&self) -> Option<u64>;
+}
+
+/// Bytes transferred by one identity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrafficUsage {
+    pub upload: u64,
+    pub download: u64,
+}
+
+impl TrafficUsage {
+    /// Returns true if either direction reached the user's limit.
+    pub fn exceeds<U: UserWithQuota + ?Sized>(&self, user: &U) -> bool {
+        user.upload_limit().is_some_and(|l| self.upload >= l)
+            || user.download_limit().is_some_and(|l| self.download >= l)
+    }
+}
+
+/// The live byte counters of one identity.
+///
+/// Connection handlers can keep the `Arc` returned by [`TrafficAccountant::counter`]
+/// and count bytes without touching the accountant's map again.
+#[derive(Debug, Default)]
+pub struct TrafficCounter {
+    upload: AtomicU64,
+    download: AtomicU64,
+}
+
+impl TrafficCounter {
+    pub fn add_upload(&self, bytes: u64) {
+        self.upload.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_download(&self, bytes: u64) {
+        self.download.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn usage(&self) -> TrafficUsage {
+        TrafficUsage {
+            upload: self.upload.load(Ordering::Relaxed),
+            download: self.download.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.upload.store(0, Ordering::Relaxed);
+        self.download.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Tracks the bytes transferred per identity and reports who exceeded their quota.
+#[derive(Debug, Default)]
+pub struct TrafficAccountant {
+    /// Maps user identity strings to their counters
+    counters: RwLock<HashMap<Arc<str>, Arc<TrafficCounter>>>,
+
+    interner: Option<Arc<IdInterner>>,
+}
+
+impl TrafficAccountant {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the identities of new counters from `interner`.
+    pub fn with_interner(mut self, interner: Arc<IdInterner>) -> Self {
+        self.interner = Some(interner);
+        self
+    }
+
+    /// Returns the counter of an identity, creating it if needed.
+    pub fn counter(&self, id: &str) -> Arc<TrafficCounter> {
+        if let Some(c) = self
+            .counters
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+        {
+            return Arc::clone(c);
+        }
+        let mut counters = self
+            .counters
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(c) = counters.get(id) {
+            return Arc::clone(c);
+        }
+        let c = Arc::new(TrafficCounter::default());
+        counters.insert(intern_with(self.interner.as_deref(), id), Arc::clone(&c));
+        c
+    }
+
+    pub fn add_upload(&self, id: &str, bytes: u64) {
+        self.counter(id).add_upload(bytes)
+    }
+
+    pub fn add_download(&self, id: &str, bytes: u64) {
+        self.counter(id).add_download(bytes)
+    }
+
+    /// Returns the bytes transferred by an identity; zero if it never transferred anything.
+    pub fn usage(&self, id: &str) -> TrafficUsage {
+        self.counters
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .map(|c| c.usage())
+            .unwrap_or_default()
+    }
+
+    /// Returns the usage of every identity seen so far
+    pub fn snapshot(&self) -> HashMap<String, TrafficUsage> {
+        self.counters
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(id, c)| (id.to_string(), c.usage()))
+            .collect()
+    }
+
+    pub fn is_over_quota<U: UserWithQuota + ?Sized>(&self, user: &U) -> bool {
+        self.usage(user.identity_str()).exceeds(user)
+    }
+
+    /// Returns the identities of the given users that exceeded their quota
+    pub fn exceeded<'a, U, I>(&self, users: I) -> Vec<String>
+    where
+        U: UserWithQuota + ?Sized + 'a,
+        I: IntoIterator<Item = &'a U>,
+    {
+        users
+            .into_iter()
+            .filter(|u| self.is_over_quota(*u))
+            .map(|u| u.identity_str().to_string())
+            .collect()
+    }
+
+    /// Resets the counters of an identity, e.g. at the start of a billing period.
+    pub fn reset(&self, id: &str) {
+        if let Some(c) = self
+            .counters
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+        {
+            c.reset()
+        }
+    }
+
+    /// Forgets every counter. Counters held by connection handlers stop being reported.
+    pub fn clear(&self) {
+        self.counters
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    use super::{TrafficAccountant, UserWithQuota};
+    use crate::PlainText;
+
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct QuotaUser {
+        user: PlainText,
+        upload: Option<u64>,
+        download: Option<u64>,
+    }
+
+    impl_wrapper_user_trait!(QuotaUser, "QuotaUser");
+
+    impl UserWithQuota for QuotaUser {
+        fn upload_limit(&self) -> Option<u64> {
+            self.upload
+        }
+
+        fn download_limit(&self) -> Option<u64> {
+            self.download
+        }
+    }
+
+    #[test]
+    fn test_quota() {
+        let limited = QuotaUser {
+            user: PlainText::from("a p"),
+            upload: None,
+            download: Some(100),
+        };
+        let unlimited = QuotaUser {
+            user: PlainText::from("b p"),
+            upload: None,
+            download: None,
+        };
+
+        let accountant = TrafficAccountant::new();
+        let counter = accountant.counter("a");
+        counter.add_download(60);
+        accountant.add_download("a", 40);
+        accountant.add_upload("b", 1 << 40);
+
+        assert_eq!(accountant.usage("a").download, 100);
+        assert_eq!(accountant.exceeded([&limited, &unlimited]), ["a"]);
+
+        accountant.reset("a");
+        assert!(!accountant.is_over_quota(&limited));
+    }
+}
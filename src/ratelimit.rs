@@ -0,0 +1,200 @@
+/*!
+Per-user connection limits.
+*/
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use crate::clock::Instant;
+use crate::UserTrait;
+
+/// A user with connection limits. `None` means unlimited.
+pub trait UserWithRateLimit: UserTrait {
+    /// Maximum number of simultaneously open connections
+    fn max_connections(&self) -> Option<usize>;
+
+    /// Maximum number of connections opened within any one-minute window
+    fn max_conn_per_minute(&self) -> Option<usize>;
+}
+
+/// Why [`ConnLimiter::acquire`] refused a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    TooManyConnections { limit: usize },
+    TooManyNewConnections { limit: usize },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::TooManyConnections { limit } => {
+                write!(f, "more than {limit} simultaneous connections")
+            }
+            LimitExceeded::TooManyNewConnections { limit } => {
+                write!(f, "more than {limit} new connections per minute")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct ConnState {
+    live: usize,
+
+    /// Start times of the connections opened within the last minute
+    recent: VecDeque<Instant>,
+}
+
+type States = Arc<Mutex<HashMap<String, ConnState>>>;
+
+fn lock(states: &States) -> MutexGuard<'_, HashMap<String, ConnState>> {
+    states.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Tracks the live connections of each identity and enforces [`UserWithRateLimit`] limits.
+///
+/// A connection counts as live until its [`ConnGuard`] is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct ConnLimiter {
+    /// Maps user identity strings to their connection state
+    states: States,
+}
+
+impl ConnLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new connection of `user`, unless it would exceed one of its limits.
+    pub fn acquire<U: UserWithRateLimit + ?Sized>(
+        &self,
+        user: &U,
+    ) -> Result<ConnGuard, LimitExceeded> {
+        let now = Instant::now();
+        let mut states = lock(&self.states);
+        let state = states.entry(user.identity_str().to_string()).or_default();
+
+        while state
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= WINDOW)
+        {
+            state.recent.pop_front();
+        }
+        if let Some(limit) = user.max_connections() {
+            if state.live >= limit {
+                return Err(LimitExceeded::TooManyConnections { limit });
+            }
+        }
+        if let Some(limit) = user.max_conn_per_minute() {
+            if state.recent.len() >= limit {
+                return Err(LimitExceeded::TooManyNewConnections { limit });
+            }
+        }
+
+        state.live += 1;
+        state.recent.push_back(now);
+        Ok(ConnGuard {
+            states: Arc::clone(&self.states),
+            id: user.identity_str().to_string(),
+        })
+    }
+
+    /// Number of live connections of an identity
+    pub fn live_connections(&self, id: &str) -> usize {
+        lock(&self.states).get(id).map_or(0, |s| s.live)
+    }
+
+    /// Forgets identities without live connections or connections opened within the last minute.
+    pub fn purge_idle(&self) {
+        let now = Instant::now();
+        lock(&self.states).retain(|_, s| {
+            s.live > 0
+                || s.recent
+                    .back()
+                    .is_some_and(|t| now.duration_since(*t) < WINDOW)
+        });
+    }
+}
+
+/// A live connection registered by [`ConnLimiter::acquire`]; dropping it releases the connection.
+#[derive(Debug)]
+pub struct ConnGuard {
+    states: States,
+    id: String,
+}
+
+impl ConnGuard {
+    pub fn identity(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        if let Some(state) = lock(&self.states).get_mut(&self.id) {
+            state.live = state.live.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    use super::{ConnLimiter, LimitExceeded, UserWithRateLimit};
+    use crate::PlainText;
+
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct LimitedUser {
+        user: PlainText,
+        max: Option<usize>,
+        per_minute: Option<usize>,
+    }
+
+    impl_wrapper_user_trait!(LimitedUser, "LimitedUser");
+
+    impl UserWithRateLimit for LimitedUser {
+        fn max_connections(&self) -> Option<usize> {
+            self.max
+        }
+
+        fn max_conn_per_minute(&self) -> Option<usize> {
+            self.per_minute
+        }
+    }
+
+    #[test]
+    fn test_conn_limiter() {
+        let limiter = ConnLimiter::new();
+        let user = LimitedUser {
+            user: PlainText::from("u p"),
+            max: Some(2),
+            per_minute: Some(3),
+        };
+
+        let g1 = limiter.acquire(&user).unwrap();
+        let _g2 = limiter.acquire(&user).unwrap();
+        assert_eq!(
+            limiter.acquire(&user).unwrap_err(),
+            LimitExceeded::TooManyConnections { limit: 2 }
+        );
+        assert_eq!(limiter.live_connections("u"), 2);
+
+        drop(g1);
+        let _g3 = limiter.acquire(&user).unwrap();
+        drop(_g3);
+        assert_eq!(
+            limiter.acquire(&user).unwrap_err(),
+            LimitExceeded::TooManyNewConnections { limit: 3 }
+        );
+    }
+}
@@ -0,0 +1,287 @@
+/*!
+Users stored in Redis, shared by every node of a deployment.
+
+Requires the `redis` feature.
+*/
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client, RedisResult};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    AsyncUserAuthenticator, AuthError, CachedAuthenticator, StoreError, UserBox, UserTrait,
+};
+
+/// Key names of a [`RedisUserStore`], all starting with its prefix.
+#[derive(Debug, Clone)]
+struct Keys {
+    prefix: String,
+}
+
+impl Keys {
+    /// Hash with the fields `data` (the user as JSON) and `cred` (the digest of its auth string)
+    fn user(&self, id: &str) -> String {
+        format!("{}:user:{id}", self.prefix)
+    }
+
+    /// String holding the identity owning the credential. Keyed by a SHA-256 digest,
+    /// so that credentials don't show up in key listings.
+    fn credential(&self, digest: &str) -> String {
+        format!("{}:cred:{digest}", self.prefix)
+    }
+
+    /// Set of every identity
+    fn index(&self) -> String {
+        format!("{}:users", self.prefix)
+    }
+
+    /// Pub/sub channel receiving the identity of every changed user
+    fn channel(&self) -> String {
+        format!("{}:changed", self.prefix)
+    }
+}
+
+fn credential_digest(authstr: &str) -> String {
+    Sha256::digest(authstr.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// A user store in Redis, for deployments where several proxy nodes share one user set.
+///
+/// Each user is a hash keyed by identity, holding the user as JSON, with a secondary
+/// key per credential pointing back to the identity. Every change is published on a
+/// channel, so that nodes caching answers with a [`CachedAuthenticator`] can drop stale
+/// ones through [`RedisUserStore::invalidate_on_change`].
+///
+/// Users of any registered type are stored as [`UserBox`]es, tagged with their type.
+/// The CRUD methods mirror [`UserStore`](crate::UserStore), asynchronously.
+#[derive(Clone)]
+pub struct RedisUserStore {
+    client: Client,
+    conn: ConnectionManager,
+    keys: Keys,
+}
+
+impl std::fmt::Debug for RedisUserStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisUserStore")
+            .field("prefix", &self.keys.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RedisUserStore {
+    /// Connects to Redis. Every key starts with `prefix`, e.g. `"proxy"`.
+    pub async fn connect(client: Client, prefix: impl Into<String>) -> RedisResult<Self> {
+        let conn = client.get_connection_manager().await?;
+        Ok(RedisUserStore {
+            client,
+            conn,
+            keys: Keys {
+                prefix: prefix.into(),
+            },
+        })
+    }
+
+    fn encode(user: &UserBox) -> Result<String, StoreError> {
+        serde_json::to_string(user).map_err(StoreError::backend)
+    }
+
+    fn decode(data: &str) -> Result<UserBox, serde_json::Error> {
+        serde_json::from_str(data)
+    }
+
+    /// Adds a user, failing with [`StoreError::AlreadyExists`] if its identity is taken.
+    pub async fn add(&self, user: UserBox) -> Result<(), StoreError> {
+        let id = user.identity_str();
+        let data = Self::encode(&user)?;
+        let digest = credential_digest(user.auth_str());
+        let mut conn = self.conn.clone();
+
+        let created: bool = conn
+            .hset_nx(self.keys.user(id), "data", &data)
+            .await
+            .map_err(StoreError::backend)?;
+        if !created {
+            return Err(StoreError::AlreadyExists(id.to_string()));
+        }
+        redis::pipe()
+            .atomic()
+            .hset(self.keys.user(id), "cred", &digest)
+            .set(self.keys.credential(&digest), id)
+            .sadd(self.keys.index(), id)
+            .publish(self.keys.channel(), id)
+            .exec_async(&mut conn)
+            .await
+            .map_err(StoreError::backend)
+    }
+
+    /// Returns the stored user and the digest of its credential.
+    async fn get_with_digest(&self, id: &str) -> Result<Option<(UserBox, String)>, StoreError> {
+        let mut conn = self.conn.clone();
+        let (data, digest): (Option<String>, Option<String>) = redis::pipe()
+            .hget(self.keys.user(id), "data")
+            .hget(self.keys.user(id), "cred")
+            .query_async(&mut conn)
+            .await
+            .map_err(StoreError::backend)?;
+        match data {
+            Some(data) => {
+                let user = Self::decode(&data).map_err(StoreError::backend)?;
+                Ok(Some((user, digest.unwrap_or_default())))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<UserBox>, StoreError> {
+        Ok(self.get_with_digest(id).await?.map(|(user, _)| user))
+    }
+
+    /// Removes a user, returning it.
+    pub async fn remove(&self, id: &str) -> Result<UserBox, StoreError> {
+        let (user, digest) = self
+            .get_with_digest(id)
+            .await?
+            .ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+        let mut conn = self.conn.clone();
+        redis::pipe()
+            .atomic()
+            .del(self.keys.user(id))
+            .del(self.keys.credential(&digest))
+            .srem(self.keys.index(), id)
+            .publish(self.keys.channel(), id)
+            .exec_async(&mut conn)
+            .await
+            .map_err(StoreError::backend)?;
+        Ok(user)
+    }
+
+    /// Replaces the user having the same identity, returning the previous one.
+    pub async fn update(&self, user: UserBox) -> Result<UserBox, StoreError> {
+        let id = user.identity_str();
+        let (old, old_digest) = self
+            .get_with_digest(id)
+            .await?
+            .ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+        let data = Self::encode(&user)?;
+        let digest = credential_digest(user.auth_str());
+        let mut conn = self.conn.clone();
+        redis::pipe()
+            .atomic()
+            .del(self.keys.credential(&old_digest))
+            .hset_multiple(self.keys.user(id), &[("data", &data), ("cred", &digest)])
+            .set(self.keys.credential(&digest), id)
+            .publish(self.keys.channel(), id)
+            .exec_async(&mut conn)
+            .await
+            .map_err(StoreError::backend)?;
+        Ok(old)
+    }
+
+    /// Returns every user, in no particular order.
+    pub async fn list(&self) -> Result<Vec<UserBox>, StoreError> {
+        let mut conn = self.conn.clone();
+        let ids: Vec<String> = conn
+            .smembers(self.keys.index())
+            .await
+            .map_err(StoreError::backend)?;
+        let mut pipe = redis::pipe();
+        for id in &ids {
+            pipe.hget(self.keys.user(id), "data");
+        }
+        let data: Vec<Option<String>> = pipe
+            .query_async(&mut conn)
+            .await
+            .map_err(StoreError::backend)?;
+        data.into_iter()
+            .flatten()
+            .map(|d| Self::decode(&d).map_err(StoreError::backend))
+            .collect()
+    }
+
+    pub async fn count(&self) -> Result<usize, StoreError> {
+        let mut conn = self.conn.clone();
+        conn.scard(self.keys.index())
+            .await
+            .map_err(StoreError::backend)
+    }
+
+    /// Looks up the owner of `authstr`, reporting Redis failures as [`AuthError::Backend`].
+    pub async fn try_auth_async(&self, authstr: &str) -> Result<UserBox, AuthError> {
+        let mut conn = self.conn.clone();
+        let id: Option<String> = conn
+            .get(self.keys.credential(&credential_digest(authstr)))
+            .await
+            .map_err(AuthError::backend)?;
+        let Some(id) = id else {
+            return Err(AuthError::UnknownUser);
+        };
+        let data: Option<String> = conn
+            .hget(self.keys.user(&id), "data")
+            .await
+            .map_err(AuthError::backend)?;
+        let user = match data {
+            Some(data) => Self::decode(&data).map_err(AuthError::backend)?,
+            None => return Err(AuthError::UnknownUser),
+        };
+        // The index may briefly point to a user whose credential just changed
+        if user.auth_eq(authstr.as_bytes()) {
+            Ok(user)
+        } else {
+            Err(AuthError::UnknownUser)
+        }
+    }
+
+    /// Drops the cached answers of every user changed through any store with the same
+    /// prefix, until the subscription fails. Run it in a background task.
+    ///
+    /// Cached refusals of new credentials are not dropped; they expire after the
+    /// cache's negative TTL.
+    pub async fn invalidate_on_change<A>(
+        &self,
+        cache: &CachedAuthenticator<UserBox, A>,
+    ) -> RedisResult<()> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.subscribe(self.keys.channel()).await?;
+        let mut messages = pubsub.on_message();
+        while let Some(msg) = messages.next().await {
+            if let Ok(id) = msg.get_payload::<String>() {
+                cache.invalidate_identity(&id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AsyncUserAuthenticator<UserBox> for RedisUserStore {
+    async fn auth_user_by_authstr(&self, authstr: &str) -> Option<UserBox> {
+        self.try_auth_async(authstr).await.ok()
+    }
+
+    async fn try_auth(&self, authstr: &str) -> Result<UserBox, AuthError> {
+        self.try_auth_async(authstr).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{credential_digest, Keys};
+
+    #[test]
+    fn test_redis_keys() {
+        let keys = Keys {
+            prefix: "proxy".into(),
+        };
+        assert_eq!(keys.user("u"), "proxy:user:u");
+        assert_eq!(keys.index(), "proxy:users");
+        let digest = credential_digest("plaintext:u\np");
+        assert_eq!(digest.len(), 64);
+        assert!(!keys.credential(&digest).contains('\n'));
+    }
+}
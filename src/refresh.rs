@@ -0,0 +1,131 @@
+/*!
+Periodic refresh of a [`SharedUsersMap`] from any source of user sets.
+
+[`spawn_refresh_task`] requires the `tokio` feature.
+*/
+
+use std::io;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+#[cfg(feature = "tokio")]
+use crate::{SharedUsersMap, UsersDiff};
+use crate::{UserTrait, UsersMap};
+
+/// Somewhere the current user set can be pulled from: a file, an HTTP server, a database.
+///
+/// Any `Fn() -> io::Result<UsersMap<T>>` is a source, e.g. a closure calling
+/// [`load_users_from_toml_path`](crate::load_users_from_toml_path) or listing a
+/// [`UserStore`](crate::UserStore).
+#[async_trait]
+pub trait UserSource<T: UserTrait + Clone>: Send + Sync {
+    /// Loads the whole user set, or returns `None` if the source knows it didn't change
+    /// since the last call.
+    async fn fetch_users(&self) -> io::Result<Option<UsersMap<T>>>;
+}
+
+#[async_trait]
+impl<T, F> UserSource<T> for F
+where
+    T: UserTrait + Clone,
+    F: Fn() -> io::Result<UsersMap<T>> + Send + Sync,
+{
+    async fn fetch_users(&self) -> io::Result<Option<UsersMap<T>>> {
+        self().map(Some)
+    }
+}
+
+/// Spawns a task pulling `source` every `interval`, starting immediately, and swapping
+/// each loaded user set into `shared`.
+///
+/// `on_refresh` receives the outcome of every load, including failures, which leave the
+/// current map untouched; loads the source reported as unchanged are not reported.
+/// The task runs until it is aborted through the returned handle.
+///
+/// Requires the `tokio` feature, and must be called from within a Tokio runtime.
+#[cfg(feature = "tokio")]
+pub fn spawn_refresh_task<T, S, F>(
+    source: S,
+    shared: Arc<SharedUsersMap<T>>,
+    interval: Duration,
+    mut on_refresh: F,
+) -> tokio::task::JoinHandle<()>
+where
+    T: UserTrait + Clone + 'static,
+    S: UserSource<T> + 'static,
+    F: FnMut(io::Result<UsersDiff>) + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match source.fetch_users().await {
+                Ok(None) => {}
+                Ok(Some(new)) => on_refresh(Ok(shared.reload(new))),
+                Err(e) => on_refresh(Err(e)),
+            }
+        }
+    })
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod test {
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::spawn_refresh_task;
+    use crate::{PlainText, SharedUsersMap, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_refresh_task() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let source = {
+            let calls = Arc::clone(&calls);
+            move || {
+                let mut um = UsersMap::new();
+                let user = match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => PlainText::from("u p"),
+                    1 => return Err(io::Error::other("unreachable")),
+                    _ => PlainText::from("v p"),
+                };
+                um.add_user(user);
+                Ok(um)
+            }
+        };
+        let shared = Arc::new(SharedUsersMap::default());
+        let reports = Arc::new(Mutex::new(Vec::new()));
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let task = {
+                let reports = Arc::clone(&reports);
+                spawn_refresh_task(
+                    source,
+                    Arc::clone(&shared),
+                    Duration::from_millis(5),
+                    move |r| reports.lock().unwrap().push(r.map_err(|e| e.to_string())),
+                )
+            };
+            while calls.load(Ordering::SeqCst) < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            task.abort();
+        });
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports[0].as_ref().unwrap().added, vec!["u".to_string()]);
+        assert!(reports[1].is_err());
+        assert_eq!(reports[2].as_ref().unwrap().removed, vec!["u".to_string()]);
+        assert!(shared.auth_user_by_authstr("plaintext:v\np").is_some());
+    }
+}
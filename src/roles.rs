@@ -0,0 +1,140 @@
+/*!
+Roles attached to users, and policies requiring them.
+*/
+
+use std::ops::Deref;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "dyn-clone")]
+use crate::UserBox;
+use crate::{PlainText, UserTrait};
+
+/// A user that carries a list of roles, for access control beyond "authenticated yes/no".
+pub trait UserWithRoles: UserTrait {
+    fn roles(&self) -> &[String];
+
+    fn has_role(&self, role: &str) -> bool {
+        self.roles().iter().any(|r| r == role)
+    }
+}
+
+/// Adds roles to any existing user type.
+///
+/// `RoledUser<PlainText>` and `RoledUser<UserBox>` implement [`UserTrait`], so they can be
+/// stored in a [`UsersMap`](crate::UsersMap) and serialized as trait objects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RoledUser<T> {
+    pub user: T,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub roles: Vec<String>,
+}
+
+impl<T> RoledUser<T> {
+    pub fn new(user: T, roles: Vec<String>) -> Self {
+        RoledUser { user, roles }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.user
+    }
+}
+
+impl<T> Deref for RoledUser<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.user
+    }
+}
+
+impl_wrapper_user_trait!(RoledUser<PlainText>, "RoledPlainText");
+#[cfg(feature = "dyn-clone")]
+impl_wrapper_user_trait!(RoledUser<UserBox>, "RoledUserBox");
+
+impl<T> UserWithRoles for RoledUser<T>
+where
+    RoledUser<T>: UserTrait,
+{
+    fn roles(&self) -> &[String] {
+        &self.roles
+    }
+}
+
+/// A set of role requirements: every role in `all` and, if `any` is not empty, at least one of `any`.
+///
+/// ```
+/// # use user_trait::{PlainText, RoledUser, RolePolicy};
+/// let policy = RolePolicy::new().require("staff").require_any_of(["ops", "dev"]);
+/// let user = RoledUser::new(PlainText::from("u p"), vec!["staff".into(), "dev".into()]);
+/// assert!(policy.check(&user));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RolePolicy {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub all: Vec<String>,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub any: Vec<String>,
+}
+
+impl RolePolicy {
+    /// A policy that every user satisfies
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires `role`
+    pub fn require(mut self, role: impl Into<String>) -> Self {
+        self.all.push(role.into());
+        self
+    }
+
+    /// Requires at least one of `roles`
+    pub fn require_any_of<I: IntoIterator<Item = R>, R: Into<String>>(mut self, roles: I) -> Self {
+        self.any.extend(roles.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn check<U: UserWithRoles + ?Sized>(&self, user: &U) -> bool {
+        self.missing(user).is_empty()
+            && (self.any.is_empty() || self.any.iter().any(|r| user.has_role(r)))
+    }
+
+    /// Returns the roles of `all` the user lacks
+    pub fn missing<'a, U: UserWithRoles + ?Sized>(&'a self, user: &U) -> Vec<&'a str> {
+        self.all
+            .iter()
+            .filter(|r| !user.has_role(r))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RolePolicy, RoledUser, UserWithRoles};
+    use crate::{PlainText, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_roles() {
+        let user = RoledUser::new(PlainText::from("u p"), vec!["admin".into()]);
+        assert!(user.has_role("admin"));
+        assert!(!user.has_role("root"));
+
+        let policy = RolePolicy::new().require("admin").require("root");
+        assert!(!policy.check(&user));
+        assert_eq!(policy.missing(&user), ["root"]);
+        assert!(!RolePolicy::new().require_any_of(["a", "b"]).check(&user));
+        assert!(RolePolicy::new().check(&user));
+
+        let mut um = UsersMap::new();
+        um.add_user(user);
+        let authed = um.auth_user_by_authstr("plaintext:u\np").unwrap();
+        assert!(RolePolicy::new().require("admin").check(&authed));
+    }
+}
@@ -0,0 +1,197 @@
+/*!
+Credential rotation with a grace window for the replaced credential.
+*/
+
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "dyn-clone")]
+use crate::UserBox;
+use crate::{Clock, PlainText, SystemClock, UserTrait};
+
+/// An auth string replaced by a rotation, accepted until `valid_until`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RetiredCredential {
+    pub auth_str: String,
+    pub valid_until: SystemTime,
+}
+
+/// A user whose credential was rotated, so that clients still holding the previous
+/// credential keep working for a grace window.
+///
+/// [`UsersMap`](crate::UsersMap) indexes both auth strings through
+/// [`UserTrait::previous_auth_str`] and refuses the previous one once its window has
+/// passed; [`UsersMap::purge_retired_credentials`](crate::UsersMap::purge_retired_credentials)
+/// drops it from the indexes. Re-add the user to the map after rotating it.
+///
+/// `RotatingUser<PlainText>` and `RotatingUser<UserBox>` implement [`UserTrait`].
+/// The clock isn't serialized; loaded users use the [`SystemClock`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RotatingUser<T> {
+    pub user: T,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub previous: Option<RetiredCredential>,
+
+    #[cfg_attr(feature = "serde", serde(skip, default = "system_clock"))]
+    clock: Arc<dyn Clock>,
+}
+
+fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+impl<T> RotatingUser<T> {
+    pub fn new(user: T) -> Self {
+        RotatingUser {
+            user,
+            previous: None,
+            clock: system_clock(),
+        }
+    }
+
+    /// Replaces the clock starting and closing the grace windows.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn into_inner(self) -> T {
+        self.user
+    }
+
+    /// Returns the previous auth string if its grace window is open at `now`.
+    pub fn previous_at(&self, now: SystemTime) -> Option<&str> {
+        self.previous
+            .as_ref()
+            .filter(|p| p.valid_until > now)
+            .map(|p| p.auth_str.as_str())
+    }
+}
+
+impl<T: UserTrait> RotatingUser<T> {
+    /// Replaces the credential with `user`'s, accepting the current one for `grace` more.
+    ///
+    /// An earlier previous credential is dropped, even if its window is still open.
+    pub fn rotate(&mut self, user: T, grace: Duration) {
+        let old = std::mem::replace(&mut self.user, user);
+        self.previous = Some(RetiredCredential {
+            auth_str: old.auth_str().to_string(),
+            valid_until: self.clock.now() + grace,
+        });
+    }
+}
+
+impl<T: Default> Default for RotatingUser<T> {
+    fn default() -> Self {
+        RotatingUser::new(T::default())
+    }
+}
+
+/// Compares the users and previous credentials, not the clocks.
+impl<T: PartialEq> PartialEq for RotatingUser<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.user == other.user && self.previous == other.previous
+    }
+}
+
+impl<T: Eq> Eq for RotatingUser<T> {}
+
+impl<T> Deref for RotatingUser<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.user
+    }
+}
+
+/// Like `impl_wrapper_user_trait!`, but reporting the previous credential of the wrapper.
+macro_rules! impl_rotating_user_trait {
+    ($ty:ty, $name:literal) => {
+        #[cfg_attr(feature = "serde", typetag::serde(name = $name))]
+        impl UserTrait for $ty {
+            fn identity_str(&self) -> &str {
+                self.user.identity_str()
+            }
+
+            fn identity_bytes(&self) -> &[u8] {
+                self.user.identity_bytes()
+            }
+
+            fn auth_str(&self) -> &str {
+                self.user.auth_str()
+            }
+
+            fn auth_bytes(&self) -> &[u8] {
+                self.user.auth_bytes()
+            }
+
+            fn previous_auth_str(&self) -> Option<&str> {
+                self.previous_at(self.clock.now())
+            }
+
+            fn fingerprint(&self) -> [u8; 32] {
+                self.user.fingerprint()
+            }
+
+            fn auth_eq(&self, other: &[u8]) -> bool {
+                self.user.auth_eq(other)
+            }
+        }
+    };
+}
+
+impl_rotating_user_trait!(RotatingUser<PlainText>, "RotatingPlainText");
+#[cfg(feature = "dyn-clone")]
+impl_rotating_user_trait!(RotatingUser<UserBox>, "RotatingUserBox");
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::RotatingUser;
+    use crate::{AuthError, ManualClock, PlainText, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_rotation() {
+        let mut user = RotatingUser::new(PlainText::from("u old"));
+        user.rotate(PlainText::from("u new"), Duration::from_secs(3600));
+
+        let mut um = UsersMap::new();
+        um.add_user(user);
+        assert_eq!(um.credentials("u").map(|c| c.len()), Some(2));
+        assert!(um.auth_user_by_authstr("plaintext:u\nnew").is_some());
+        assert!(um.auth_user_by_authstr("plaintext:u\nold").is_some());
+        assert!(um.auth_user_constant_time("plaintext:u\nold").is_some());
+
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let mut user =
+            RotatingUser::new(PlainText::from("v old")).with_clock(Arc::new(clock.clone()));
+        user.rotate(PlainText::from("v new"), Duration::from_secs(60));
+        um.add_user(user);
+        assert!(um.auth_user_by_authstr("plaintext:v\nold").is_some());
+        clock.advance(Duration::from_secs(59));
+        assert!(um.auth_user_by_authstr("plaintext:v\nold").is_some());
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(
+            um.try_auth("plaintext:v\nold"),
+            Err(AuthError::BadCredential)
+        ));
+        assert!(um.auth_user_constant_time("plaintext:v\nold").is_none());
+        assert!(um.auth_user_by_authstr("plaintext:v\nnew").is_some());
+
+        assert_eq!(um.purge_retired_credentials(), 1);
+        assert!(um.get_user_by_authstr("plaintext:v\nold").is_none());
+        assert_eq!(um.credentials("v").map(|c| c.len()), Some(1));
+    }
+}
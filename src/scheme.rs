@@ -0,0 +1,125 @@
+/*!
+Creating users from the credentials clients present, by auth scheme.
+*/
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use core::fmt;
+
+use crate::auth_str::check_scheme;
+use crate::{AuthFormat, AuthStr, HashMap, PlainText, Result, UserBox};
+
+/// Creates a user from the payload of an auth string, or refuses it
+type SchemeConstructor = Arc<dyn Fn(&str) -> Option<UserBox> + Send + Sync>;
+
+/// Constructors of users by auth scheme, e.g. `plaintext` or `token`, so that users can
+/// be created from the auth strings received on the wire with
+/// [`AuthSchemeRegistry::parse_auth_str`], without naming their types.
+#[derive(Clone, Default)]
+pub struct AuthSchemeRegistry {
+    constructors: HashMap<String, SchemeConstructor>,
+}
+
+impl AuthSchemeRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry of the schemes of this crate's users: `plaintext`, whose payload
+    /// is `user\npass`, and its escaped version `plaintext.v2`, see [`AuthFormat`].
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.insert("plaintext", |payload| {
+            let (user, pass) = payload.split_once('\n')?;
+            let user = PlainText::new(user.to_string(), pass.to_string());
+            user.valid().then(|| UserBox::new(user))
+        });
+        registry.insert("plaintext.v2", |payload| {
+            let authstr = format!("plaintext.v2:{payload}");
+            let decoded = AuthFormat::decode(&authstr).ok()?;
+            let user =
+                PlainText::with_format(decoded.user.into(), decoded.pass.into(), AuthFormat::V2);
+            user.valid().then(|| UserBox::new(user))
+        });
+        registry
+    }
+
+    fn insert(
+        &mut self,
+        scheme: &str,
+        f: impl Fn(&str) -> Option<UserBox> + Send + Sync + 'static,
+    ) {
+        self.constructors.insert(scheme.to_string(), Arc::new(f));
+    }
+
+    /// Registers the constructor of `scheme`, replacing the previous one.
+    /// Fails if the scheme isn't valid in an [`AuthStr`].
+    pub fn register(
+        &mut self,
+        scheme: &str,
+        f: impl Fn(&str) -> Option<UserBox> + Send + Sync + 'static,
+    ) -> Result<()> {
+        check_scheme(scheme)?;
+        self.insert(scheme, f);
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, scheme: &str) -> bool {
+        self.constructors.remove(scheme).is_some()
+    }
+
+    pub fn contains(&self, scheme: &str) -> bool {
+        self.constructors.contains_key(scheme)
+    }
+
+    /// Creates the user of `authstr` with the constructor of its scheme.
+    ///
+    /// Returns `None` if it isn't a valid [`AuthStr`], its scheme is unknown, or the
+    /// constructor refuses its payload.
+    pub fn parse_auth_str(&self, authstr: &str) -> Option<UserBox> {
+        let (scheme, payload) = AuthStr::parse(authstr).ok()?.parts();
+        self.constructors.get(scheme)?(payload)
+    }
+}
+
+/// Lists the schemes.
+impl fmt::Debug for AuthSchemeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.constructors.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AuthSchemeRegistry;
+    use crate::{PlainText, UserBox, UserTrait};
+
+    #[test]
+    fn test_scheme_registry() {
+        let mut registry = AuthSchemeRegistry::with_builtin();
+        let user = registry.parse_auth_str("plaintext:u\np:w").unwrap();
+        assert_eq!(user, UserBox::new(PlainText::from("u p:w")));
+        assert!(registry.parse_auth_str("plaintext:\np").is_none());
+        assert!(registry.parse_auth_str("token:abc").is_none());
+        let user = registry.parse_auth_str("plaintext.v2:u\\nv\np").unwrap();
+        assert_eq!(user.identity_str(), "u\nv");
+        assert_eq!(user.auth_str(), "plaintext.v2:u\\nv\np");
+
+        registry
+            .register("token", |token| {
+                let user = PlainText::new(token.to_string(), String::new());
+                Some(UserBox::new(user))
+            })
+            .unwrap();
+        let user = registry.parse_auth_str("token:abc").unwrap();
+        assert_eq!(user.identity_str(), "abc");
+        assert!(matches!(
+            registry.register("bad scheme", |_| None),
+            Err(crate::Error::AuthStr(_))
+        ));
+        assert!(registry.unregister("token"));
+        assert!(!registry.contains("token"));
+    }
+}
@@ -0,0 +1,172 @@
+/*!
+A string holding a credential, for the secret fields of user types, and a
+redacted form of users for logs.
+*/
+
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::ops::Deref;
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+/// A `String` for passwords, tokens and auth strings.
+///
+/// It compares in constant time, and its `Debug` output doesn't show it. With the
+/// `zeroize` feature, its memory is overwritten with zeros when it is dropped, so that
+/// the secret doesn't linger in freed memory. It serializes as a plain string.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(secret: String) -> Self {
+        SecretString(secret)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        SecretString(secret)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(secret: &str) -> Self {
+        SecretString(secret.to_string())
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_bytes().ct_eq(other.0.as_bytes()).into()
+    }
+}
+
+impl Eq for SecretString {}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString(..)")
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for SecretString {
+    fn zeroize(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for SecretString {}
+
+/// The auth string of a user type deriving [`UserTrait`](crate::UserTrait) with
+/// `#[auth(prefix = ...)]`, built from its fields on first use.
+///
+/// Mark it `#[auth_cache]` and `#[serde(skip)]`, and create it with `AuthCache::default()`.
+/// It isn't rebuilt when the fields change, so build a new user instead of changing them.
+/// Caches always compare equal, so that deriving `PartialEq` compares the other fields.
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+pub struct AuthCache(OnceLock<Box<str>>);
+
+#[cfg(feature = "std")]
+impl AuthCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached auth string, building it with `f` the first time.
+    pub fn get_or_init(&self, f: impl FnOnce() -> String) -> &str {
+        self.0.get_or_init(|| f().into())
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for AuthCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "std")]
+impl Eq for AuthCache {}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for AuthCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthCache(..)")
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for AuthCache {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        if let Some(s) = self.0.get_mut() {
+            zeroize::Zeroize::zeroize(s);
+        }
+    }
+}
+
+/// A user shown as its identity followed by a masked credential, e.g. `alice:✱✱✱`,
+/// for logs and admin UIs. See [`UserTrait::redacted`](crate::UserTrait::redacted).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Redacted<'a>(pub &'a str);
+
+impl Redacted<'_> {
+    /// Always the same, so that it doesn't tell the length of the credential
+    pub const MASK: &'static str = "✱✱✱";
+}
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.0, Self::MASK)
+    }
+}
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SecretString;
+
+    #[test]
+    fn test_secret_string() {
+        let s = SecretString::from("hunter2");
+        assert_eq!(format!("{s:?}"), "SecretString(..)");
+        assert_eq!(&*s, "hunter2");
+        assert_eq!(s, SecretString::from("hunter2"));
+        assert_ne!(s, SecretString::from("hunter3"));
+        #[cfg(feature = "serde")]
+        assert_eq!(serde_json::to_string(&s).unwrap(), "\"hunter2\"");
+    }
+}
@@ -0,0 +1,320 @@
+/*!
+Sessions issued after a successful authentication.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::{Duration, SystemTime};
+
+use crate::interner::intern_with;
+use crate::{
+    AuthError, Clock, IdInterner, SystemClock, User, UserAuthenticator, UserTrait, UsersMap,
+};
+
+/// An opaque, unguessable session identifier: 32 random bytes in hex.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SessionId(String);
+
+impl SessionId {
+    fn random() -> Self {
+        let mut bytes = [0u8; 32];
+        getrandom::fill(&mut bytes).expect("the system random number generator failed");
+        SessionId(bytes.iter().map(|b| format!("{b:02x}")).collect())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for SessionId {
+    /// Wraps an id received from a client, e.g. from a cookie.
+    fn from(id: String) -> Self {
+        SessionId(id)
+    }
+}
+
+/// A live session.
+#[derive(Debug, Clone)]
+pub struct Session<T> {
+    pub id: SessionId,
+    pub user: T,
+    pub created_at: SystemTime,
+
+    /// When the session was created or last refreshed
+    pub last_seen: SystemTime,
+}
+
+/// When sessions end on their own. `None` means never.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionPolicy {
+    /// Ends sessions not refreshed for this long
+    pub idle_timeout: Option<Duration>,
+
+    /// Ends sessions this long after they were created, even if they are in use
+    pub absolute_timeout: Option<Duration>,
+}
+
+impl SessionPolicy {
+    fn is_expired<T>(&self, session: &Session<T>, now: SystemTime) -> bool {
+        let older_than = |since: SystemTime, limit: Option<Duration>| {
+            limit.is_some_and(|limit| now.duration_since(since).is_ok_and(|d| d >= limit))
+        };
+        older_than(session.last_seen, self.idle_timeout)
+            || older_than(session.created_at, self.absolute_timeout)
+    }
+}
+
+#[derive(Debug)]
+struct SessionState<T> {
+    sessions: HashMap<SessionId, Session<T>>,
+
+    /// Maps user identity strings to the ids of their sessions
+    by_identity: HashMap<Arc<str>, HashSet<SessionId>>,
+}
+
+impl<T: UserTrait> SessionState<T> {
+    fn remove(&mut self, id: &SessionId) -> Option<Session<T>> {
+        let session = self.sessions.remove(id)?;
+        let identity = session.user.identity_str();
+        if let Some(ids) = self.by_identity.get_mut(identity) {
+            ids.remove(id);
+            if ids.is_empty() {
+                self.by_identity.remove(identity);
+            }
+        }
+        Some(session)
+    }
+}
+
+/// Issues session ids after successful authentication, so that clients don't have to
+/// present their credential on every request.
+///
+/// Expired sessions are removed when they are looked up, or by
+/// [`SessionManager::purge_expired`].
+#[derive(Debug)]
+pub struct SessionManager<T: User + Clone> {
+    policy: SessionPolicy,
+    clock: Arc<dyn Clock>,
+    state: Mutex<SessionState<T>>,
+    interner: Option<Arc<IdInterner>>,
+}
+
+impl<T: User + Clone> SessionManager<T> {
+    pub fn new(policy: SessionPolicy) -> Self {
+        SessionManager {
+            policy,
+            clock: Arc::new(SystemClock),
+            state: Mutex::new(SessionState {
+                sessions: HashMap::new(),
+                by_identity: HashMap::new(),
+            }),
+            interner: None,
+        }
+    }
+
+    /// Replaces the clock deciding when sessions time out.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Takes the identities of users starting sessions from `interner`.
+    pub fn with_interner(mut self, interner: Arc<IdInterner>) -> Self {
+        self.interner = Some(interner);
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, SessionState<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn policy(&self) -> SessionPolicy {
+        self.policy
+    }
+
+    /// Starts a session for an already authenticated user.
+    pub fn create(&self, user: T) -> SessionId {
+        let now = self.clock.now();
+        let id = SessionId::random();
+        let mut state = self.state();
+        state
+            .by_identity
+            .entry(intern_with(self.interner.as_deref(), user.identity_str()))
+            .or_default()
+            .insert(id.clone());
+        state.sessions.insert(
+            id.clone(),
+            Session {
+                id: id.clone(),
+                user,
+                created_at: now,
+                last_seen: now,
+            },
+        );
+        id
+    }
+
+    /// Authenticates `authstr` with `auth` and starts a session on success.
+    pub fn login<A: UserAuthenticator<T> + ?Sized>(
+        &self,
+        auth: &A,
+        authstr: &str,
+    ) -> Result<(SessionId, T), AuthError> {
+        let user = auth.try_auth(authstr)?;
+        Ok((self.create(user.clone()), user))
+    }
+
+    /// Returns the session if it exists and has not timed out, without refreshing it.
+    pub fn get(&self, id: &SessionId) -> Option<Session<T>> {
+        let now = self.clock.now();
+        let mut state = self.state();
+        match state.sessions.get(id) {
+            Some(s) if self.policy.is_expired(s, now) => {
+                state.remove(id);
+                None
+            }
+            s => s.cloned(),
+        }
+    }
+
+    /// Returns the user of the session, resetting its idle timeout.
+    pub fn refresh(&self, id: &SessionId) -> Option<T> {
+        let now = self.clock.now();
+        let mut state = self.state();
+        let session = state.sessions.get_mut(id)?;
+        if self.policy.is_expired(session, now) {
+            state.remove(id);
+            return None;
+        }
+        session.last_seen = now;
+        Some(session.user.clone())
+    }
+
+    /// Ends a session, returning false if it didn't exist.
+    pub fn revoke(&self, id: &SessionId) -> bool {
+        self.state().remove(id).is_some()
+    }
+
+    /// Ends every session of an identity, returning how many were ended.
+    pub fn revoke_identity(&self, identity: &str) -> usize {
+        let mut state = self.state();
+        let Some(ids) = state.by_identity.remove(identity) else {
+            return 0;
+        };
+        for id in &ids {
+            state.sessions.remove(id);
+        }
+        ids.len()
+    }
+
+    /// Returns the live sessions of an identity
+    pub fn sessions_of(&self, identity: &str) -> Vec<Session<T>> {
+        let now = self.clock.now();
+        let state = self.state();
+        state
+            .by_identity
+            .get(identity)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| state.sessions.get(id))
+            .filter(|s| !self.policy.is_expired(s, now))
+            .cloned()
+            .collect()
+    }
+
+    /// Removes timed out sessions, returning how many were removed.
+    pub fn purge_expired(&self) -> usize {
+        let now = self.clock.now();
+        let mut state = self.state();
+        let expired: Vec<SessionId> = state
+            .sessions
+            .values()
+            .filter(|s| self.policy.is_expired(s, now))
+            .map(|s| s.id.clone())
+            .collect();
+        for id in &expired {
+            state.remove(id);
+        }
+        expired.len()
+    }
+
+    /// Number of sessions, including timed out ones that were not purged yet.
+    pub fn len(&self) -> usize {
+        self.state().sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state().sessions.is_empty()
+    }
+}
+
+impl<T: User + Clone + 'static> SessionManager<T> {
+    /// Ends the sessions of every user removed from `map` from now on.
+    ///
+    /// The hook only holds a weak reference, so it doesn't keep the manager alive.
+    pub fn revoke_on_remove<S: BuildHasher>(self: &Arc<Self>, map: &mut UsersMap<T, S>) {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        map.on_remove(move |user| {
+            if let Some(manager) = manager.upgrade() {
+                manager.revoke_identity(user.identity_str());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::{SessionManager, SessionPolicy};
+    use crate::{ManualClock, PlainText, UsersMap};
+
+    #[test]
+    fn test_sessions() -> Result<(), Box<dyn std::error::Error>> {
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let manager = Arc::new(
+            SessionManager::new(SessionPolicy {
+                idle_timeout: Some(Duration::from_secs(60)),
+                absolute_timeout: Some(Duration::from_secs(150)),
+            })
+            .with_clock(Arc::new(clock.clone())),
+        );
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+        manager.revoke_on_remove(&mut um);
+
+        let (a, _) = manager.login(&um, "plaintext:u\np")?;
+        let b = manager.create(PlainText::from("u p"));
+        assert_ne!(a, b);
+        assert!(manager.login(&um, "plaintext:u\nx").is_err());
+        assert_eq!(manager.sessions_of("u").len(), 2);
+
+        clock.advance(Duration::from_secs(50));
+        assert!(manager.refresh(&a).is_some());
+        clock.advance(Duration::from_secs(50));
+        assert!(manager.get(&a).is_some());
+        assert!(manager.get(&b).is_none());
+
+        clock.advance(Duration::from_secs(50));
+        assert!(manager.refresh(&a).is_none());
+
+        let c = manager.create(PlainText::from("u p"));
+        assert!(manager.revoke(&c));
+        assert!(!manager.revoke(&c));
+
+        manager.create(PlainText::from("u p"));
+        um.remove_user("u");
+        assert!(manager.is_empty());
+        Ok(())
+    }
+}
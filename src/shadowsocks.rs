@@ -0,0 +1,335 @@
+/*!
+Shadowsocks users, and importing the multi-user configs of shadowsocks servers and managers.
+*/
+
+#[cfg(feature = "serde")]
+use std::fs;
+#[cfg(feature = "serde")]
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_json::{Map, Value};
+
+#[cfg(feature = "serde")]
+use crate::{LoadError, UsersMap};
+use crate::{SecretString, UserTrait};
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SsEntry {
+    name: String,
+    password: String,
+    method: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+}
+
+/// A shadowsocks user: a password for a cipher method, possibly bound to a server port.
+///
+/// Its auth string is `ss:{method}\n{password}`, or `ss:{method}@{port}\n{password}`
+/// for users bound to a port, so that the same password on two ports makes two
+/// distinct credentials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "SsEntry", into = "SsEntry"))]
+pub struct SsUser {
+    name: String,
+    password: SecretString,
+    method: String,
+    port: Option<u16>,
+    auth_str: SecretString,
+}
+
+#[cfg(feature = "serde")]
+impl From<SsEntry> for SsUser {
+    fn from(e: SsEntry) -> Self {
+        SsUser::new(e.name, e.password, e.method, e.port)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SsUser> for SsEntry {
+    fn from(u: SsUser) -> Self {
+        SsEntry {
+            name: u.name,
+            password: u.password.to_string(),
+            method: u.method,
+            port: u.port,
+        }
+    }
+}
+
+impl SsUser {
+    pub fn new(name: String, password: String, method: String, port: Option<u16>) -> Self {
+        let auth_str = match port {
+            Some(port) => format!("ss:{method}@{port}\n{password}"),
+            None => format!("ss:{method}\n{password}"),
+        };
+        SsUser {
+            name,
+            password: password.into(),
+            method,
+            port,
+            auth_str: auth_str.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    /// The cipher, e.g. `aes-256-gcm` or `2022-blake3-aes-256-gcm`
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl UserTrait for SsUser {
+    fn identity_str(&self) -> &str {
+        &self.name
+    }
+
+    fn identity_bytes(&self) -> &[u8] {
+        self.name.as_bytes()
+    }
+
+    fn auth_str(&self) -> &str {
+        &self.auth_str
+    }
+
+    fn auth_bytes(&self) -> &[u8] {
+        self.auth_str.as_bytes()
+    }
+}
+
+#[cfg(feature = "serde")]
+fn syntax(message: String) -> LoadError {
+    LoadError::Syntax {
+        line: None,
+        message,
+    }
+}
+
+#[cfg(feature = "serde")]
+fn string_field<'a>(
+    obj: &'a Map<String, Value>,
+    key: &str,
+    at: &str,
+) -> Result<&'a str, LoadError> {
+    match obj.get(key) {
+        Some(Value::String(s)) => Ok(s),
+        Some(_) => Err(syntax(format!("{at}: `{key}` must be a string"))),
+        None => Err(syntax(format!("{at}: missing `{key}`"))),
+    }
+}
+
+/// Reads a port, written as a number or a string.
+#[cfg(feature = "serde")]
+fn port_of(v: &Value, at: &str) -> Result<u16, LoadError> {
+    match v {
+        Value::Number(n) => n.as_u64().and_then(|n| u16::try_from(n).ok()),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| syntax(format!("{at}: invalid port {v}")))
+}
+
+#[cfg(feature = "serde")]
+struct SsBuilder {
+    map: UsersMap<SsUser>,
+}
+
+#[cfg(feature = "serde")]
+impl SsBuilder {
+    fn add(&mut self, user: SsUser) -> Result<(), LoadError> {
+        if self.map.get_user(user.identity_str()).is_some() {
+            return Err(LoadError::Duplicate {
+                line: None,
+                id: user.identity_str().to_string(),
+            });
+        }
+        self.map.add_user(user);
+        Ok(())
+    }
+
+    /// Reads a `users` list of `{"name", "password"}`, as in shadowsocks-rust.
+    fn users(
+        &mut self,
+        users: &Value,
+        method: &str,
+        port: Option<u16>,
+        at: &str,
+    ) -> Result<(), LoadError> {
+        let Value::Array(users) = users else {
+            return Err(syntax(format!("{at}: expected a list")));
+        };
+        for (i, u) in users.iter().enumerate() {
+            let at = format!("{at}[{i}]");
+            let Value::Object(u) = u else {
+                return Err(syntax(format!("{at}: expected an object")));
+            };
+            self.add(SsUser::new(
+                string_field(u, "name", &at)?.to_string(),
+                string_field(u, "password", &at)?.to_string(),
+                method.to_string(),
+                port,
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// Imports the users of a shadowsocks config, in the formats of common servers
+/// and managers:
+///
+/// - `port_password`, mapping ports to passwords (or to `{"password", "method"}`), as in
+///   shadowsocks-libev and ss-manager. Users are named after their port.
+/// - `users`, a list of `{"name", "password"}`, as in shadowsocks-rust multi-user servers.
+/// - `servers`, a list of `{"server_port", "password", "method"}`, each optionally with its
+///   own `users`, as in shadowsocks-rust. Servers without `users` are named after their port.
+///
+/// Entries without a `method` use the top-level one.
+#[cfg(feature = "serde")]
+pub fn load_shadowsocks_config(json: &str) -> Result<UsersMap<SsUser>, LoadError> {
+    let doc: Value = serde_json::from_str(json).map_err(|e| {
+        let message = e.to_string();
+        LoadError::Syntax {
+            line: Some(e.line()),
+            message: match message.rfind(" at line ") {
+                Some(i) => message[..i].to_string(),
+                None => message,
+            },
+        }
+    })?;
+    let Value::Object(doc) = doc else {
+        return Err(syntax("expected an object".into()));
+    };
+    let default_method = match doc.get("method") {
+        Some(Value::String(m)) => Some(m.as_str()),
+        _ => None,
+    };
+    let method_of = |obj: &Map<String, Value>, at: &str| -> Result<String, LoadError> {
+        match obj.get("method") {
+            Some(_) => Ok(string_field(obj, "method", at)?.to_string()),
+            None => default_method
+                .map(str::to_string)
+                .ok_or_else(|| syntax(format!("{at}: missing `method`"))),
+        }
+    };
+
+    let mut builder = SsBuilder {
+        map: UsersMap::new(),
+    };
+    if let Some(ports) = doc.get("port_password") {
+        let Value::Object(ports) = ports else {
+            return Err(syntax("port_password: expected an object".into()));
+        };
+        for (port, entry) in ports {
+            let at = format!("port_password.{port}");
+            let port = port_of(&Value::String(port.clone()), &at)?;
+            let (password, method) = match entry {
+                Value::String(p) => (p.as_str(), method_of(&Map::new(), &at)?),
+                Value::Object(o) => (string_field(o, "password", &at)?, method_of(o, &at)?),
+                _ => return Err(syntax(format!("{at}: expected a password"))),
+            };
+            builder.add(SsUser::new(
+                port.to_string(),
+                password.to_string(),
+                method,
+                Some(port),
+            ))?;
+        }
+    }
+    if let Some(users) = doc.get("users") {
+        builder.users(users, &method_of(&doc, "users")?, None, "users")?;
+    }
+    if let Some(servers) = doc.get("servers") {
+        let Value::Array(servers) = servers else {
+            return Err(syntax("servers: expected a list".into()));
+        };
+        for (i, server) in servers.iter().enumerate() {
+            let at = format!("servers[{i}]");
+            let Value::Object(server) = server else {
+                return Err(syntax(format!("{at}: expected an object")));
+            };
+            let port = match server.get("server_port") {
+                Some(p) => Some(port_of(p, &at)?),
+                None => None,
+            };
+            let method = method_of(server, &at)?;
+            match server.get("users") {
+                Some(users) => builder.users(users, &method, port, &format!("{at}.users"))?,
+                None => {
+                    let port =
+                        port.ok_or_else(|| syntax(format!("{at}: missing `server_port`")))?;
+                    builder.add(SsUser::new(
+                        port.to_string(),
+                        string_field(server, "password", &at)?.to_string(),
+                        method,
+                        Some(port),
+                    ))?;
+                }
+            }
+        }
+    }
+    Ok(builder.map)
+}
+
+/// Reads a file with [`load_shadowsocks_config`].
+#[cfg(feature = "serde")]
+pub fn load_shadowsocks_config_path(path: &Path) -> Result<UsersMap<SsUser>, LoadError> {
+    load_shadowsocks_config(&fs::read_to_string(path)?)
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use super::load_shadowsocks_config;
+    use crate::{LoadError, UserAuthenticator};
+
+    #[test]
+    fn test_shadowsocks_config() -> Result<(), LoadError> {
+        let um = load_shadowsocks_config(
+            r#"{
+                "method": "aes-256-gcm",
+                "port_password": {"8388": "p1", "8389": {"password": "p2", "method": "chacha20-ietf-poly1305"}},
+                "servers": [
+                    {"server_port": 9000, "method": "2022-blake3-aes-128-gcm", "password": "k",
+                     "users": [{"name": "alice", "password": "ka"}]}
+                ]
+            }"#,
+        )?;
+        assert_eq!(um.len(), 3);
+        assert_eq!(
+            um.auth_user_by_authstr("ss:aes-256-gcm@8388\np1")
+                .map(|u| u.port()),
+            Some(Some(8388))
+        );
+        assert!(um
+            .auth_user_by_authstr("ss:chacha20-ietf-poly1305@8389\np2")
+            .is_some());
+        assert_eq!(
+            um.get_user("alice").map(|u| u.method().to_string()),
+            Some("2022-blake3-aes-128-gcm".into())
+        );
+
+        let err =
+            load_shadowsocks_config(r#"{"users": [{"name": "a", "password": "p"}]}"#).unwrap_err();
+        assert_eq!(err.to_string(), "users: missing `method`");
+        let err =
+            load_shadowsocks_config(r#"{"method": "m", "port_password": {"x": "p"}}"#).unwrap_err();
+        assert_eq!(err.to_string(), "port_password.x: invalid port \"x\"");
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+/*!
+A concurrent users map split into shards to reduce lock contention.
+*/
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{UserAuthenticator, UserTrait};
+
+type Shard<T> = RwLock<HashMap<String, Arc<T>>>;
+
+fn read<T>(shard: &Shard<T>) -> RwLockReadGuard<'_, HashMap<String, Arc<T>>> {
+    shard.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(shard: &Shard<T>) -> RwLockWriteGuard<'_, HashMap<String, Arc<T>>> {
+    shard.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A users map for very large user sets mutated concurrently.
+///
+/// Identities and auth strings are each spread over N internal maps by hash, every one
+/// behind its own [`RwLock`], so writers only block the readers of a single shard.
+/// Unlike [`SharedUsersMap`](crate::SharedUsersMap), it is mutated through `&self`.
+///
+/// Adding a user with an existing identity replaces it, including its auth string.
+#[derive(Debug)]
+pub struct ShardedUsersMap<T: UserTrait + Clone> {
+    id_shards: Box<[Shard<T>]>,
+    auth_shards: Box<[Shard<T>]>,
+    hasher: RandomState,
+}
+
+impl<T: UserTrait + Clone> Default for ShardedUsersMap<T> {
+    /// Creates a map with 16 shards
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl<T: UserTrait + Clone> ShardedUsersMap<T> {
+    /// Creates a map with `shard_count` shards, at least one.
+    pub fn new(shard_count: usize) -> Self {
+        let shard_count = shard_count.max(1);
+        ShardedUsersMap {
+            id_shards: (0..shard_count).map(|_| Shard::default()).collect(),
+            auth_shards: (0..shard_count).map(|_| Shard::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.id_shards.len()
+    }
+
+    fn shard_index(&self, key: &str) -> usize {
+        (self.hasher.hash_one(key) % self.id_shards.len() as u64) as usize
+    }
+
+    fn id_shard(&self, id: &str) -> &Shard<T> {
+        &self.id_shards[self.shard_index(id)]
+    }
+
+    fn auth_shard(&self, authstr: &str) -> &Shard<T> {
+        &self.auth_shards[self.shard_index(authstr)]
+    }
+
+    /// Adds a user, replacing the one with the same identity, if any.
+    pub fn add_user(&self, user: T) {
+        let user = Arc::new(user);
+
+        // Lock order is always id shard, then auth shard.
+        let mut ids = write(self.id_shard(user.identity_str()));
+        if let Some(old) = ids.insert(user.identity_str().to_string(), Arc::clone(&user)) {
+            self.remove_auth(&old);
+        }
+        write(self.auth_shard(user.auth_str())).insert(user.auth_str().to_string(), user);
+    }
+
+    /// Removes `user`'s auth string, unless it was taken over by another user meanwhile.
+    fn remove_auth(&self, user: &Arc<T>) {
+        let mut auths = write(self.auth_shard(user.auth_str()));
+        if auths
+            .get(user.auth_str())
+            .is_some_and(|u| Arc::ptr_eq(u, user))
+        {
+            auths.remove(user.auth_str());
+        }
+    }
+
+    /// Removes a user using their identity string, returning it.
+    pub fn remove_user(&self, id: &str) -> Option<Arc<T>> {
+        let mut ids = write(self.id_shard(id));
+        let user = ids.remove(id)?;
+        self.remove_auth(&user);
+        Some(user)
+    }
+
+    /// Number of users. Concurrent mutations may or may not be counted.
+    pub fn len(&self) -> usize {
+        self.id_shards.iter().map(|s| read(s).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id_shards.iter().all(|s| read(s).is_empty())
+    }
+
+    /// Retrieves a user by their identity string
+    pub fn get_user(&self, id: &str) -> Option<Arc<T>> {
+        read(self.id_shard(id)).get(id).map(Arc::clone)
+    }
+
+    /// Retrieves a user by their authentication string
+    pub fn get_user_by_authstr(&self, authstr: &str) -> Option<Arc<T>> {
+        read(self.auth_shard(authstr)).get(authstr).map(Arc::clone)
+    }
+}
+
+impl<T: UserTrait + Clone> UserAuthenticator<T> for ShardedUsersMap<T> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.get_user_by_authstr(authstr)
+            .map(|arc_user| arc_user.as_ref().clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::ShardedUsersMap;
+    use crate::{PlainText, UserAuthenticator};
+
+    #[test]
+    fn test_sharded_concurrent() {
+        let um: Arc<ShardedUsersMap<PlainText>> = Arc::new(ShardedUsersMap::new(4));
+
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let um = Arc::clone(&um);
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        um.add_user(PlainText::new(format!("u{t}_{i}"), "p".into()));
+                    }
+                })
+            })
+            .collect();
+        writers.into_iter().for_each(|w| w.join().unwrap());
+
+        assert_eq!(um.len(), 400);
+        assert!(um.auth_user_by_authstr("plaintext:u3_99\np").is_some());
+
+        um.add_user(PlainText::new("u0_0".into(), "new".into()));
+        assert!(um.auth_user_by_authstr("plaintext:u0_0\np").is_none());
+        assert!(um.auth_user_by_authstr("plaintext:u0_0\nnew").is_some());
+
+        assert!(um.remove_user("u0_0").is_some());
+        assert!(um.auth_user_by_authstr("plaintext:u0_0\nnew").is_none());
+        assert_eq!(um.len(), 399);
+    }
+}
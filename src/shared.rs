@@ -0,0 +1,382 @@
+/*!
+Thread-safe sharing of a [`UsersMap`] between many readers and a few writers.
+*/
+
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{AuthError, MapStats, UserAuthenticator, UserTrait, UsersDiff, UsersMap};
+
+type RemovedHook<T> = Arc<dyn Fn(&T) + Send + Sync>;
+
+/// A change of the user set of a [`SharedUsersMap`], published to
+/// [`SharedUsersMap::subscribe`] receivers.
+///
+/// Requires the `tokio` feature.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Default)]
+pub struct UsersUpdate {
+    /// The [`UsersMap::generation`] of the map after the change
+    pub generation: u64,
+
+    pub diff: Arc<UsersDiff>,
+}
+
+/// A [`UsersMap`] behind a [`RwLock`], intended to be shared (usually through an [`Arc`])
+/// between connection handlers that authenticate concurrently and an admin task
+/// that mutates the user set.
+///
+/// A whole rebuilt map can be swapped in atomically with [`SharedUsersMap::swap`],
+/// so readers never observe a half-updated user set.
+pub struct SharedUsersMap<T: UserTrait + Clone> {
+    inner: RwLock<UsersMap<T>>,
+
+    /// Called for every user removed, see [`SharedUsersMap::on_user_removed`]
+    removed_hooks: RwLock<Vec<RemovedHook<T>>>,
+
+    #[cfg(feature = "tokio")]
+    updates: tokio::sync::watch::Sender<UsersUpdate>,
+}
+
+impl<T: UserTrait + Clone> std::fmt::Debug for SharedUsersMap<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedUsersMap")
+            .field("inner", &self.inner)
+            .field("removed_hooks", &self.hooks().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: UserTrait + Clone> Default for SharedUsersMap<T> {
+    fn default() -> Self {
+        SharedUsersMap::new(UsersMap::new())
+    }
+}
+
+impl<T: UserTrait + Clone> From<UsersMap<T>> for SharedUsersMap<T> {
+    fn from(map: UsersMap<T>) -> Self {
+        SharedUsersMap::new(map)
+    }
+}
+
+impl<T: UserTrait + Clone> SharedUsersMap<T> {
+    pub fn new(map: UsersMap<T>) -> Self {
+        SharedUsersMap {
+            #[cfg(feature = "tokio")]
+            updates: tokio::sync::watch::Sender::new(UsersUpdate {
+                generation: map.generation(),
+                diff: Arc::default(),
+            }),
+            inner: RwLock::new(map),
+            removed_hooks: RwLock::default(),
+        }
+    }
+
+    /// Locks the map for reading.
+    ///
+    /// A poisoned lock is recovered, as every mutation of [`UsersMap`] leaves it consistent.
+    pub fn read(&self) -> RwLockReadGuard<'_, UsersMap<T>> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the map for writing.
+    pub fn write(&self) -> RwLockWriteGuard<'_, UsersMap<T>> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Atomically replaces the whole map, returning the previous one.
+    ///
+    /// The [`SharedUsersMap::on_user_removed`] hooks are called for the users missing
+    /// from `map`.
+    pub fn swap(&self, map: UsersMap<T>) -> UsersMap<T> {
+        let mut current = self.write();
+        #[cfg(feature = "tokio")]
+        let publish = self.updates.receiver_count() > 0;
+        #[cfg(not(feature = "tokio"))]
+        let publish = false;
+        if publish || !self.hooks().is_empty() {
+            let diff = UsersDiff::between(&current, &map);
+            self.removed(&current, &diff);
+            #[cfg(feature = "tokio")]
+            self.publish(&map, diff);
+        }
+        std::mem::replace(&mut *current, map)
+    }
+
+    /// Atomically replaces the whole map with a reloaded one and returns what changed,
+    /// e.g. to log it.
+    ///
+    /// The statistics collector of the current map, if any, is carried over to the new one.
+    /// Before the lock is released, the [`SharedUsersMap::on_user_removed`] hooks are called
+    /// for every user missing from `map`, so that no connection of a deleted user is
+    /// authenticated again once `reload` returns.
+    pub fn reload(&self, mut map: UsersMap<T>) -> UsersDiff {
+        let mut current = self.write();
+        map.set_stats(current.stats());
+        let diff = UsersDiff::between(&current, &map);
+        self.removed(&current, &diff);
+        #[cfg(feature = "tokio")]
+        self.publish(&map, diff.clone());
+        *current = map;
+        diff
+    }
+
+    /// Calls `f` with every user removed from now on, by [`SharedUsersMap::remove_user`],
+    /// [`SharedUsersMap::reload`] or [`SharedUsersMap::swap`], e.g. to terminate their
+    /// sessions.
+    ///
+    /// `f` runs while the map is locked for writing, so it must not use this map.
+    pub fn on_user_removed(&self, f: impl Fn(&T) + Send + Sync + 'static) {
+        self.removed_hooks
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::new(f));
+    }
+
+    fn hooks(&self) -> RwLockReadGuard<'_, Vec<RemovedHook<T>>> {
+        self.removed_hooks
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Calls the removal hooks with the users of `current` that `diff` removes.
+    fn removed(&self, current: &UsersMap<T>, diff: &UsersDiff) {
+        let hooks = self.hooks();
+        if hooks.is_empty() {
+            return;
+        }
+        for user in diff.removed.iter().filter_map(|id| current.get_user(id)) {
+            hooks.iter().for_each(|f| f(&user));
+        }
+    }
+
+    /// Receives every change made through [`SharedUsersMap::add_user`],
+    /// [`SharedUsersMap::remove_user`], [`SharedUsersMap::swap`] and [`SharedUsersMap::reload`],
+    /// which [`spawn_refresh_task`](crate::spawn_refresh_task) and the file and HTTP sources use,
+    /// so that connection handlers can e.g. close the connections of removed users at once.
+    ///
+    /// A receiver that falls behind only sees the latest update, and changes made through
+    /// [`SharedUsersMap::write`] are not published; on any update, handlers that must not
+    /// miss a removal should also check that their user still exists.
+    ///
+    /// Requires the `tokio` feature.
+    #[cfg(feature = "tokio")]
+    pub fn subscribe(&self) -> tokio::sync::watch::Receiver<UsersUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Publishes `diff` as the change leading to `map`, unless it is empty.
+    #[cfg(feature = "tokio")]
+    fn publish(&self, map: &UsersMap<T>, diff: UsersDiff) {
+        if !diff.is_empty() {
+            self.updates.send_replace(UsersUpdate {
+                generation: map.generation(),
+                diff: Arc::new(diff),
+            });
+        }
+    }
+
+    /// Consumes the wrapper, returning the inner map.
+    pub fn into_inner(self) -> UsersMap<T> {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds a user, returning the one it replaced, see [`UsersMap::add_user`].
+    pub fn add_user(&self, user: T) -> Option<Arc<T>> {
+        let mut map = self.write();
+        #[cfg(feature = "tokio")]
+        let diff = {
+            let id = user.identity_str().to_string();
+            match map.get_user(&id) {
+                None => UsersDiff {
+                    added: vec![id],
+                    ..UsersDiff::default()
+                },
+                Some(old) if old.auth_str() != user.auth_str() => UsersDiff {
+                    changed: vec![id],
+                    ..UsersDiff::default()
+                },
+                Some(_) => UsersDiff::default(),
+            }
+        };
+        let old = map.add_user(user);
+        #[cfg(feature = "tokio")]
+        self.publish(&map, diff);
+        old
+    }
+
+    /// Adds a user unless its identity is taken, returning false in that case.
+    pub(crate) fn add_new_user(&self, user: T) -> bool {
+        let mut map = self.write();
+        if map.get_user(user.identity_str()).is_some() {
+            return false;
+        }
+        #[cfg(feature = "tokio")]
+        let diff = UsersDiff {
+            added: vec![user.identity_str().to_string()],
+            ..UsersDiff::default()
+        };
+        map.add_user(user);
+        #[cfg(feature = "tokio")]
+        self.publish(&map, diff);
+        true
+    }
+
+    /// Replaces the user having the same identity, see [`UsersMap::update_user`].
+    pub fn update_user(&self, user: T) -> Option<Arc<T>> {
+        let mut map = self.write();
+        #[cfg(feature = "tokio")]
+        let diff = UsersDiff {
+            changed: map
+                .get_user(user.identity_str())
+                .filter(|old| old.auth_str() != user.auth_str())
+                .map(|_| user.identity_str().to_string())
+                .into_iter()
+                .collect(),
+            ..UsersDiff::default()
+        };
+        let old = map.update_user(user);
+        #[cfg(feature = "tokio")]
+        self.publish(&map, diff);
+        old
+    }
+
+    /// Removes a user using their identity string, returning it.
+    pub fn remove_user(&self, id: &str) -> Option<Arc<T>> {
+        let mut map = self.write();
+        let removed = map.remove_user(id);
+        if let Some(user) = &removed {
+            self.hooks().iter().for_each(|f| f(user));
+            #[cfg(feature = "tokio")]
+            self.publish(
+                &map,
+                UsersDiff {
+                    removed: vec![user.identity_str().to_string()],
+                    ..UsersDiff::default()
+                },
+            );
+        }
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Retrieves a user by their identity string
+    pub fn get_user(&self, id: &str) -> Option<Arc<T>> {
+        self.read().get_user(id)
+    }
+
+    /// Retrieves a user by their authentication string
+    pub fn get_user_by_authstr(&self, authstr: &str) -> Option<Arc<T>> {
+        self.read().get_user_by_authstr(authstr)
+    }
+
+    /// Retrieves a user by the raw bytes of their credential, see [`UsersMap::get_user_by_authbytes`]
+    pub fn get_user_by_authbytes(&self, authbytes: &[u8]) -> Option<Arc<T>> {
+        self.read().get_user_by_authbytes(authbytes)
+    }
+
+    /// Returns the statistics collector of the current map, if statistics are enabled.
+    ///
+    /// To keep collecting into the same [`MapStats`] across [`SharedUsersMap::swap`],
+    /// pass it to the rebuilt map with [`UsersMap::set_stats`] before swapping.
+    pub fn stats(&self) -> Option<Arc<MapStats>> {
+        self.read().stats()
+    }
+}
+
+impl<T: UserTrait + Clone> UserAuthenticator<T> for SharedUsersMap<T> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.read().auth_user_by_authstr(authstr)
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.read().try_auth(authstr)
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        self.read().try_auth_as(identity, authstr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use super::SharedUsersMap;
+    use crate::{PlainText, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_shared_swap() {
+        let shared: Arc<SharedUsersMap<PlainText>> = Arc::default();
+        shared.add_user(PlainText::new("u".into(), "p".into()));
+
+        let reader = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || shared.auth_user_by_authstr("plaintext:u\np").is_some())
+        };
+        assert!(reader.join().unwrap());
+
+        let mut rebuilt = UsersMap::default();
+        rebuilt.add_user(PlainText::new("u2".into(), "p2".into()));
+        let old = shared.swap(rebuilt);
+
+        assert_eq!(old.len(), 1);
+        assert!(shared.auth_user_by_authstr("plaintext:u\np").is_none());
+        assert!(shared.auth_user_by_authstr("plaintext:u2\np2").is_some());
+    }
+
+    #[test]
+    fn test_reload_hooks() {
+        let shared = SharedUsersMap::default();
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        {
+            let removed = Arc::clone(&removed);
+            shared
+                .on_user_removed(move |u: &PlainText| removed.lock().unwrap().push(u.user.clone()));
+        }
+        shared.add_user(PlainText::from("a p"));
+        shared.add_user(PlainText::from("b p"));
+        shared.add_user(PlainText::from("c p"));
+        shared.remove_user("c");
+
+        let mut new = UsersMap::new();
+        new.add_user(PlainText::from("a p2"));
+        new.add_user(PlainText::from("d p"));
+        let diff = shared.reload(new);
+        assert_eq!(diff.added, ["d"]);
+        assert_eq!(diff.removed, ["b"]);
+        assert_eq!(diff.changed, ["a"]);
+        assert_eq!(*removed.lock().unwrap(), ["c", "b"]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_subscribe() {
+        let shared = SharedUsersMap::default();
+        let mut rx = shared.subscribe();
+        shared.add_user(PlainText::from("u p"));
+        shared.add_user(PlainText::from("v p"));
+        assert!(rx.has_changed().unwrap());
+        // Only the latest update is kept
+        assert_eq!(rx.borrow_and_update().diff.added, vec!["v".to_string()]);
+
+        shared.add_user(PlainText::from("u p"));
+        assert!(!rx.has_changed().unwrap());
+        shared.remove_user("u");
+        let update = rx.borrow_and_update().clone();
+        assert_eq!(update.diff.removed, vec!["u".to_string()]);
+        assert_eq!(update.generation, shared.read().generation());
+
+        shared.swap(UsersMap::new());
+        assert_eq!(rx.borrow_and_update().diff.removed, vec!["v".to_string()]);
+    }
+}
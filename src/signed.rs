@@ -0,0 +1,129 @@
+/*!
+User lists accepted only with a valid ed25519 signature, for nodes that pull them
+from semi-trusted distribution channels.
+
+Requires the `signing` feature.
+*/
+
+use std::fs;
+use std::hash::BuildHasher;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::persist::write_atomic;
+use crate::{UserTrait, UsersMap};
+
+/// `users.json` -> `users.json.sig`
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// Reads a detached signature: 64 raw bytes, or 128 hex digits optionally
+/// surrounded by whitespace, as written by most signing tools.
+fn parse_signature(data: &[u8]) -> io::Result<Signature> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed signature file");
+    let bytes: [u8; 64] = match data.len() {
+        64 => data.try_into().expect("length checked"),
+        _ => {
+            let text = std::str::from_utf8(data).map_err(|_| invalid())?.trim();
+            if text.len() != 128 || !text.is_ascii() {
+                return Err(invalid());
+            }
+            let mut bytes = [0u8; 64];
+            for (b, pair) in bytes.iter_mut().zip(text.as_bytes().chunks(2)) {
+                let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+                *b = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+            }
+            bytes
+        }
+    };
+    Ok(Signature::from_bytes(&bytes))
+}
+
+/// Checks that `signature` is a valid signature of `data` by `key`.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] otherwise.
+pub fn verify_signed(data: &[u8], signature: &[u8], key: &VerifyingKey) -> io::Result<()> {
+    key.verify_strict(data, &parse_signature(signature)?)
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the user list signature doesn't verify",
+            )
+        })
+}
+
+impl<T: UserTrait + Clone + Serialize, S: BuildHasher> UsersMap<T, S> {
+    /// Like [`UsersMap::save_to_path`], also writing the signature of the file
+    /// next to it, see [`signature_path`].
+    pub fn save_signed(&self, path: &Path, key: &SigningKey) -> io::Result<()> {
+        let json = self.to_json()?;
+        let signature = key.sign(&json);
+        write_atomic(path, &json)?;
+        write_atomic(&signature_path(path), &signature.to_bytes())
+    }
+}
+
+impl<T: UserTrait + Clone + DeserializeOwned> UsersMap<T> {
+    /// Reads a map written by [`UsersMap::save_to_path`], only if the signature
+    /// file next to it verifies against `key`.
+    ///
+    /// A missing signature is reported as [`io::ErrorKind::NotFound`], a wrong
+    /// one as [`io::ErrorKind::InvalidData`].
+    pub fn load_signed(path: &Path, key: &VerifyingKey) -> io::Result<Self> {
+        let json = fs::read(path)?;
+        let signature = fs::read(signature_path(path))?;
+        verify_signed(&json, &signature, key)?;
+        Self::load_from_slice(&json)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ed25519_dalek::SigningKey;
+
+    use super::signature_path;
+    use crate::{PlainText, UsersMap};
+
+    #[test]
+    fn test_signed_list() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("user_trait_signed_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("users.json");
+
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+        um.save_signed(&path, &key)?;
+        let loaded: UsersMap<PlainText> = UsersMap::load_signed(&path, &key.verifying_key())?;
+        assert_eq!(loaded.len(), 1);
+
+        // Hex signatures are accepted too
+        let sig = std::fs::read(signature_path(&path))?;
+        let hex: String = sig.iter().map(|b| format!("{b:02x}")).collect();
+        std::fs::write(signature_path(&path), format!("{hex}\n"))?;
+        assert!(UsersMap::<PlainText>::load_signed(&path, &key.verifying_key()).is_ok());
+
+        let other = SigningKey::from_bytes(&[2; 32]).verifying_key();
+        let err = UsersMap::<PlainText>::load_signed(&path, &other).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut json = std::fs::read(&path)?;
+        json.push(b' ');
+        std::fs::write(&path, json)?;
+        assert!(UsersMap::<PlainText>::load_signed(&path, &key.verifying_key()).is_err());
+
+        std::fs::remove_file(signature_path(&path))?;
+        let err = UsersMap::<PlainText>::load_signed(&path, &key.verifying_key()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
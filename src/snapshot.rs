@@ -0,0 +1,109 @@
+/*!
+Lock-free sharing of a [`UsersMap`] that is replaced rather than mutated.
+
+Requires the `arc-swap` feature.
+*/
+
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use crate::{AuthError, UserAuthenticator, UserTrait, UsersMap};
+
+/// A [`UsersMap`] in an [`ArcSwap`], for servers authenticating on every connection
+/// while the user set changes rarely.
+///
+/// Unlike [`SharedUsersMap`](crate::SharedUsersMap), readers never wait for a lock:
+/// authenticating reads the current snapshot, and writers build a new map and swap it in.
+/// A snapshot obtained with [`SnapshotAuthenticator::load`] stays valid, and unchanged,
+/// after it is replaced. As every change copies the map, prefer [`SharedUsersMap`](crate::SharedUsersMap)
+/// for frequent small updates.
+///
+/// It is asynchronous through the blanket implementation of
+/// [`AsyncUserAuthenticator`](crate::AsyncUserAuthenticator).
+#[derive(Debug, Default)]
+pub struct SnapshotAuthenticator<T: UserTrait + Clone> {
+    map: ArcSwap<UsersMap<T>>,
+}
+
+impl<T: UserTrait + Clone> From<UsersMap<T>> for SnapshotAuthenticator<T> {
+    fn from(map: UsersMap<T>) -> Self {
+        SnapshotAuthenticator::new(map)
+    }
+}
+
+impl<T: UserTrait + Clone> SnapshotAuthenticator<T> {
+    pub fn new(map: UsersMap<T>) -> Self {
+        SnapshotAuthenticator {
+            map: ArcSwap::from_pointee(map),
+        }
+    }
+
+    /// Returns the current snapshot.
+    pub fn load(&self) -> Arc<UsersMap<T>> {
+        self.map.load_full()
+    }
+
+    /// Atomically replaces the whole map, returning the previous snapshot.
+    pub fn store(&self, map: UsersMap<T>) -> Arc<UsersMap<T>> {
+        self.map.swap(Arc::new(map))
+    }
+
+    /// Replaces the map with `f` of the current one, e.g. a clone with a user added,
+    /// returning the snapshot that was replaced.
+    ///
+    /// If another writer replaced the map meanwhile, `f` is called again on its map,
+    /// so no concurrent change is lost; `f` must not have side effects.
+    pub fn rcu<F>(&self, mut f: F) -> Arc<UsersMap<T>>
+    where
+        F: FnMut(&UsersMap<T>) -> UsersMap<T>,
+    {
+        self.map.rcu(|map| f(map))
+    }
+}
+
+impl<T: UserTrait + Clone> UserAuthenticator<T> for SnapshotAuthenticator<T> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.map.load().auth_user_by_authstr(authstr)
+    }
+
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.map.load().try_auth(authstr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::SnapshotAuthenticator;
+    use crate::{PlainText, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_snapshot_rcu() {
+        let auth = Arc::new(SnapshotAuthenticator::new(UsersMap::new()));
+        let writers: Vec<_> = (0..4)
+            .map(|i| {
+                let auth = Arc::clone(&auth);
+                thread::spawn(move || {
+                    auth.rcu(|map| {
+                        let mut map = map.clone();
+                        map.add_user(PlainText::new(format!("u{i}"), "p".into()));
+                        map
+                    });
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert!(auth.auth_user_by_authstr("plaintext:u3\np").is_some());
+
+        let before = auth.load();
+        let old = auth.store(UsersMap::new());
+        assert!(Arc::ptr_eq(&before, &old));
+        assert_eq!(before.len(), 4);
+        assert!(auth.auth_user_by_authstr("plaintext:u3\np").is_none());
+    }
+}
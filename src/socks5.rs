@@ -0,0 +1,317 @@
+/*!
+The server side of SOCKS5 username/password authentication (RFC 1928 and RFC 1929).
+*/
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+use crate::{AuthError, AuthFormat, User, UserAuthenticator};
+
+const VERSION: u8 = 5;
+const METHOD_USER_PASS: u8 = 2;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const USER_PASS_VERSION: u8 = 1;
+
+/// Why a SOCKS5 negotiation ended without a user.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Socks5Error {
+    /// The client doesn't speak SOCKS5, or the username/password sub-negotiation version 1.
+    UnsupportedVersion(u8),
+
+    /// The client doesn't offer username/password authentication.
+    NoAcceptableMethod,
+
+    /// The credentials were refused.
+    Auth(AuthError),
+
+    /// The negotiation already ended.
+    Finished,
+
+    Io(io::Error),
+}
+
+impl fmt::Display for Socks5Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Socks5Error::UnsupportedVersion(v) => write!(f, "unsupported SOCKS version {v}"),
+            Socks5Error::NoAcceptableMethod => {
+                f.write_str("the client doesn't offer username/password authentication")
+            }
+            Socks5Error::Auth(e) => write!(f, "SOCKS5 authentication failed: {e}"),
+            Socks5Error::Finished => f.write_str("the SOCKS5 negotiation already ended"),
+            Socks5Error::Io(e) => write!(f, "SOCKS5 negotiation failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Socks5Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Socks5Error::Auth(e) => Some(e),
+            Socks5Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Socks5Error {
+    fn from(e: io::Error) -> Self {
+        Socks5Error::Io(e)
+    }
+}
+
+/// What to do after feeding bytes to a [`Socks5AuthNegotiator`]
+#[derive(Debug)]
+pub enum Socks5Step<T> {
+    /// The input is incomplete; nothing was consumed. Read more and feed it all again.
+    NeedMore,
+
+    /// Send `reply` to the client and feed the bytes after the first `consumed` ones.
+    Reply { consumed: usize, reply: Vec<u8> },
+
+    /// Send `reply`; the client is authenticated as `user`, and its request follows
+    /// the first `consumed` bytes.
+    Done {
+        consumed: usize,
+        reply: Vec<u8>,
+        user: T,
+    },
+
+    /// Send `reply`, if any, and close the connection.
+    Failed {
+        reply: Option<Vec<u8>>,
+        error: Socks5Error,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    MethodSelection,
+    UserPass,
+    Finished,
+}
+
+/// Drives the authentication phase of a SOCKS5 server: it reads the method selection
+/// and the username/password sub-negotiation sent by the client, produces the replies,
+/// and yields the user authenticated by `A`.
+///
+/// The credentials are checked as the auth string of a [`PlainText`](crate::PlainText)
+/// user, `plaintext:{user}\n{pass}`. [`Socks5AuthNegotiator::feed`] works on any
+/// transport; [`Socks5AuthNegotiator::negotiate`] runs it on a blocking stream.
+pub struct Socks5AuthNegotiator<T, A> {
+    authenticator: A,
+    state: State,
+    _user: PhantomData<fn() -> T>,
+}
+
+impl<T: User, A: UserAuthenticator<T>> Socks5AuthNegotiator<T, A> {
+    pub fn new(authenticator: A) -> Self {
+        Socks5AuthNegotiator {
+            authenticator,
+            state: State::MethodSelection,
+            _user: PhantomData,
+        }
+    }
+
+    /// Consumes the bytes received from the client, starting after those consumed so far.
+    pub fn feed(&mut self, input: &[u8]) -> Socks5Step<T> {
+        match self.state {
+            State::MethodSelection => self.select_method(input),
+            State::UserPass => self.check_user_pass(input),
+            State::Finished => Socks5Step::Failed {
+                reply: None,
+                error: Socks5Error::Finished,
+            },
+        }
+    }
+
+    fn fail(&mut self, reply: Option<Vec<u8>>, error: Socks5Error) -> Socks5Step<T> {
+        self.state = State::Finished;
+        Socks5Step::Failed { reply, error }
+    }
+
+    fn select_method(&mut self, input: &[u8]) -> Socks5Step<T> {
+        let [version, n, ..] = *input else {
+            return Socks5Step::NeedMore;
+        };
+        if version != VERSION {
+            return self.fail(None, Socks5Error::UnsupportedVersion(version));
+        }
+        let Some(methods) = input.get(2..2 + n as usize) else {
+            return Socks5Step::NeedMore;
+        };
+        if !methods.contains(&METHOD_USER_PASS) {
+            let reply = vec![VERSION, NO_ACCEPTABLE_METHOD];
+            return self.fail(Some(reply), Socks5Error::NoAcceptableMethod);
+        }
+        self.state = State::UserPass;
+        Socks5Step::Reply {
+            consumed: 2 + methods.len(),
+            reply: vec![VERSION, METHOD_USER_PASS],
+        }
+    }
+
+    fn check_user_pass(&mut self, input: &[u8]) -> Socks5Step<T> {
+        let [version, ulen, ..] = *input else {
+            return Socks5Step::NeedMore;
+        };
+        if version != USER_PASS_VERSION {
+            return self.fail(None, Socks5Error::UnsupportedVersion(version));
+        }
+        let user_end = 2 + ulen as usize;
+        let Some(&plen) = input.get(user_end) else {
+            return Socks5Step::NeedMore;
+        };
+        let end = user_end + 1 + plen as usize;
+        if input.len() < end {
+            return Socks5Step::NeedMore;
+        }
+        let user = std::str::from_utf8(&input[2..user_end]);
+        let pass = std::str::from_utf8(&input[user_end + 1..end]);
+        let result = match (user, pass) {
+            (Ok(user), Ok(pass)) => {
+                let authstr = AuthFormat::Legacy.encode("plaintext", user, pass);
+                self.authenticator.try_auth_as(user, &authstr)
+            }
+            _ => Err(AuthError::UnknownUser),
+        };
+        match result {
+            Ok(user) => {
+                self.state = State::Finished;
+                Socks5Step::Done {
+                    consumed: end,
+                    reply: vec![USER_PASS_VERSION, 0],
+                    user,
+                }
+            }
+            Err(e) => self.fail(Some(vec![USER_PASS_VERSION, 1]), Socks5Error::Auth(e)),
+        }
+    }
+
+    /// Runs the negotiation on a blocking stream, returning the user and the bytes
+    /// the client sent after the negotiation, usually the start of its request.
+    ///
+    /// On failure, the refusal is sent before returning the error.
+    pub fn negotiate<S: Read + Write>(
+        mut self,
+        stream: &mut S,
+    ) -> Result<(T, Vec<u8>), Socks5Error> {
+        let mut buf = Vec::with_capacity(64);
+        let mut chunk = [0; 256];
+        loop {
+            match self.feed(&buf) {
+                Socks5Step::NeedMore => {
+                    let n = stream.read(&mut chunk)?;
+                    if n == 0 {
+                        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                    }
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                Socks5Step::Reply { consumed, reply } => {
+                    stream.write_all(&reply)?;
+                    buf.drain(..consumed);
+                }
+                Socks5Step::Done {
+                    consumed,
+                    reply,
+                    user,
+                } => {
+                    stream.write_all(&reply)?;
+                    buf.drain(..consumed);
+                    return Ok((user, buf));
+                }
+                Socks5Step::Failed { reply, error } => {
+                    if let Some(reply) = reply {
+                        stream.write_all(&reply)?;
+                    }
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Read, Write};
+    use std::sync::Arc;
+
+    use super::{Socks5AuthNegotiator, Socks5Error, Socks5Step};
+    use crate::{PlainText, ThrottlePolicy, ThrottledAuthenticator, UsersMap};
+
+    /// Reads from `input` five bytes at a time, collecting what is written.
+    struct Stream<'a> {
+        input: &'a [u8],
+        output: Vec<u8>,
+    }
+
+    impl Read for Stream<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.input.len().min(buf.len()).min(5);
+            buf[..n].copy_from_slice(&self.input[..n]);
+            self.input = &self.input[n..];
+            Ok(n)
+        }
+    }
+
+    impl Write for Stream<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_socks5_negotiator() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("alice secret"));
+        let um = Arc::new(um);
+
+        let mut stream = Stream {
+            input: b"\x05\x02\x00\x02\x01\x05alice\x06secret\x05\x01",
+            output: Vec::new(),
+        };
+        let (user, rest) = Socks5AuthNegotiator::new(Arc::clone(&um))
+            .negotiate(&mut stream)
+            .unwrap();
+        assert_eq!(user.user, "alice");
+        assert_eq!(stream.output, b"\x05\x02\x01\x00");
+        assert_eq!(rest, b"\x05\x01");
+
+        let mut stream = Stream {
+            input: b"\x05\x01\x02\x01\x05alice\x05wrong",
+            output: Vec::new(),
+        };
+        let throttled = Arc::new(ThrottledAuthenticator::new(
+            Arc::clone(&um),
+            ThrottlePolicy::default(),
+        ));
+        let e = Socks5AuthNegotiator::new(Arc::clone(&throttled)).negotiate(&mut stream);
+        assert!(matches!(e, Err(Socks5Error::Auth(_))));
+        assert_eq!(stream.output, b"\x05\x02\x01\x01");
+        assert_eq!(throttled.identity_failures("alice"), 1);
+
+        let mut negotiator = Socks5AuthNegotiator::new(Arc::clone(&um));
+        assert!(matches!(negotiator.feed(b"\x05\x01"), Socks5Step::NeedMore));
+        assert!(matches!(
+            negotiator.feed(b"\x05\x01\x00"),
+            Socks5Step::Failed {
+                reply: Some(_),
+                error: Socks5Error::NoAcceptableMethod
+            }
+        ));
+        assert!(matches!(
+            negotiator.feed(b"\x05\x01\x02"),
+            Socks5Step::Failed {
+                error: Socks5Error::Finished,
+                ..
+            }
+        ));
+    }
+}
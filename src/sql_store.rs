@@ -0,0 +1,298 @@
+/*!
+Users stored in a SQL database through sqlx, e.g. Postgres or MySQL.
+
+Requires the `sqlx` feature, and the sqlx feature of the database driver.
+*/
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use sqlx::{
+    AssertSqlSafe, ColumnIndex, Database, Decode, Encode, Executor, IntoArguments, Pool,
+    SqlSafeStr, SqlStr, Type,
+};
+
+use crate::{AsyncUserAuthenticator, AuthError, StoreError, UserBox, UserTrait};
+
+/// The statements of a [`SqlUserStore`], built once for its table and dialect so that
+/// sqlx prepares each of them once per connection.
+#[derive(Debug, Clone)]
+struct Queries {
+    create: SqlStr,
+    get: SqlStr,
+    by_credential: SqlStr,
+    insert: SqlStr,
+    update: SqlStr,
+    delete: SqlStr,
+    list: SqlStr,
+    count: SqlStr,
+}
+
+impl Queries {
+    /// Postgres numbers its placeholders, MySQL and SQLite take `?`.
+    fn new(table: &str, numbered: bool) -> Self {
+        let p = |n: usize| {
+            if numbered {
+                format!("${n}")
+            } else {
+                "?".to_string()
+            }
+        };
+        let sql = |s: String| AssertSqlSafe(Arc::<str>::from(s)).into_sql_str();
+        Queries {
+            create: sql(format!(
+                "CREATE TABLE IF NOT EXISTS {table} (
+                    id VARCHAR(255) PRIMARY KEY NOT NULL,
+                    credential CHAR(64) NOT NULL UNIQUE,
+                    data TEXT NOT NULL
+                )"
+            )),
+            get: sql(format!("SELECT data FROM {table} WHERE id = {}", p(1))),
+            by_credential: sql(format!(
+                "SELECT data FROM {table} WHERE credential = {}",
+                p(1)
+            )),
+            insert: sql(format!(
+                "INSERT INTO {table} (id, credential, data) VALUES ({}, {}, {})",
+                p(1),
+                p(2),
+                p(3)
+            )),
+            update: sql(format!(
+                "UPDATE {table} SET credential = {}, data = {} WHERE id = {}",
+                p(1),
+                p(2),
+                p(3)
+            )),
+            delete: sql(format!("DELETE FROM {table} WHERE id = {}", p(1))),
+            list: sql(format!("SELECT data FROM {table}")),
+            count: sql(format!("SELECT COUNT(*) FROM {table}")),
+        }
+    }
+}
+
+fn credential_digest(authstr: &str) -> String {
+    Sha256::digest(authstr.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// A user store in a SQL database, for panel-style deployments that already keep
+/// users in SQL. Generic over the sqlx driver, e.g. `SqlUserStore<sqlx::Postgres>`.
+///
+/// Each row holds the identity, a SHA-256 digest of the auth string, which is unique
+/// and looked up on authentication, and the user as JSON. Users of any registered
+/// type are stored as [`UserBox`]es. The CRUD methods mirror
+/// [`UserStore`](crate::UserStore), asynchronously.
+#[derive(Debug, Clone)]
+pub struct SqlUserStore<DB: Database> {
+    pool: Pool<DB>,
+    queries: Queries,
+}
+
+impl<DB> SqlUserStore<DB>
+where
+    DB: Database,
+    DB::Arguments: IntoArguments<DB>,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'a> &'a str: Encode<'a, DB> + Type<DB>,
+    String: for<'r> Decode<'r, DB> + Type<DB>,
+    i64: for<'r> Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    /// Uses the table `users`.
+    pub fn new(pool: Pool<DB>) -> Self {
+        Self::with_table(pool, "users")
+    }
+
+    /// Uses the given table, which may be schema-qualified.
+    ///
+    /// Panics if `table` is not made of ASCII letters, digits, `_` and `.`.
+    pub fn with_table(pool: Pool<DB>, table: &str) -> Self {
+        assert!(
+            !table.is_empty()
+                && table
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'.'),
+            "invalid SQL table name {table:?}"
+        );
+        SqlUserStore {
+            pool,
+            queries: Queries::new(table, DB::NAME == "PostgreSQL"),
+        }
+    }
+
+    pub fn pool(&self) -> &Pool<DB> {
+        &self.pool
+    }
+
+    /// Creates the table if it doesn't exist.
+    pub async fn migrate(&self) -> Result<(), StoreError> {
+        sqlx::query(self.queries.create.clone())
+            .execute(&self.pool)
+            .await
+            .map_err(StoreError::backend)?;
+        Ok(())
+    }
+
+    fn encode(user: &UserBox) -> Result<String, StoreError> {
+        serde_json::to_string(user).map_err(StoreError::backend)
+    }
+
+    fn decode(data: &str) -> Result<UserBox, StoreError> {
+        serde_json::from_str(data).map_err(StoreError::backend)
+    }
+
+    async fn get_data<'c, E>(&self, executor: E, id: &str) -> Result<Option<String>, StoreError>
+    where
+        E: Executor<'c, Database = DB>,
+    {
+        sqlx::query_scalar(self.queries.get.clone())
+            .bind(id)
+            .fetch_optional(executor)
+            .await
+            .map_err(StoreError::backend)
+    }
+
+    /// Adds a user, failing with [`StoreError::AlreadyExists`] if its identity is taken.
+    pub async fn add(&self, user: UserBox) -> Result<(), StoreError> {
+        let id = user.identity_str();
+        let data = Self::encode(&user)?;
+        let result = sqlx::query(self.queries.insert.clone())
+            .bind(id)
+            .bind(credential_digest(user.auth_str()).as_str())
+            .bind(data.as_str())
+            .execute(&self.pool)
+            .await;
+        match result {
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(e))
+                if e.is_unique_violation() && self.get(id).await?.is_some() =>
+            {
+                Err(StoreError::AlreadyExists(id.to_string()))
+            }
+            Err(e) => Err(StoreError::backend(e)),
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<UserBox>, StoreError> {
+        self.get_data(&self.pool, id)
+            .await?
+            .map(|d| Self::decode(&d))
+            .transpose()
+    }
+
+    /// Removes a user, returning it.
+    pub async fn remove(&self, id: &str) -> Result<UserBox, StoreError> {
+        let mut tx = self.pool.begin().await.map_err(StoreError::backend)?;
+        let data = self
+            .get_data(&mut *tx, id)
+            .await?
+            .ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+        sqlx::query(self.queries.delete.clone())
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(StoreError::backend)?;
+        tx.commit().await.map_err(StoreError::backend)?;
+        Self::decode(&data)
+    }
+
+    /// Replaces the user having the same identity, returning the previous one.
+    pub async fn update(&self, user: UserBox) -> Result<UserBox, StoreError> {
+        let id = user.identity_str();
+        let data = Self::encode(&user)?;
+        let mut tx = self.pool.begin().await.map_err(StoreError::backend)?;
+        let old = self
+            .get_data(&mut *tx, id)
+            .await?
+            .ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+        sqlx::query(self.queries.update.clone())
+            .bind(credential_digest(user.auth_str()).as_str())
+            .bind(data.as_str())
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(StoreError::backend)?;
+        tx.commit().await.map_err(StoreError::backend)?;
+        Self::decode(&old)
+    }
+
+    /// Returns every user, in no particular order.
+    pub async fn list(&self) -> Result<Vec<UserBox>, StoreError> {
+        let rows: Vec<String> = sqlx::query_scalar(self.queries.list.clone())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(StoreError::backend)?;
+        rows.iter().map(|d| Self::decode(d)).collect()
+    }
+
+    pub async fn count(&self) -> Result<usize, StoreError> {
+        let count: i64 = sqlx::query_scalar(self.queries.count.clone())
+            .fetch_one(&self.pool)
+            .await
+            .map_err(StoreError::backend)?;
+        Ok(count as usize)
+    }
+
+    /// Looks up the owner of `authstr`, reporting database failures as [`AuthError::Backend`].
+    pub async fn try_auth_async(&self, authstr: &str) -> Result<UserBox, AuthError> {
+        let data: Option<String> = sqlx::query_scalar(self.queries.by_credential.clone())
+            .bind(credential_digest(authstr).as_str())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(AuthError::backend)?;
+        let data = data.ok_or(AuthError::UnknownUser)?;
+        let user: UserBox = serde_json::from_str(&data).map_err(AuthError::backend)?;
+        if user.auth_eq(authstr.as_bytes()) {
+            Ok(user)
+        } else {
+            Err(AuthError::UnknownUser)
+        }
+    }
+}
+
+#[async_trait]
+impl<DB> AsyncUserAuthenticator<UserBox> for SqlUserStore<DB>
+where
+    DB: Database,
+    DB::Arguments: IntoArguments<DB>,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'a> &'a str: Encode<'a, DB> + Type<DB>,
+    String: for<'r> Decode<'r, DB> + Type<DB>,
+    i64: for<'r> Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    async fn auth_user_by_authstr(&self, authstr: &str) -> Option<UserBox> {
+        self.try_auth_async(authstr).await.ok()
+    }
+
+    async fn try_auth(&self, authstr: &str) -> Result<UserBox, AuthError> {
+        self.try_auth_async(authstr).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Queries;
+
+    #[test]
+    fn test_sql_queries() {
+        let pg = Queries::new("proxy.users", true);
+        assert_eq!(
+            pg.update.as_str(),
+            "UPDATE proxy.users SET credential = $1, data = $2 WHERE id = $3"
+        );
+        let my = Queries::new("users", false);
+        assert_eq!(
+            my.insert.as_str(),
+            "INSERT INTO users (id, credential, data) VALUES (?, ?, ?)"
+        );
+        assert!(my
+            .create
+            .as_str()
+            .contains("CREATE TABLE IF NOT EXISTS users"));
+    }
+}
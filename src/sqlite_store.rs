@@ -0,0 +1,242 @@
+/*!
+Users stored in an SQLite database, for single-binary deployments.
+
+Requires the `sqlite` feature.
+*/
+
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{AuthError, StoreError, User, UserAuthenticator, UserStore};
+
+/// Schema changes, applied in order. `PRAGMA user_version` holds how many ran.
+const MIGRATIONS: &[&str] = &["CREATE TABLE users (
+        id TEXT PRIMARY KEY NOT NULL,
+        credential BLOB NOT NULL UNIQUE,
+        data TEXT NOT NULL
+    );"];
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    let version: i64 = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for m in MIGRATIONS.iter().skip(version as usize) {
+        tx.execute_batch(m)?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len() as i64)?;
+    tx.commit()
+}
+
+/// SHA-256 of the auth string, so that credentials are not stored as such.
+fn credential_digest(authstr: &str) -> Vec<u8> {
+    Sha256::digest(authstr.as_bytes()).to_vec()
+}
+
+/// A [`UserStore`] in an SQLite database, that also authenticates its users.
+///
+/// Users are stored as JSON next to a digest of their auth string, which is
+/// indexed for authentication. The schema is created or upgraded on open.
+/// Use [`UserBox`](crate::UserBox) as `T` to store users of several types.
+#[derive(Debug)]
+pub struct SqliteUserStore<T> {
+    conn: Mutex<Connection>,
+    _user: PhantomData<fn() -> T>,
+}
+
+impl<T: User + Serialize + DeserializeOwned> SqliteUserStore<T> {
+    /// Opens or creates the database file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    /// Uses an open connection, migrating its schema.
+    pub fn from_connection(mut conn: Connection) -> rusqlite::Result<Self> {
+        migrate(&mut conn)?;
+        Ok(SqliteUserStore {
+            conn: Mutex::new(conn),
+            _user: PhantomData,
+        })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn decode(data: &str) -> Result<T, StoreError> {
+        serde_json::from_str(data).map_err(StoreError::backend)
+    }
+
+    fn encode(user: &T) -> Result<String, StoreError> {
+        serde_json::to_string(user).map_err(StoreError::backend)
+    }
+}
+
+impl<T: User + Serialize + DeserializeOwned> UserStore<T> for SqliteUserStore<T> {
+    fn add(&mut self, user: T) -> Result<(), StoreError> {
+        let data = Self::encode(&user)?;
+        let inserted = self
+            .conn()
+            .execute(
+                "INSERT INTO users (id, credential, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO NOTHING",
+                params![
+                    user.identity_str(),
+                    credential_digest(user.auth_str()),
+                    data
+                ],
+            )
+            .map_err(StoreError::backend)?;
+        if inserted == 0 {
+            return Err(StoreError::AlreadyExists(user.identity_str().to_string()));
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &str) -> Result<T, StoreError> {
+        let data: Option<String> = self
+            .conn()
+            .query_row(
+                "DELETE FROM users WHERE id = ?1 RETURNING data",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(StoreError::backend)?;
+        match data {
+            Some(data) => Self::decode(&data),
+            None => Err(StoreError::NotFound(id.to_string())),
+        }
+    }
+
+    fn update(&mut self, user: T) -> Result<T, StoreError> {
+        let id = user.identity_str();
+        let data = Self::encode(&user)?;
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(StoreError::backend)?;
+        let old: Option<String> = tx
+            .query_row("SELECT data FROM users WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(StoreError::backend)?;
+        let Some(old) = old else {
+            return Err(StoreError::NotFound(id.to_string()));
+        };
+        tx.execute(
+            "UPDATE users SET credential = ?2, data = ?3 WHERE id = ?1",
+            params![id, credential_digest(user.auth_str()), data],
+        )
+        .map_err(StoreError::backend)?;
+        tx.commit().map_err(StoreError::backend)?;
+        Self::decode(&old)
+    }
+
+    fn get(&self, id: &str) -> Result<Option<T>, StoreError> {
+        let data: Option<String> = self
+            .conn()
+            .query_row("SELECT data FROM users WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(StoreError::backend)?;
+        data.map(|d| Self::decode(&d)).transpose()
+    }
+
+    fn list(&self) -> Result<Vec<T>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT data FROM users")
+            .map_err(StoreError::backend)?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(StoreError::backend)?;
+        rows.map(|data| Self::decode(&data.map_err(StoreError::backend)?))
+            .collect()
+    }
+
+    fn count(&self) -> Result<usize, StoreError> {
+        let count: i64 = self
+            .conn()
+            .query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
+            .map_err(StoreError::backend)?;
+        Ok(count as usize)
+    }
+}
+
+impl<T: User + Serialize + DeserializeOwned> UserAuthenticator<T> for SqliteUserStore<T> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.try_auth(authstr).ok()
+    }
+
+    /// Reports database failures as [`AuthError::Backend`].
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        let data: Option<String> = self
+            .conn()
+            .query_row(
+                "SELECT data FROM users WHERE credential = ?1",
+                [credential_digest(authstr)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(AuthError::backend)?;
+        let data = data.ok_or(AuthError::UnknownUser)?;
+        let user: T = serde_json::from_str(&data).map_err(AuthError::backend)?;
+        if user.auth_eq(authstr.as_bytes()) {
+            Ok(user)
+        } else {
+            Err(AuthError::UnknownUser)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SqliteUserStore;
+    use crate::{AuthError, PlainText, StoreError, UserAuthenticator, UserBox, UserStore};
+
+    #[test]
+    fn test_sqlite_store() -> Result<(), StoreError> {
+        let dir = std::env::temp_dir().join(format!("user_trait_sqlite_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("users.db");
+        let _ = std::fs::remove_file(&path);
+
+        let mut store = SqliteUserStore::open(&path).map_err(StoreError::backend)?;
+        store.add(PlainText::from("u p"))?;
+        assert!(matches!(
+            store.add(PlainText::from("u other")),
+            Err(StoreError::AlreadyExists(_))
+        ));
+        assert_eq!(store.update(PlainText::from("u p2"))?.pass, "p");
+        assert!(matches!(
+            store.try_auth("plaintext:u\np"),
+            Err(AuthError::UnknownUser)
+        ));
+        assert!(store.auth_user_by_authstr("plaintext:u\np2").is_some());
+        drop(store);
+
+        // Reopening keeps the users and doesn't rerun migrations
+        let mut store: SqliteUserStore<PlainText> =
+            SqliteUserStore::open(&path).map_err(StoreError::backend)?;
+        assert_eq!(store.count()?, 1);
+        assert_eq!(store.remove("u")?.pass, "p2");
+        assert!(store.get("u")?.is_none());
+        assert!(matches!(store.remove("u"), Err(StoreError::NotFound(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut boxed = SqliteUserStore::open_in_memory().map_err(StoreError::backend)?;
+        boxed.add(UserBox::new(PlainText::from("v q")))?;
+        assert_eq!(boxed.list()?.len(), 1);
+        assert!(boxed.auth_user_by_authstr("plaintext:v\nq").is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+/*!
+Authentication statistics collected by [`UsersMap`](crate::UsersMap).
+*/
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use crate::{Clock, SystemClock};
+
+/// Counters of authentication lookups, plus the last successful authentication time of each user.
+///
+/// Collection is optional; see [`UsersMap::enable_stats`](crate::UsersMap::enable_stats).
+/// A single `MapStats` can be shared by successive maps, e.g. across
+/// [`SharedUsersMap::swap`](crate::SharedUsersMap::swap), through
+/// [`UsersMap::set_stats`](crate::UsersMap::set_stats).
+#[derive(Debug)]
+pub struct MapStats {
+    successes: AtomicU64,
+    failures: AtomicU64,
+
+    /// Maps user identity strings to their last successful authentication
+    last_auth: Mutex<HashMap<String, SystemTime>>,
+
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for MapStats {
+    fn default() -> Self {
+        MapStats {
+            successes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            last_auth: Mutex::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl MapStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the clock timestamping successful authentications.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub(crate) fn record_success(&self, id: &str) {
+        self.successes.fetch_add(1, Ordering::Relaxed);
+        let mut last_auth = self
+            .last_auth
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let now = self.clock.now();
+        match last_auth.get_mut(id) {
+            Some(t) => *t = now,
+            None => {
+                last_auth.insert(id.to_string(), now);
+            }
+        }
+    }
+
+    pub(crate) fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of successful authentications
+    pub fn successes(&self) -> u64 {
+        self.successes.load(Ordering::Relaxed)
+    }
+
+    /// Number of authentication lookups that found no usable user
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Returns when the user last authenticated successfully
+    pub fn last_auth(&self, id: &str) -> Option<SystemTime> {
+        self.last_auth
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .copied()
+    }
+
+    /// Returns a copy of every user's last successful authentication time
+    pub fn last_auths(&self) -> HashMap<String, SystemTime> {
+        self.last_auth
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Resets every counter and timestamp
+    pub fn reset(&self) {
+        self.successes.store(0, Ordering::Relaxed);
+        self.failures.store(0, Ordering::Relaxed);
+        self.last_auth
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
@@ -0,0 +1,106 @@
+/*!
+A storage abstraction for admin APIs, separate from authentication.
+*/
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+
+use crate::{StoreError, UserTrait, UsersMap};
+
+/// Create, read, update and delete operations on a set of users, keyed by identity.
+///
+/// Authentication goes through [`UserAuthenticator`](crate::UserAuthenticator); this trait
+/// is the mutation surface of admin tooling, implemented by [`UsersMap`] and intended
+/// for persistent backends.
+pub trait UserStore<T> {
+    /// Adds a user, failing with [`StoreError::AlreadyExists`] if its identity is taken.
+    fn add(&mut self, user: T) -> Result<(), StoreError>;
+
+    /// Removes a user, returning it.
+    fn remove(&mut self, id: &str) -> Result<T, StoreError>;
+
+    /// Replaces the user having the same identity, returning the previous one.
+    fn update(&mut self, user: T) -> Result<T, StoreError>;
+
+    fn get(&self, id: &str) -> Result<Option<T>, StoreError>;
+
+    /// Returns every user, in no particular order.
+    fn list(&self) -> Result<Vec<T>, StoreError>;
+
+    fn count(&self) -> Result<usize, StoreError>;
+}
+
+impl<T: UserTrait + Clone, S: BuildHasher> UserStore<T> for UsersMap<T, S> {
+    fn add(&mut self, user: T) -> Result<(), StoreError> {
+        Ok(self.add_user_checked(user)?)
+    }
+
+    fn remove(&mut self, id: &str) -> Result<T, StoreError> {
+        self.remove_user(id)
+            .map(|user| user.as_ref().clone())
+            .ok_or_else(|| StoreError::NotFound(id.to_string()))
+    }
+
+    /// Keeps the user's extra credentials, enabled flag and groups, see [`UsersMap::update_user`].
+    fn update(&mut self, user: T) -> Result<T, StoreError> {
+        let id = user.identity_str().to_string();
+        self.update_user(user)
+            .map(|old| old.as_ref().clone())
+            .ok_or(StoreError::NotFound(id))
+    }
+
+    fn get(&self, id: &str) -> Result<Option<T>, StoreError> {
+        Ok(self.get_user(id).map(|u| u.as_ref().clone()))
+    }
+
+    fn list(&self) -> Result<Vec<T>, StoreError> {
+        Ok(self.iter().map(|u| u.as_ref().clone()).collect())
+    }
+
+    fn count(&self) -> Result<usize, StoreError> {
+        Ok(self.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UserStore;
+    use crate::{PlainText, StoreError, UserAuthenticator, UsersMap};
+
+    fn admin(store: &mut dyn UserStore<PlainText>) -> Result<(), StoreError> {
+        store.add(PlainText::from("u p"))?;
+        assert!(matches!(
+            store.add(PlainText::from("u other")),
+            Err(StoreError::AlreadyExists(_))
+        ));
+        let old = store.update(PlainText::from("u p2"))?;
+        assert_eq!(old.pass, "p");
+        assert!(matches!(
+            store.update(PlainText::from("v p")),
+            Err(StoreError::NotFound(_))
+        ));
+        assert_eq!(store.count()?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_users_map_store() -> Result<(), StoreError> {
+        let mut um = UsersMap::new();
+        admin(&mut um)?;
+
+        um.add_credential("u", "token:t");
+        um.update_user(PlainText::from("u p3"));
+        assert!(um.auth_user_by_authstr("plaintext:u\np2").is_none());
+        assert!(um.auth_user_by_authstr("plaintext:u\np3").is_some());
+        assert_eq!(
+            um.auth_user_by_authstr("token:t").map(|u| u.pass.clone()),
+            Some("p3".into())
+        );
+
+        assert_eq!(um.remove("u")?.pass, "p3");
+        assert!(um.get("u")?.is_none());
+        assert!(um.list()?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,481 @@
+/*!
+Protection against brute-force and credential-stuffing attacks.
+*/
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    AuthContext, AuthError, AuthFormat, Clock, ContextAuthenticator, SystemClock, User,
+    UserAuthenticator,
+};
+
+/// How long attempts are refused after repeated failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottlePolicy {
+    /// Failures allowed before attempts are delayed
+    pub free_attempts: u32,
+
+    /// Delay after the first failure past `free_attempts`, doubled by every further failure
+    pub base_delay: Duration,
+
+    /// Upper bound of the delay. Failures older than this are forgotten by
+    /// [`ThrottledAuthenticator::purge_idle`].
+    pub max_delay: Duration,
+}
+
+impl Default for ThrottlePolicy {
+    /// 5 free attempts, then 1s, 2s, 4s, ... up to 15 minutes.
+    fn default() -> Self {
+        ThrottlePolicy {
+            free_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl ThrottlePolicy {
+    /// The delay imposed after `failures` consecutive failures, if any.
+    pub fn delay_after(&self, failures: u32) -> Option<Duration> {
+        let over = failures.checked_sub(self.free_attempts)?.checked_sub(1)?;
+        let delay = self
+            .base_delay
+            .checked_mul(1 << over.min(31))
+            .unwrap_or(self.max_delay);
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// The user name of `plaintext:` auth strings, in any [`AuthFormat`]
+pub(crate) fn claimed_identity(authstr: &str) -> Option<Cow<'_, str>> {
+    AuthFormat::decode(authstr)
+        .ok()
+        .filter(|decoded| decoded.scheme == "plaintext")
+        .map(|decoded| decoded.user)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: SystemTime,
+    blocked_until: Option<SystemTime>,
+
+    /// Attempts admitted but not finished yet, which may still fail
+    pending: u32,
+}
+
+impl Failures {
+    fn new(now: SystemTime) -> Self {
+        Failures {
+            count: 0,
+            last: now,
+            blocked_until: None,
+            pending: 0,
+        }
+    }
+
+    /// How long attempts are refused, counting the pending ones as failures, so that
+    /// concurrent guesses can't all pass before the first one fails.
+    fn retry_after(&self, policy: &ThrottlePolicy, now: SystemTime) -> Option<Duration> {
+        let blocked = self
+            .blocked_until
+            .and_then(|t| t.duration_since(now).ok())
+            .filter(|d| !d.is_zero());
+        let if_pending_fail = || policy.delay_after(self.count.saturating_add(self.pending));
+        blocked.or_else(|| (self.pending > 0).then(if_pending_fail).flatten())
+    }
+
+    fn is_unused(&self) -> bool {
+        self.count == 0 && self.pending == 0
+    }
+
+    /// True if nothing happened for `max_delay`, so the failures can be forgotten.
+    fn is_idle(&self, now: SystemTime, max_delay: Duration) -> bool {
+        self.pending == 0
+            && now
+                .duration_since(self.last)
+                .is_ok_and(|elapsed| elapsed >= max_delay)
+    }
+
+    fn is_blocked(&self, now: SystemTime) -> bool {
+        self.blocked_until.is_some_and(|t| t > now)
+    }
+}
+
+/// Ends an attempt reserved by [`reserve`].
+fn settle(map: &mut HashMap<String, Failures>, key: &str) {
+    if let Some(f) = map.get_mut(key) {
+        f.pending = f.pending.saturating_sub(1);
+    }
+}
+
+/// Forgets the failures of `key`, but not its pending attempts.
+fn reset(map: &mut HashMap<String, Failures>, key: &str) {
+    if let Some(f) = map.get_mut(key) {
+        f.count = 0;
+        f.blocked_until = None;
+    }
+    remove_if_unused(map, key);
+}
+
+fn remove_if_unused(map: &mut HashMap<String, Failures>, key: &str) {
+    if map.get(key).is_some_and(Failures::is_unused) {
+        map.remove(key);
+    }
+}
+
+#[derive(Debug, Default)]
+struct ThrottleState {
+    identities: HashMap<String, Failures>,
+    sources: HashMap<String, Failures>,
+}
+
+/// Wraps an authenticator, counting failed attempts per identity and per source key
+/// (e.g. the client's IP address), and refusing further attempts with
+/// [`AuthError::RateLimited`] for an exponentially growing delay.
+///
+/// Only [`AuthError::UnknownUser`] and [`AuthError::BadCredential`] count as failures.
+/// A success resets the failures of the identity, but not those of the source,
+/// so an attacker can't reset their counter by logging into their own account.
+///
+/// At most [`ThrottledAuthenticator::with_max_tracked`] identities and as many sources
+/// are tracked, so that a spray of made-up user names can't exhaust the memory.
+#[derive(Debug)]
+pub struct ThrottledAuthenticator<A> {
+    backend: A,
+    policy: ThrottlePolicy,
+    clock: Arc<dyn Clock>,
+    max_tracked: usize,
+    state: Mutex<ThrottleState>,
+}
+
+impl<A> ThrottledAuthenticator<A> {
+    pub fn new(backend: A, policy: ThrottlePolicy) -> Self {
+        ThrottledAuthenticator {
+            backend,
+            policy,
+            clock: Arc::new(SystemClock),
+            max_tracked: 100_000,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Sets how many identities, and how many sources, are tracked; 100,000 by default.
+    ///
+    /// When full, idle entries are dropped, then those not currently blocked; if every
+    /// entry is blocked, new keys are not tracked until some expire.
+    pub fn with_max_tracked(mut self, max_tracked: usize) -> Self {
+        self.max_tracked = max_tracked;
+        self
+    }
+
+    /// Replaces the clock measuring delays, e.g. with a
+    /// [`ManualClock`](crate::ManualClock) in tests.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, ThrottleState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn backend(&self) -> &A {
+        &self.backend
+    }
+
+    pub fn policy(&self) -> ThrottlePolicy {
+        self.policy
+    }
+
+    /// Authenticates `authstr` unless the claimed identity or the source is throttled.
+    ///
+    /// Protocols where the identity is sent separately (e.g. SOCKS5, HTTP basic) should
+    /// pass it, so that failures are counted against it even if the user is unknown.
+    /// Without it, the user name of `plaintext:` auth strings is used.
+    pub fn try_auth_from<T: User>(
+        &self,
+        source: Option<&str>,
+        identity: Option<&str>,
+        authstr: &str,
+    ) -> Result<T, AuthError>
+    where
+        A: UserAuthenticator<T>,
+    {
+        let claimed = identity
+            .map(Cow::Borrowed)
+            .or_else(|| claimed_identity(authstr));
+        self.throttle(source, claimed.as_deref(), || {
+            self.backend.try_auth(authstr)
+        })
+    }
+
+    /// Runs `attempt` unless the identity or the source is throttled, counting its failure.
+    ///
+    /// The attempt is reserved as pending before the lock is released, and settled after.
+    fn throttle<T: User>(
+        &self,
+        source: Option<&str>,
+        identity: Option<&str>,
+        attempt: impl FnOnce() -> Result<T, AuthError>,
+    ) -> Result<T, AuthError> {
+        let now = self.clock.now();
+        {
+            let mut state = self.state();
+            let retry_after = [
+                identity.and_then(|id| state.identities.get(id)),
+                source.and_then(|src| state.sources.get(src)),
+            ]
+            .into_iter()
+            .flatten()
+            .filter_map(|f| f.retry_after(&self.policy, now))
+            .max();
+            if let Some(retry_after) = retry_after {
+                return Err(AuthError::RateLimited {
+                    retry_after: Some(retry_after),
+                });
+            }
+            if let Some(id) = identity {
+                self.reserve(&mut state.identities, id, now);
+            }
+            if let Some(src) = source {
+                self.reserve(&mut state.sources, src, now);
+            }
+        }
+
+        let result = attempt();
+        let mut state = self.state();
+        if let Some(id) = identity {
+            settle(&mut state.identities, id);
+        }
+        if let Some(src) = source {
+            settle(&mut state.sources, src);
+        }
+        match &result {
+            Ok(user) => {
+                reset(&mut state.identities, user.identity_str());
+                if let Some(id) = identity {
+                    reset(&mut state.identities, id);
+                }
+            }
+            Err(AuthError::UnknownUser | AuthError::BadCredential) => {
+                if let Some(id) = identity {
+                    self.record_failure(&mut state.identities, id, now);
+                }
+                if let Some(src) = source {
+                    self.record_failure(&mut state.sources, src, now);
+                }
+            }
+            Err(_) => {}
+        }
+        if let Some(id) = identity {
+            remove_if_unused(&mut state.identities, id);
+        }
+        if let Some(src) = source {
+            remove_if_unused(&mut state.sources, src);
+        }
+        result
+    }
+
+    /// Counts an attempt against `key` as pending, making room for it if needed.
+    fn reserve(&self, map: &mut HashMap<String, Failures>, key: &str, now: SystemTime) {
+        if !map.contains_key(key) && map.len() >= self.max_tracked {
+            let max_delay = self.policy.max_delay;
+            map.retain(|_, f| !f.is_idle(now, max_delay));
+            if map.len() >= self.max_tracked {
+                map.retain(|_, f| f.pending > 0 || f.is_blocked(now));
+            }
+            if map.len() >= self.max_tracked {
+                return;
+            }
+        }
+        let f = map
+            .entry(key.to_string())
+            .or_insert_with(|| Failures::new(now));
+        f.pending = f.pending.saturating_add(1);
+    }
+
+    /// Counts a failure against `key`, if it is tracked.
+    fn record_failure(&self, map: &mut HashMap<String, Failures>, key: &str, now: SystemTime) {
+        let Some(f) = map.get_mut(key) else {
+            return;
+        };
+        f.count = f.count.saturating_add(1);
+        f.last = now;
+        f.blocked_until = self.policy.delay_after(f.count).map(|d| now + d);
+    }
+
+    /// Number of attempts of an identity admitted and not finished yet
+    pub fn identity_pending(&self, id: &str) -> u32 {
+        self.state().identities.get(id).map_or(0, |f| f.pending)
+    }
+
+    /// Number of consecutive failures counted against an identity
+    pub fn identity_failures(&self, id: &str) -> u32 {
+        self.state().identities.get(id).map_or(0, |f| f.count)
+    }
+
+    /// Number of failures counted against a source key
+    pub fn source_failures(&self, source: &str) -> u32 {
+        self.state().sources.get(source).map_or(0, |f| f.count)
+    }
+
+    /// Forgets the failures of an identity, e.g. after an admin verified the user.
+    pub fn reset_identity(&self, id: &str) {
+        self.state().identities.remove(id);
+    }
+
+    pub fn reset_source(&self, source: &str) {
+        self.state().sources.remove(source);
+    }
+
+    /// Forgets failures older than [`ThrottlePolicy::max_delay`].
+    pub fn purge_idle(&self) {
+        let now = self.clock.now();
+        let max_delay = self.policy.max_delay;
+        let mut state = self.state();
+        state.identities.retain(|_, f| !f.is_idle(now, max_delay));
+        state.sources.retain(|_, f| !f.is_idle(now, max_delay));
+    }
+}
+
+impl<T: User, A: UserAuthenticator<T>> UserAuthenticator<T> for ThrottledAuthenticator<A> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.try_auth(authstr).ok()
+    }
+
+    /// Doesn't know the source, so failures are only counted against the user name of
+    /// `plaintext:` auth strings; prefer [`ThrottledAuthenticator::try_auth_from`].
+    fn try_auth(&self, authstr: &str) -> Result<T, AuthError> {
+        self.try_auth_from(None, None, authstr)
+    }
+
+    fn try_auth_as(&self, identity: &str, authstr: &str) -> Result<T, AuthError> {
+        self.try_auth_from(None, Some(identity), authstr)
+    }
+}
+
+/// Uses the source address of the context as the source key, and its identity, or else
+/// the user name of `plaintext:` auth strings, as the identity.
+impl<T: User, A: ContextAuthenticator<T>> ContextAuthenticator<T> for ThrottledAuthenticator<A> {
+    fn auth_with_context(&self, authstr: &str, cx: &AuthContext) -> Result<T, AuthError> {
+        let source = cx.source_addr.map(|ip| ip.to_string());
+        let identity = cx
+            .identity
+            .as_deref()
+            .map(Cow::Borrowed)
+            .or_else(|| claimed_identity(authstr));
+        self.throttle(source.as_deref(), identity.as_deref(), || {
+            self.backend.auth_with_context(authstr, cx)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::{ThrottlePolicy, ThrottledAuthenticator};
+    use crate::{
+        AuditingAuthenticator, AuthError, CachedAuthenticator, ChainAuthenticator, LruUsersCache,
+        ManualClock, MemoryAuditSink, PlainText, UserAuthenticator, UsersMap,
+    };
+
+    #[test]
+    fn test_throttle() {
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("u p"));
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let policy = ThrottlePolicy {
+            free_attempts: 2,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(30),
+        };
+        assert_eq!(policy.delay_after(2), None);
+        assert_eq!(policy.delay_after(4), Some(Duration::from_secs(20)));
+        assert_eq!(policy.delay_after(9), Some(Duration::from_secs(30)));
+
+        let auth = ThrottledAuthenticator::new(um, policy).with_clock(Arc::new(clock.clone()));
+        let attempt = |pass: &str| {
+            auth.try_auth_from(Some("10.0.0.1"), Some("u"), &format!("plaintext:u\n{pass}"))
+        };
+
+        assert!(matches!(attempt("x"), Err(AuthError::UnknownUser)));
+        assert!(matches!(attempt("x"), Err(AuthError::UnknownUser)));
+        assert!(matches!(attempt("x"), Err(AuthError::UnknownUser)));
+        assert!(matches!(
+            attempt("p"),
+            Err(AuthError::RateLimited {
+                retry_after: Some(d)
+            }) if d == Duration::from_secs(10)
+        ));
+
+        clock.advance(Duration::from_secs(10));
+        assert!(attempt("p").is_ok());
+        assert_eq!(auth.identity_failures("u"), 0);
+        assert_eq!(auth.source_failures("10.0.0.1"), 3);
+
+        clock.advance(Duration::from_secs(30));
+        auth.purge_idle();
+        assert_eq!(auth.source_failures("10.0.0.1"), 0);
+
+        // Without a source, failures count against the claimed identity.
+        assert!(auth.try_auth("plaintext:u\nx").is_err());
+        assert!(auth.try_auth_as("nobody", "token:x").is_err());
+        assert_eq!(auth.identity_failures("u"), 1);
+        assert_eq!(auth.identity_failures("nobody"), 1);
+
+        // Attempts in flight count as failures until they finish: with the free failures
+        // used up by one counted and one pending, a concurrent attempt is refused.
+        assert!(auth.try_auth("plaintext:u\nx").is_err());
+        let fail = || Err::<PlainText, _>(AuthError::BadCredential);
+        let outer = auth.throttle(None, Some("u"), || {
+            assert_eq!(auth.identity_pending("u"), 1);
+            let concurrent = auth.throttle(None, Some("u"), fail);
+            assert!(matches!(concurrent, Err(AuthError::RateLimited { .. })));
+            fail()
+        });
+        assert!(matches!(outer, Err(AuthError::BadCredential)));
+        assert_eq!(auth.identity_pending("u"), 0);
+        assert_eq!(auth.identity_failures("u"), 3);
+
+        // Made-up user names that never get blocked make room for new ones.
+        let auth = auth.with_max_tracked(4);
+        for i in 0..10 {
+            assert!(auth.try_auth(&format!("plaintext:nobody{i}\nx")).is_err());
+        }
+        assert!(auth.state().identities.len() <= 4);
+        assert_eq!(auth.identity_failures("nobody9"), 1);
+    }
+
+    #[test]
+    fn test_wrappers_pass_identity() {
+        let throttled = Arc::new(ThrottledAuthenticator::new(
+            UsersMap::<PlainText>::new(),
+            ThrottlePolicy::default(),
+        ));
+        let fail_as = |auth: &dyn UserAuthenticator<PlainText>, id: &str| {
+            assert!(auth.try_auth_as(id, &format!("token:{id}")).is_err());
+            assert_eq!(throttled.identity_failures(id), 1);
+        };
+        let ttl = Duration::from_secs(60);
+        fail_as(
+            &CachedAuthenticator::new(Arc::clone(&throttled), ttl, ttl),
+            "cached",
+        );
+        fail_as(&LruUsersCache::new(Arc::clone(&throttled), 8), "lru");
+        fail_as(
+            &ChainAuthenticator::new().with("throttled", Arc::clone(&throttled)),
+            "chain",
+        );
+        let sink = Arc::new(MemoryAuditSink::new());
+        fail_as(
+            &AuditingAuthenticator::new(Arc::clone(&throttled), sink),
+            "audited",
+        );
+    }
+}
@@ -0,0 +1,106 @@
+/*!
+[`UserRef`], a borrowed view of any user.
+*/
+
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+
+use crate::UserTrait;
+
+/// The identity and auth string of a user, borrowed from it, e.g. to key a temporary
+/// `HashSet` or compare users on a hot path without boxing or cloning them.
+///
+/// Like [`UserBox`](crate::UserBox), it compares, orders and hashes by auth string,
+/// with the same hash, and its `Debug` output shows the identity only.
+#[derive(Clone, Copy)]
+pub struct UserRef<'a> {
+    id: &'a str,
+    auth: &'a str,
+}
+
+impl<'a> UserRef<'a> {
+    pub fn new(id: &'a str, auth: &'a str) -> Self {
+        UserRef { id, auth }
+    }
+
+    pub fn id(&self) -> &'a str {
+        self.id
+    }
+
+    pub fn auth(&self) -> &'a str {
+        self.auth
+    }
+}
+
+impl<'a, T: UserTrait + ?Sized> From<&'a T> for UserRef<'a> {
+    fn from(user: &'a T) -> Self {
+        UserRef::new(user.identity_str(), user.auth_str())
+    }
+}
+
+impl fmt::Debug for UserRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("UserRef").field(&self.id).finish()
+    }
+}
+
+/// Writes the hash a [`UserBox`](crate::UserBox) of the same user writes.
+impl Hash for UserRef<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.auth.hash(state);
+    }
+}
+
+impl PartialEq for UserRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.auth == other.auth
+    }
+}
+
+impl Eq for UserRef<'_> {}
+
+impl PartialOrd for UserRef<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UserRef<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.auth.cmp(other.auth)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::UserRef;
+    use crate::{PlainText, UserTrait};
+
+    #[test]
+    fn test_user_ref() {
+        let a = PlainText::from("a p");
+        let a2 = PlainText::from("a p");
+        let b = PlainText::from("b p");
+        let users: HashSet<UserRef> = [&a, &a2, &b].into_iter().map(UserRef::from).collect();
+        assert_eq!(users.len(), 2);
+
+        let dynamic: &dyn UserTrait = &b;
+        let b_ref = UserRef::from(dynamic);
+        assert!(users.contains(&b_ref));
+        assert_eq!(format!("{b_ref:?}"), "UserRef(\"b\")");
+        assert!(UserRef::from(&a) < b_ref);
+
+        #[cfg(feature = "dyn-clone")]
+        {
+            use std::hash::{BuildHasher, RandomState};
+
+            let boxed = crate::UserBox::new(b.clone());
+            let state = RandomState::new();
+            assert_eq!(state.hash_one(&boxed), state.hash_one(b_ref));
+            assert_eq!(boxed.as_user_ref(), b_ref);
+        }
+    }
+}
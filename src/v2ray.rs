@@ -0,0 +1,347 @@
+/*!
+Users identified by a UUID, as in V2Ray and Xray, and importing their `clients` lists.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(feature = "serde")]
+use std::fs;
+#[cfg(feature = "serde")]
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_json::Value;
+
+#[cfg(feature = "serde")]
+use crate::LoadError;
+use crate::{SecretString, UserTrait, UsersMap};
+
+/// The error of parsing a malformed UUID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UuidParseError(String);
+
+impl fmt::Display for UuidParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid UUID {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UuidParseError {}
+
+/// Parses the hyphenated form, e.g. `b831381d-6324-4d53-ad4f-8cda48b30811`,
+/// or the same 32 hex digits without hyphens.
+fn parse_uuid(s: &str) -> Result<[u8; 16], UuidParseError> {
+    let err = || UuidParseError(s.to_string());
+    let hex: Vec<u8> = match s.len() {
+        36 => {
+            if [8, 13, 18, 23].iter().any(|&i| s.as_bytes()[i] != b'-') {
+                return Err(err());
+            }
+            s.bytes().filter(|&b| b != b'-').collect()
+        }
+        32 => s.bytes().collect(),
+        _ => return Err(err()),
+    };
+    if hex.len() != 32 || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return Err(err());
+    }
+    let mut uuid = [0u8; 16];
+    for (b, pair) in uuid.iter_mut().zip(hex.chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| err())?;
+        *b = u8::from_str_radix(pair, 16).map_err(|_| err())?;
+    }
+    Ok(uuid)
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct UuidEntry {
+    id: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+}
+
+/// A V2Ray/Xray user: its credential is a UUID, and its identity is its email if it
+/// has one, the UUID otherwise.
+///
+/// Its auth string is `uuid:` followed by the lowercase hyphenated UUID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UuidEntry", into = "UuidEntry"))]
+pub struct UuidUser {
+    uuid: [u8; 16],
+    email: Option<String>,
+
+    /// `uuid:{hyphenated}`
+    auth_str: SecretString,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<UuidEntry> for UuidUser {
+    type Error = UuidParseError;
+
+    fn try_from(e: UuidEntry) -> Result<Self, Self::Error> {
+        Ok(UuidUser::new(parse_uuid(&e.id)?, e.email))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<UuidUser> for UuidEntry {
+    fn from(u: UuidUser) -> Self {
+        UuidEntry {
+            id: u.uuid_str().to_string(),
+            email: u.email.clone(),
+        }
+    }
+}
+
+/// The auth string is cleared by its [`SecretString`].
+#[cfg(feature = "zeroize")]
+impl Drop for UuidUser {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.uuid);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for UuidUser {}
+
+impl FromStr for UuidUser {
+    type Err = UuidParseError;
+
+    /// Parses a UUID, creating a user without email.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(UuidUser::new(parse_uuid(s)?, None))
+    }
+}
+
+impl UuidUser {
+    pub fn new(uuid: [u8; 16], email: Option<String>) -> Self {
+        let mut auth_str = String::with_capacity(41);
+        auth_str.push_str("uuid:");
+        for (i, b) in uuid.iter().enumerate() {
+            if [4, 6, 8, 10].contains(&i) {
+                auth_str.push('-');
+            }
+            auth_str.push_str(&format!("{b:02x}"));
+        }
+        UuidUser {
+            uuid,
+            email,
+            auth_str: auth_str.into(),
+        }
+    }
+
+    /// Parses `uuid` in the hyphenated form.
+    pub fn parse(uuid: &str, email: Option<String>) -> Result<Self, UuidParseError> {
+        Ok(UuidUser::new(parse_uuid(uuid)?, email))
+    }
+
+    pub fn uuid(&self) -> &[u8; 16] {
+        &self.uuid
+    }
+
+    /// The lowercase hyphenated UUID
+    pub fn uuid_str(&self) -> &str {
+        &self.auth_str["uuid:".len()..]
+    }
+
+    pub fn email(&self) -> Option<&str> {
+        self.email.as_deref()
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl UserTrait for UuidUser {
+    fn identity_str(&self) -> &str {
+        self.email.as_deref().unwrap_or(self.uuid_str())
+    }
+
+    fn identity_bytes(&self) -> &[u8] {
+        self.identity_str().as_bytes()
+    }
+
+    fn auth_str(&self) -> &str {
+        &self.auth_str
+    }
+
+    fn auth_bytes(&self) -> &[u8] {
+        self.auth_str.as_bytes()
+    }
+}
+
+/// [`UuidUser`]s by the 16 bytes of their id, for servers resolving the user of every
+/// VLESS request, or of every VMess request with its alter ids, in one hash lookup.
+#[derive(Debug, Clone, Default)]
+pub struct UuidIndex {
+    users: HashMap<[u8; 16], Arc<UuidUser>>,
+}
+
+impl UuidIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes the user by its UUID, replacing the user that had it.
+    pub fn insert(&mut self, user: Arc<UuidUser>) {
+        self.insert_id(*user.uuid(), user);
+    }
+
+    /// Indexes the user by another id, e.g. a derived VMess alter id.
+    pub fn insert_id(&mut self, id: [u8; 16], user: Arc<UuidUser>) {
+        self.users.insert(id, user);
+    }
+
+    /// Removes every id of the user with the UUID, returning it.
+    pub fn remove(&mut self, uuid: &[u8; 16]) -> Option<Arc<UuidUser>> {
+        let user = self.users.remove(uuid)?;
+        self.users.retain(|_, u| !Arc::ptr_eq(u, &user));
+        Some(user)
+    }
+
+    pub fn get(&self, id: &[u8; 16]) -> Option<&Arc<UuidUser>> {
+        self.users.get(id)
+    }
+
+    /// Number of indexed ids, which may exceed the number of users
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+/// Indexes the users of the map by their UUID, sharing them with the map.
+impl<S: std::hash::BuildHasher> From<&UsersMap<UuidUser, S>> for UuidIndex {
+    fn from(map: &UsersMap<UuidUser, S>) -> Self {
+        let mut index = UuidIndex::new();
+        for user in map.iter() {
+            index.insert(Arc::clone(user));
+        }
+        index
+    }
+}
+
+/// Imports the clients of a V2Ray/Xray config, e.g.
+///
+/// ```json
+/// {"clients": [{"id": "b831381d-6324-4d53-ad4f-8cda48b30811", "email": "alice@example.com"}]}
+/// ```
+///
+/// The `clients` list may also be in the `settings` of the `inbounds` of a whole config;
+/// the clients of every inbound are imported. Other client fields, such as `flow` or
+/// `level`, are ignored.
+#[cfg(feature = "serde")]
+pub fn load_v2ray_clients(json: &str) -> Result<UsersMap<UuidUser>, LoadError> {
+    let doc: Value = serde_json::from_str(json).map_err(|e| {
+        let message = e.to_string();
+        LoadError::Syntax {
+            line: Some(e.line()),
+            message: match message.rfind(" at line ") {
+                Some(i) => message[..i].to_string(),
+                None => message,
+            },
+        }
+    })?;
+
+    let mut lists = Vec::new();
+    if let Some(clients) = doc.get("clients") {
+        lists.push(("clients".to_string(), clients));
+    }
+    if let Some(Value::Array(inbounds)) = doc.get("inbounds") {
+        for (i, inbound) in inbounds.iter().enumerate() {
+            if let Some(clients) = inbound.get("settings").and_then(|s| s.get("clients")) {
+                lists.push((format!("inbounds[{i}].settings.clients"), clients));
+            }
+        }
+    }
+
+    let mut map = UsersMap::new();
+    for (path, clients) in lists {
+        let Value::Array(clients) = clients else {
+            return Err(LoadError::Syntax {
+                line: None,
+                message: format!("{path}: expected a list"),
+            });
+        };
+        for (i, client) in clients.iter().enumerate() {
+            let user = UuidEntry::deserialize(client)
+                .map_err(|e| e.to_string())
+                .and_then(|e| UuidUser::try_from(e).map_err(|e| e.to_string()))
+                .map_err(|message| LoadError::Syntax {
+                    line: None,
+                    message: format!("{path}[{i}]: {message}"),
+                })?;
+            if map.get_user(user.identity_str()).is_some() {
+                return Err(LoadError::Duplicate {
+                    line: None,
+                    id: user.identity_str().to_string(),
+                });
+            }
+            map.add_user(user);
+        }
+    }
+    Ok(map)
+}
+
+/// Reads a file with [`load_v2ray_clients`].
+#[cfg(feature = "serde")]
+pub fn load_v2ray_clients_path(path: &Path) -> Result<UsersMap<UuidUser>, LoadError> {
+    load_v2ray_clients(&fs::read_to_string(path)?)
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use std::sync::Arc;
+
+    use super::{load_v2ray_clients, UuidIndex, UuidUser};
+    use crate::{LoadError, UserAuthenticator, UserTrait};
+
+    #[test]
+    fn test_v2ray_clients() -> Result<(), LoadError> {
+        let um = load_v2ray_clients(
+            r#"{"inbounds": [{"protocol": "vless", "settings": {"clients": [
+                {"id": "B831381D-6324-4D53-AD4F-8CDA48B30811", "email": "alice@example.com", "flow": "xtls-rprx-vision"},
+                {"id": "27848739-7e62-4138-9fd3-098a63964b6b"}
+            ]}}]}"#,
+        )?;
+        assert_eq!(um.len(), 2);
+        let alice = um
+            .auth_user_by_authstr("uuid:b831381d-6324-4d53-ad4f-8cda48b30811")
+            .unwrap();
+        assert_eq!(alice.identity_str(), "alice@example.com");
+        assert!(um
+            .get_user("27848739-7e62-4138-9fd3-098a63964b6b")
+            .is_some());
+
+        let err = load_v2ray_clients(r#"{"clients": [{"id": "nope"}]}"#).unwrap_err();
+        assert_eq!(err.to_string(), "clients[0]: invalid UUID \"nope\"");
+
+        let user: UuidUser = "27848739-7e62-4138-9fd3-098a63964b6b".parse().unwrap();
+        let json = serde_json::to_string(&user).unwrap();
+        assert_eq!(json, r#"{"id":"27848739-7e62-4138-9fd3-098a63964b6b"}"#);
+        assert!("27848739-7e62-4138-9fd3_098a63964b6b"
+            .parse::<UuidUser>()
+            .is_err());
+
+        let err = load_v2ray_clients("{\n\"clients\": [,]}").unwrap_err();
+        assert!(err.to_string().starts_with("line 2: "), "{err}");
+
+        let mut index = UuidIndex::from(&um);
+        let id = *alice.uuid();
+        assert_eq!(index.get(&id).unwrap().email(), Some("alice@example.com"));
+        index.insert_id([1; 16], Arc::clone(index.get(&id).unwrap()));
+        assert_eq!(index.len(), 3);
+        assert!(index.remove(&id).is_some());
+        assert!(index.get(&[1; 16]).is_none());
+        assert_eq!(index.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+/*!
+Authentication by verifying a presented secret, instead of looking up an exact auth string.
+*/
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+use subtle::ConstantTimeEq;
+
+use crate::{AuthError, PlainText, UserTrait, UsersMap};
+
+/// A user that checks a presented secret itself.
+///
+/// Users storing a password hash can't be found by their [`UserTrait::auth_str`],
+/// as the client sends the password, not the hash.
+pub trait VerifyUser: UserTrait {
+    /// Returns true if `presented` is a valid secret of this user.
+    fn verify(&self, presented: &[u8]) -> bool;
+}
+
+impl VerifyUser for PlainText {
+    /// Compares `presented` with the password in constant time.
+    fn verify(&self, presented: &[u8]) -> bool {
+        self.pass.as_bytes().ct_eq(presented).into()
+    }
+}
+
+/// Authenticates users of a [`UsersMap`] by finding them by identity,
+/// then calling [`VerifyUser::verify`] with the presented secret.
+///
+/// Disabled and expired users are refused, and statistics are recorded,
+/// like with [`UserAuthenticator`](crate::UserAuthenticator).
+#[derive(Debug, Clone, Default)]
+pub struct VerifyingAuthenticator<T: VerifyUser + Clone, S = RandomState> {
+    map: UsersMap<T, S>,
+}
+
+impl<T: VerifyUser + Clone, S> From<UsersMap<T, S>> for VerifyingAuthenticator<T, S> {
+    fn from(map: UsersMap<T, S>) -> Self {
+        VerifyingAuthenticator { map }
+    }
+}
+
+impl<T: VerifyUser + Clone, S: BuildHasher> VerifyingAuthenticator<T, S> {
+    pub fn new(map: UsersMap<T, S>) -> Self {
+        VerifyingAuthenticator { map }
+    }
+
+    /// Returns a clone of the user if `id` is known and `presented` is one of its secrets.
+    pub fn authenticate(&self, id: &str, presented: &[u8]) -> Option<T> {
+        self.try_authenticate(id, presented).ok()
+    }
+
+    /// Like [`VerifyingAuthenticator::authenticate`], telling why authentication failed.
+    ///
+    /// Disabled, expired and locked out users are refused before the secret is verified,
+    /// so the refusal is the same whether it was right or not.
+    pub fn try_authenticate(&self, id: &str, presented: &[u8]) -> Result<T, AuthError> {
+        let user = self.map.get_user(id);
+        let user = self.map.check_admissible(user.as_ref())?;
+        if !user.verify(presented) {
+            if let Some(lockout) = self.map.lockout() {
+                lockout.record_failure(user.identity_str());
+            }
+            return self.map.refuse(AuthError::BadCredential);
+        }
+        self.map.admit(Some(user))
+    }
+
+    /// Authenticates many `(identity, secret)` pairs, e.g. to check imported password hashes
+    /// or the accounts of a migration, returning the outcomes in the same order.
+    ///
+    /// With the `rayon` feature, the attempts are verified in parallel on all cores, as the
+    /// cost of hashes such as bcrypt or Argon2 dominates such batches.
+    pub fn verify_batch<I, C>(&self, attempts: &[(I, C)]) -> Vec<Result<T, AuthError>>
+    where
+        I: AsRef<str> + Sync,
+        C: AsRef<[u8]> + Sync,
+        S: Sync,
+    {
+        let verify =
+            |(id, presented): &(I, C)| self.try_authenticate(id.as_ref(), presented.as_ref());
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            attempts.par_iter().map(verify).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        attempts.iter().map(verify).collect()
+    }
+
+    pub fn map(&self) -> &UsersMap<T, S> {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut UsersMap<T, S> {
+        &mut self.map
+    }
+
+    pub fn into_inner(self) -> UsersMap<T, S> {
+        self.map
+    }
+}
+
+/// Authenticates an identity with a presented secret, e.g. a password.
+pub trait SecretAuthenticator<T> {
+    fn try_authenticate(&self, id: &str, presented: &[u8]) -> Result<T, AuthError>;
+}
+
+impl<T: VerifyUser + Clone, S: BuildHasher> SecretAuthenticator<T>
+    for VerifyingAuthenticator<T, S>
+{
+    fn try_authenticate(&self, id: &str, presented: &[u8]) -> Result<T, AuthError> {
+        VerifyingAuthenticator::try_authenticate(self, id, presented)
+    }
+}
+
+impl<T, A: SecretAuthenticator<T> + ?Sized> SecretAuthenticator<T> for Arc<A> {
+    fn try_authenticate(&self, id: &str, presented: &[u8]) -> Result<T, AuthError> {
+        self.as_ref().try_authenticate(id, presented)
+    }
+}
+
+/// Makes refusing an unknown identity take as long as refusing a wrong secret, so that
+/// response times don't tell which identities exist.
+///
+/// A [`VerifyingAuthenticator`] of hashed users answers at once for unknown identities,
+/// but spends a whole hash verification, e.g. bcrypt, on a wrong password. This wrapper
+/// verifies the presented secret against a decoy user when the backend reports
+/// [`AuthError::UnknownUser`]. The decoy should use the same hash scheme and cost as the
+/// real users, e.g. `HtpasswdUser::bcrypt("decoy", <random password>)`.
+pub struct EqualizedAuthenticator<A> {
+    backend: A,
+    decoy: Box<dyn VerifyUser>,
+}
+
+impl<A: fmt::Debug> fmt::Debug for EqualizedAuthenticator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EqualizedAuthenticator")
+            .field("backend", &self.backend)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> EqualizedAuthenticator<A> {
+    pub fn new(backend: A, decoy: impl VerifyUser + 'static) -> Self {
+        EqualizedAuthenticator {
+            backend,
+            decoy: Box::new(decoy),
+        }
+    }
+
+    pub fn backend(&self) -> &A {
+        &self.backend
+    }
+
+    /// Returns a clone of the user if `id` is known and `presented` is one of its secrets.
+    pub fn authenticate<T>(&self, id: &str, presented: &[u8]) -> Option<T>
+    where
+        A: SecretAuthenticator<T>,
+    {
+        self.try_authenticate(id, presented).ok()
+    }
+}
+
+impl<T, A: SecretAuthenticator<T>> SecretAuthenticator<T> for EqualizedAuthenticator<A> {
+    fn try_authenticate(&self, id: &str, presented: &[u8]) -> Result<T, AuthError> {
+        let result = self.backend.try_authenticate(id, presented);
+        if let Err(AuthError::UnknownUser) = result {
+            std::hint::black_box(self.decoy.verify(std::hint::black_box(presented)));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+    use sha2::{Digest, Sha256};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{EqualizedAuthenticator, SecretAuthenticator, VerifyUser, VerifyingAuthenticator};
+    use crate::{
+        AuthError, LockoutPolicy, LockoutTracker, PlainText, UserAuthenticator, UserTrait, UsersMap,
+    };
+
+    /// Stores only the SHA-256 of the password
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct HashedUser {
+        name: String,
+        hash: String,
+    }
+
+    impl HashedUser {
+        fn new(name: &str, pass: &str) -> Self {
+            let digest: [u8; 32] = Sha256::digest(pass.as_bytes()).into();
+            let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+            HashedUser {
+                name: name.into(),
+                hash: format!("sha256:{hex}"),
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "serde", typetag::serde)]
+    impl UserTrait for HashedUser {
+        fn identity_str(&self) -> &str {
+            &self.name
+        }
+
+        fn identity_bytes(&self) -> &[u8] {
+            self.name.as_bytes()
+        }
+
+        fn auth_str(&self) -> &str {
+            &self.hash
+        }
+
+        fn auth_bytes(&self) -> &[u8] {
+            self.hash.as_bytes()
+        }
+    }
+
+    impl VerifyUser for HashedUser {
+        fn verify(&self, presented: &[u8]) -> bool {
+            HashedUser::new(&self.name, &String::from_utf8_lossy(presented)).hash == self.hash
+        }
+    }
+
+    #[test]
+    fn test_verifying_authenticator() {
+        let mut um = UsersMap::new();
+        um.add_user(HashedUser::new("u", "secret"));
+        let mut auth = VerifyingAuthenticator::new(um);
+
+        assert!(auth.authenticate("u", b"secret").is_some());
+        assert!(auth.authenticate("u", b"wrong").is_none());
+        assert!(matches!(
+            auth.try_authenticate("u", b"wrong"),
+            Err(AuthError::BadCredential)
+        ));
+        assert!(matches!(
+            auth.try_authenticate("nobody", b"secret"),
+            Err(AuthError::UnknownUser)
+        ));
+
+        let lockout = LockoutTracker::new(LockoutPolicy {
+            max_failures: 2,
+            window: Duration::from_secs(60),
+            lock_duration: Duration::from_secs(300),
+        });
+        auth.map_mut().set_lockout(Some(Arc::new(lockout)));
+        assert!(auth.authenticate("u", b"wrong").is_none());
+        assert!(auth.authenticate("u", b"wrong").is_none());
+        for presented in [b"secret".as_slice(), b"wrong"] {
+            assert!(matches!(
+                auth.try_authenticate("u", presented),
+                Err(AuthError::RateLimited { .. })
+            ));
+        }
+        auth.map_mut().set_lockout(None);
+
+        auth.map_mut().set_enabled("u", false);
+        for presented in [b"secret".as_slice(), b"wrong"] {
+            assert!(matches!(
+                auth.try_authenticate("u", presented),
+                Err(AuthError::Disabled)
+            ));
+        }
+        assert!(matches!(
+            auth.map().try_auth(&HashedUser::new("u", "secret").hash),
+            Err(AuthError::Disabled)
+        ));
+
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("p pass"));
+        let auth = VerifyingAuthenticator::from(um);
+        assert!(auth.authenticate("p", b"pass").is_some());
+        assert!(auth.authenticate("p", b"pas").is_none());
+
+        let outcomes = auth.verify_batch(&[("p", "pass"), ("p", "pas"), ("q", "pass")]);
+        assert!(outcomes[0].is_ok());
+        assert!(matches!(outcomes[1], Err(AuthError::BadCredential)));
+        assert!(matches!(outcomes[2], Err(AuthError::UnknownUser)));
+    }
+
+    static DECOY_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct Decoy {
+        user: PlainText,
+    }
+
+    impl_wrapper_user_trait!(Decoy, "TestDecoy");
+
+    impl VerifyUser for Decoy {
+        fn verify(&self, presented: &[u8]) -> bool {
+            DECOY_CALLS.fetch_add(1, Ordering::Relaxed);
+            self.user.verify(presented)
+        }
+    }
+
+    #[test]
+    fn test_equalized_authenticator() {
+        let mut um = UsersMap::new();
+        um.add_user(HashedUser::new("u", "secret"));
+        let auth = EqualizedAuthenticator::new(
+            VerifyingAuthenticator::new(um),
+            Decoy {
+                user: PlainText::from("decoy x"),
+            },
+        );
+
+        assert!(auth.authenticate("u", b"secret").is_some());
+        assert!(matches!(
+            auth.try_authenticate("u", b"wrong"),
+            Err(AuthError::BadCredential)
+        ));
+        assert_eq!(DECOY_CALLS.load(Ordering::Relaxed), 0);
+        assert!(matches!(
+            auth.try_authenticate("nobody", b"x"),
+            Err(AuthError::UnknownUser)
+        ));
+        assert_eq!(DECOY_CALLS.load(Ordering::Relaxed), 1);
+    }
+}
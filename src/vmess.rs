@@ -0,0 +1,95 @@
+/*!
+The keys and ids VMess derives from the UUID of a [`UuidUser`].
+
+Requires the `vmess` feature.
+*/
+
+use std::sync::Arc;
+
+use md5::{Digest, Md5};
+
+use crate::{UuidIndex, UuidUser};
+
+/// Salt of the command key, from the V2Ray sources
+const CMD_KEY_SALT: &[u8] = b"c48619fe-8f02-49e0-b9e9-edf763e17e21";
+
+/// Salt of the next alter id
+const NEXT_ID_SALT: &[u8] = b"16167dc8-16b6-4e6d-b8bb-65dd68113a81";
+
+/// Salt added while the next alter id equals the previous one
+const NEXT_ID_RETRY_SALT: &[u8] = b"533eff8a-4113-4b10-b5ce-0f5d76b98cd2";
+
+/// The id following `id` in the chain of VMess alter ids
+fn next_id(id: &[u8; 16]) -> [u8; 16] {
+    let mut hasher = Md5::new().chain_update(id).chain_update(NEXT_ID_SALT);
+    loop {
+        let next: [u8; 16] = hasher.clone().finalize().into();
+        if next != *id {
+            return next;
+        }
+        hasher.update(NEXT_ID_RETRY_SALT);
+    }
+}
+
+impl UuidUser {
+    /// The VMess command key, `MD5(uuid || "c48619fe-8f02-49e0-b9e9-edf763e17e21")`,
+    /// which encrypts the request header and keys the AEAD auth ids.
+    pub fn vmess_cmd_key(&self) -> [u8; 16] {
+        Md5::new()
+            .chain_update(self.uuid())
+            .chain_update(CMD_KEY_SALT)
+            .finalize()
+            .into()
+    }
+
+    /// The first `count` legacy VMess alter ids derived from the UUID, which clients
+    /// with an `alterId` greater than 0 may present instead of it.
+    pub fn vmess_alter_ids(&self, count: u16) -> Vec<[u8; 16]> {
+        let mut ids = Vec::with_capacity(count.into());
+        let mut id = *self.uuid();
+        for _ in 0..count {
+            id = next_id(&id);
+            ids.push(id);
+        }
+        ids
+    }
+}
+
+impl UuidIndex {
+    /// Indexes the user by its UUID and its first `alter_ids` VMess alter ids.
+    pub fn insert_vmess(&mut self, user: Arc<UuidUser>, alter_ids: u16) {
+        for id in user.vmess_alter_ids(alter_ids) {
+            self.insert_id(id, Arc::clone(&user));
+        }
+        self.insert(user);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{UuidIndex, UuidUser};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn test_vmess_ids() {
+        let user = UuidUser::parse("b831381d-6324-4d53-ad4f-8cda48b30811", None).unwrap();
+        assert_eq!(
+            hex(&user.vmess_cmd_key()),
+            "b50d916ac0cec067981af8e5f38a758f"
+        );
+
+        let alter_ids = user.vmess_alter_ids(2);
+        assert_eq!(hex(&alter_ids[0]), "5a07183412d5980a72ac845d5568d17d");
+        assert_ne!(alter_ids[0], alter_ids[1]);
+
+        let mut index = UuidIndex::new();
+        index.insert_vmess(Arc::new(user), 2);
+        assert_eq!(index.len(), 3);
+        assert!(index.get(&alter_ids[1]).is_some());
+    }
+}
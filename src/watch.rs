@@ -0,0 +1,179 @@
+/*!
+Hot reload of a [`SharedUsersMap`] from a users file, watched with [`notify`].
+
+Requires the `notify` feature.
+*/
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+
+use crate::{load_users_from_toml_path, PlainText, SharedUsersMap, UserTrait, UsersDiff, UsersMap};
+
+/// Builds a [`UsersMap`] from the contents of a users file.
+pub type UsersFileLoader<T> = Arc<dyn Fn(&Path) -> io::Result<UsersMap<T>> + Send + Sync>;
+
+/// A users file that can be (re)loaded into a [`SharedUsersMap`].
+///
+/// Each reload rebuilds the whole map and swaps it in atomically, so connection
+/// handlers never see a partially loaded file. A file that fails to load leaves
+/// the current map untouched.
+pub struct FileUserSource<T: UserTrait + Clone> {
+    path: PathBuf,
+    shared: Arc<SharedUsersMap<T>>,
+    loader: UsersFileLoader<T>,
+}
+
+impl<T: UserTrait + Clone> std::fmt::Debug for FileUserSource<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileUserSource")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: UserTrait + Clone + DeserializeOwned> FileUserSource<T> {
+    /// Creates a source reading JSON files written by [`UsersMap::save_to_path`].
+    pub fn new(path: impl Into<PathBuf>, shared: Arc<SharedUsersMap<T>>) -> Self {
+        Self::with_loader(
+            path,
+            shared,
+            Arc::new(|p: &Path| UsersMap::load_from_path(p)),
+        )
+    }
+}
+
+impl FileUserSource<PlainText> {
+    /// Creates a source reading TOML user lists with [`load_users_from_toml_path`].
+    pub fn toml(path: impl Into<PathBuf>, shared: Arc<SharedUsersMap<PlainText>>) -> Self {
+        Self::with_loader(
+            path,
+            shared,
+            Arc::new(|p: &Path| Ok(load_users_from_toml_path(p)?)),
+        )
+    }
+}
+
+impl<T: UserTrait + Clone> FileUserSource<T> {
+    /// Creates a source parsing the file with a custom loader, e.g. for another format.
+    pub fn with_loader(
+        path: impl Into<PathBuf>,
+        shared: Arc<SharedUsersMap<T>>,
+        loader: UsersFileLoader<T>,
+    ) -> Self {
+        FileUserSource {
+            path: path.into(),
+            shared,
+            loader,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn shared(&self) -> &Arc<SharedUsersMap<T>> {
+        &self.shared
+    }
+
+    /// Loads the file and swaps the result into the shared map, returning what changed.
+    ///
+    /// The statistics collector of the current map, if any, is carried over to the new one.
+    pub fn reload(&self) -> io::Result<UsersDiff> {
+        let new = (self.loader)(&self.path)?;
+        Ok(self.shared.reload(new))
+    }
+
+    /// Watches the file and reloads it on every change, until the returned [`FileWatch`] is dropped.
+    ///
+    /// The parent directory is watched rather than the file itself, so that editors and
+    /// [`UsersMap::save_to_path`] replacing the file through a rename are noticed.
+    /// `on_change` receives the outcome of every reload, including failures.
+    pub fn watch(
+        self,
+        mut on_change: impl FnMut(io::Result<UsersDiff>) + Send + 'static,
+    ) -> notify::Result<FileWatch>
+    where
+        T: 'static,
+    {
+        let dir = match self.path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name: OsString = self.path.file_name().unwrap_or_default().to_owned();
+
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Ok(e) => e,
+                    Err(e) => return on_change(Err(io::Error::other(e))),
+                };
+                if matches!(event.kind, EventKind::Access(_)) {
+                    return;
+                }
+                if event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == Some(file_name.as_os_str()))
+                {
+                    on_change(self.reload());
+                }
+            })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+        Ok(FileWatch { _watcher: watcher })
+    }
+}
+
+/// Keeps a [`FileUserSource`] watching; dropping it stops the watch.
+pub struct FileWatch {
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl std::fmt::Debug for FileWatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileWatch").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::FileUserSource;
+    use crate::{PlainText, SharedUsersMap, UserAuthenticator, UsersMap};
+
+    #[test]
+    fn test_reload() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("user_trait_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("users.json");
+
+        let mut um = UsersMap::new();
+        um.add_user(PlainText::from("a 1"));
+        um.save_to_path(&path)?;
+
+        let shared: Arc<SharedUsersMap<PlainText>> = Arc::default();
+        let source = FileUserSource::new(&path, Arc::clone(&shared));
+        assert_eq!(source.reload()?.added, ["a"]);
+
+        um.remove_user("a");
+        um.add_user(PlainText::from("b 1"));
+        um.save_to_path(&path)?;
+        let diff = source.reload()?;
+        assert_eq!(diff.added, ["b"]);
+        assert_eq!(diff.removed, ["a"]);
+        assert!(shared.auth_user_by_authstr("plaintext:b\n1").is_some());
+
+        std::fs::write(&path, "broken")?;
+        assert!(source.reload().is_err());
+        assert_eq!(shared.len(), 1);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
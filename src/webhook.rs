@@ -0,0 +1,519 @@
+/*!
+Delegating authentication decisions to an external HTTP service.
+
+Requires the `webhook` feature.
+*/
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::throttle::claimed_identity;
+use crate::{
+    AsyncUserAuthenticator, AuthContext, AuthError, Clock, SecretString, SystemClock, UserTrait,
+    UserWithExpiry, UserWithRoles,
+};
+
+/// A user admitted by a [`WebhookAuthenticator`], as described by the hook's answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookUser {
+    pub identity: String,
+
+    /// The auth string that was presented
+    auth_str: SecretString,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<SystemTime>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+#[typetag::serde]
+impl UserTrait for WebhookUser {
+    fn identity_str(&self) -> &str {
+        &self.identity
+    }
+
+    fn identity_bytes(&self) -> &[u8] {
+        self.identity.as_bytes()
+    }
+
+    fn auth_str(&self) -> &str {
+        &self.auth_str
+    }
+
+    fn auth_bytes(&self) -> &[u8] {
+        self.auth_str.as_bytes()
+    }
+}
+
+impl UserWithExpiry for WebhookUser {
+    fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+}
+
+impl UserWithRoles for WebhookUser {
+    fn roles(&self) -> &[String] {
+        &self.roles
+    }
+}
+
+/// The body POSTed to the hook.
+#[derive(Serialize)]
+struct HookRequest<'a> {
+    /// The identity of the context, or the user name of `plaintext:` auth strings;
+    /// other schemes have to be recognized by their hash.
+    identity: Option<&'a str>,
+
+    /// Hex SHA-256 of the auth string, so that the hook doesn't receive credentials
+    credential_sha256: String,
+
+    context: HookContext<'a>,
+}
+
+#[derive(Serialize)]
+struct HookContext<'a> {
+    source_addr: Option<String>,
+    host: Option<&'a str>,
+    protocol: Option<&'a str>,
+
+    /// Seconds since the Unix epoch
+    timestamp: u64,
+
+    extensions: &'a HashMap<String, String>,
+}
+
+/// The hook's answer.
+#[derive(Debug, Deserialize)]
+struct HookResponse {
+    allow: bool,
+
+    /// Why the attempt was denied: `unknown_user`, `bad_credential`, `expired`,
+    /// `disabled` or `forbidden`. Defaults to `unknown_user`.
+    #[serde(default)]
+    reason: Option<String>,
+
+    /// Defaults to the identity of the request
+    #[serde(default)]
+    identity: Option<String>,
+
+    /// Seconds since the Unix epoch
+    #[serde(default)]
+    expires_at: Option<u64>,
+
+    #[serde(default)]
+    roles: Vec<String>,
+
+    /// How long the answer may be reused for the same auth string and context, in
+    /// seconds. Defaults to the authenticator's TTL; a hook whose answers depend on the
+    /// time of the attempt should answer 0.
+    #[serde(default)]
+    cache_ttl: Option<u64>,
+}
+
+/// What a cached answer is reused for: the auth string and the context the hook was
+/// asked about, except the timestamp.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    authstr: String,
+    source_addr: Option<IpAddr>,
+    host: Option<String>,
+    protocol: Option<String>,
+    identity: Option<String>,
+
+    /// Sorted by key
+    extensions: Vec<(String, String)>,
+}
+
+impl CacheKey {
+    fn new(authstr: &str, cx: &AuthContext) -> Self {
+        let mut extensions: Vec<_> = cx
+            .extensions
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        extensions.sort();
+        CacheKey {
+            authstr: authstr.to_string(),
+            source_addr: cx.source_addr,
+            host: cx.host.clone(),
+            protocol: cx.protocol.clone(),
+            identity: cx.identity.clone(),
+            extensions,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    result: Result<WebhookUser, AuthError>,
+    expires_at: SystemTime,
+}
+
+fn refusal(reason: Option<&str>) -> AuthError {
+    match reason {
+        Some("bad_credential") => AuthError::BadCredential,
+        Some("expired") => AuthError::Expired,
+        Some("disabled") => AuthError::Disabled,
+        Some("forbidden") => AuthError::Forbidden,
+        _ => AuthError::UnknownUser,
+    }
+}
+
+/// Copies a refusal; the ones a hook can give are all unit variants.
+fn copy_refusal(e: &AuthError) -> AuthError {
+    match e {
+        AuthError::BadCredential => AuthError::BadCredential,
+        AuthError::Expired => AuthError::Expired,
+        AuthError::Disabled => AuthError::Disabled,
+        AuthError::Forbidden => AuthError::Forbidden,
+        _ => AuthError::UnknownUser,
+    }
+}
+
+/// Asks an HTTP service whether to admit each attempt, the easiest way to plug in an
+/// external user system.
+///
+/// Each attempt is POSTed as JSON with the identity (from the context or a
+/// `plaintext:` auth string), the SHA-256 of the auth string and the
+/// [`AuthContext`]. The hook answers e.g.
+/// `{"allow": true, "expires_at": 1767225600, "roles": ["admin"], "cache_ttl": 60}`
+/// or `{"allow": false, "reason": "disabled"}`.
+///
+/// Requests time out, and failed requests and 5xx answers are retried; when every
+/// try fails, the attempt fails with [`AuthError::Backend`]. Answers are cached by auth
+/// string and context, apart from its timestamp, for their `cache_ttl`, or
+/// [`WebhookAuthenticator::with_cache_ttl`] by default.
+#[derive(Debug)]
+pub struct WebhookAuthenticator {
+    client: reqwest::Client,
+    url: String,
+    retries: u32,
+    cache_ttl: Duration,
+    cache_capacity: usize,
+    clock: Arc<dyn Clock>,
+    cache: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl WebhookAuthenticator {
+    /// Uses a 5s timeout, 2 retries, a 30s cache TTL and caches up to 10000 answers.
+    ///
+    /// Fails if the HTTP client can't be initialized, e.g. its TLS backend.
+    pub fn new(url: impl Into<String>) -> reqwest::Result<Self> {
+        Self::with_timeout(url, Duration::from_secs(5))
+    }
+
+    pub fn with_timeout(url: impl Into<String>, timeout: Duration) -> reqwest::Result<Self> {
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        Ok(WebhookAuthenticator {
+            client,
+            url: url.into(),
+            retries: 2,
+            cache_ttl: Duration::from_secs(30),
+            cache_capacity: 10_000,
+            clock: Arc::new(SystemClock),
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sets how many times a failed request is retried, with a doubling delay from 100ms.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets how long answers without `cache_ttl` are reused; zero disables caching.
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    /// Sets how many answers are cached at most; once full, expired answers are
+    /// purged and new answers aren't cached until some expire.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Replaces the clock deciding when cached answers and users expire.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn cache(&self) -> MutexGuard<'_, HashMap<CacheKey, CacheEntry>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drops every cached answer.
+    pub fn clear_cache(&self) {
+        self.cache().clear();
+    }
+
+    /// Removes expired answers, returning how many were removed.
+    pub fn purge_expired(&self) -> usize {
+        let now = self.clock.now();
+        let mut cache = self.cache();
+        let before = cache.len();
+        cache.retain(|_, e| e.expires_at > now);
+        before - cache.len()
+    }
+
+    fn store(&self, key: CacheKey, entry: CacheEntry) {
+        let mut cache = self.cache();
+        if cache.len() >= self.cache_capacity && !cache.contains_key(&key) {
+            let now = self.clock.now();
+            cache.retain(|_, e| e.expires_at > now);
+            if cache.len() >= self.cache_capacity {
+                return;
+            }
+        }
+        cache.insert(key, entry);
+    }
+
+    fn cached(&self, key: &CacheKey) -> Option<Result<WebhookUser, AuthError>> {
+        let now = self.clock.now();
+        let mut cache = self.cache();
+        match cache.get(key) {
+            Some(e) if e.expires_at > now => Some(match &e.result {
+                Ok(user) => Ok(user.clone()),
+                Err(e) => Err(copy_refusal(e)),
+            }),
+            Some(_) => {
+                cache.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// POSTs the request, retrying transport failures and server errors.
+    async fn call(&self, body: &HookRequest<'_>) -> Result<HookResponse, AuthError> {
+        let mut delay = Duration::from_millis(100);
+        let mut attempt = 0;
+        loop {
+            let result = self.client.post(&self.url).json(body).send().await;
+            let retriable = match result {
+                Ok(resp) if resp.status().is_server_error() => {
+                    AuthError::backend(format!("webhook answered {}", resp.status()))
+                }
+                Ok(resp) => {
+                    let resp = resp.error_for_status().map_err(AuthError::backend)?;
+                    return resp.json().await.map_err(AuthError::backend);
+                }
+                Err(e) => AuthError::backend(e),
+            };
+            if attempt >= self.retries {
+                return Err(retriable);
+            }
+            attempt += 1;
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    /// Asks the hook about an attempt, or reuses a cached answer.
+    pub async fn auth_with_context(
+        &self,
+        authstr: &str,
+        cx: &AuthContext,
+    ) -> Result<WebhookUser, AuthError> {
+        let key = CacheKey::new(authstr, cx);
+        if let Some(cached) = self.cached(&key) {
+            return self.check_expiry(cached);
+        }
+
+        let identity = cx
+            .identity
+            .as_deref()
+            .map(Cow::Borrowed)
+            .or_else(|| claimed_identity(authstr));
+        let body = HookRequest {
+            identity: identity.as_deref(),
+            credential_sha256: Sha256::digest(authstr.as_bytes())
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+            context: HookContext {
+                source_addr: cx.source_addr.map(|a| a.to_string()),
+                host: cx.host.as_deref(),
+                protocol: cx.protocol.as_deref(),
+                timestamp: cx
+                    .timestamp
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs()),
+                extensions: &cx.extensions,
+            },
+        };
+        let resp = self.call(&body).await?;
+
+        let result = if resp.allow {
+            match resp.identity.as_deref().or(identity.as_deref()) {
+                Some(id) => Ok(WebhookUser {
+                    identity: id.to_string(),
+                    auth_str: authstr.into(),
+                    expires_at: resp.expires_at.map(|t| UNIX_EPOCH + Duration::from_secs(t)),
+                    roles: resp.roles,
+                }),
+                None => Err(AuthError::backend(
+                    "webhook allowed an attempt without naming the identity",
+                )),
+            }
+        } else {
+            Err(refusal(resp.reason.as_deref()))
+        };
+
+        let ttl = resp.cache_ttl.map_or(self.cache_ttl, Duration::from_secs);
+        let cacheable = match &result {
+            Ok(_) => true,
+            Err(e) => !matches!(e, AuthError::Backend(_)),
+        };
+        if cacheable && !ttl.is_zero() {
+            let entry = match &result {
+                Ok(user) => Ok(user.clone()),
+                Err(e) => Err(copy_refusal(e)),
+            };
+            self.store(
+                key,
+                CacheEntry {
+                    result: entry,
+                    expires_at: self.clock.now() + ttl,
+                },
+            );
+        }
+        self.check_expiry(result)
+    }
+
+    fn check_expiry(
+        &self,
+        result: Result<WebhookUser, AuthError>,
+    ) -> Result<WebhookUser, AuthError> {
+        match result {
+            Ok(user) if user.is_expired_at(self.clock.now()) => Err(AuthError::Expired),
+            other => other,
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncUserAuthenticator<WebhookUser> for WebhookAuthenticator {
+    async fn auth_user_by_authstr(&self, authstr: &str) -> Option<WebhookUser> {
+        self.auth_with_context(authstr, &AuthContext::new())
+            .await
+            .ok()
+    }
+
+    async fn try_auth(&self, authstr: &str) -> Result<WebhookUser, AuthError> {
+        self.auth_with_context(authstr, &AuthContext::new()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::{CacheKey, WebhookAuthenticator};
+    use crate::{AuthContext, AuthError, AuthFormat, UserWithRoles};
+
+    /// Serves one canned answer per connection, after a 503 on the first one.
+    fn serve(answers: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/auth", listener.local_addr().unwrap());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        thread::spawn(move || {
+            let mut answers = answers.into_iter();
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        len = v.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                reader.read_exact(&mut vec![0; len]).unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                let (status, body) = if i == 0 {
+                    ("503 Service Unavailable", "")
+                } else {
+                    ("200 OK", answers.next().unwrap_or("{}"))
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        (url, calls)
+    }
+
+    #[test]
+    fn test_webhook() {
+        let (url, calls) = serve(vec![
+            r#"{"allow": true, "roles": ["admin"]}"#,
+            r#"{"allow": false, "reason": "forbidden"}"#,
+            r#"{"allow": false, "reason": "disabled", "cache_ttl": 0}"#,
+            r#"{"allow": true}"#,
+        ]);
+        let auth = WebhookAuthenticator::new(url)
+            .unwrap()
+            .with_cache_capacity(2);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let cx = AuthContext::new().with_protocol("socks5");
+            let user = auth.auth_with_context("plaintext:u\np", &cx).await.unwrap();
+            assert_eq!(user.identity, "u");
+            assert!(user.has_role("admin"));
+            // The first answer was a 503, retried
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+            // Cached
+            assert!(auth.auth_with_context("plaintext:u\np", &cx).await.is_ok());
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+            // Not reused for another context
+            let elsewhere = AuthContext::new().with_protocol("http");
+            assert!(matches!(
+                auth.auth_with_context("plaintext:u\np", &elsewhere).await,
+                Err(AuthError::Forbidden)
+            ));
+            assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+            assert!(matches!(
+                auth.auth_with_context("plaintext:v\np", &cx).await,
+                Err(AuthError::Disabled)
+            ));
+            assert!(auth.cached(&CacheKey::new("plaintext:v\np", &cx)).is_none());
+
+            // The identity of V2 auth strings is decoded too
+            let v2 = AuthFormat::V2.encode("plaintext", "w\nx", "p");
+            let user = auth.auth_with_context(&v2, &cx).await.unwrap();
+            assert_eq!(user.identity, "w\nx");
+            // Not cached beyond the capacity, nothing has expired to make room
+            assert!(auth.cached(&CacheKey::new(&v2, &cx)).is_none());
+            assert_eq!(auth.purge_expired(), 0);
+        });
+    }
+}
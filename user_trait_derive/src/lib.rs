@@ -0,0 +1,162 @@
+/*!
+`#[derive(UserTrait)]`, re-exported by `user_trait` with its `derive` feature.
+*/
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, LitStr, Result};
+
+/// How the auth string is made of the `#[auth]` field
+struct Auth {
+    field: Ident,
+
+    /// `prefix = "..."`: the auth string is `prefix:secret`, cached in the `#[auth_cache]` field
+    prefix: Option<LitStr>,
+
+    /// `identity`: the auth string is `prefix:identity\nsecret`
+    with_identity: bool,
+}
+
+/// Implements `UserTrait`, registered with typetag, for a struct with named fields.
+///
+/// * `#[identity]` marks the field returned by `identity_str`.
+/// * `#[auth]` marks the field returned by `auth_str`, as is.
+/// * `#[auth(prefix = "mytype")]` makes the auth string `mytype:<field>`, and
+///   `#[auth(prefix = "mytype", identity)]` makes it `mytype:<identity>\n<field>`, like
+///   that of `PlainText`. The string is built once into the `#[auth_cache]` field,
+///   an `AuthCache`.
+/// * `#[user_trait(name = "...")]` on the struct sets the typetag name, the struct name
+///   by default.
+///
+/// Fields must dereference to `str`, e.g. `String` or `SecretString`.
+#[proc_macro_derive(UserTrait, attributes(identity, auth, auth_cache, user_trait))]
+pub fn derive_user_trait(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "typetag cannot register generic user types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(&input.ident, "expected named fields")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "expected a struct")),
+    };
+
+    let mut name = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("user_trait"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `name`"))
+            }
+        })?;
+    }
+
+    let mut identity = None;
+    let mut auth: Option<Auth> = None;
+    let mut cache = None;
+    for field in fields {
+        let ident = field.ident.clone().expect("named field");
+        for attr in &field.attrs {
+            if attr.path().is_ident("identity") {
+                if identity.replace(ident.clone()).is_some() {
+                    return Err(Error::new_spanned(attr, "duplicate #[identity] field"));
+                }
+            } else if attr.path().is_ident("auth_cache") {
+                if cache.replace(ident.clone()).is_some() {
+                    return Err(Error::new_spanned(attr, "duplicate #[auth_cache] field"));
+                }
+            } else if attr.path().is_ident("auth") {
+                let mut parsed = Auth {
+                    field: ident.clone(),
+                    prefix: None,
+                    with_identity: false,
+                };
+                if !matches!(attr.meta, syn::Meta::Path(_)) {
+                    attr.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("prefix") {
+                            parsed.prefix = Some(meta.value()?.parse()?);
+                            Ok(())
+                        } else if meta.path.is_ident("identity") {
+                            parsed.with_identity = true;
+                            Ok(())
+                        } else {
+                            Err(meta.error("expected `prefix` or `identity`"))
+                        }
+                    })?;
+                }
+                if auth.replace(parsed).is_some() {
+                    return Err(Error::new_spanned(attr, "duplicate #[auth] field"));
+                }
+            }
+        }
+    }
+    let ty = &input.ident;
+    let identity =
+        identity.ok_or_else(|| Error::new_spanned(ty, "missing an #[identity] field"))?;
+    let auth = auth.ok_or_else(|| Error::new_spanned(ty, "missing an #[auth] field"))?;
+    let secret = &auth.field;
+
+    let auth_str = match (&auth.prefix, &cache) {
+        (None, _) if auth.with_identity => {
+            return Err(Error::new_spanned(secret, "`identity` requires a `prefix`"))
+        }
+        (None, _) => quote!(&*self.#secret),
+        (Some(_), None) => {
+            return Err(Error::new_spanned(
+                ty,
+                "#[auth(prefix = ...)] requires an #[auth_cache] field",
+            ))
+        }
+        (Some(prefix), Some(cache)) if auth.with_identity => quote! {
+            self.#cache.get_or_init(|| {
+                ::std::format!("{}:{}\n{}", #prefix, &*self.#identity, &*self.#secret)
+            })
+        },
+        (Some(prefix), Some(cache)) => quote! {
+            self.#cache.get_or_init(|| ::std::format!("{}:{}", #prefix, &*self.#secret))
+        },
+    };
+    let typetag = match name {
+        Some(name) => quote!(#[::typetag::serde(name = #name)]),
+        None => quote!(#[::typetag::serde]),
+    };
+
+    Ok(quote! {
+        #typetag
+        impl ::user_trait::UserTrait for #ty {
+            fn identity_str(&self) -> &str {
+                &*self.#identity
+            }
+
+            fn identity_bytes(&self) -> &[u8] {
+                ::user_trait::UserTrait::identity_str(self).as_bytes()
+            }
+
+            fn auth_str(&self) -> &str {
+                #auth_str
+            }
+
+            fn auth_bytes(&self) -> &[u8] {
+                ::user_trait::UserTrait::auth_str(self).as_bytes()
+            }
+        }
+    })
+}
@@ -0,0 +1,203 @@
+/*!
+Python bindings of [`user_trait`], so that operators can script user provisioning with the
+same parsing, loading and storage logic as the server.
+
+Build the `user_trait` Python module with `maturin build` in this directory:
+
+```python
+import user_trait
+
+users = user_trait.load_users_from_toml_path("users.toml")
+users.add_user(user_trait.PlainText("alice", "secret"))
+assert users.authenticate("plaintext:alice\nsecret").user == "alice"
+users.save("users.json")
+```
+*/
+
+use std::path::PathBuf;
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use user_trait::{LoadError, PlainText, UserAuthenticator, UserTrait, UsersMap};
+
+fn load_error(e: LoadError) -> PyErr {
+    match e {
+        LoadError::Io(e) => e.into(),
+        e => PyValueError::new_err(e.to_string()),
+    }
+}
+
+/// A user with a plaintext password. Its `repr` doesn't show the password.
+#[pyclass(name = "PlainText", frozen, skip_from_py_object)]
+#[derive(Clone)]
+struct PyPlainText(PlainText);
+
+#[pymethods]
+impl PyPlainText {
+    #[new]
+    fn new(user: String, password: String) -> Self {
+        PyPlainText(PlainText::new(user, password))
+    }
+
+    /// Parses `"user password"`, refusing an empty user name or password.
+    #[staticmethod]
+    fn parse(userpass: &str) -> PyResult<Self> {
+        userpass
+            .parse()
+            .map(PyPlainText)
+            .map_err(|e: user_trait::ParseUserError| PyValueError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn user(&self) -> &str {
+        &self.0.user
+    }
+
+    #[getter]
+    fn password(&self) -> &str {
+        &self.0.pass
+    }
+
+    #[getter]
+    fn auth_str(&self) -> &str {
+        self.0.auth_str()
+    }
+
+    /// The hex SHA-256 digest of the credential, for logs
+    fn fingerprint(&self) -> String {
+        self.0
+            .fingerprint()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+/// A map of [`PlainText`] users by identity and by auth string.
+#[pyclass(name = "UsersMap")]
+#[derive(Default)]
+struct PyUsersMap(UsersMap<PlainText>);
+
+#[pymethods]
+impl PyUsersMap {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a user, replacing the user with the same name.
+    fn add_user(&mut self, user: &PyPlainText) {
+        self.0.add_user(user.0.clone());
+    }
+
+    /// Removes a user and all of its credentials, returning it.
+    fn remove_user(&mut self, id: &str) -> Option<PyPlainText> {
+        self.0
+            .remove_user(id)
+            .map(|u| PyPlainText(u.as_ref().clone()))
+    }
+
+    fn get_user(&self, id: &str) -> Option<PyPlainText> {
+        self.0.get_user(id).map(|u| PyPlainText(u.as_ref().clone()))
+    }
+
+    /// Returns the user owning `authstr` if it may log in, like the server would.
+    fn authenticate(&self, authstr: &str) -> Option<PyPlainText> {
+        self.0.auth_user_by_authstr(authstr).map(PyPlainText)
+    }
+
+    /// Adds an extra auth string, e.g. a token, for an existing user.
+    fn add_credential(&mut self, id: &str, authstr: &str) -> bool {
+        self.0.add_credential(id, authstr)
+    }
+
+    fn remove_credential(&mut self, id: &str, authstr: &str) -> bool {
+        self.0.remove_credential(id, authstr)
+    }
+
+    fn set_enabled(&mut self, id: &str, enabled: bool) -> bool {
+        self.0.set_enabled(id, enabled)
+    }
+
+    fn is_enabled(&self, id: &str) -> bool {
+        self.0.is_enabled(id)
+    }
+
+    /// The user names, sorted
+    fn identities(&self) -> Vec<String> {
+        let mut ids: Vec<_> = self.0.iter().map(|u| u.user.clone()).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Writes the map as JSON, replacing the file atomically.
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        self.0.save_to_path(&path).map_err(PyOSError::new_err)
+    }
+
+    /// Reads a map written by `save`.
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        UsersMap::load_from_path(&path)
+            .map(PyUsersMap)
+            .map_err(PyErr::from)
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+
+    fn __contains__(&self, id: &str) -> bool {
+        self.0.get_user(id).is_some()
+    }
+}
+
+/// Parses a TOML user list, e.g. `users = ["alice secret"]`.
+#[pyfunction]
+fn load_users_from_toml(text: &str) -> PyResult<PyUsersMap> {
+    user_trait::load_users_from_toml(text)
+        .map(PyUsersMap)
+        .map_err(load_error)
+}
+
+#[pyfunction]
+fn load_users_from_toml_path(path: PathBuf) -> PyResult<PyUsersMap> {
+    user_trait::load_users_from_toml_path(&path)
+        .map(PyUsersMap)
+        .map_err(load_error)
+}
+
+/// Parses a YAML user list.
+#[pyfunction]
+fn load_users_from_yaml(text: &str) -> PyResult<PyUsersMap> {
+    user_trait::load_users_from_yaml(text)
+        .map(PyUsersMap)
+        .map_err(load_error)
+}
+
+#[pyfunction]
+fn load_users_from_yaml_path(path: PathBuf) -> PyResult<PyUsersMap> {
+    user_trait::load_users_from_yaml_path(&path)
+        .map(PyUsersMap)
+        .map_err(load_error)
+}
+
+#[pymodule]
+#[pyo3(name = "user_trait")]
+fn user_trait_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyPlainText>()?;
+    m.add_class::<PyUsersMap>()?;
+    m.add_function(wrap_pyfunction!(load_users_from_toml, m)?)?;
+    m.add_function(wrap_pyfunction!(load_users_from_toml_path, m)?)?;
+    m.add_function(wrap_pyfunction!(load_users_from_yaml, m)?)?;
+    m.add_function(wrap_pyfunction!(load_users_from_yaml_path, m)?)?;
+    Ok(())
+}